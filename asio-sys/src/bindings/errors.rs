@@ -5,6 +5,7 @@ use std::fmt;
 #[derive(Debug)]
 pub enum LoadDriverError {
     LoadDriverFailed,
+    /// Another driver is loaded, as ASIO only supports loading one driver at a time.
     DriverAlreadyExists,
     InitializationFailed(AsioError),
 }
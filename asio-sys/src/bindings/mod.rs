@@ -8,6 +8,7 @@ use once_cell::sync::Lazy;
 use std::ffi::CStr;
 use std::ffi::CString;
 use std::os::raw::{c_char, c_double, c_long, c_void};
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::{Arc, Mutex, MutexGuard, Weak};
 
 // Bindings import
@@ -95,6 +96,24 @@ pub struct CallbackInfo {
 /// Holds the pointer to the callbacks that come from cpal
 struct BufferCallback(Box<dyn FnMut(&CallbackInfo) + Send>);
 
+/// A notification from the driver, outside of the processing of buffers.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum DriverEvent {
+    /// The sample rate of the driver changed to the given rate, e.g. from its control panel or
+    /// following a change of its clock source.
+    SampleRateChanged(c_double),
+    /// The driver asks for its buffers to be created again, e.g. after their size was changed in
+    /// its control panel. See `Driver::is_reset_requested`.
+    ResetRequest,
+    /// The driver lost some data, without the streams having to be recreated.
+    ResyncRequest,
+    /// The input or output latencies of the driver changed.
+    LatenciesChanged,
+}
+
+/// A callback notified of the events of the driver.
+struct MessageCallback(Box<dyn FnMut(DriverEvent) + Send>);
+
 /// Input and Output streams.
 ///
 /// There is only ever max one input and one output.
@@ -291,18 +310,40 @@ pub struct AsioTimeCode {
 /// fields.
 pub type AsioSampleRate = f64;
 
-// A helper type to simplify retrieval of available buffer sizes.
-#[derive(Default)]
-struct BufferSizes {
-    min: c_long,
-    max: c_long,
-    pref: c_long,
-    grans: c_long,
+/// The buffer sizes supported by a driver, in frames, as reported by `ASIOGetBufferSize`.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BufferSizes {
+    pub min: c_long,
+    pub max: c_long,
+    /// The buffer size the driver prefers, usually set in its control panel.
+    pub pref: c_long,
+    /// The step between the supported sizes, starting at `min`. `-1` if every power of two
+    /// multiple of `min` is supported, `0` if `min` is the only supported size.
+    pub grans: c_long,
+}
+
+impl BufferSizes {
+    /// Whether buffers of `size` frames can be created.
+    pub fn supports(&self, size: c_long) -> bool {
+        if size < self.min || size > self.max {
+            return false;
+        }
+        match self.grans {
+            -1 => self.min > 0 && size % self.min == 0 && (size / self.min).count_ones() == 1,
+            // Some drivers report no granularity along with a range, only accepting their
+            // preferred size.
+            0 => size == self.min || size == self.pref,
+            grans => (size - self.min) % grans == 0,
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct CallbackId(usize);
 
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MessageCallbackId(usize);
+
 /// A global way to access all the callbacks.
 ///
 /// This is required because of how ASIO calls the `buffer_switch` function with no data
@@ -314,6 +355,13 @@ pub struct CallbackId(usize);
 static BUFFER_CALLBACK: Lazy<Mutex<Vec<(CallbackId, BufferCallback)>>> =
     Lazy::new(|| Mutex::new(Vec::new()));
 
+/// The callbacks notified of the events of the driver, for the same reason as `BUFFER_CALLBACK`.
+static MESSAGE_CALLBACK: Lazy<Mutex<Vec<(MessageCallbackId, MessageCallback)>>> =
+    Lazy::new(|| Mutex::new(Vec::new()));
+
+/// Whether the driver asked for a reset since the buffers were last created.
+static RESET_REQUESTED: AtomicBool = AtomicBool::new(false);
+
 impl Asio {
     /// Initialise the ASIO API.
     pub fn new() -> Self {
@@ -422,6 +470,14 @@ impl BufferCallback {
     }
 }
 
+impl MessageCallback {
+    /// Calls the inner callback.
+    fn run(&mut self, event: DriverEvent) {
+        let cb = &mut self.0;
+        cb(event);
+    }
+}
+
 impl Driver {
     /// The name used to uniquely identify this driver.
     pub fn name(&self) -> &str {
@@ -447,6 +503,18 @@ impl Driver {
         Ok((min, max))
     }
 
+    /// The name the driver gives the channel at the given index on either the input or output
+    /// stream (`true` for input), e.g. "Analog In 1".
+    pub fn channel_name(&self, channel: c_long, is_input: bool) -> Result<String, AsioError> {
+        let channel_info = asio_channel_info(channel, is_input)?;
+        Ok(channel_name_to_utf8(&channel_info.name).into_owned())
+    }
+
+    /// Get the min, max and preferred buffersizes of the driver, along with their granularity.
+    pub fn buffer_sizes(&self) -> Result<BufferSizes, AsioError> {
+        asio_get_buffer_sizes()
+    }
+
     /// Get current sample rate of the driver.
     pub fn sample_rate(&self) -> Result<c_double, AsioError> {
         let mut rate: c_double = 0.0;
@@ -475,6 +543,17 @@ impl Driver {
         Ok(())
     }
 
+    /// Open the control panel of the driver, letting the user change its settings.
+    ///
+    /// Drivers usually show the panel without blocking, and report changes that require the
+    /// buffers to be recreated through a reset request.
+    pub fn open_control_panel(&self) -> Result<(), AsioError> {
+        unsafe {
+            asio_result!(ai::ASIOControlPanel())?;
+        }
+        Ok(())
+    }
+
     /// Get the current data type of the driver's input stream.
     ///
     /// This queries a single channel's type assuming all channels have the same sample type.
@@ -494,8 +573,8 @@ impl Driver {
     /// This will destroy any already allocated buffers.
     ///
     /// If buffersize is None then the preferred buffer size from ASIO is used,
-    /// otherwise the desired buffersize is used if the requested size is within
-    /// the range of accepted buffersizes for the device.
+    /// otherwise the desired buffersize is used if the device supports it, see
+    /// `BufferSizes::supports`.
     fn create_buffers(
         &self,
         buffer_infos: &mut [AsioBufferInfo],
@@ -515,13 +594,8 @@ impl Driver {
         }
 
         let buffer_size = match buffer_size {
-            Some(v) => {
-                if v <= buffer_sizes.max {
-                    v
-                } else {
-                    return Err(AsioError::InvalidBufferSize);
-                }
-            }
+            Some(v) if buffer_sizes.supports(v) => v,
+            Some(_) => return Err(AsioError::InvalidBufferSize),
             None => buffer_sizes.pref,
         };
 
@@ -541,6 +615,7 @@ impl Driver {
             ))?;
         }
         *state = DriverState::Prepared;
+        RESET_REQUESTED.store(false, Ordering::SeqCst);
 
         Ok(buffer_size)
     }
@@ -627,7 +702,21 @@ impl Driver {
         num_channels: usize,
         buffer_size: Option<i32>,
     ) -> Result<AsioStreams, AsioError> {
-        let input_buffer_infos = prepare_buffer_infos(true, num_channels);
+        let channels: Vec<usize> = (0..num_channels).collect();
+        self.prepare_input_stream_on_channels(output, &channels, buffer_size)
+    }
+
+    /// Prepare the input stream on the given input channels only, the `n`th channel of the stream
+    /// being the device channel with index `channels[n]`.
+    ///
+    /// Otherwise behaves like `prepare_input_stream`.
+    pub fn prepare_input_stream_on_channels(
+        &self,
+        output: Option<AsioStream>,
+        channels: &[usize],
+        buffer_size: Option<i32>,
+    ) -> Result<AsioStreams, AsioError> {
+        let input_buffer_infos = prepare_buffer_infos(true, channels);
         let output_buffer_infos = output
             .map(|output| output.buffer_infos)
             .unwrap_or_else(Vec::new);
@@ -652,11 +741,25 @@ impl Driver {
         input: Option<AsioStream>,
         num_channels: usize,
         buffer_size: Option<i32>,
+    ) -> Result<AsioStreams, AsioError> {
+        let channels: Vec<usize> = (0..num_channels).collect();
+        self.prepare_output_stream_on_channels(input, &channels, buffer_size)
+    }
+
+    /// Prepare the output stream on the given output channels only, the `n`th channel of the
+    /// stream being the device channel with index `channels[n]`.
+    ///
+    /// Otherwise behaves like `prepare_output_stream`.
+    pub fn prepare_output_stream_on_channels(
+        &self,
+        input: Option<AsioStream>,
+        channels: &[usize],
+        buffer_size: Option<i32>,
     ) -> Result<AsioStreams, AsioError> {
         let input_buffer_infos = input
             .map(|input| input.buffer_infos)
             .unwrap_or_else(Vec::new);
-        let output_buffer_infos = prepare_buffer_infos(false, num_channels);
+        let output_buffer_infos = prepare_buffer_infos(false, channels);
         self.create_streams(input_buffer_infos, output_buffer_infos, buffer_size)
     }
 
@@ -723,6 +826,42 @@ impl Driver {
         bc.retain(|&(id, _)| id != rem_id);
     }
 
+    /// Adds a callback notified of the events of the driver, on whatever thread the driver
+    /// reports them.
+    ///
+    /// The callback must not add or remove callbacks itself.
+    ///
+    /// Returns an ID uniquely associated with the given callback so that it may be removed later.
+    pub fn add_message_callback<F>(&self, callback: F) -> MessageCallbackId
+    where
+        F: 'static + FnMut(DriverEvent) + Send,
+    {
+        let mut mc = MESSAGE_CALLBACK.lock().unwrap();
+        let id = mc
+            .last()
+            .map(|&(id, _)| {
+                MessageCallbackId(id.0.checked_add(1).expect("message callback ID overflowed"))
+            })
+            .unwrap_or(MessageCallbackId(0));
+        mc.push((id, MessageCallback(Box::new(callback))));
+        id
+    }
+
+    /// Remove the message callback with the given ID.
+    pub fn remove_message_callback(&self, rem_id: MessageCallbackId) {
+        let mut mc = MESSAGE_CALLBACK.lock().unwrap();
+        mc.retain(|&(id, _)| id != rem_id);
+    }
+
+    /// Whether the driver asked for a reset since the buffers were last created, after which the
+    /// existing buffers shouldn't be used anymore.
+    ///
+    /// ASIO doesn't allow resetting the driver from its own callback, so the buffers are only
+    /// created again once new streams are prepared.
+    pub fn is_reset_requested(&self) -> bool {
+        RESET_REQUESTED.load(Ordering::SeqCst)
+    }
+
     /// Consumes and destroys the `Driver`, stopping the streams if they are running and releasing
     /// any associated resources.
     ///
@@ -808,6 +947,9 @@ impl DriverInner {
             if let Ok(mut bcs) = BUFFER_CALLBACK.lock() {
                 bcs.clear();
             }
+            if let Ok(mut mcs) = MESSAGE_CALLBACK.lock() {
+                mcs.clear();
+            }
         }
 
         // Signal that the driver has been destroyed.
@@ -830,10 +972,11 @@ impl Drop for DriverInner {
 unsafe impl Send for AsioStream {}
 
 /// Used by the input and output stream creation process.
-fn prepare_buffer_infos(is_input: bool, n_channels: usize) -> Vec<AsioBufferInfo> {
+fn prepare_buffer_infos(is_input: bool, channels: &[usize]) -> Vec<AsioBufferInfo> {
     let is_input = if is_input { 1 } else { 0 };
-    (0..n_channels)
-        .map(|ch| {
+    channels
+        .iter()
+        .map(|&ch| {
             let channel_num = ch as c_long;
             // To be filled by ASIOCreateBuffers.
             let buffers = [std::ptr::null_mut(); 2];
@@ -896,15 +1039,21 @@ fn driver_name_to_utf8(bytes: &[c_char]) -> std::borrow::Cow<str> {
 /// ASIO uses null terminated c strings for channel names.
 ///
 /// This converts to utf8.
-fn _channel_name_to_utf8(bytes: &[c_char]) -> std::borrow::Cow<str> {
+fn channel_name_to_utf8(bytes: &[c_char]) -> std::borrow::Cow<str> {
     unsafe { CStr::from_ptr(bytes.as_ptr()).to_string_lossy() }
 }
 
+/// Run every message callback with the given event.
+fn notify(event: DriverEvent) {
+    let mut mc = MESSAGE_CALLBACK.lock().unwrap();
+    for &mut (_, ref mut callback) in mc.iter_mut() {
+        callback.run(event);
+    }
+}
+
 /// Indicates the stream sample rate has changed.
-///
-/// TODO: Provide some way of allowing CPAL to handle this.
 extern "C" fn sample_rate_did_change(s_rate: c_double) -> () {
-    eprintln!("unhandled sample rate change to {}", s_rate);
+    notify(DriverEvent::SampleRateChanged(s_rate));
 }
 
 /// Message callback for ASIO to notify of certain events.
@@ -935,7 +1084,8 @@ extern "C" fn asio_message(
             // You cannot reset the driver right now, as this code is called from the driver. Reset
             // the driver is done by completely destruct it. I.e. ASIOStop(), ASIODisposeBuffers(),
             // Destruction. Afterwards you initialize the driver again.
-            // TODO: Handle this.
+            RESET_REQUESTED.store(true, Ordering::SeqCst);
+            notify(DriverEvent::ResetRequest);
             1
         }
 
@@ -945,7 +1095,7 @@ extern "C" fn asio_message(
             // around the Win16Mutex problems in Windows 95/98 with the Windows Multimedia system,
             // which could loose data because the Mutex was hold too long by another thread.
             // However a driver can issue it in other situations, too.
-            // TODO: Handle this.
+            notify(DriverEvent::ResyncRequest);
             1
         }
 
@@ -953,7 +1103,7 @@ extern "C" fn asio_message(
             // This will inform the host application that the drivers were latencies changed.
             // Beware, it this does not mean that the buffer sizes have changed! You might need to
             // update internal delay data.
-            // TODO: Handle this.
+            notify(DriverEvent::LatenciesChanged);
             1
         }
 
@@ -0,0 +1,192 @@
+//! Streams on a subset of a device's channels, for streams that can only exchange all of them.
+
+use crate::{Data, InputCallbackInfo, OutputCallbackInfo, SampleFormat};
+
+/// Copies the channels of a stream between the buffers of its data callback and buffers holding
+/// all of the channels of its device.
+struct ChannelSubset {
+    // The device channel of each of the stream's channels.
+    device_channels: Vec<usize>,
+    device_channel_count: usize,
+    buffer: Vec<u8>,
+    // The bytes of a silent sample, in the first `sample_size` bytes.
+    silence: [u8; 8],
+}
+
+impl ChannelSubset {
+    fn new(
+        device_channels: &[u16],
+        device_channel_count: u16,
+        sample_format: SampleFormat,
+    ) -> Self {
+        ChannelSubset {
+            device_channels: device_channels.iter().map(|&c| c as usize).collect(),
+            device_channel_count: device_channel_count as usize,
+            buffer: Vec::new(),
+            silence: silence(sample_format),
+        }
+    }
+
+    /// Resize the buffer of the stream to the frames in `device`, returning it as `Data`.
+    fn stream_data(&mut self, device: &Data) -> Data {
+        let sample_format = device.sample_format();
+        let frames = device.len() / self.device_channel_count;
+        let len = frames * self.device_channels.len();
+        // Only allocates when the host exchanges more frames than ever before.
+        self.buffer.resize(len * sample_format.sample_size(), 0);
+        unsafe { Data::from_parts(self.buffer.as_mut_ptr() as *mut (), len, sample_format) }
+    }
+
+    fn gather(&mut self, device: &Data) {
+        let sample_size = device.sample_format().sample_size();
+        let device_frames = device
+            .bytes()
+            .chunks_exact(self.device_channel_count * sample_size);
+        let frames = self
+            .buffer
+            .chunks_exact_mut(self.device_channels.len() * sample_size);
+        for (device_frame, frame) in device_frames.zip(frames) {
+            let samples = frame.chunks_exact_mut(sample_size);
+            for (sample, &channel) in samples.zip(&self.device_channels) {
+                let offset = channel * sample_size;
+                sample.copy_from_slice(&device_frame[offset..offset + sample_size]);
+            }
+        }
+    }
+
+    fn scatter(&self, device: &mut Data) {
+        let sample_format = device.sample_format();
+        let sample_size = sample_format.sample_size();
+        let silence = &self.silence[..sample_size];
+        let device_frames = device
+            .bytes_mut()
+            .chunks_exact_mut(self.device_channel_count * sample_size);
+        let frames = self
+            .buffer
+            .chunks_exact(self.device_channels.len() * sample_size);
+        for (device_frame, frame) in device_frames.zip(frames) {
+            // The channels that aren't part of the stream are left silent.
+            for sample in device_frame.chunks_exact_mut(sample_size) {
+                sample.copy_from_slice(silence);
+            }
+            let samples = frame.chunks_exact(sample_size);
+            for (sample, &channel) in samples.zip(&self.device_channels) {
+                let offset = channel * sample_size;
+                device_frame[offset..offset + sample_size].copy_from_slice(sample);
+            }
+        }
+    }
+}
+
+/// The bytes of a silent sample in `sample_format`, padded to the largest sample size.
+fn silence(sample_format: SampleFormat) -> [u8; 8] {
+    let mut silence = [0; 8];
+    match sample_format {
+        SampleFormat::U8 => silence[0] = 0x80,
+        SampleFormat::U16 => silence[..2].copy_from_slice(&(1u16 << 15).to_ne_bytes()),
+        SampleFormat::U32 => silence[..4].copy_from_slice(&(1u32 << 31).to_ne_bytes()),
+        SampleFormat::U64 => silence.copy_from_slice(&(1u64 << 63).to_ne_bytes()),
+        _ => {}
+    }
+    silence
+}
+
+/// Wrap the data callback of an input stream on `device_channels` so that it can be used for a
+/// stream on all `device_channel_count` channels of the device.
+pub(crate) fn select_input_channels<D>(
+    device_channels: &[u16],
+    device_channel_count: u16,
+    sample_format: SampleFormat,
+    mut data_callback: D,
+) -> impl FnMut(&Data, &InputCallbackInfo) + Send + 'static
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+{
+    let mut subset = ChannelSubset::new(device_channels, device_channel_count, sample_format);
+    move |device: &Data, info: &InputCallbackInfo| {
+        let data = subset.stream_data(device);
+        subset.gather(device);
+        data_callback(&data, info);
+    }
+}
+
+/// Wrap the data callback of an output stream on `device_channels` so that it can be used for a
+/// stream on all `device_channel_count` channels of the device.
+pub(crate) fn select_output_channels<D>(
+    device_channels: &[u16],
+    device_channel_count: u16,
+    sample_format: SampleFormat,
+    mut data_callback: D,
+) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    let mut subset = ChannelSubset::new(device_channels, device_channel_count, sample_format);
+    move |device: &mut Data, info: &OutputCallbackInfo| {
+        let mut data = subset.stream_data(device);
+        data_callback(&mut data, info);
+        subset.scatter(device);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ChannelSubset;
+    use crate::{Data, SampleFormat};
+
+    fn data(samples: &mut [i16]) -> Data {
+        unsafe {
+            Data::from_parts(
+                samples.as_mut_ptr() as *mut (),
+                samples.len(),
+                SampleFormat::I16,
+            )
+        }
+    }
+
+    #[test]
+    fn gather_selected_channels() {
+        let mut subset = ChannelSubset::new(&[3, 0], 4, SampleFormat::I16);
+        let mut device = [10, 11, 12, 13, 20, 21, 22, 23];
+        let device = data(&mut device);
+        let len = subset.stream_data(&device).len();
+        subset.gather(&device);
+        assert_eq!(len, 4);
+        let stream = subset.stream_data(&device);
+        assert_eq!(stream.as_slice::<i16>().unwrap(), [13, 10, 23, 20]);
+    }
+
+    #[test]
+    fn scatter_zero_fills_other_channels() {
+        let mut subset = ChannelSubset::new(&[2, 1], 3, SampleFormat::I16);
+        let mut device = [7; 6];
+        let mut device = data(&mut device);
+        let mut stream = subset.stream_data(&device);
+        stream
+            .as_slice_mut::<i16>()
+            .unwrap()
+            .copy_from_slice(&[1, 2, 3, 4]);
+        subset.scatter(&mut device);
+        assert_eq!(device.as_slice::<i16>().unwrap(), [0, 2, 1, 0, 4, 3]);
+    }
+
+    #[test]
+    fn scatter_silences_unsigned_samples() {
+        let mut subset = ChannelSubset::new(&[1], 2, SampleFormat::U8);
+        let mut device = [0u8; 4];
+        let mut device = unsafe {
+            Data::from_parts(
+                device.as_mut_ptr() as *mut (),
+                device.len(),
+                SampleFormat::U8,
+            )
+        };
+        let mut stream = subset.stream_data(&device);
+        stream
+            .as_slice_mut::<u8>()
+            .unwrap()
+            .copy_from_slice(&[1, 2]);
+        subset.scatter(&mut device);
+        assert_eq!(device.as_slice::<u8>().unwrap(), [0x80, 1, 0x80, 2]);
+    }
+}
@@ -0,0 +1,159 @@
+//! Playback of DSD over PCM (DoP) streams.
+
+use std::time::Duration;
+
+use crate::traits::DeviceTrait;
+use crate::{
+    BufferSize, BuildStreamError, Data, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig,
+    StreamError, StreamOptions,
+};
+
+/// The markers identifying DoP samples, in the most significant byte of each sample. Consecutive
+/// frames alternate between them.
+const DOP_MARKERS: [u8; 2] = [0x05, 0xFA];
+
+/// A byte of DSD silence.
+const DSD_SILENCE: u8 = 0x69;
+
+type OutputDataCallback = dyn FnMut(&mut Data, &OutputCallbackInfo) + Send;
+
+/// Build an output stream playing the DSD written by `data_callback` as DoP: each `I32` sample of
+/// the underlying PCM stream carries a marker and two bytes of DSD of its channel.
+///
+/// `config` and `sample_format` describe the DSD stream as documented by
+/// [`DsdMode`](crate::DsdMode).
+pub(crate) fn build_dop_output_stream<Dev, D, E>(
+    device: &Dev,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    options: &StreamOptions,
+    mut data_callback: D,
+    error_callback: E,
+    timeout: Option<Duration>,
+) -> Result<Dev::Stream, BuildStreamError>
+where
+    Dev: DeviceTrait + ?Sized,
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    if sample_format != SampleFormat::U8 {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    let pcm_config = StreamConfig {
+        channels: config.channels,
+        sample_rate: SampleRate(config.sample_rate.0 / 2),
+        buffer_size: match config.buffer_size {
+            BufferSize::Fixed(frames) => BufferSize::Fixed(frames / 2),
+            BufferSize::Default => BufferSize::Default,
+        },
+    };
+    // The DSD must reach the device unconverted.
+    let pcm_options = StreamOptions {
+        dsd: None,
+        conversion: None,
+        ..options.clone()
+    };
+
+    let mut encoder = DopEncoder::new(config.channels as usize);
+    let mut dsd = Vec::new();
+    // Boxed, as the PCM stream is built by the method that called this function.
+    let data_callback: Box<OutputDataCallback> =
+        Box::new(move |data: &mut Data, info: &OutputCallbackInfo| {
+            let samples = data
+                .as_slice_mut::<i32>()
+                .expect("DoP streams are built with `I32` samples");
+            // Only allocates when the host requests more frames than ever before.
+            dsd.resize(samples.len() * 2, DSD_SILENCE);
+            let mut dsd_data = unsafe {
+                Data::from_parts(dsd.as_mut_ptr() as *mut (), dsd.len(), SampleFormat::U8)
+            };
+            data_callback(&mut dsd_data, info);
+            encoder.encode(&dsd, samples);
+        });
+
+    device.build_output_stream_raw_with_options(
+        &pcm_config,
+        SampleFormat::I32,
+        &pcm_options,
+        data_callback,
+        error_callback,
+        timeout,
+    )
+}
+
+/// Packs the interleaved DSD of a stream into DoP samples, alternating the markers across
+/// buffers.
+struct DopEncoder {
+    channels: usize,
+    marker: usize,
+}
+
+impl DopEncoder {
+    fn new(channels: usize) -> Self {
+        DopEncoder {
+            channels,
+            marker: 0,
+        }
+    }
+
+    /// Write the DoP samples carrying `dsd`, which holds two bytes per sample.
+    fn encode(&mut self, dsd: &[u8], samples: &mut [i32]) {
+        let channels = self.channels;
+        // Each PCM frame carries two consecutive DSD frames, the earlier one in the upper byte.
+        for (frame, dsd_frames) in samples.chunks_mut(channels).zip(dsd.chunks(channels * 2)) {
+            for (channel, sample) in frame.iter_mut().enumerate() {
+                *sample = i32::from_be_bytes([
+                    DOP_MARKERS[self.marker],
+                    dsd_frames[channel],
+                    dsd_frames[channels + channel],
+                    0,
+                ]);
+            }
+            self.marker ^= 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DopEncoder;
+
+    fn bytes(sample: i32) -> [u8; 4] {
+        sample.to_be_bytes()
+    }
+
+    #[test]
+    fn markers_alternate_per_frame() {
+        let mut encoder = DopEncoder::new(2);
+        let mut samples = [0; 6];
+        encoder.encode(&[0; 12], &mut samples);
+        let markers: Vec<u8> = samples.iter().map(|&s| bytes(s)[0]).collect();
+        assert_eq!(markers, [0x05, 0x05, 0xFA, 0xFA, 0x05, 0x05]);
+    }
+
+    #[test]
+    fn bytes_are_ordered_per_channel() {
+        let mut encoder = DopEncoder::new(2);
+        let mut samples = [0; 4];
+        // Two DSD frames of (left, right) per PCM frame.
+        encoder.encode(
+            &[0x11, 0x21, 0x12, 0x22, 0x13, 0x23, 0x14, 0x24],
+            &mut samples,
+        );
+        assert_eq!(bytes(samples[0]), [0x05, 0x11, 0x12, 0]);
+        assert_eq!(bytes(samples[1]), [0x05, 0x21, 0x22, 0]);
+        assert_eq!(bytes(samples[2]), [0xFA, 0x13, 0x14, 0]);
+        assert_eq!(bytes(samples[3]), [0xFA, 0x23, 0x24, 0]);
+    }
+
+    #[test]
+    fn marker_carries_across_buffers() {
+        let mut encoder = DopEncoder::new(1);
+        let mut first = [0; 3];
+        encoder.encode(&[0x69; 6], &mut first);
+        let mut second = [0; 2];
+        encoder.encode(&[0x69; 4], &mut second);
+        let markers: Vec<u8> = first.iter().chain(&second).map(|&s| bytes(s)[0]).collect();
+        assert_eq!(markers, [0x05, 0xFA, 0x05, 0xFA, 0x05]);
+    }
+}
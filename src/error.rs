@@ -207,6 +207,9 @@ impl From<BackendSpecificError> for BuildStreamError {
 pub enum PlayStreamError {
     /// The device associated with the stream is no longer available.
     DeviceNotAvailable,
+    /// The autoplay policy of the browser keeps the stream from starting until the user
+    /// interacts with the page, upon which the stream starts if it is resumed then.
+    AutoplayBlocked,
     /// See the [`BackendSpecificError`] docs for more information about this error variant.
     BackendSpecific { err: BackendSpecificError },
 }
@@ -218,6 +221,9 @@ impl Display for PlayStreamError {
             PlayStreamError::DeviceNotAvailable => {
                 f.write_str("the device associated with the stream is no longer available")
             }
+            PlayStreamError::AutoplayBlocked => f.write_str(
+                "the stream can't start until the user interacts with the page (autoplay policy)",
+            ),
         }
     }
 }
@@ -268,10 +274,55 @@ pub enum StreamError {
     /// The device no longer exists. This can happen if the device is disconnected while the
     /// program is running.
     DeviceNotAvailable,
+    /// The device has not become ready within the `timeout` given when building the stream, or
+    /// the data callback has not been invoked for longer than allowed by
+    /// [`StreamOptions::watchdog_periods`](crate::StreamOptions::watchdog_periods), while the
+    /// stream was playing. This can happen if the driver silently stopped delivering callbacks.
+    Stalled,
+    /// The stream was disconnected from its device for the given reason, after which it must be
+    /// rebuilt. Reported instead of [`DeviceNotAvailable`](Self::DeviceNotAvailable) by hosts
+    /// that know why, currently WASAPI, Android, and CoreAudio on macOS and ASIO for changes to
+    /// the sample rate of the device.
+    Disconnected { reason: DisconnectReason },
     /// See the [`BackendSpecificError`] docs for more information about this error variant.
     BackendSpecific { err: BackendSpecificError },
 }
 
+/// Why a stream was disconnected from its device, reported through
+/// [`StreamError::Disconnected`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum DisconnectReason {
+    /// The device was removed, e.g. unplugged or disabled.
+    DeviceRemoved,
+    /// The format of the device was changed, e.g. in the system's sound settings.
+    FormatChanged,
+    /// Another application took exclusive access of the device.
+    ExclusiveModeOverride,
+    /// The system's audio service was stopped.
+    ServerShutdown,
+    /// The user's session ended, e.g. by logging off or disconnecting a remote desktop session.
+    SessionEnded,
+    /// The audio of the stream was routed to another device, e.g. because headphones were
+    /// plugged in or a Bluetooth device connected.
+    RouteChanged,
+}
+
+impl Display for DisconnectReason {
+    fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
+        f.write_str(match self {
+            DisconnectReason::DeviceRemoved => "the device was removed",
+            DisconnectReason::FormatChanged => "the format of the device was changed",
+            DisconnectReason::ExclusiveModeOverride => {
+                "another application took exclusive access of the device"
+            }
+            DisconnectReason::ServerShutdown => "the audio service was stopped",
+            DisconnectReason::SessionEnded => "the user's session ended",
+            DisconnectReason::RouteChanged => "the audio was routed to another device",
+        })
+    }
+}
+
 impl Display for StreamError {
     fn fmt(&self, f: &mut Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -279,6 +330,16 @@ impl Display for StreamError {
             StreamError::DeviceNotAvailable => f.write_str(
                 "The requested device is no longer available. For example, it has been unplugged.",
             ),
+            StreamError::Stalled => {
+                f.write_str("The stream's data callback has stopped being invoked.")
+            }
+            StreamError::Disconnected { reason } => {
+                write!(
+                    f,
+                    "The stream was disconnected from its device: {}.",
+                    reason
+                )
+            }
         }
     }
 }
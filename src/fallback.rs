@@ -0,0 +1,213 @@
+//! Building a stream from the first of several candidate configurations that succeeds.
+
+use std::sync::{Arc, Mutex};
+
+use crate::{BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo, SampleFormat};
+use crate::{StreamConfig, StreamError};
+
+/// A callback lent to each attempt at building a stream in turn.
+///
+/// The callback of an attempt takes the lent callback out of the slot the first time it is
+/// invoked, so that the audio thread only locks the slot once, and puts it back when dropped, e.g.
+/// because building the stream failed after all.
+pub(crate) struct Lender<T> {
+    slot: Arc<Mutex<Option<T>>>,
+}
+
+struct Lease<T> {
+    slot: Arc<Mutex<Option<T>>>,
+    callback: Option<T>,
+}
+
+impl<T> Lender<T> {
+    pub(crate) fn new(callback: T) -> Self {
+        Lender {
+            slot: Arc::new(Mutex::new(Some(callback))),
+        }
+    }
+
+    fn lease(&self) -> Lease<T> {
+        Lease {
+            slot: self.slot.clone(),
+            callback: None,
+        }
+    }
+}
+
+impl<T> Lease<T> {
+    fn callback(&mut self) -> Option<&mut T> {
+        if self.callback.is_none() {
+            self.callback = self.slot.lock().ok().and_then(|mut slot| slot.take());
+        }
+        self.callback.as_mut()
+    }
+}
+
+impl<T> Drop for Lease<T> {
+    fn drop(&mut self) {
+        if let Some(callback) = self.callback.take() {
+            if let Ok(mut slot) = self.slot.lock() {
+                *slot = Some(callback);
+            }
+        }
+    }
+}
+
+impl<D> Lender<D>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+{
+    pub(crate) fn input(&self) -> impl FnMut(&Data, &InputCallbackInfo) + Send + 'static {
+        let mut lease = self.lease();
+        move |data: &Data, info: &InputCallbackInfo| {
+            if let Some(callback) = lease.callback() {
+                callback(data, info)
+            }
+        }
+    }
+}
+
+impl<D> Lender<D>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    pub(crate) fn output(&self) -> impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static {
+        let mut lease = self.lease();
+        move |data: &mut Data, info: &OutputCallbackInfo| {
+            if let Some(callback) = lease.callback() {
+                callback(data, info)
+            }
+        }
+    }
+}
+
+impl<E> Lender<E>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    pub(crate) fn error(&self) -> impl FnMut(StreamError) + Send + 'static {
+        let mut lease = self.lease();
+        move |err: StreamError| {
+            if let Some(callback) = lease.callback() {
+                callback(err)
+            }
+        }
+    }
+}
+
+/// Call `build` with each of the `candidates` in turn, returning the first stream that could be
+/// built along with the index of its candidate.
+///
+/// Gives up early if the device is no longer available, and otherwise fails with the error of the
+/// last candidate.
+pub(crate) fn build_first<S, F>(
+    candidates: &[(StreamConfig, SampleFormat)],
+    mut build: F,
+) -> Result<(S, usize), BuildStreamError>
+where
+    F: FnMut(&StreamConfig, SampleFormat) -> Result<S, BuildStreamError>,
+{
+    let mut last_err = BuildStreamError::StreamConfigNotSupported;
+    for (index, (config, sample_format)) in candidates.iter().enumerate() {
+        match build(config, *sample_format) {
+            Ok(stream) => return Ok((stream, index)),
+            Err(BuildStreamError::DeviceNotAvailable) => {
+                return Err(BuildStreamError::DeviceNotAvailable)
+            }
+            Err(err) => last_err = err,
+        }
+    }
+    Err(last_err)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{build_first, Lender};
+    use crate::{
+        BufferSize, BuildStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
+    };
+    use std::sync::{Arc, Mutex};
+
+    fn candidates(count: u32) -> Vec<(StreamConfig, SampleFormat)> {
+        (1..=count)
+            .map(|rate| {
+                let config = StreamConfig {
+                    channels: 2,
+                    sample_rate: SampleRate(rate),
+                    buffer_size: BufferSize::Default,
+                };
+                (config, SampleFormat::F32)
+            })
+            .collect()
+    }
+
+    #[test]
+    fn first_candidate_succeeds() {
+        let mut attempts = 0;
+        let result = build_first(&candidates(3), |config, _| {
+            attempts += 1;
+            Ok(config.sample_rate.0)
+        });
+        assert_eq!(result.unwrap(), (1, 0));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn falls_back_after_failures() {
+        let result = build_first(&candidates(4), |config, _| match config.sample_rate.0 {
+            1 | 2 => Err(BuildStreamError::StreamConfigNotSupported),
+            rate => Ok(rate),
+        });
+        assert_eq!(result.unwrap(), (3, 2));
+    }
+
+    #[test]
+    fn fails_with_the_last_error() {
+        let result = build_first(&candidates(2), |config, _| -> Result<(), _> {
+            match config.sample_rate.0 {
+                1 => Err(BuildStreamError::StreamConfigNotSupported),
+                _ => Err(BuildStreamError::InvalidArgument),
+            }
+        });
+        assert!(matches!(result, Err(BuildStreamError::InvalidArgument)));
+        let result = build_first::<(), _>(&[], |_, _| unreachable!());
+        assert!(matches!(
+            result,
+            Err(BuildStreamError::StreamConfigNotSupported)
+        ));
+    }
+
+    #[test]
+    fn gives_up_when_the_device_is_gone() {
+        let mut attempts = 0;
+        let result = build_first(&candidates(3), |_, _| -> Result<(), _> {
+            attempts += 1;
+            Err(BuildStreamError::DeviceNotAvailable)
+        });
+        assert!(matches!(result, Err(BuildStreamError::DeviceNotAvailable)));
+        assert_eq!(attempts, 1);
+    }
+
+    #[test]
+    fn callbacks_are_lent_back_after_a_failed_attempt() {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let received = errors.clone();
+        let lender = Lender::new(move |err: StreamError| received.lock().unwrap().push(err));
+        {
+            // The callback of an attempt that is given up on after invoking it.
+            let mut failed = lender.error();
+            failed(StreamError::Stalled);
+            // Not lent to another attempt while the failed one holds it.
+            let mut concurrent = lender.error();
+            concurrent(StreamError::DeviceNotAvailable);
+            drop(failed);
+        }
+        let mut succeeded = lender.error();
+        succeeded(StreamError::Stalled);
+        let errors = errors.lock().unwrap();
+        assert!(matches!(
+            errors[..],
+            [StreamError::Stalled, StreamError::Stalled]
+        ));
+    }
+}
@@ -0,0 +1,87 @@
+use std::sync::Arc;
+
+extern crate ndk;
+
+use self::ndk::audio::{AudioError, AudioErrorResult};
+use crate::{
+    BackendSpecificError, BuildStreamError, DisconnectReason, PauseStreamError, PlayStreamError,
+    RouteChangeReason, StreamError, StreamEvent,
+};
+
+pub type EventCallback = Arc<dyn Fn(StreamEvent) + Send + Sync>;
+
+/// The error of a stream that stopped with `error`. AAudio disconnects streams whenever their
+/// route changes, which is reported to `event_callback` first. Unlike the Oboe host, this one
+/// can't list the devices to tell whether the device of the stream is gone.
+pub fn stream_error(error: AudioError, event_callback: Option<&EventCallback>) -> StreamError {
+    if !matches!(
+        error,
+        AudioError::ErrorResult(AudioErrorResult::Disconnected)
+    ) {
+        return error.into();
+    }
+    if let Some(event_callback) = event_callback {
+        event_callback(StreamEvent::RouteChanged {
+            reason: RouteChangeReason::Unknown,
+        });
+    }
+    StreamError::Disconnected {
+        reason: DisconnectReason::RouteChanged,
+    }
+}
+
+fn device_gone(error: &AudioError) -> bool {
+    use self::AudioErrorResult::*;
+    matches!(
+        error,
+        AudioError::ErrorResult(Disconnected | Unavailable | InvalidHandle | NoService)
+    )
+}
+
+fn backend_error(error: AudioError) -> BackendSpecificError {
+    BackendSpecificError {
+        description: error.to_string(),
+    }
+}
+
+impl From<AudioError> for StreamError {
+    fn from(error: AudioError) -> Self {
+        if device_gone(&error) {
+            return Self::DeviceNotAvailable;
+        }
+        backend_error(error).into()
+    }
+}
+
+impl From<AudioError> for PlayStreamError {
+    fn from(error: AudioError) -> Self {
+        if device_gone(&error) {
+            return Self::DeviceNotAvailable;
+        }
+        backend_error(error).into()
+    }
+}
+
+impl From<AudioError> for PauseStreamError {
+    fn from(error: AudioError) -> Self {
+        if device_gone(&error) {
+            return Self::DeviceNotAvailable;
+        }
+        backend_error(error).into()
+    }
+}
+
+impl From<AudioError> for BuildStreamError {
+    fn from(error: AudioError) -> Self {
+        use self::AudioErrorResult::*;
+        match error {
+            ref error if device_gone(error) => Self::DeviceNotAvailable,
+            AudioError::ErrorResult(NoFreeHandles) => Self::StreamIdOverflow,
+            AudioError::ErrorResult(InvalidFormat | InvalidRate | OutOfRange) => {
+                Self::StreamConfigNotSupported
+            }
+            AudioError::ErrorResult(IllegalArgument) => Self::InvalidArgument,
+            error => backend_error(error).into(),
+        }
+    }
+}
@@ -0,0 +1,502 @@
+//! An AAudio host, using the C API of the NDK directly rather than through Oboe, so no C++
+//! toolchain is needed to build it.
+//!
+//! The host requires Android 9 (API level 28), the first version whose AAudio reports the audio
+//! session of streams, and only the default devices, routed by the system, are provided.
+
+use std::mem;
+use std::num::NonZeroI32;
+use std::time::Duration;
+use std::vec::IntoIter as VecIntoIter;
+
+extern crate ndk;
+
+use self::ndk::audio::{
+    AudioCallbackResult, AudioDirection, AudioFormat, AudioInputPreset, AudioPerformanceMode,
+    AudioSharingMode, AudioStream, AudioStreamBuilder, Clockid, SessionId as AudioSessionId,
+};
+use crate::dsd;
+use crate::host::android_media::default_supported_configs;
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::watchdog::{self, Watchdog};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, DsdMode, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError, PerformanceMode, PlayStreamError,
+    SampleFormat, SessionId, ShareMode, StreamConfig, StreamError, StreamInstant, StreamOptions,
+    StreamRole, SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+
+mod convert;
+
+pub use self::ndk::audio::AudioInputPreset as InputPreset;
+
+use self::convert::stream_error;
+
+pub struct Host;
+/// The default input or output device, the streams of which are routed by the system.
+pub struct Device {
+    allocate_session_id: bool,
+    /// The input preset of the input streams, overriding that of their role.
+    input_preset: Option<InputPreset>,
+    /// The capacity of the buffer of the streams, in frames, left to AAudio if `None`.
+    buffer_capacity: Option<u32>,
+}
+pub struct Stream {
+    stream: AudioStream,
+    is_output: bool,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+}
+pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
+pub type Devices = VecIntoIter<Device>;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        Ok(vec![Device::new()].into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        Some(Device::new())
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        Some(Device::new())
+    }
+}
+
+impl Device {
+    fn new() -> Self {
+        Device {
+            allocate_session_id: false,
+            input_preset: None,
+            buffer_capacity: None,
+        }
+    }
+
+    /// Set the input preset of the input streams built from this device afterwards, e.g.
+    /// [`InputPreset::VoiceCommunication`], which enables the echo canceler and noise suppressor
+    /// of the platform, [`InputPreset::VoiceRecognition`] or [`InputPreset::Unprocessed`],
+    /// overriding the one mapped from `StreamOptions::role`.
+    pub fn set_input_preset(&mut self, input_preset: Option<InputPreset>) {
+        self.input_preset = input_preset;
+    }
+
+    /// Set the capacity of the buffer of the streams built from this device afterwards, in
+    /// frames, which bounds how far their latency can grow to absorb underruns, rather than leaving
+    /// it to AAudio. The capacity granted is reported by [`Stream::buffer_capacity`].
+    pub fn set_buffer_capacity(&mut self, frames: Option<u32>) {
+        self.buffer_capacity = frames;
+    }
+
+    /// Have the streams built from this device afterwards, without a `StreamOptions::session`,
+    /// allocate an audio session of their own, reported by [`Stream::session_id`]. Effects like
+    /// `AcousticEchoCanceler`, `NoiseSuppressor` or `Equalizer` of `android.media.audiofx` are
+    /// attached to audio sessions.
+    ///
+    /// Streams with an audio session may have a higher latency.
+    pub fn set_allocate_session_id(&mut self, allocate: bool) {
+        self.allocate_session_id = allocate;
+    }
+
+    // The builder of a stream in `direction` with `config`, if AAudio can take them.
+    fn stream_builder(
+        &self,
+        direction: AudioDirection,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+    ) -> Result<AudioStreamBuilder, BuildStreamError> {
+        if options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let format = match sample_format {
+            SampleFormat::I16 => AudioFormat::PCM_I16,
+            SampleFormat::F32 => AudioFormat::PCM_Float,
+            sample_format => {
+                return Err(BackendSpecificError {
+                    description: format!("{} format is not supported on Android.", sample_format),
+                }
+                .into())
+            }
+        };
+        if config.channels == 0 || config.channels > 2 {
+            return Err(BackendSpecificError {
+                description: "More than 2 channels are not supported by AAudio.".to_owned(),
+            }
+            .into());
+        }
+        let mut builder = AudioStreamBuilder::new()?
+            .direction(direction)
+            .format(format)
+            .channel_count(config.channels as i32)
+            .sample_rate(config.sample_rate.0 as i32);
+        if options.share_mode == ShareMode::Exclusive {
+            builder = builder.sharing_mode(AudioSharingMode::Exclusive);
+        }
+        builder = match options.performance_mode {
+            Some(PerformanceMode::LowLatency) => {
+                builder.performance_mode(AudioPerformanceMode::LowLatency)
+            }
+            Some(PerformanceMode::PowerSaving) => {
+                builder.performance_mode(AudioPerformanceMode::PowerSaving)
+            }
+            None => builder,
+        };
+        if direction == AudioDirection::Input {
+            let input_preset = self.input_preset.or(match options.role {
+                Some(StreamRole::Communications | StreamRole::GameChat) => {
+                    Some(AudioInputPreset::VoiceCommunication)
+                }
+                Some(StreamRole::Speech) => Some(AudioInputPreset::VoiceRecognition),
+                _ => None,
+            });
+            if let Some(input_preset) = input_preset {
+                builder = builder.input_preset(input_preset);
+            }
+        }
+        match options.session {
+            Some(SessionId(id)) => {
+                let id = i32::try_from(id)
+                    .ok()
+                    .and_then(NonZeroI32::new)
+                    .ok_or(BuildStreamError::InvalidArgument)?;
+                builder = builder.session_id(Some(AudioSessionId::Allocated(id)));
+            }
+            // Allocated when the stream is opened.
+            None if self.allocate_session_id => builder = builder.session_id(None),
+            None => {}
+        }
+        if let Some(frames) = self.buffer_capacity {
+            builder = builder.buffer_capacity_in_frames(frames as i32);
+        }
+        Ok(match config.buffer_size {
+            BufferSize::Default => builder,
+            BufferSize::Fixed(size) => builder.frames_per_data_callback(size as i32),
+        })
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok("default".to_owned())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Ok(default_supported_configs(false))
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Ok(default_supported_configs(true))
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        let mut configs: Vec<_> = self.supported_input_configs().unwrap().collect();
+        configs.sort_by(|a, b| b.cmp_default_heuristics(a));
+        let config = configs
+            .into_iter()
+            .next()
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)?
+            .with_max_sample_rate();
+        Ok(config)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        let mut configs: Vec<_> = self.supported_output_configs().unwrap().collect();
+        configs.sort_by(|a, b| b.cmp_default_heuristics(a));
+        let config = configs
+            .into_iter()
+            .next()
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)?
+            .with_max_sample_rate();
+        Ok(config)
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let builder = self.stream_builder(AudioDirection::Input, config, sample_format, options)?;
+        let (watchdog, mut data_callback, mut error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
+        let channels = config.channels as usize;
+        let rate = config.sample_rate.0;
+        let event_callback = options.event_callback.clone();
+        let stream = builder
+            .data_callback(Box::new(move |stream, data, frames| {
+                let callback = now();
+                // The first frame of `data` is the next one to be read.
+                let capture =
+                    frame_instant(stream, stream.get_frames_read(), rate).unwrap_or(callback);
+                let data = unsafe {
+                    Data::from_parts(data as _, frames as usize * channels, sample_format)
+                };
+                let timestamp = InputStreamTimestamp { callback, capture };
+                data_callback(
+                    &data,
+                    &InputCallbackInfo {
+                        timestamp,
+                        transport: None,
+                    },
+                );
+                AudioCallbackResult::Continue
+            }))
+            .error_callback(Box::new(move |_, error| {
+                error_callback(stream_error(error, event_callback.as_ref()))
+            }))
+            .open_stream()?;
+        Ok(Stream {
+            stream,
+            is_output: false,
+            watchdog,
+        })
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        let builder =
+            self.stream_builder(AudioDirection::Output, config, sample_format, options)?;
+        let (watchdog, mut data_callback, mut error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+        let channels = config.channels as usize;
+        let rate = config.sample_rate.0;
+        let event_callback = options.event_callback.clone();
+        let stream = builder
+            .data_callback(Box::new(move |stream, data, frames| {
+                let callback = now();
+                // The first frame of `data` is the next one to be written.
+                let playback =
+                    frame_instant(stream, stream.get_frames_written(), rate).unwrap_or(callback);
+                let mut data = unsafe {
+                    Data::from_parts(data as _, frames as usize * channels, sample_format)
+                };
+                let timestamp = OutputStreamTimestamp { callback, playback };
+                data_callback(
+                    &mut data,
+                    &OutputCallbackInfo {
+                        timestamp,
+                        transport: None,
+                    },
+                );
+                AudioCallbackResult::Continue
+            }))
+            .error_callback(Box::new(move |_, error| {
+                error_callback(stream_error(error, event_callback.as_ref()))
+            }))
+            .open_stream()?;
+        Ok(Stream {
+            stream,
+            is_output: true,
+            watchdog,
+        })
+    }
+}
+
+impl Stream {
+    /// The performance mode AAudio granted the stream, `None` if it has no particular one, e.g.
+    /// when the device has no low latency path.
+    pub fn performance_mode(&self) -> Option<PerformanceMode> {
+        match self.stream.get_performance_mode() {
+            Ok(AudioPerformanceMode::LowLatency) => Some(PerformanceMode::LowLatency),
+            Ok(AudioPerformanceMode::PowerSaving) => Some(PerformanceMode::PowerSaving),
+            Ok(AudioPerformanceMode::None) | Err(_) => None,
+        }
+    }
+
+    /// The sharing mode AAudio granted the stream, [`ShareMode::Shared`] if the device couldn't
+    /// give it the exclusive access requested through `StreamOptions::share_mode`.
+    pub fn share_mode(&self) -> ShareMode {
+        match self.stream.get_sharing_mode() {
+            Ok(AudioSharingMode::Exclusive) => ShareMode::Exclusive,
+            Ok(AudioSharingMode::Shared) | Err(_) => ShareMode::Shared,
+        }
+    }
+
+    /// The audio session of the stream, which effects of `android.media.audiofx` are attached to,
+    /// `None` unless the stream was built with a `StreamOptions::session` or its device allocates
+    /// them.
+    pub fn session_id(&self) -> Option<SessionId> {
+        match self.stream.get_session_id() {
+            AudioSessionId::Allocated(id) => Some(SessionId(id.get() as u128)),
+            AudioSessionId::None => None,
+        }
+    }
+
+    /// Whether the stream runs on the MMAP path of AAudio, exchanging samples with the buffer of
+    /// the DSP directly, which has the lowest latency. `None` if that can't be told: AAudio
+    /// doesn't report it, but exclusive streams only run on that path, and streams without low
+    /// latency never do.
+    pub fn mmap_used(&self) -> Option<bool> {
+        if self.performance_mode() != Some(PerformanceMode::LowLatency) {
+            Some(false)
+        } else if self.share_mode() == ShareMode::Exclusive {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// The number of frames the device consumes or produces at a time, which buffer sizes are
+    /// best made a multiple of. `None` if the stream doesn't report it.
+    pub fn frames_per_burst(&self) -> Option<u32> {
+        let frames = self.stream.get_frames_per_burst();
+        (frames > 0).then_some(frames as u32)
+    }
+
+    /// The number of frames of every data callback, those of a `BufferSize::Fixed` buffer size,
+    /// `None` if their number varies, as with `BufferSize::Default`.
+    pub fn frames_per_callback(&self) -> Option<u32> {
+        self.stream
+            .get_frames_per_data_callback()
+            .filter(|&frames| frames > 0)
+            .map(|frames| frames as u32)
+    }
+
+    /// The capacity AAudio granted the buffer of the stream, in frames, `None` if the stream
+    /// doesn't report it.
+    pub fn buffer_capacity(&self) -> Option<u32> {
+        let frames = self.stream.get_buffer_capacity_in_frames();
+        (frames > 0).then_some(frames as u32)
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.stream.request_start()?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        // Input streams can't be paused, so they are stopped, dropping what was recorded but not
+        // read yet.
+        if self.is_output {
+            self.stream.request_pause()?;
+        } else {
+            self.stream.request_stop()?;
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        Ok(())
+    }
+}
+
+// When the frame at `position` of `stream` is presented to the hardware, going by the last
+// position AAudio timestamped, if one was.
+fn frame_instant(stream: &AudioStream, position: i64, rate: u32) -> Option<StreamInstant> {
+    let timestamp = stream.get_timestamp(Clockid::Monotonic).ok()?;
+    let frames = position - timestamp.frame_position;
+    Some(StreamInstant::from_nanos(
+        timestamp.time_nanoseconds + frames * 1_000_000_000 / rate as i64,
+    ))
+}
+
+// AAudio timestamps frames on `CLOCK_MONOTONIC`.
+fn now() -> StreamInstant {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    StreamInstant::from_nanos(now.tv_sec as i64 * 1_000_000_000 + now.tv_nsec as i64)
+}
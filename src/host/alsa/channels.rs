@@ -0,0 +1,138 @@
+//! The channel maps of devices, telling the speaker or jack behind each interleaved channel.
+
+use super::alsa;
+
+/// The position of a channel in a channel map, mirroring `SND_CHMAP_*`.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelPosition {
+    /// The position of the channel is not known.
+    Unknown,
+    /// The channel is not available, and silent.
+    NotAvailable,
+    Mono,
+    FrontLeft,
+    FrontRight,
+    RearLeft,
+    RearRight,
+    FrontCenter,
+    LowFrequencyEffects,
+    SideLeft,
+    SideRight,
+    RearCenter,
+    FrontLeftCenter,
+    FrontRightCenter,
+    RearLeftCenter,
+    RearRightCenter,
+    FrontLeftWide,
+    FrontRightWide,
+    FrontLeftHigh,
+    FrontCenterHigh,
+    FrontRightHigh,
+    TopCenter,
+    TopFrontLeft,
+    TopFrontRight,
+    TopFrontCenter,
+    TopRearLeft,
+    TopRearRight,
+    TopRearCenter,
+    TopFrontLeftCenter,
+    TopFrontRightCenter,
+    TopSideLeft,
+    TopSideRight,
+    LeftLowFrequencyEffects,
+    RightLowFrequencyEffects,
+    BottomCenter,
+    BottomLeftCenter,
+    BottomRightCenter,
+    /// A position specific to the driver, with the given value.
+    DriverSpecific(u32),
+}
+
+impl ChannelPosition {
+    // From the abbreviation printed by `snd_pcm_chmap_print`.
+    fn from_abbreviation(abbreviation: &str) -> Self {
+        match abbreviation {
+            "NA" => ChannelPosition::NotAvailable,
+            "MONO" => ChannelPosition::Mono,
+            "FL" => ChannelPosition::FrontLeft,
+            "FR" => ChannelPosition::FrontRight,
+            "RL" => ChannelPosition::RearLeft,
+            "RR" => ChannelPosition::RearRight,
+            "FC" => ChannelPosition::FrontCenter,
+            "LFE" => ChannelPosition::LowFrequencyEffects,
+            "SL" => ChannelPosition::SideLeft,
+            "SR" => ChannelPosition::SideRight,
+            "RC" => ChannelPosition::RearCenter,
+            "FLC" => ChannelPosition::FrontLeftCenter,
+            "FRC" => ChannelPosition::FrontRightCenter,
+            "RLC" => ChannelPosition::RearLeftCenter,
+            "RRC" => ChannelPosition::RearRightCenter,
+            "FLW" => ChannelPosition::FrontLeftWide,
+            "FRW" => ChannelPosition::FrontRightWide,
+            "FLH" => ChannelPosition::FrontLeftHigh,
+            "FCH" => ChannelPosition::FrontCenterHigh,
+            "FRH" => ChannelPosition::FrontRightHigh,
+            "TC" => ChannelPosition::TopCenter,
+            "TFL" => ChannelPosition::TopFrontLeft,
+            "TFR" => ChannelPosition::TopFrontRight,
+            "TFC" => ChannelPosition::TopFrontCenter,
+            "TRL" => ChannelPosition::TopRearLeft,
+            "TRR" => ChannelPosition::TopRearRight,
+            "TRC" => ChannelPosition::TopRearCenter,
+            "TFLC" => ChannelPosition::TopFrontLeftCenter,
+            "TFRC" => ChannelPosition::TopFrontRightCenter,
+            "TSL" => ChannelPosition::TopSideLeft,
+            "TSR" => ChannelPosition::TopSideRight,
+            "LLFE" => ChannelPosition::LeftLowFrequencyEffects,
+            "RLFE" => ChannelPosition::RightLowFrequencyEffects,
+            "BC" => ChannelPosition::BottomCenter,
+            "BLC" => ChannelPosition::BottomLeftCenter,
+            "BRC" => ChannelPosition::BottomRightCenter,
+            _ => match abbreviation.parse() {
+                Ok(value) => ChannelPosition::DriverSpecific(value),
+                Err(_) => ChannelPosition::Unknown,
+            },
+        }
+    }
+}
+
+/// The positions of the channels of `map`, in order.
+///
+/// `alsa::pcm::ChmapPosition` lacks some positions, e.g. `FC` and `LFE`, so they are read from the
+/// printed map rather than converted.
+pub(super) fn positions(map: &alsa::pcm::Chmap) -> Vec<ChannelPosition> {
+    map.to_string()
+        .split(' ')
+        .filter(|abbreviation| !abbreviation.is_empty())
+        // Phase inversion doesn't change the position of a channel.
+        .map(|abbreviation| {
+            ChannelPosition::from_abbreviation(abbreviation.trim_end_matches("[INV]"))
+        })
+        .collect()
+}
+
+/// The channel maps `handle` can be set to, for any channel count.
+pub(super) fn channel_maps(handle: &alsa::PCM) -> Vec<Vec<ChannelPosition>> {
+    handle
+        .query_chmaps()
+        .map(|(_, map)| positions(&map))
+        .collect()
+}
+
+/// Set the channel map of `handle` to `positions`, one of its `channel_maps`. Returns `false` if
+/// it isn't one of them.
+pub(super) fn set_channel_map(
+    handle: &alsa::PCM,
+    positions: &[ChannelPosition],
+) -> Result<bool, alsa::Error> {
+    // Maps can only be built from the positions known to `alsa::pcm::ChmapPosition`, so the
+    // matching map of the device is set instead.
+    for (_, map) in handle.query_chmaps() {
+        if self::positions(&map) == positions {
+            handle.set_chmap(&map)?;
+            return Ok(true);
+        }
+    }
+    Ok(false)
+}
@@ -3,19 +3,60 @@ use super::parking_lot::Mutex;
 use super::{Device, DeviceHandles};
 use crate::{BackendSpecificError, DevicesError};
 
+/// Which devices are listed by the ALSA host, set through `AlsaHost::set_enumeration`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Enumeration {
+    /// The devices defined by the ALSA configuration, such as `default` and
+    /// `sysdefault:CARD=...`, as listed by `aplay -L`. The default.
+    Plugins,
+    /// The PCM devices of the sound cards, as `hw:X,Y` for device `Y` of card `X`, as listed by
+    /// `aplay -l`. These tell identical cards apart and access them without any conversion.
+    Hardware,
+    /// The devices defined by the ALSA configuration followed by the PCM devices of the sound cards.
+    All,
+}
+
 /// ALSA's implementation for `Devices`.
 pub struct Devices {
-    hint_iter: alsa::device_name::HintIter,
+    hint_iter: Option<alsa::device_name::HintIter>,
+    hw_names: std::vec::IntoIter<String>,
 }
 
 impl Devices {
-    pub fn new() -> Result<Self, DevicesError> {
+    pub fn new(enumeration: Enumeration) -> Result<Self, DevicesError> {
+        let hint_iter = match enumeration {
+            Enumeration::Hardware => None,
+            _ => Some(alsa::device_name::HintIter::new_str(None, "pcm")?),
+        };
+        let hw_names = match enumeration {
+            Enumeration::Plugins => vec![],
+            _ => hw_names(),
+        };
         Ok(Devices {
-            hint_iter: alsa::device_name::HintIter::new_str(None, "pcm")?,
+            hint_iter,
+            hw_names: hw_names.into_iter(),
         })
     }
 }
 
+/// The `hw:X,Y` names of the PCM devices of all sound cards.
+fn hw_names() -> Vec<String> {
+    let mut names = vec![];
+    for card in alsa::card::Iter::new().filter_map(Result::ok) {
+        let ctl = match alsa::Ctl::from_card(&card, false) {
+            Ok(ctl) => ctl,
+            Err(err) => {
+                log_debug!("skipping ALSA card {}: {}", card.get_index(), err);
+                continue;
+            }
+        };
+        for device in alsa::ctl::DeviceIter::new(&ctl) {
+            names.push(format!("hw:{},{}", card.get_index(), device));
+        }
+    }
+    names
+}
+
 unsafe impl Send for Devices {}
 unsafe impl Sync for Devices {}
 
@@ -24,21 +65,31 @@ impl Iterator for Devices {
 
     fn next(&mut self) -> Option<Device> {
         loop {
-            match self.hint_iter.next() {
+            let hint = self.hint_iter.as_mut().and_then(Iterator::next);
+            match hint
+                .map(|hint| hint.name)
+                .or_else(|| self.hw_names.next().map(Some))
+            {
                 None => return None,
-                Some(hint) => {
-                    let name = match hint.name {
+                Some(name) => {
+                    let name = match name {
                         None => continue,
                         // Ignoring the `null` device.
                         Some(name) if name == "null" => continue,
                         Some(name) => name,
                     };
 
-                    if let Ok(handles) = DeviceHandles::open(&name) {
-                        return Some(Device {
-                            name,
-                            handles: Mutex::new(handles),
-                        });
+                    match DeviceHandles::open(&name) {
+                        Ok(handles) => {
+                            log_debug!("found ALSA device {}", name);
+                            return Some(Device {
+                                name,
+                                handles: Mutex::new(handles),
+                                input_channel_map: None,
+                                output_channel_map: None,
+                            });
+                        }
+                        Err(err) => log_debug!("skipping ALSA device {}: {}", name, err),
                     }
                 }
             }
@@ -51,6 +102,8 @@ pub fn default_input_device() -> Option<Device> {
     Some(Device {
         name: "default".to_owned(),
         handles: Mutex::new(Default::default()),
+        input_channel_map: None,
+        output_channel_map: None,
     })
 }
 
@@ -59,6 +112,8 @@ pub fn default_output_device() -> Option<Device> {
     Some(Device {
         name: "default".to_owned(),
         handles: Mutex::new(Default::default()),
+        input_channel_map: None,
+        output_channel_map: None,
     })
 }
 
@@ -4,35 +4,52 @@ extern crate parking_lot;
 
 use self::alsa::poll::Descriptors;
 use self::parking_lot::Mutex;
+use crate::dsd;
+use crate::stats::StreamStatsCollector;
+use crate::thread::{self, AudioThread};
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::watchdog::{self, Watchdog};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, DsdMode, HardwareFormat,
+    InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat,
+    SampleRate, ShareMode, SignalPath, StreamConfig, StreamError, StreamEvent, StreamOptions,
+    StreamStats, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
     SupportedStreamConfigsError,
 };
 use std::cmp;
 use std::convert::TryInto;
+use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::thread::{self, JoinHandle};
 use std::time::Duration;
 use std::vec::IntoIter as VecIntoIter;
 
-pub use self::enumerate::{default_input_device, default_output_device, Devices};
+pub use self::channels::ChannelPosition;
+pub use self::enumerate::{default_input_device, default_output_device, Devices, Enumeration};
 
 pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 
+mod channels;
 mod enumerate;
 
 /// The default linux, dragonfly, freebsd and netbsd host type.
 #[derive(Debug)]
-pub struct Host;
+pub struct Host {
+    enumeration: Enumeration,
+}
 
 impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
-        Ok(Host)
+        Ok(Host {
+            enumeration: Enumeration::Plugins,
+        })
+    }
+
+    /// Set which devices are listed by `devices`, those defined by the ALSA configuration by
+    /// default.
+    pub fn set_enumeration(&mut self, enumeration: Enumeration) {
+        self.enumeration = enumeration;
     }
 }
 
@@ -46,7 +63,7 @@ impl HostTrait for Host {
     }
 
     fn devices(&self) -> Result<Self::Devices, DevicesError> {
-        Devices::new()
+        Devices::new(self.enumeration)
     }
 
     fn default_input_device(&self) -> Option<Self::Device> {
@@ -99,15 +116,53 @@ impl DeviceTrait for Device {
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.device_channels.is_some() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         let stream_inner =
-            self.build_stream_inner(conf, sample_format, alsa::Direction::Capture)?;
-        let stream = Stream::new_input(
+            self.build_stream_inner(conf, sample_format, options, alsa::Direction::Capture)?;
+        let period = frames_to_duration(
+            stream_inner.period_len / conf.channels as usize,
+            conf.sample_rate,
+        );
+        let (watchdog, data_callback, error_callback) = watchdog::watch_input(
+            options.watchdog_periods.map(|periods| period * periods),
+            data_callback,
+            error_callback,
+        )?;
+        let thread_name = thread::thread_name(options, "cpal_alsa_in")?;
+        Stream::new_input(
             Arc::new(stream_inner),
+            options,
+            thread_name,
+            watchdog,
             data_callback,
             error_callback,
             timeout,
-        );
-        Ok(stream)
+        )
     }
 
     fn build_output_stream_raw<D, E>(
@@ -122,15 +177,64 @@ impl DeviceTrait for Device {
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                conf,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if options.device_channels.is_some() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         let stream_inner =
-            self.build_stream_inner(conf, sample_format, alsa::Direction::Playback)?;
-        let stream = Stream::new_output(
+            self.build_stream_inner(conf, sample_format, options, alsa::Direction::Playback)?;
+        let period = frames_to_duration(
+            stream_inner.period_len / conf.channels as usize,
+            conf.sample_rate,
+        );
+        let (watchdog, data_callback, error_callback) = watchdog::watch_output(
+            options.watchdog_periods.map(|periods| period * periods),
+            data_callback,
+            error_callback,
+        )?;
+        let thread_name = thread::thread_name(options, "cpal_alsa_out")?;
+        Stream::new_output(
             Arc::new(stream_inner),
+            options,
+            thread_name,
+            watchdog,
             data_callback,
             error_callback,
             timeout,
-        );
-        Ok(stream)
+        )
     }
 }
 
@@ -229,6 +333,14 @@ impl DeviceHandles {
         Ok(self.try_open(name, stream_type)?.as_mut().unwrap())
     }
 
+    /// Close the `alsa::PCM` handle for a specific `stream_type`, if it is open.
+    fn close(&mut self, stream_type: alsa::Direction) {
+        match stream_type {
+            alsa::Direction::Playback => self.playback = None,
+            alsa::Direction::Capture => self.capture = None,
+        }
+    }
+
     /// Take ownership of the `alsa::PCM` handle for a specific `stream_type`.
     /// If the handle is not yet opened, it will be opened and returned.
     fn take(&mut self, name: &str, stream_type: alsa::Direction) -> Result<alsa::PCM, alsa::Error> {
@@ -239,20 +351,81 @@ impl DeviceHandles {
 pub struct Device {
     name: String,
     handles: Mutex<DeviceHandles>,
+    input_channel_map: Option<Vec<ChannelPosition>>,
+    output_channel_map: Option<Vec<ChannelPosition>>,
 }
 
 impl Device {
+    /// The channel maps input streams of the device can be set to, for any channel count, as the
+    /// positions of their channels in order. Empty if the driver doesn't report them.
+    pub fn input_channel_maps(&self) -> Result<Vec<Vec<ChannelPosition>>, BackendSpecificError> {
+        let mut handles = self.handles.lock();
+        let handle = handles.get_mut(&self.name, alsa::Direction::Capture)?;
+        Ok(channels::channel_maps(handle))
+    }
+
+    /// The channel maps output streams of the device can be set to, for any channel count, as the
+    /// positions of their channels in order. Empty if the driver doesn't report them.
+    pub fn output_channel_maps(&self) -> Result<Vec<Vec<ChannelPosition>>, BackendSpecificError> {
+        let mut handles = self.handles.lock();
+        let handle = handles.get_mut(&self.name, alsa::Direction::Playback)?;
+        Ok(channels::channel_maps(handle))
+    }
+
+    /// Set the channel map of the input streams built afterwards to one of the
+    /// `input_channel_maps`, with as many channels as their config. `None`, the default, leaves
+    /// the map of the driver.
+    pub fn set_input_channel_map(&mut self, map: Option<Vec<ChannelPosition>>) {
+        self.input_channel_map = map;
+    }
+
+    /// Set the channel map of the output streams built afterwards to one of the
+    /// `output_channel_maps`, with as many channels as their config, e.g. to route a 5.1 stream
+    /// to the right speakers of an HDMI receiver. `None`, the default, leaves the map of the
+    /// driver.
+    pub fn set_output_channel_map(&mut self, map: Option<Vec<ChannelPosition>>) {
+        self.output_channel_map = map;
+    }
+
     fn build_stream_inner(
         &self,
         conf: &StreamConfig,
         sample_format: SampleFormat,
+        options: &StreamOptions,
         stream_type: alsa::Direction,
     ) -> Result<StreamInner, BuildStreamError> {
-        let handle_result = self
-            .handles
-            .lock()
-            .take(&self.name, stream_type)
-            .map_err(|e| (e, e.errno()));
+        let (share_mode, passthrough, dsd) = (options.share_mode, options.passthrough, options.dsd);
+        let mut pcm_name = match share_mode {
+            ShareMode::Shared => self.name.clone(),
+            ShareMode::Exclusive => {
+                hw_device_name(&self.name).ok_or(BuildStreamError::StreamConfigNotSupported)?
+            }
+        };
+        if passthrough.is_some() {
+            if stream_type == alsa::Direction::Capture || sample_format != SampleFormat::I16 {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+            pcm_name = non_audio_device_name(&pcm_name)
+                .ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        }
+        // DoP streams are built as PCM streams.
+        let native_dsd = dsd.is_some();
+        if native_dsd
+            && (stream_type == alsa::Direction::Capture || sample_format != SampleFormat::U8)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let handle_result = {
+            let mut handles = self.handles.lock();
+            if pcm_name == self.name {
+                handles.take(&self.name, stream_type)
+            } else {
+                // Our handle to the plugin keeps the hardware device busy.
+                handles.close(stream_type);
+                alsa::pcm::PCM::new(&pcm_name, stream_type, true)
+            }
+        }
+        .map_err(|e| (e, e.errno()));
 
         let handle = match handle_result {
             Err((_, alsa::nix::errno::Errno::EBUSY)) => {
@@ -264,8 +437,49 @@ impl Device {
             Err((e, _)) => return Err(e.into()),
             Ok(handle) => handle,
         };
-        let can_pause = set_hw_params_from_format(&handle, conf, sample_format)?;
+        // Resampling would corrupt bit-perfect samples, bitstreams and DSD.
+        let resample = share_mode == ShareMode::Shared && passthrough.is_none() && !native_dsd;
+        let GrantedHwParams {
+            can_pause,
+            mmap,
+            packing,
+        } = set_hw_params_from_format(
+            &handle,
+            conf,
+            sample_format,
+            native_dsd,
+            resample,
+            options.periods,
+            options.mmap,
+        )?;
         let period_len = set_sw_params_from_format(&handle, conf, stream_type)?;
+        let channel_map = match stream_type {
+            alsa::Direction::Capture => &self.input_channel_map,
+            alsa::Direction::Playback => &self.output_channel_map,
+        };
+        if let Some(channel_map) = channel_map {
+            if channel_map.len() != conf.channels as usize
+                || !channels::set_channel_map(&handle, channel_map)?
+            {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+        }
+        let buffer_len = handle.get_params()?.0 as usize * conf.channels as usize;
+        let signal_path = signal_path(
+            &handle,
+            &pcm_name,
+            conf,
+            sample_format,
+            packing,
+            stream_type,
+        );
+        log_debug!(
+            "opened ALSA device {} with a period of {} frames and a buffer of {} frames{}",
+            self.name,
+            period_len / conf.channels as usize,
+            buffer_len / conf.channels as usize,
+            if mmap { ", memory mapped" } else { "" },
+        );
 
         handle.prepare()?;
 
@@ -294,8 +508,15 @@ impl Device {
             num_descriptors,
             conf: conf.clone(),
             period_len,
+            buffer_len,
+            mmap,
+            packing,
             can_pause,
             creation_instant,
+            signal_path,
+            stats: StreamStatsCollector::new(),
+            paused: AtomicBool::new(false),
+            event_callback: options.event_callback.clone(),
         };
 
         Ok(stream_inner)
@@ -377,6 +598,14 @@ impl Device {
                 supported_formats.push(sample_format);
             }
         }
+        // Streams of `I32` samples are converted to and from the packed formats of the device.
+        if !supported_formats.contains(&SampleFormat::I32)
+            && Packing::ALL
+                .into_iter()
+                .any(|packing| hw_params.test_format(packing.alsa_format()).is_ok())
+        {
+            supported_formats.push(SampleFormat::I32);
+        }
 
         let min_rate = hw_params.get_rate_min()?;
         let max_rate = hw_params.get_rate_max()?;
@@ -406,7 +635,14 @@ impl Device {
         let min_channels = hw_params.get_channels_min()?;
         let max_channels = hw_params.get_channels_max()?;
 
-        let max_channels = cmp::min(max_channels, 32); // TODO: limiting to 32 channels or too much stuff is returned
+        // Plugins converting to any channel count report absurd maximums (e.g. 10000 for `plug`),
+        // so only list their first channel counts. Hardware, including multichannel interfaces,
+        // reports its actual range.
+        let max_channels = if max_channels > MAX_HARDWARE_CHANNELS {
+            cmp::min(max_channels, 32)
+        } else {
+            max_channels
+        };
         let supported_channels = (min_channels..max_channels + 1)
             .filter_map(|num| {
                 if hw_params.test_channels(num).is_ok() {
@@ -433,8 +669,8 @@ impl Device {
                 for &(min_rate, max_rate) in sample_rates.iter() {
                     output.push(SupportedStreamConfigRange {
                         channels,
-                        min_sample_rate: SampleRate(min_rate as u32),
-                        max_sample_rate: SampleRate(max_rate as u32),
+                        min_sample_rate: SampleRate(min_rate),
+                        max_sample_rate: SampleRate(max_rate),
                         buffer_size: buffer_size_range.clone(),
                         sample_format,
                     });
@@ -523,6 +759,16 @@ struct StreamInner {
     // Minimum number of samples to put in the buffer.
     period_len: usize,
 
+    // Number of samples the buffer of the device holds.
+    buffer_len: usize,
+
+    // Whether samples are exchanged through the memory mapped buffer of the device rather than
+    // read and written.
+    mmap: bool,
+
+    // The layout of the samples of the device, if converted from and to those of the stream.
+    packing: Option<Packing>,
+
     #[allow(dead_code)]
     // Whether or not the hardware supports pausing the stream.
     // TODO: We need an API to expose this. See #197, #284.
@@ -537,6 +783,19 @@ struct StreamInner {
     // If this field is `None` then the elapsed duration between `get_trigger_htstamp` and
     // `get_htstamp` is used.
     creation_instant: Option<std::time::Instant>,
+
+    // Whether the stream is bit-perfect and the format of the hardware.
+    signal_path: SignalPath,
+
+    // Runtime statistics, updated by the worker thread.
+    stats: StreamStatsCollector,
+
+    // Whether the stream was paused with `pause`, which the device forgets when the stream is
+    // prepared again after a suspend.
+    paused: AtomicBool,
+
+    // Notified when the stream resumes after being suspended by the system.
+    event_callback: Option<Arc<dyn Fn(StreamEvent) + Send + Sync>>,
 }
 
 // Assume that the ALSA library is built with thread safe option.
@@ -551,18 +810,23 @@ enum StreamType {
 pub struct Stream {
     /// The high-priority audio processing thread calling callbacks.
     /// Option used for moving out in destructor.
-    thread: Option<JoinHandle<()>>,
+    thread: Option<AudioThread>,
 
     /// Handle to the underlying stream for playback controls.
     inner: Arc<StreamInner>,
 
     /// Used to signal to stop processing.
     trigger: TriggerSender,
+
+    /// Reports stalls of the worker thread, if requested.
+    watchdog: Option<Watchdog>,
 }
 
 struct StreamWorkerContext {
     descriptors: Vec<libc::pollfd>,
     buffer: Vec<u8>,
+    // The samples of the device, when their packing differs from those of the stream.
+    packed: Vec<u8>,
     poll_timeout: i32,
 }
 
@@ -577,6 +841,7 @@ impl StreamWorkerContext {
         Self {
             descriptors: Vec::new(),
             buffer: Vec::new(),
+            packed: Vec::new(),
             poll_timeout,
         }
     }
@@ -602,15 +867,44 @@ fn input_stream_worker(
                 continue;
             }
             PollDescriptorsFlow::XRun => {
-                if let Err(err) = stream.channel.prepare() {
+                stream.stats.record_xrun();
+                // Reading from the mapped buffer doesn't restart the stream like `readi` does.
+                let mut recovered = stream.channel.prepare();
+                if stream.mmap {
+                    recovered = recovered.and_then(|()| stream.channel.start());
+                }
+                if let Err(err) = recovered {
                     error_callback(err.into());
                 }
                 continue;
             }
+            PollDescriptorsFlow::Suspended => {
+                match resume(stream, StreamType::Input) {
+                    Ok(true) => {
+                        if let Some(event_callback) = &stream.event_callback {
+                            event_callback(StreamEvent::Resumed);
+                        }
+                    }
+                    Ok(false) => (),
+                    Err(err) => error_callback(err.into()),
+                }
+                continue;
+            }
+            PollDescriptorsFlow::Disconnected => {
+                error_callback(StreamError::DeviceNotAvailable);
+                return;
+            }
             PollDescriptorsFlow::Return => return,
+            PollDescriptorsFlow::TimedOut => {
+                // A paused stream is not expected to become ready.
+                if stream.channel.state() == alsa::pcm::State::Running {
+                    error_callback(StreamError::Stalled);
+                }
+                continue;
+            }
             PollDescriptorsFlow::Ready {
                 status,
-                avail_frames: _,
+                avail_frames,
                 delay_frames,
                 stream_type,
             } => {
@@ -621,8 +915,9 @@ fn input_stream_worker(
                 );
                 if let Err(err) = process_input(
                     stream,
-                    &mut ctxt.buffer,
+                    &mut ctxt,
                     status,
+                    avail_frames,
                     delay_frames,
                     data_callback,
                 ) {
@@ -651,12 +946,36 @@ fn output_stream_worker(
         match flow {
             PollDescriptorsFlow::Continue => continue,
             PollDescriptorsFlow::XRun => {
+                stream.stats.record_xrun();
                 if let Err(err) = stream.channel.prepare() {
                     error_callback(err.into());
                 }
                 continue;
             }
+            PollDescriptorsFlow::Suspended => {
+                match resume(stream, StreamType::Output) {
+                    Ok(true) => {
+                        if let Some(event_callback) = &stream.event_callback {
+                            event_callback(StreamEvent::Resumed);
+                        }
+                    }
+                    Ok(false) => (),
+                    Err(err) => error_callback(err.into()),
+                }
+                continue;
+            }
+            PollDescriptorsFlow::Disconnected => {
+                error_callback(StreamError::DeviceNotAvailable);
+                return;
+            }
             PollDescriptorsFlow::Return => return,
+            PollDescriptorsFlow::TimedOut => {
+                // A paused stream is not expected to become ready.
+                if stream.channel.state() == alsa::pcm::State::Running {
+                    error_callback(StreamError::Stalled);
+                }
+                continue;
+            }
             PollDescriptorsFlow::Ready {
                 status,
                 avail_frames,
@@ -670,7 +989,7 @@ fn output_stream_worker(
                 );
                 if let Err(err) = process_output(
                     stream,
-                    &mut ctxt.buffer,
+                    &mut ctxt,
                     status,
                     avail_frames,
                     delay_frames,
@@ -694,6 +1013,11 @@ enum PollDescriptorsFlow {
         delay_frames: usize,
     },
     XRun,
+    /// The system suspended the stream, e.g. while going to sleep.
+    Suspended,
+    /// The device was unplugged, the stream can't recover.
+    Disconnected,
+    TimedOut,
 }
 
 // This block is shared between both input and output stream worker functions.
@@ -706,6 +1030,7 @@ fn poll_descriptors_and_prepare_buffer(
         ref mut descriptors,
         ref mut buffer,
         ref poll_timeout,
+        ..
     } = *ctxt;
 
     descriptors.clear();
@@ -730,11 +1055,10 @@ fn poll_descriptors_and_prepare_buffer(
     let filled = stream.channel.fill(&mut descriptors[len..])?;
     debug_assert_eq!(filled, stream.num_descriptors);
 
-    // Don't timeout, wait forever.
+    // Wait forever unless the user requested a timeout.
     let res = alsa::poll::poll(descriptors, *poll_timeout)?;
     if res == 0 {
-        let description = String::from("`alsa::poll()` spuriously returned");
-        return Err(BackendSpecificError { description });
+        return Ok(PollDescriptorsFlow::TimedOut);
     }
 
     if descriptors[0].revents != 0 {
@@ -743,7 +1067,18 @@ fn poll_descriptors_and_prepare_buffer(
         return Ok(PollDescriptorsFlow::Return);
     }
 
-    let stream_type = match stream.channel.revents(&descriptors[1..])? {
+    let revents = stream.channel.revents(&descriptors[1..])?;
+    if revents.intersects(alsa::poll::Flags::ERR | alsa::poll::Flags::HUP) {
+        // The descriptors of a stream in error stay ready until it is recovered, handle the error
+        // rather than poll again straight away.
+        return Ok(match stream.channel.state() {
+            alsa::pcm::State::XRun => PollDescriptorsFlow::XRun,
+            alsa::pcm::State::Suspended => PollDescriptorsFlow::Suspended,
+            alsa::pcm::State::Disconnected => PollDescriptorsFlow::Disconnected,
+            _ => PollDescriptorsFlow::Continue,
+        });
+    }
+    let stream_type = match revents {
         alsa::poll::Flags::OUT => StreamType::Output,
         alsa::poll::Flags::IN => StreamType::Input,
         _ => {
@@ -757,6 +1092,12 @@ fn poll_descriptors_and_prepare_buffer(
         Err(err) if err.errno() == alsa::nix::errno::Errno::EPIPE => {
             return Ok(PollDescriptorsFlow::XRun)
         }
+        Err(err) if err.errno() == alsa::nix::errno::Errno::ESTRPIPE => {
+            return Ok(PollDescriptorsFlow::Suspended)
+        }
+        Err(err) if err.errno() == alsa::nix::errno::Errno::ENODEV => {
+            return Ok(PollDescriptorsFlow::Disconnected)
+        }
         res => res,
     }? as usize;
     let delay_frames = match status.get_delay() {
@@ -771,9 +1112,11 @@ fn poll_descriptors_and_prepare_buffer(
         return Ok(PollDescriptorsFlow::Continue);
     }
 
-    // Prepare the data buffer.
-    let buffer_size = stream.sample_format.sample_size() * available_samples;
-    buffer.resize(buffer_size, 0u8);
+    // Prepare the data buffer, unless the callback is given that of the device.
+    if !stream.mmap {
+        let buffer_size = stream.sample_format.sample_size() * available_samples;
+        buffer.resize(buffer_size, 0u8);
+    }
 
     Ok(PollDescriptorsFlow::Ready {
         stream_type,
@@ -783,15 +1126,64 @@ fn poll_descriptors_and_prepare_buffer(
     })
 }
 
+// Resume a stream suspended by the system, or prepare it again if the device can't resume where it
+// left off. Returns whether the stream was resumed, which it can't be until the device has woken up.
+fn resume(stream: &StreamInner, stream_type: StreamType) -> Result<bool, alsa::Error> {
+    match stream.channel.resume() {
+        Ok(()) => return Ok(true),
+        Err(err) if err.errno() == alsa::nix::errno::Errno::EAGAIN => {
+            // The descriptors stay ready until then, don't spin on them.
+            std::thread::sleep(Duration::from_millis(10));
+            return Ok(false);
+        }
+        Err(_) => (),
+    }
+    stream.channel.prepare()?;
+    // Playback starts again once the buffer is filled, and paused captures once played.
+    if stream_type == StreamType::Input && !stream.paused.load(Ordering::Relaxed) {
+        stream.channel.start()?;
+    }
+    Ok(true)
+}
+
 // Read input data from ALSA and deliver it to the user.
 fn process_input(
     stream: &StreamInner,
-    buffer: &mut [u8],
+    ctxt: &mut StreamWorkerContext,
     status: alsa::pcm::Status,
+    available_frames: usize,
     delay_frames: usize,
     data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
 ) -> Result<(), BackendSpecificError> {
-    stream.channel.io_bytes().readi(buffer)?;
+    if stream.mmap {
+        return process_input_mmap(
+            stream,
+            status,
+            available_frames,
+            delay_frames,
+            data_callback,
+        );
+    }
+    let StreamWorkerContext {
+        ref mut buffer,
+        ref mut packed,
+        ..
+    } = *ctxt;
+    let read = match stream.packing {
+        None => stream.channel.io_bytes().readi(buffer),
+        Some(packing) => {
+            packed.resize(packing.packed_len(buffer.len()), 0);
+            stream.channel.io_bytes().readi(packed)
+        }
+    };
+    match read {
+        // Resumed by the next poll.
+        Err(err) if err.errno() == alsa::nix::errno::Errno::ESTRPIPE => return Ok(()),
+        result => result?,
+    };
+    if let Some(packing) = stream.packing {
+        packing.unpack(packed, buffer);
+    }
     let sample_format = stream.sample_format;
     let data = buffer.as_mut_ptr() as *mut ();
     let len = buffer.len() / sample_format.sample_size();
@@ -802,8 +1194,17 @@ fn process_input(
         .sub(delay_duration)
         .expect("`capture` is earlier than representation supported by `StreamInstant`");
     let timestamp = crate::InputStreamTimestamp { callback, capture };
-    let info = crate::InputCallbackInfo { timestamp };
+    let info = crate::InputCallbackInfo {
+        timestamp,
+        transport: None,
+    };
+    let start = std::time::Instant::now();
     data_callback(&data, &info);
+    stream.stats.record_callback(
+        start,
+        len / stream.conf.channels as usize,
+        stream.conf.sample_rate,
+    );
 
     Ok(())
 }
@@ -813,13 +1214,27 @@ fn process_input(
 // Returns `true`
 fn process_output(
     stream: &StreamInner,
-    buffer: &mut [u8],
+    ctxt: &mut StreamWorkerContext,
     status: alsa::pcm::Status,
     available_frames: usize,
     delay_frames: usize,
     data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
     error_callback: &mut dyn FnMut(StreamError),
 ) -> Result<(), BackendSpecificError> {
+    if stream.mmap {
+        return process_output_mmap(
+            stream,
+            status,
+            available_frames,
+            delay_frames,
+            data_callback,
+        );
+    }
+    let StreamWorkerContext {
+        ref mut buffer,
+        ref mut packed,
+        ..
+    } = *ctxt;
     {
         // We're now sure that we're ready to write data.
         let sample_format = stream.sample_format;
@@ -832,16 +1247,34 @@ fn process_output(
             .add(delay_duration)
             .expect("`playback` occurs beyond representation supported by `StreamInstant`");
         let timestamp = crate::OutputStreamTimestamp { callback, playback };
-        let info = crate::OutputCallbackInfo { timestamp };
+        let info = crate::OutputCallbackInfo {
+            timestamp,
+            transport: None,
+        };
+        let start = std::time::Instant::now();
         data_callback(&mut data, &info);
+        stream
+            .stats
+            .record_callback(start, available_frames, stream.conf.sample_rate);
     }
+    let buffer = match stream.packing {
+        None => buffer,
+        Some(packing) => {
+            packed.resize(packing.packed_len(buffer.len()), 0);
+            packing.pack(buffer, packed);
+            &mut packed[..]
+        }
+    };
     loop {
         match stream.channel.io_bytes().writei(buffer) {
             Err(err) if err.errno() == alsa::nix::errno::Errno::EPIPE => {
                 // buffer underrun
                 // TODO: Notify the user of this.
+                stream.stats.record_xrun();
                 let _ = stream.channel.try_recover(err, false);
             }
+            // Resumed by the next poll.
+            Err(err) if err.errno() == alsa::nix::errno::Errno::ESTRPIPE => break,
             Err(err) => {
                 error_callback(err.into());
                 continue;
@@ -863,6 +1296,120 @@ fn process_output(
     Ok(())
 }
 
+// Deliver the recorded data to the user straight from the mapped buffer of the device.
+//
+// The mapped area ends with the buffer, so the available frames may be delivered in two parts.
+fn process_input_mmap(
+    stream: &StreamInner,
+    status: alsa::pcm::Status,
+    available_frames: usize,
+    delay_frames: usize,
+    data_callback: &mut (dyn FnMut(&Data, &InputCallbackInfo) + Send + 'static),
+) -> Result<(), BackendSpecificError> {
+    let sample_format = stream.sample_format;
+    let channels = stream.conf.channels as usize;
+    let callback = stream_timestamp(&status, stream.creation_instant)?;
+    let io = stream.channel.io_bytes();
+    let mut read = 0;
+    while read < available_frames {
+        let result = io.mmap(available_frames - read, |buffer| {
+            let data = buffer.as_mut_ptr() as *mut ();
+            let len = buffer.len() / sample_format.sample_size();
+            let data = unsafe { Data::from_parts(data, len, sample_format) };
+            let delay_duration =
+                frames_to_duration(delay_frames.saturating_sub(read), stream.conf.sample_rate);
+            let capture = callback
+                .sub(delay_duration)
+                .expect("`capture` is earlier than representation supported by `StreamInstant`");
+            let timestamp = crate::InputStreamTimestamp { callback, capture };
+            let info = crate::InputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
+            let start = std::time::Instant::now();
+            data_callback(&data, &info);
+            stream
+                .stats
+                .record_callback(start, len / channels, stream.conf.sample_rate);
+            len / channels
+        });
+        match result {
+            Ok(0) => break,
+            Ok(frames) => read += frames,
+            Err(err) if err.errno() == alsa::nix::errno::Errno::EPIPE => {
+                // Overrun, recover like the worker does from one found by polling.
+                stream.stats.record_xrun();
+                stream.channel.prepare()?;
+                stream.channel.start()?;
+                break;
+            }
+            // Resumed by the next poll.
+            Err(err) if err.errno() == alsa::nix::errno::Errno::ESTRPIPE => break,
+            Err(err) => return Err(err.into()),
+        }
+    }
+    Ok(())
+}
+
+// Let the user's function write straight into the mapped buffer of the device.
+//
+// The mapped area ends with the buffer, so the available frames may be requested in two parts.
+fn process_output_mmap(
+    stream: &StreamInner,
+    status: alsa::pcm::Status,
+    available_frames: usize,
+    delay_frames: usize,
+    data_callback: &mut (dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static),
+) -> Result<(), BackendSpecificError> {
+    let sample_format = stream.sample_format;
+    let channels = stream.conf.channels as usize;
+    let callback = stream_timestamp(&status, stream.creation_instant)?;
+    let io = stream.channel.io_bytes();
+    let mut written = 0;
+    while written < available_frames {
+        let result = io.mmap(available_frames - written, |buffer| {
+            let data = buffer.as_mut_ptr() as *mut ();
+            let len = buffer.len() / sample_format.sample_size();
+            let mut data = unsafe { Data::from_parts(data, len, sample_format) };
+            let delay_duration =
+                frames_to_duration(delay_frames + written, stream.conf.sample_rate);
+            let playback = callback
+                .add(delay_duration)
+                .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+            let timestamp = crate::OutputStreamTimestamp { callback, playback };
+            let info = crate::OutputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
+            let start = std::time::Instant::now();
+            data_callback(&mut data, &info);
+            stream
+                .stats
+                .record_callback(start, len / channels, stream.conf.sample_rate);
+            len / channels
+        });
+        match result {
+            Ok(0) => break,
+            Ok(frames) => written += frames,
+            Err(err) if err.errno() == alsa::nix::errno::Errno::EPIPE => {
+                // buffer underrun
+                stream.stats.record_xrun();
+                let _ = stream.channel.try_recover(err, false);
+                return Ok(());
+            }
+            // Resumed by the next poll.
+            Err(err) if err.errno() == alsa::nix::errno::Errno::ESTRPIPE => return Ok(()),
+            Err(err) => return Err(err.into()),
+        }
+    }
+    // Unlike `writei`, committing to the mapped buffer doesn't start the stream once the start
+    // threshold is reached. The whole buffer having been filled, start it now.
+    if stream.channel.state() == alsa::pcm::State::Prepared {
+        stream.channel.start()?;
+    }
+    Ok(())
+}
+
 // Use the elapsed duration since the start of the stream.
 //
 // This ensures positive values that are compatible with our `StreamInstant` representation.
@@ -895,6 +1442,8 @@ fn stream_timestamp(
 
 // Adapted from `timestamp2ns` here:
 // https://fossies.org/linux/alsa-lib/test/audio_time.c
+// `time_t` and `c_long` are not `i64` on every target.
+#[allow(clippy::unnecessary_cast)]
 fn timespec_to_nanos(ts: libc::timespec) -> i64 {
     ts.tv_sec as i64 * 1_000_000_000 + ts.tv_nsec as i64
 }
@@ -916,10 +1465,13 @@ fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Dura
 impl Stream {
     fn new_input<D, E>(
         inner: Arc<StreamInner>,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
         mut data_callback: D,
         mut error_callback: E,
         timeout: Option<Duration>,
-    ) -> Stream
+    ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
@@ -927,9 +1479,8 @@ impl Stream {
         let (tx, rx) = trigger();
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
-        let thread = thread::Builder::new()
-            .name("cpal_alsa_in".to_owned())
-            .spawn(move || {
+        let thread =
+            AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
                 input_stream_worker(
                     rx,
                     &stream,
@@ -938,20 +1489,26 @@ impl Stream {
                     timeout,
                 );
             })
-            .unwrap();
-        Stream {
+            .map_err(|err| BackendSpecificError {
+                description: format!("failed to spawn the audio thread: {}", err),
+            })?;
+        Ok(Stream {
             thread: Some(thread),
             inner,
             trigger: tx,
-        }
+            watchdog,
+        })
     }
 
     fn new_output<D, E>(
         inner: Arc<StreamInner>,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
         mut data_callback: D,
         mut error_callback: E,
         timeout: Option<Duration>,
-    ) -> Stream
+    ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
@@ -959,9 +1516,8 @@ impl Stream {
         let (tx, rx) = trigger();
         // Clone the handle for passing into worker thread.
         let stream = inner.clone();
-        let thread = thread::Builder::new()
-            .name("cpal_alsa_out".to_owned())
-            .spawn(move || {
+        let thread =
+            AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
                 output_stream_worker(
                     rx,
                     &stream,
@@ -970,42 +1526,171 @@ impl Stream {
                     timeout,
                 );
             })
-            .unwrap();
-        Stream {
+            .map_err(|err| BackendSpecificError {
+                description: format!("failed to spawn the audio thread: {}", err),
+            })?;
+        Ok(Stream {
             thread: Some(thread),
             inner,
             trigger: tx,
-        }
+            watchdog,
+        })
+    }
+}
+
+impl Stream {
+    /// The size of the periods of the buffer of the device granted to the stream, in frames. The
+    /// data callback is invoked whenever at least a period can be exchanged with the device.
+    pub fn period_size(&self) -> u32 {
+        (self.inner.period_len / self.inner.conf.channels as usize) as u32
+    }
+
+    /// The size of the buffer of the device granted to the stream, in frames.
+    pub fn buffer_size(&self) -> u32 {
+        (self.inner.buffer_len / self.inner.conf.channels as usize) as u32
+    }
+
+    /// The positions of the channels of the stream in order, if the driver reports them.
+    pub fn channel_map(&self) -> Option<Vec<ChannelPosition>> {
+        let map = self.inner.channel.get_chmap().ok()?;
+        Some(channels::positions(&map))
     }
 }
 
 impl Drop for Stream {
     fn drop(&mut self) {
+        log_debug!("dropping ALSA stream");
         self.trigger.wakeup();
-        self.thread.take().unwrap().join().unwrap();
+        self.thread.take().unwrap().join();
     }
 }
 
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        self.inner.channel.pause(false).ok();
+        self.inner.paused.store(false, Ordering::Relaxed);
+        let prepared_capture = self.inner.channel.state() == alsa::pcm::State::Prepared
+            && self
+                .inner
+                .channel
+                .info()
+                .is_ok_and(|info| info.get_stream() == alsa::Direction::Capture);
+        // A capture paused across a suspend was prepared again, and must be started instead.
+        if prepared_capture {
+            self.inner.channel.start().ok();
+        } else {
+            self.inner.channel.pause(false).ok();
+        }
+        self.inner.stats.restart();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
         Ok(())
     }
     fn pause(&self) -> Result<(), PauseStreamError> {
+        self.inner.paused.store(true, Ordering::Relaxed);
         self.inner.channel.pause(true).ok();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
         Ok(())
     }
+    fn stats(&self) -> Option<StreamStats> {
+        Some(self.inner.stats.snapshot())
+    }
+
+    fn signal_path(&self) -> Option<SignalPath> {
+        Some(self.inner.signal_path.clone())
+    }
 }
 
-fn set_hw_params_from_format(
-    pcm_handle: &alsa::pcm::PCM,
-    config: &StreamConfig,
-    sample_format: SampleFormat,
-) -> Result<bool, BackendSpecificError> {
-    let hw_params = alsa::pcm::HwParams::any(pcm_handle)?;
-    hw_params.set_access(alsa::pcm::Access::RWInterleaved)?;
+/// A layout of 24-bit or narrower samples, which the `I32` samples of a stream are converted to
+/// or from for devices that don't take 32-bit samples. Devices taking them with fewer significant
+/// bits need no conversion.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Packing {
+    /// `S24_3`: 24 bits in 3 bytes.
+    S24In3,
+    /// `S24`: 24 bits in the low bytes of 4.
+    S24In4,
+    /// `S20_3`: 20 bits in 3 bytes.
+    S20In3,
+}
 
-    let sample_format = if cfg!(target_endian = "big") {
+impl Packing {
+    /// In order of preference.
+    const ALL: [Packing; 3] = [Packing::S24In3, Packing::S24In4, Packing::S20In3];
+
+    fn alsa_format(self) -> alsa::pcm::Format {
+        let little_endian = cfg!(target_endian = "little");
+        match (self, little_endian) {
+            (Packing::S24In3, true) => alsa::pcm::Format::S243LE,
+            (Packing::S24In3, false) => alsa::pcm::Format::S243BE,
+            (Packing::S24In4, true) => alsa::pcm::Format::S24LE,
+            (Packing::S24In4, false) => alsa::pcm::Format::S24BE,
+            (Packing::S20In3, true) => alsa::pcm::Format::S203LE,
+            (Packing::S20In3, false) => alsa::pcm::Format::S203BE,
+        }
+    }
+
+    fn sample_size(self) -> usize {
+        match self {
+            Packing::S24In3 | Packing::S20In3 => 3,
+            Packing::S24In4 => 4,
+        }
+    }
+
+    // The number of bits the samples of the stream are truncated by.
+    fn shift(self) -> u32 {
+        match self {
+            Packing::S24In3 | Packing::S24In4 => 8,
+            Packing::S20In3 => 12,
+        }
+    }
+
+    // The size of the packed samples for a buffer of `I32` samples of `len` bytes.
+    fn packed_len(self, len: usize) -> usize {
+        len / 4 * self.sample_size()
+    }
+
+    fn pack(self, samples: &[u8], packed: &mut [u8]) {
+        let sample_size = self.sample_size();
+        for (sample, packed) in samples
+            .chunks_exact(4)
+            .zip(packed.chunks_exact_mut(sample_size))
+        {
+            let sample = i32::from_ne_bytes(sample.try_into().unwrap()) >> self.shift();
+            match sample_size {
+                4 => packed.copy_from_slice(&sample.to_ne_bytes()),
+                _ if cfg!(target_endian = "little") => {
+                    packed.copy_from_slice(&sample.to_le_bytes()[..3])
+                }
+                _ => packed.copy_from_slice(&sample.to_be_bytes()[1..]),
+            }
+        }
+    }
+
+    fn unpack(self, packed: &[u8], samples: &mut [u8]) {
+        let sample_size = self.sample_size();
+        for (packed, sample) in packed
+            .chunks_exact(sample_size)
+            .zip(samples.chunks_exact_mut(4))
+        {
+            // Sign-extend the 24 bits of the packed sample.
+            let packed = match sample_size {
+                4 => i32::from_ne_bytes(packed.try_into().unwrap()) << 8 >> 8,
+                _ if cfg!(target_endian = "little") => {
+                    i32::from_le_bytes([0, packed[0], packed[1], packed[2]]) >> 8
+                }
+                _ => i32::from_be_bytes([packed[0], packed[1], packed[2], 0]) >> 8,
+            };
+            sample.copy_from_slice(&(packed << self.shift()).to_ne_bytes());
+        }
+    }
+}
+
+/// The ALSA equivalent of `sample_format` in native endianness, if any.
+fn alsa_format(sample_format: SampleFormat) -> Option<alsa::pcm::Format> {
+    let format = if cfg!(target_endian = "big") {
         match sample_format {
             SampleFormat::I8 => alsa::pcm::Format::S8,
             SampleFormat::I16 => alsa::pcm::Format::S16BE,
@@ -1021,14 +1706,7 @@ fn set_hw_params_from_format(
             // SampleFormat::U64 => alsa::pcm::Format::U64BE,
             SampleFormat::F32 => alsa::pcm::Format::FloatBE,
             SampleFormat::F64 => alsa::pcm::Format::Float64BE,
-            sample_format => {
-                return Err(BackendSpecificError {
-                    description: format!(
-                        "Sample format '{}' is not supported by this backend",
-                        sample_format
-                    ),
-                })
-            }
+            _ => return None,
         }
     } else {
         match sample_format {
@@ -1046,37 +1724,260 @@ fn set_hw_params_from_format(
             // SampleFormat::U64 => alsa::pcm::Format::U64LE,
             SampleFormat::F32 => alsa::pcm::Format::FloatLE,
             SampleFormat::F64 => alsa::pcm::Format::Float64LE,
-            sample_format => {
-                return Err(BackendSpecificError {
-                    description: format!(
-                        "Sample format '{}' is not supported by this backend",
-                        sample_format
-                    ),
-                })
+            _ => return None,
+        }
+    };
+    Some(format)
+}
+
+/// Whether a stream opened on the device `pcm_name` is bit-perfect and the format the hardware is
+/// running at.
+fn signal_path(
+    handle: &alsa::pcm::PCM,
+    pcm_name: &str,
+    conf: &StreamConfig,
+    sample_format: SampleFormat,
+    packing: Option<Packing>,
+    stream_type: alsa::Direction,
+) -> SignalPath {
+    let stream_format = HardwareFormat {
+        channels: conf.channels,
+        sample_rate: conf.sample_rate,
+        sample_format: Some(sample_format),
+    };
+    // The samples of the stream are truncated to those of the device.
+    if packing.is_some() {
+        return SignalPath {
+            bit_perfect: false,
+            hardware_format: Some(HardwareFormat {
+                sample_format: None,
+                ..stream_format
+            }),
+        };
+    }
+    if pcm_name.starts_with("hw:") {
+        return SignalPath {
+            bit_perfect: true,
+            hardware_format: Some(stream_format),
+        };
+    }
+    // `plughw:` only converts samples the hardware doesn't support the format of, while other
+    // plugins may mix, resample or otherwise process them.
+    let hardware_format = hardware_format(handle, stream_type);
+    SignalPath {
+        bit_perfect: pcm_name.starts_with("plughw:") && hardware_format == Some(stream_format),
+        hardware_format,
+    }
+}
+
+/// The format the hardware underlying `handle` is running at.
+///
+/// ALSA only exposes the parameters of the outermost plugin, so they are read from procfs.
+fn hardware_format(
+    handle: &alsa::pcm::PCM,
+    stream_type: alsa::Direction,
+) -> Option<HardwareFormat> {
+    let info = handle.info().ok()?;
+    // Plugins that are not backed by a sound card, e.g. `pulse`, report no card.
+    if info.get_card() < 0 {
+        return None;
+    }
+    let direction = match stream_type {
+        alsa::Direction::Playback => 'p',
+        alsa::Direction::Capture => 'c',
+    };
+    let path = format!(
+        "/proc/asound/card{}/pcm{}{}/sub{}/hw_params",
+        info.get_card(),
+        info.get_device(),
+        direction,
+        info.get_subdevice()
+    );
+    parse_proc_hw_params(&std::fs::read_to_string(path).ok()?)
+}
+
+// Parse the contents of a procfs `hw_params` file, the relevant lines of which look like:
+//
+// format: S32_LE
+// channels: 2
+// rate: 48000 (48000/1)
+fn parse_proc_hw_params(hw_params: &str) -> Option<HardwareFormat> {
+    const SAMPLE_FORMATS: [SampleFormat; 8] = [
+        SampleFormat::I8,
+        SampleFormat::I16,
+        SampleFormat::I32,
+        SampleFormat::U8,
+        SampleFormat::U16,
+        SampleFormat::U32,
+        SampleFormat::F32,
+        SampleFormat::F64,
+    ];
+
+    let (mut channels, mut sample_rate, mut sample_format) = (None, None, None);
+    for line in hw_params.lines() {
+        match line.split_once(": ") {
+            Some(("format", format)) => {
+                let format = format.parse::<alsa::pcm::Format>().ok()?;
+                sample_format = Some(
+                    SAMPLE_FORMATS
+                        .into_iter()
+                        .find(|&sample_format| alsa_format(sample_format) == Some(format)),
+                );
             }
+            Some(("channels", value)) => channels = value.parse().ok(),
+            Some(("rate", value)) => {
+                sample_rate = value
+                    .split(' ')
+                    .next()
+                    .and_then(|rate| rate.parse().ok())
+                    .map(SampleRate)
+            }
+            _ => (),
         }
+    }
+    Some(HardwareFormat {
+        channels: channels?,
+        sample_rate: sample_rate?,
+        sample_format: sample_format?,
+    })
+}
+
+/// The largest channel count reported by actual hardware, e.g. MADI or Dante interfaces.
+const MAX_HARDWARE_CHANNELS: u32 = 256;
+
+/// The name of the hardware device underlying the device `name`, which a stream must open to get
+/// exclusive access to it.
+///
+/// Only `hw:` devices and the `plughw:` devices converting to and from them are supported, as the
+/// underlying hardware devices of other plugins can't be determined from their names.
+fn hw_device_name(name: &str) -> Option<String> {
+    if name.starts_with("hw:") {
+        Some(name.to_owned())
+    } else {
+        name.strip_prefix("plughw:")
+            .map(|card| format!("hw:{}", card))
+    }
+}
+
+/// The name of the `iec958` or `hdmi` device `name` with its IEC 60958 channel status marking the
+/// samples as non-audio, so that the receiver decodes the bitstream they carry.
+fn non_audio_device_name(name: &str) -> Option<String> {
+    let (device, args) = name.split_once(':').unwrap_or((name, ""));
+    if device != "iec958" && device != "hdmi" {
+        return None;
+    }
+    if args.contains("AES0=") {
+        return Some(name.to_owned());
+    }
+    // IEC958_AES0_NONAUDIO | IEC958_AES0_CON_NOT_COPYRIGHT
+    let aes0 = "AES0=0x06";
+    match args {
+        "" => Some(format!("{}:{}", device, aes0)),
+        _ if args.contains('=') => Some(format!("{}:{},{}", device, args, aes0)),
+        // Name the positional card argument, as the named argument can't follow positional ones.
+        _ if !args.contains(',') => Some(format!("{}:CARD={},{}", device, args, aes0)),
+        _ => None,
+    }
+}
+
+fn set_hw_params_from_format(
+    pcm_handle: &alsa::pcm::PCM,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    native_dsd: bool,
+    resample: bool,
+    periods: Option<u32>,
+    mmap: bool,
+) -> Result<GrantedHwParams, BackendSpecificError> {
+    let hw_params = alsa::pcm::HwParams::any(pcm_handle)?;
+
+    let alsa_sample_format = if native_dsd {
+        alsa::pcm::Format::DSDU8
+    } else {
+        alsa_format(sample_format).ok_or_else(|| BackendSpecificError {
+            description: format!(
+                "Sample format '{}' is not supported by this backend",
+                sample_format
+            ),
+        })?
+    };
+
+    let packing = match hw_params.set_format(alsa_sample_format) {
+        Ok(()) => None,
+        // Devices without 32-bit samples, e.g. many USB interfaces, may take 24-bit samples
+        // converted from and to those of the stream.
+        Err(err) => Some(
+            Packing::ALL
+                .into_iter()
+                .filter(|_| sample_format == SampleFormat::I32 && !native_dsd)
+                .find(|packing| hw_params.set_format(packing.alsa_format()).is_ok())
+                .ok_or(err)?,
+        ),
     };
 
-    hw_params.set_format(sample_format)?;
+    // Only interleaved buffers of the samples of the stream can be mapped into a slice of them,
+    // other devices are read and written instead.
+    let mmap = mmap
+        && packing.is_none()
+        && hw_params
+            .set_access(alsa::pcm::Access::MMapInterleaved)
+            .is_ok();
+    if !mmap {
+        hw_params.set_access(alsa::pcm::Access::RWInterleaved)?;
+    }
+
+    if !resample {
+        // Fail rather than convert to another rate.
+        hw_params.set_rate_resample(false)?;
+    }
     hw_params.set_rate(config.sample_rate.0, alsa::ValueOr::Nearest)?;
     hw_params.set_channels(config.channels as u32)?;
 
-    match config.buffer_size {
-        BufferSize::Fixed(v) => {
+    match (config.buffer_size, periods) {
+        (BufferSize::Fixed(v), None) => {
             hw_params.set_period_size_near((v / 4) as alsa::pcm::Frames, alsa::ValueOr::Nearest)?;
             hw_params.set_buffer_size(v as alsa::pcm::Frames)?;
         }
-        BufferSize::Default => {
+        (BufferSize::Default, None) => {
             // These values together represent a moderate latency and wakeup interval.
             // Without them, we are at the mercy of the device
             hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
             hw_params.set_buffer_time_near(100_000, alsa::ValueOr::Nearest)?;
         }
+        (buffer_size, Some(periods)) => {
+            let period = match buffer_size {
+                BufferSize::Fixed(v) => hw_params
+                    .set_period_size_near(v as alsa::pcm::Frames, alsa::ValueOr::Nearest)?,
+                BufferSize::Default => {
+                    hw_params.set_period_time_near(25_000, alsa::ValueOr::Nearest)?;
+                    hw_params.get_period_size()?
+                }
+            };
+            // Devices that can't have this many periods of this size get the buffer size nearest
+            // to it instead.
+            if hw_params
+                .set_periods(periods, alsa::ValueOr::Nearest)
+                .is_err()
+            {
+                hw_params.set_buffer_size_near(period * periods as alsa::pcm::Frames)?;
+            }
+        }
     }
 
     pcm_handle.hw_params(&hw_params)?;
 
-    Ok(hw_params.can_pause())
+    Ok(GrantedHwParams {
+        can_pause: hw_params.can_pause(),
+        mmap,
+        packing,
+    })
+}
+
+// What the device granted of the parameters requested by `set_hw_params_from_format`.
+struct GrantedHwParams {
+    can_pause: bool,
+    mmap: bool,
+    packing: Option<Packing>,
 }
 
 fn set_sw_params_from_format(
@@ -1165,3 +2066,151 @@ impl From<alsa::Error> for StreamError {
         err.into()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{hw_device_name, non_audio_device_name, parse_proc_hw_params, Packing};
+    use crate::{HardwareFormat, SampleFormat, SampleRate};
+
+    fn to_bytes(samples: &[i32]) -> Vec<u8> {
+        samples.iter().flat_map(|s| s.to_ne_bytes()).collect()
+    }
+
+    fn pack(packing: Packing, samples: &[i32]) -> Vec<u8> {
+        let samples = to_bytes(samples);
+        let mut packed = vec![0; packing.packed_len(samples.len())];
+        packing.pack(&samples, &mut packed);
+        packed
+    }
+
+    fn unpack(packing: Packing, packed: &[u8]) -> Vec<i32> {
+        let mut samples = vec![0; packed.len() / packing.sample_size() * 4];
+        packing.unpack(packed, &mut samples);
+        samples
+            .chunks_exact(4)
+            .map(|s| i32::from_ne_bytes(s.try_into().unwrap()))
+            .collect()
+    }
+
+    #[test]
+    fn pack_24_in_3() {
+        let packed = pack(Packing::S24In3, &[0x1234_5678, -0x100]);
+        if cfg!(target_endian = "little") {
+            assert_eq!(packed, [0x56, 0x34, 0x12, 0xff, 0xff, 0xff]);
+        } else {
+            assert_eq!(packed, [0x12, 0x34, 0x56, 0xff, 0xff, 0xff]);
+        }
+    }
+
+    #[test]
+    fn pack_24_in_4() {
+        let packed = pack(Packing::S24In4, &[0x1234_5678, i32::MIN]);
+        assert_eq!(packed, to_bytes(&[0x12_3456, -0x80_0000]));
+    }
+
+    #[test]
+    fn pack_20_in_3() {
+        let packed = pack(Packing::S20In3, &[0x1234_5678]);
+        if cfg!(target_endian = "little") {
+            assert_eq!(packed, [0x45, 0x23, 0x01]);
+        } else {
+            assert_eq!(packed, [0x01, 0x23, 0x45]);
+        }
+    }
+
+    #[test]
+    fn unpack_sign_extends() {
+        let packed = pack(Packing::S24In3, &[i32::MIN, -0x100, i32::MAX]);
+        assert_eq!(
+            unpack(Packing::S24In3, &packed),
+            [i32::MIN, -0x100, 0x7fff_ff00]
+        );
+        // The unused high byte of 24 bits in 4 is ignored.
+        let packed = to_bytes(&[0x7f80_0000, 0x0000_0001]);
+        assert_eq!(unpack(Packing::S24In4, &packed), [i32::MIN, 0x100]);
+    }
+
+    #[test]
+    fn unpack_round_trips() {
+        let samples = [0, 1 << 12, -(1 << 12), 0x1234_5000, -0x1234_5000];
+        for packing in Packing::ALL {
+            let packed = pack(packing, &samples);
+            assert_eq!(packed.len(), samples.len() * packing.sample_size());
+            assert_eq!(unpack(packing, &packed), samples);
+        }
+    }
+
+    #[test]
+    fn parse_hw_params_of_running_stream() {
+        let hw_params = "access: MMAP_INTERLEAVED\n\
+                         format: S32_LE\n\
+                         subformat: STD\n\
+                         channels: 8\n\
+                         rate: 96000 (96000/1)\n\
+                         period_size: 1024\n\
+                         buffer_size: 4096\n";
+        assert_eq!(
+            parse_proc_hw_params(hw_params),
+            Some(HardwareFormat {
+                channels: 8,
+                sample_rate: SampleRate(96_000),
+                sample_format: Some(SampleFormat::I32),
+            })
+        );
+    }
+
+    #[test]
+    fn parse_hw_params_without_sample_format() {
+        let hw_params = "format: S24_3LE\nchannels: 2\nrate: 44100 (44100/1)\n";
+        assert_eq!(
+            parse_proc_hw_params(hw_params),
+            Some(HardwareFormat {
+                channels: 2,
+                sample_rate: SampleRate(44_100),
+                sample_format: None,
+            })
+        );
+    }
+
+    #[test]
+    fn parse_hw_params_of_closed_device() {
+        assert_eq!(parse_proc_hw_params("closed\n"), None);
+        assert_eq!(parse_proc_hw_params("format: S16_LE\nchannels: 2\n"), None);
+    }
+
+    #[test]
+    fn hw_device_names() {
+        assert_eq!(hw_device_name("hw:0,3").as_deref(), Some("hw:0,3"));
+        assert_eq!(hw_device_name("plughw:1,0").as_deref(), Some("hw:1,0"));
+        assert_eq!(
+            hw_device_name("plughw:CARD=PCH,DEV=0").as_deref(),
+            Some("hw:CARD=PCH,DEV=0")
+        );
+        assert_eq!(hw_device_name("default"), None);
+        assert_eq!(hw_device_name("front:CARD=PCH,DEV=0"), None);
+    }
+
+    #[test]
+    fn non_audio_device_names() {
+        assert_eq!(
+            non_audio_device_name("iec958").as_deref(),
+            Some("iec958:AES0=0x06")
+        );
+        assert_eq!(
+            non_audio_device_name("hdmi:CARD=HDMI,DEV=1").as_deref(),
+            Some("hdmi:CARD=HDMI,DEV=1,AES0=0x06")
+        );
+        assert_eq!(
+            non_audio_device_name("iec958:PCH").as_deref(),
+            Some("iec958:CARD=PCH,AES0=0x06")
+        );
+        // The channel status already set is kept.
+        assert_eq!(
+            non_audio_device_name("iec958:CARD=PCH,AES0=0x02").as_deref(),
+            Some("iec958:CARD=PCH,AES0=0x02")
+        );
+        // Positional arguments can't be followed by named ones.
+        assert_eq!(non_audio_device_name("hdmi:0,1"), None);
+        assert_eq!(non_audio_device_name("front:CARD=PCH"), None);
+    }
+}
@@ -0,0 +1,217 @@
+use std::sync::Arc;
+use std::vec::IntoIter as VecIntoIter;
+
+use crate::{SampleFormat, SampleRate, SupportedBufferSize, SupportedStreamConfigRange};
+
+extern crate jni;
+
+use self::jni::objects::{JObject, JValue};
+use self::jni::sys::jobject;
+use self::jni::Executor;
+use self::jni::{errors::Result as JResult, JNIEnv, JavaVM};
+
+// constants from android.media.AudioFormat
+pub const ENCODING_PCM_16BIT: i32 = 2;
+pub const ENCODING_PCM_FLOAT: i32 = 4;
+pub const CHANNEL_OUT_MONO: i32 = 4;
+pub const CHANNEL_OUT_STEREO: i32 = 12;
+
+// constants from android.media.AudioManager
+const STREAM_MUSIC: i32 = 3;
+const AUDIOFOCUS_REQUEST_GRANTED: i32 = 1;
+
+// Android Java API supports up to 8 channels, but oboe API
+// only exposes mono and stereo, which the AAudio host sticks to as well.
+pub const CHANNEL_MASKS: [i32; 2] = [CHANNEL_OUT_MONO, CHANNEL_OUT_STEREO];
+
+pub const SAMPLE_RATES: [i32; 13] = [
+    5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176_400, 192_000,
+];
+
+/// The audio focus the streams of a device request while playing, set through
+/// `Device::set_audio_focus`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum AudioFocus {
+    /// For playback of unknown duration, e.g. music, during which other applications stop
+    /// playing.
+    Gain,
+    /// For short playback, e.g. navigation directions, after which other applications resume.
+    GainTransient,
+    /// Like `GainTransient`, but other applications may keep playing at a lower volume.
+    GainTransientMayDuck,
+    /// Like `GainTransient`, but no notifications are played either, e.g. while recording a
+    /// voice memo.
+    GainTransientExclusive,
+}
+
+impl AudioFocus {
+    // The `AUDIOFOCUS_GAIN*` constant of android.media.AudioManager.
+    fn duration_hint(self) -> i32 {
+        match self {
+            AudioFocus::Gain => 1,
+            AudioFocus::GainTransient => 2,
+            AudioFocus::GainTransientMayDuck => 3,
+            AudioFocus::GainTransientExclusive => 4,
+        }
+    }
+}
+
+fn with_attached<F, R>(closure: F) -> JResult<R>
+where
+    F: FnOnce(&JNIEnv) -> JResult<R>,
+{
+    let android_context = ndk_context::android_context();
+    let vm = Arc::new(unsafe { JavaVM::from_raw(android_context.vm().cast())? });
+    Executor::new(vm).with_attached(|env| closure(env))
+}
+
+fn with_audio_manager<F, R>(closure: F) -> JResult<R>
+where
+    F: FnOnce(&JNIEnv, JObject) -> JResult<R>,
+{
+    let context = JObject::from(ndk_context::android_context().context() as jobject);
+    with_attached(|env| {
+        let service = env.new_string("audio")?;
+        let audio_manager = env
+            .call_method(
+                context,
+                "getSystemService",
+                "(Ljava/lang/String;)Ljava/lang/Object;",
+                &[JValue::Object(service.into())],
+            )?
+            .l()?;
+        closure(env, audio_manager)
+    })
+}
+
+/// Request `focus` for the application, returning whether it was granted. It isn't e.g. during a
+/// phone call.
+///
+/// Without a listener to call back, the loss of the focus to another application isn't reported.
+pub fn request_audio_focus(focus: AudioFocus) -> JResult<bool> {
+    with_audio_manager(|env, audio_manager| {
+        let result = env
+            .call_method(
+                audio_manager,
+                "requestAudioFocus",
+                "(Landroid/media/AudioManager$OnAudioFocusChangeListener;II)I",
+                &[
+                    JObject::null().into(),
+                    STREAM_MUSIC.into(),
+                    focus.duration_hint().into(),
+                ],
+            )?
+            .i()?;
+        Ok(result == AUDIOFOCUS_REQUEST_GRANTED)
+    })
+}
+
+/// Abandon the focus requested by `request_audio_focus`.
+pub fn abandon_audio_focus() -> JResult<()> {
+    with_audio_manager(|env, audio_manager| {
+        env.call_method(
+            audio_manager,
+            "abandonAudioFocus",
+            "(Landroid/media/AudioManager$OnAudioFocusChangeListener;)I",
+            &[JObject::null().into()],
+        )?;
+        Ok(())
+    })
+}
+
+fn get_min_buffer_size(
+    class: &'static str,
+    sample_rate: i32,
+    channel_mask: i32,
+    format: i32,
+) -> i32 {
+    // Unwrapping everything because these operations are not expected to fail
+    // or throw exceptions. Android returns negative values for invalid parameters,
+    // which is what we expect.
+    with_attached(|env| {
+        let class = env.find_class(class).unwrap();
+        env.call_static_method(
+            class,
+            "getMinBufferSize",
+            "(III)I",
+            &[sample_rate.into(), channel_mask.into(), format.into()],
+        )
+        .unwrap()
+        .i()
+    })
+    .unwrap()
+}
+
+pub fn get_audio_track_min_buffer_size(sample_rate: i32, channel_mask: i32, format: i32) -> i32 {
+    get_min_buffer_size(
+        "android/media/AudioTrack",
+        sample_rate,
+        channel_mask,
+        format,
+    )
+}
+
+pub fn get_audio_record_min_buffer_size(sample_rate: i32, channel_mask: i32, format: i32) -> i32 {
+    get_min_buffer_size(
+        "android/media/AudioRecord",
+        sample_rate,
+        channel_mask,
+        format,
+    )
+}
+
+pub fn buffer_size_range_for_params(
+    is_output: bool,
+    sample_rate: i32,
+    channel_mask: i32,
+    android_format: i32,
+) -> SupportedBufferSize {
+    let min_buffer_size = if is_output {
+        get_audio_track_min_buffer_size(sample_rate, channel_mask, android_format)
+    } else {
+        get_audio_record_min_buffer_size(sample_rate, channel_mask, android_format)
+    };
+    if min_buffer_size > 0 {
+        SupportedBufferSize::Range {
+            min: min_buffer_size as u32,
+            max: i32::MAX as u32,
+        }
+    } else {
+        SupportedBufferSize::Unknown
+    }
+}
+
+pub fn default_supported_configs(is_output: bool) -> VecIntoIter<SupportedStreamConfigRange> {
+    // Have to "brute force" the parameter combinations with getMinBufferSize
+    const FORMATS: [SampleFormat; 2] = [SampleFormat::I16, SampleFormat::F32];
+
+    let mut output = Vec::with_capacity(SAMPLE_RATES.len() * CHANNEL_MASKS.len() * FORMATS.len());
+    for sample_format in &FORMATS {
+        let android_format = if *sample_format == SampleFormat::I16 {
+            ENCODING_PCM_16BIT
+        } else {
+            ENCODING_PCM_FLOAT
+        };
+        for (mask_idx, channel_mask) in CHANNEL_MASKS.iter().enumerate() {
+            let channel_count = mask_idx + 1;
+            for sample_rate in &SAMPLE_RATES {
+                if let SupportedBufferSize::Range { min, max } = buffer_size_range_for_params(
+                    is_output,
+                    *sample_rate,
+                    *channel_mask,
+                    android_format,
+                ) {
+                    output.push(SupportedStreamConfigRange {
+                        channels: channel_count as u16,
+                        min_sample_rate: SampleRate(*sample_rate as u32),
+                        max_sample_rate: SampleRate(*sample_rate as u32),
+                        buffer_size: SupportedBufferSize::Range { min, max },
+                        sample_format: *sample_format,
+                    });
+                }
+            }
+        }
+    }
+
+    output.into_iter()
+}
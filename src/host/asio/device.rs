@@ -14,8 +14,9 @@ use crate::SupportedBufferSize;
 use crate::SupportedStreamConfig;
 use crate::SupportedStreamConfigRange;
 use crate::SupportedStreamConfigsError;
+use once_cell::sync::Lazy;
 use std::hash::{Hash, Hasher};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 
 /// A ASIO Device
 pub struct Device {
@@ -24,11 +25,14 @@ pub struct Device {
 
     // Input and/or Output stream.
     // A driver can only have one of each.
-    // They need to be created at the same time.
+    // They need to be created at the same time, so they're shared by all devices of the driver.
     pub asio_streams: Arc<Mutex<sys::AsioStreams>>,
 }
 
 /// All available devices.
+///
+/// Only the loaded driver is listed while any device or stream of it exists, see
+/// [`Host::load_device`](super::Host::load_device).
 pub struct Devices {
     asio: Arc<sys::Asio>,
     drivers: std::vec::IntoIter<String>,
@@ -49,6 +53,14 @@ impl Hash for Device {
 }
 
 impl Device {
+    pub(crate) fn load(asio: &sys::Asio, name: &str) -> Result<Self, sys::LoadDriverError> {
+        let driver = Arc::new(asio.load_driver(name)?);
+        Ok(Device {
+            driver,
+            asio_streams: loaded_asio_streams(),
+        })
+    }
+
     pub fn name(&self) -> Result<String, DeviceNameError> {
         Ok(self.driver.name().to_string())
     }
@@ -127,6 +139,45 @@ impl Device {
         Ok(supported_configs.into_iter())
     }
 
+    /// Opens the control panel of the ASIO driver, e.g. to change its buffer size or clock
+    /// source.
+    pub fn open_control_panel(&self) -> Result<(), BackendSpecificError> {
+        self.driver
+            .open_control_panel()
+            .map_err(backend_specific_err)
+    }
+
+    /// The names the driver gives the input channels of the device, in order.
+    ///
+    /// A subset of them can be opened through [`StreamOptions::device_channels`].
+    ///
+    /// [`StreamOptions::device_channels`]: crate::StreamOptions::device_channels
+    pub fn input_channel_names(&self) -> Result<Vec<String>, BackendSpecificError> {
+        self.channel_names(true)
+    }
+
+    /// The names the driver gives the output channels of the device, in order.
+    ///
+    /// A subset of them can be opened through [`StreamOptions::device_channels`].
+    ///
+    /// [`StreamOptions::device_channels`]: crate::StreamOptions::device_channels
+    pub fn output_channel_names(&self) -> Result<Vec<String>, BackendSpecificError> {
+        self.channel_names(false)
+    }
+
+    fn channel_names(&self, is_input: bool) -> Result<Vec<String>, BackendSpecificError> {
+        let channels = self.driver.channels().map_err(backend_specific_err)?;
+        let n_channels = if is_input {
+            channels.ins
+        } else {
+            channels.outs
+        };
+        (0..n_channels)
+            .map(|channel| self.driver.channel_name(channel, is_input))
+            .collect::<Result<_, _>>()
+            .map_err(backend_specific_err)
+    }
+
     /// Returns the default input config
     pub fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         let channels = self.driver.channels().map_err(default_config_err)?.ins as u16;
@@ -183,18 +234,8 @@ impl Iterator for Devices {
     fn next(&mut self) -> Option<Device> {
         loop {
             match self.drivers.next() {
-                Some(name) => match self.asio.load_driver(&name) {
-                    Ok(driver) => {
-                        let driver = Arc::new(driver);
-                        let asio_streams = Arc::new(Mutex::new(sys::AsioStreams {
-                            input: None,
-                            output: None,
-                        }));
-                        return Some(Device {
-                            driver,
-                            asio_streams,
-                        });
-                    }
+                Some(name) => match Device::load(&self.asio, &name) {
+                    Ok(device) => return Some(device),
                     Err(_) => continue,
                 },
                 None => return None,
@@ -203,6 +244,26 @@ impl Iterator for Devices {
     }
 }
 
+/// The streams of the loaded driver.
+///
+/// Devices enumerated separately, e.g. the default input and output devices, share them so that
+/// preparing the streams of one direction keeps those of the other. Alive streams imply that their
+/// driver is still loaded, and thus that they belong to the driver being loaded.
+fn loaded_asio_streams() -> Arc<Mutex<sys::AsioStreams>> {
+    static ASIO_STREAMS: Lazy<Mutex<Weak<Mutex<sys::AsioStreams>>>> =
+        Lazy::new(|| Mutex::new(Weak::new()));
+    let mut loaded = ASIO_STREAMS.lock();
+    if let Some(asio_streams) = loaded.upgrade() {
+        return asio_streams;
+    }
+    let asio_streams = Arc::new(Mutex::new(sys::AsioStreams {
+        input: None,
+        output: None,
+    }));
+    *loaded = Arc::downgrade(&asio_streams);
+    asio_streams
+}
+
 pub(crate) fn convert_data_type(ty: &sys::AsioSampleType) -> Option<SampleFormat> {
     let fmt = match *ty {
         sys::AsioSampleType::ASIOSTInt16MSB => SampleFormat::I16,
@@ -216,6 +277,11 @@ pub(crate) fn convert_data_type(ty: &sys::AsioSampleType) -> Option<SampleFormat
     Some(fmt)
 }
 
+fn backend_specific_err(e: sys::AsioError) -> BackendSpecificError {
+    let description = format!("{}", e);
+    BackendSpecificError { description }
+}
+
 fn default_config_err(e: sys::AsioError) -> DefaultStreamConfigError {
     match e {
         sys::AsioError::NoDrivers | sys::AsioError::HardwareMalfunction => {
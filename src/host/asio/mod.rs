@@ -1,17 +1,20 @@
 extern crate asio_sys as sys;
 extern crate parking_lot;
 
+use crate::dsd;
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError, DsdMode,
     InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat,
-    StreamConfig, StreamError, SupportedStreamConfig, SupportedStreamConfigsError,
+    ShareMode, StreamConfig, StreamError, StreamOptions, SupportedStreamConfig,
+    SupportedStreamConfigsError,
 };
 
 pub use self::device::{Device, Devices, SupportedInputConfigs, SupportedOutputConfigs};
 pub use self::stream::Stream;
 use std::sync::Arc;
 use std::time::Duration;
+pub use sys::LoadDriverError;
 
 mod device;
 mod stream;
@@ -28,6 +31,18 @@ impl Host {
         let host = Host { asio };
         Ok(host)
     }
+
+    /// Loads the device of the ASIO driver with the given name, which is also the name of the
+    /// device.
+    ///
+    /// ASIO only supports loading one driver at a time, which stays loaded while any of its
+    /// devices or streams exist. Until they are dropped, this fails with
+    /// [`LoadDriverError::DriverAlreadyExists`] for the other drivers, which
+    /// [`devices`](HostTrait::devices) leaves out. Input and output streams must therefore use the
+    /// same driver, whose buffers are created for both directions at once.
+    pub fn load_device(&self, name: &str) -> Result<Device, LoadDriverError> {
+        Device::load(&self.asio, name)
+    }
 }
 
 impl HostTrait for Host {
@@ -95,10 +110,40 @@ impl DeviceTrait for Device {
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         Device::build_input_stream_raw(
             self,
             config,
             sample_format,
+            options,
             data_callback,
             error_callback,
             timeout,
@@ -117,10 +162,51 @@ impl DeviceTrait for Device {
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         Device::build_output_stream_raw(
             self,
             config,
             sample_format,
+            options,
             data_callback,
             error_callback,
             timeout,
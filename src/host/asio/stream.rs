@@ -4,10 +4,11 @@ extern crate num_traits;
 use self::num_traits::PrimInt;
 use super::parking_lot::Mutex;
 use super::Device;
+use crate::watchdog::{self, Watchdog};
 use crate::{
-    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DisconnectReason, InputCallbackInfo,
     OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, SizedSample, StreamConfig,
-    StreamError,
+    StreamError, StreamOptions,
 };
 use std;
 use std::sync::atomic::{AtomicBool, Ordering};
@@ -28,18 +29,35 @@ pub struct Stream {
     driver: Arc<sys::Driver>,
     asio_streams: Arc<Mutex<sys::AsioStreams>>,
     callback_id: sys::CallbackId,
+    message_callback_id: sys::MessageCallbackId,
+    // The size of the buffers of the driver, in frames.
+    buffer_size: u32,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
 }
 
 impl Stream {
     pub fn play(&self) -> Result<(), PlayStreamError> {
         self.playing.store(true, Ordering::SeqCst);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
         Ok(())
     }
 
     pub fn pause(&self) -> Result<(), PauseStreamError> {
         self.playing.store(false, Ordering::SeqCst);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
         Ok(())
     }
+
+    /// The size of the buffers the driver created for the stream, in frames, which is the number
+    /// of frames of every data callback.
+    pub fn buffer_size(&self) -> u32 {
+        self.buffer_size
+    }
 }
 
 impl Device {
@@ -47,9 +65,10 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
-        _error_callback: E,
-        _timeout: Option<Duration>,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
@@ -65,13 +84,16 @@ impl Device {
         }
 
         let num_channels = config.channels.clone();
-        let buffer_size = self.get_or_create_input_stream(config, sample_format)?;
+        let buffer_size = self.get_or_create_input_stream(config, sample_format, options)?;
         let cpal_num_samples = buffer_size * num_channels as usize;
 
         // Create the buffer depending on the size of the data type.
         let len_bytes = cpal_num_samples * sample_format.sample_size();
         let mut interleaved = vec![0u8; len_bytes];
 
+        let (watchdog, mut data_callback, error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
+
         let stream_playing = Arc::new(AtomicBool::new(false));
         let playing = Arc::clone(&stream_playing);
         let asio_streams = self.asio_streams.clone();
@@ -92,6 +114,13 @@ impl Device {
                 None => return,
             };
 
+            // Streams built before a reset of the driver don't match its new buffers.
+            if asio_stream.buffer_size as usize != buffer_size
+                || asio_stream.buffer_infos.len() < num_channels as usize
+            {
+                return;
+            }
+
             /// 1. Write from the ASIO buffer to the interleaved CPAL buffer.
             /// 2. Deliver the CPAL buffer to the user callback.
             unsafe fn process_input_callback<A, D, F>(
@@ -128,7 +157,10 @@ impl Device {
                     .sub(delay)
                     .expect("`capture` occurs before origin of alsa `StreamInstant`");
                 let timestamp = crate::InputStreamTimestamp { callback, capture };
-                let info = InputCallbackInfo { timestamp };
+                let info = InputCallbackInfo {
+                    timestamp,
+                    transport: None,
+                };
                 data_callback(&data, &info);
             }
 
@@ -211,6 +243,7 @@ impl Device {
             }
         });
 
+        let message_callback_id = self.add_message_callback(config.sample_rate, error_callback);
         let driver = self.driver.clone();
         let asio_streams = self.asio_streams.clone();
 
@@ -222,6 +255,9 @@ impl Device {
             driver,
             asio_streams,
             callback_id,
+            message_callback_id,
+            buffer_size: buffer_size as u32,
+            watchdog,
         })
     }
 
@@ -229,9 +265,10 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
-        _error_callback: E,
-        _timeout: Option<Duration>,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
@@ -247,7 +284,7 @@ impl Device {
         }
 
         let num_channels = config.channels.clone();
-        let buffer_size = self.get_or_create_output_stream(config, sample_format)?;
+        let buffer_size = self.get_or_create_output_stream(config, sample_format, options)?;
         let cpal_num_samples = buffer_size * num_channels as usize;
 
         // Create buffers depending on data type.
@@ -255,6 +292,9 @@ impl Device {
         let mut interleaved = vec![0u8; len_bytes];
         let mut silence_asio_buffer = SilenceAsioBuffer::default();
 
+        let (watchdog, mut data_callback, error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+
         let stream_playing = Arc::new(AtomicBool::new(false));
         let playing = Arc::clone(&stream_playing);
         let asio_streams = self.asio_streams.clone();
@@ -273,6 +313,13 @@ impl Device {
                 None => return,
             };
 
+            // Streams built before a reset of the driver don't match its new buffers.
+            if asio_stream.buffer_size as usize != buffer_size
+                || asio_stream.buffer_infos.len() < num_channels as usize
+            {
+                return;
+            }
+
             // Silence the ASIO buffer that is about to be used.
             //
             // This checks if any other callbacks have already silenced the buffer associated with
@@ -324,7 +371,10 @@ impl Device {
                     .add(delay)
                     .expect("`playback` occurs beyond representation supported by `StreamInstant`");
                 let timestamp = crate::OutputStreamTimestamp { callback, playback };
-                let info = OutputCallbackInfo { timestamp };
+                let info = OutputCallbackInfo {
+                    timestamp,
+                    transport: None,
+                };
                 data_callback(&mut data, &info);
 
                 // 2. Silence ASIO channels if necessary.
@@ -435,6 +485,7 @@ impl Device {
             }
         });
 
+        let message_callback_id = self.add_message_callback(config.sample_rate, error_callback);
         let driver = self.driver.clone();
         let asio_streams = self.asio_streams.clone();
 
@@ -446,6 +497,34 @@ impl Device {
             driver,
             asio_streams,
             callback_id,
+            message_callback_id,
+            buffer_size: buffer_size as u32,
+            watchdog,
+        })
+    }
+
+    /// Report the changes of the driver that a stream at `sample_rate` doesn't survive to its
+    /// error callback, after which it must be rebuilt.
+    fn add_message_callback<E>(
+        &self,
+        sample_rate: crate::SampleRate,
+        mut error_callback: E,
+    ) -> sys::MessageCallbackId
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.driver.add_message_callback(move |event| {
+            let disconnected = match event {
+                // Drivers also report the rate the stream was built with being set.
+                sys::DriverEvent::SampleRateChanged(rate) => rate != sample_rate.0 as f64,
+                sys::DriverEvent::ResetRequest => true,
+                sys::DriverEvent::ResyncRequest | sys::DriverEvent::LatenciesChanged => false,
+            };
+            if disconnected {
+                error_callback(StreamError::Disconnected {
+                    reason: DisconnectReason::FormatChanged,
+                });
+            }
         })
     }
 
@@ -458,30 +537,40 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
+        options: &StreamOptions,
     ) -> Result<usize, BuildStreamError> {
-        match self.default_input_config() {
-            Ok(f) => {
-                let num_asio_channels = f.channels;
-                check_config(&self.driver, config, sample_format, num_asio_channels)
-            }
-            Err(_) => Err(BuildStreamError::StreamConfigNotSupported),
-        }?;
-        let num_channels = config.channels as usize;
-        let ref mut streams = *self.asio_streams.lock();
-
-        let buffer_size = match config.buffer_size {
-            BufferSize::Fixed(v) => Some(v as i32),
-            BufferSize::Default => None,
+        let num_asio_channels = match self.default_input_config() {
+            Ok(f) => f.channels,
+            Err(_) => return Err(BuildStreamError::StreamConfigNotSupported),
         };
+        check_config(&self.driver, config, sample_format, num_asio_channels)?;
+        // Only create buffers for the requested channels.
+        let channels: Vec<usize> =
+            match options.checked_device_channels(config.channels, num_asio_channels)? {
+                Some(device_channels) => device_channels.iter().map(|&c| c as usize).collect(),
+                None => (0..config.channels as usize).collect(),
+            };
+        let ref mut streams = *self.asio_streams.lock();
+        // The buffers of the driver can't be used anymore once it asked for a reset.
+        if self.driver.is_reset_requested() {
+            *streams = sys::AsioStreams {
+                input: None,
+                output: None,
+            };
+        }
 
         // Either create a stream if thers none or had back the
         // size of the current one.
         match streams.input {
-            Some(ref input) => Ok(input.buffer_size as usize),
+            Some(ref input) => {
+                requested_buffer_size(config, Some(input))?;
+                Ok(input.buffer_size as usize)
+            }
             None => {
+                let buffer_size = requested_buffer_size(config, streams.output.as_ref())?;
                 let output = streams.output.take();
                 self.driver
-                    .prepare_input_stream(output, num_channels, buffer_size)
+                    .prepare_input_stream_on_channels(output, &channels, buffer_size)
                     .map(|new_streams| {
                         let bs = match new_streams.input {
                             Some(ref inp) => inp.buffer_size as usize,
@@ -490,10 +579,7 @@ impl Device {
                         *streams = new_streams;
                         bs
                     })
-                    .map_err(|ref e| {
-                        println!("Error preparing stream: {}", e);
-                        BuildStreamError::DeviceNotAvailable
-                    })
+                    .map_err(build_stream_err)
             }
         }
     }
@@ -505,30 +591,40 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
+        options: &StreamOptions,
     ) -> Result<usize, BuildStreamError> {
-        match self.default_output_config() {
-            Ok(f) => {
-                let num_asio_channels = f.channels;
-                check_config(&self.driver, config, sample_format, num_asio_channels)
-            }
-            Err(_) => Err(BuildStreamError::StreamConfigNotSupported),
-        }?;
-        let num_channels = config.channels as usize;
-        let ref mut streams = *self.asio_streams.lock();
-
-        let buffer_size = match config.buffer_size {
-            BufferSize::Fixed(v) => Some(v as i32),
-            BufferSize::Default => None,
+        let num_asio_channels = match self.default_output_config() {
+            Ok(f) => f.channels,
+            Err(_) => return Err(BuildStreamError::StreamConfigNotSupported),
         };
+        check_config(&self.driver, config, sample_format, num_asio_channels)?;
+        // Only create buffers for the requested channels.
+        let channels: Vec<usize> =
+            match options.checked_device_channels(config.channels, num_asio_channels)? {
+                Some(device_channels) => device_channels.iter().map(|&c| c as usize).collect(),
+                None => (0..config.channels as usize).collect(),
+            };
+        let ref mut streams = *self.asio_streams.lock();
+        // The buffers of the driver can't be used anymore once it asked for a reset.
+        if self.driver.is_reset_requested() {
+            *streams = sys::AsioStreams {
+                input: None,
+                output: None,
+            };
+        }
 
         // Either create a stream if thers none or had back the
         // size of the current one.
         match streams.output {
-            Some(ref output) => Ok(output.buffer_size as usize),
+            Some(ref output) => {
+                requested_buffer_size(config, Some(output))?;
+                Ok(output.buffer_size as usize)
+            }
             None => {
+                let buffer_size = requested_buffer_size(config, streams.input.as_ref())?;
                 let input = streams.input.take();
                 self.driver
-                    .prepare_output_stream(input, num_channels, buffer_size)
+                    .prepare_output_stream_on_channels(input, &channels, buffer_size)
                     .map(|new_streams| {
                         let bs = match new_streams.output {
                             Some(ref out) => out.buffer_size as usize,
@@ -537,10 +633,7 @@ impl Device {
                         *streams = new_streams;
                         bs
                     })
-                    .map_err(|ref e| {
-                        println!("Error preparing stream: {}", e);
-                        BuildStreamError::DeviceNotAvailable
-                    })
+                    .map_err(build_stream_err)
             }
         }
     }
@@ -549,6 +642,8 @@ impl Device {
 impl Drop for Stream {
     fn drop(&mut self) {
         self.driver.remove_callback(self.callback_id);
+        self.driver
+            .remove_message_callback(self.message_callback_id);
     }
 }
 
@@ -575,6 +670,24 @@ fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Dura
     std::time::Duration::new(secs, nanos)
 }
 
+/// The buffer size to create the buffers of a stream with, `None` for the preferred size of the
+/// driver.
+///
+/// The buffers of both directions are created together, so the size of any existing stream of the
+/// device is kept.
+fn requested_buffer_size(
+    config: &StreamConfig,
+    existing: Option<&sys::AsioStream>,
+) -> Result<Option<i32>, BuildStreamError> {
+    match (config.buffer_size, existing) {
+        (BufferSize::Fixed(v), Some(existing)) if v as i32 != existing.buffer_size => {
+            Err(BuildStreamError::StreamConfigNotSupported)
+        }
+        (BufferSize::Fixed(v), _) => Ok(Some(v as i32)),
+        (BufferSize::Default, existing) => Ok(existing.map(|existing| existing.buffer_size)),
+    }
+}
+
 /// Check whether or not the desired config is supported by the stream.
 ///
 /// Checks sample rate, data type and then finally the number of channels.
@@ -674,6 +787,7 @@ fn build_stream_err(e: sys::AsioError) -> BuildStreamError {
             BuildStreamError::DeviceNotAvailable
         }
         sys::AsioError::InvalidInput | sys::AsioError::BadMode => BuildStreamError::InvalidArgument,
+        sys::AsioError::InvalidBufferSize => BuildStreamError::StreamConfigNotSupported,
         err => {
             let description = format!("{}", err);
             BackendSpecificError { description }.into()
@@ -0,0 +1,319 @@
+//! A host built on the WinRT `AudioGraph` API, for packaged UWP and Store apps where the
+//! activation of WASAPI endpoints is restricted.
+//!
+//! The audio graph converts the format of each stream to and from the format of its device, so
+//! streams are built with [`SampleFormat::F32`] samples at the rate of the graph and common
+//! channel counts.
+
+use crate::traits::{DeviceTrait, HostTrait};
+use crate::watchdog;
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate,
+    StreamConfig, StreamError, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+use std::time::Duration;
+use windows::core::HSTRING;
+use windows::Devices::Enumeration::DeviceInformation;
+use windows::Media::Audio::{AudioDeviceNodeCreationStatus, QuantumSizeSelectionMode};
+use windows::Media::Audio::{AudioGraph, AudioGraphCreationStatus, AudioGraphSettings};
+use windows::Media::Devices::{AudioDeviceRole, MediaDevice};
+use windows::Media::MediaProperties::AudioEncodingProperties;
+use windows::Media::Render::AudioRenderCategory;
+
+pub use self::stream::Stream;
+
+mod stream;
+
+pub type Devices = std::vec::IntoIter<Device>;
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+/// The AudioGraph host, available on Windows 10 and later when the `audiograph` feature is
+/// enabled.
+#[derive(Debug)]
+pub struct Host;
+
+/// An audio endpoint, as enumerated by `Windows.Devices.Enumeration`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Device {
+    info: DeviceInformation,
+    input: bool,
+}
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        // The API is part of every edition of Windows 10.
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let mut devices = Vec::new();
+        for input in [false, true] {
+            let selector = if input {
+                MediaDevice::GetAudioCaptureSelector()
+            } else {
+                MediaDevice::GetAudioRenderSelector()
+            };
+            let infos = selector
+                .and_then(|selector| DeviceInformation::FindAllAsyncAqsFilter(&selector)?.get())
+                .map_err(BackendSpecificError::from)?;
+            devices.extend(infos.into_iter().map(|info| Device { info, input }));
+        }
+        Ok(devices.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        let id = MediaDevice::GetDefaultAudioCaptureId(AudioDeviceRole::Default).ok()?;
+        Device::from_id(&id, true)
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        let id = MediaDevice::GetDefaultAudioRenderId(AudioDeviceRole::Default).ok()?;
+        Device::from_id(&id, false)
+    }
+}
+
+// The channel counts of streams other than the channel count of the graph.
+const COMMON_CHANNEL_COUNTS: [u16; 2] = [1, 2];
+
+impl Device {
+    fn from_id(id: &HSTRING, input: bool) -> Option<Self> {
+        let info = DeviceInformation::CreateFromIdAsync(id).ok()?.get().ok()?;
+        Some(Device { info, input })
+    }
+
+    /// Create an audio graph rendering to this device if it is an output device, or to the
+    /// default output device otherwise, with quanta of about `buffer_size` frames.
+    fn graph(&self, buffer_size: &BufferSize) -> Result<AudioGraph, BuildStreamError> {
+        let settings = AudioGraphSettings::Create(AudioRenderCategory::Media)
+            .map_err(BackendSpecificError::from)?;
+        if !self.input {
+            settings
+                .SetPrimaryRenderDevice(&self.info)
+                .map_err(BackendSpecificError::from)?;
+        }
+        if let BufferSize::Fixed(frames) = *buffer_size {
+            settings
+                .SetQuantumSizeSelectionMode(QuantumSizeSelectionMode::ClosestToDesired)
+                .and_then(|()| settings.SetDesiredSamplesPerQuantum(frames as i32))
+                .map_err(BackendSpecificError::from)?;
+        }
+        let result = AudioGraph::CreateAsync(&settings)
+            .and_then(|operation| operation.get())
+            .map_err(BackendSpecificError::from)?;
+        match result.Status().map_err(BackendSpecificError::from)? {
+            AudioGraphCreationStatus::Success => {
+                Ok(result.Graph().map_err(BackendSpecificError::from)?)
+            }
+            AudioGraphCreationStatus::DeviceNotAvailable => {
+                Err(BuildStreamError::DeviceNotAvailable)
+            }
+            status => {
+                let description = format!("failed to create the audio graph: {:?}", status);
+                Err(BackendSpecificError { description }.into())
+            }
+        }
+    }
+
+    // The config of the graph of this device.
+    fn graph_config(&self) -> Result<SupportedStreamConfig, BuildStreamError> {
+        let graph = self.graph(&BufferSize::Default)?;
+        let encoding = graph
+            .EncodingProperties()
+            .map_err(BackendSpecificError::from)?;
+        let config = SupportedStreamConfig {
+            channels: encoding
+                .ChannelCount()
+                .map_err(BackendSpecificError::from)? as _,
+            sample_rate: SampleRate(encoding.SampleRate().map_err(BackendSpecificError::from)?),
+            buffer_size: SupportedBufferSize::Unknown,
+            sample_format: SampleFormat::F32,
+        };
+        let _ = graph.Close();
+        Ok(config)
+    }
+
+    fn default_config(
+        &self,
+        input: bool,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if self.input != input {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        self.graph_config().map_err(|err| match err {
+            BuildStreamError::DeviceNotAvailable => DefaultStreamConfigError::DeviceNotAvailable,
+            BuildStreamError::BackendSpecific { err } => err.into(),
+            err => BackendSpecificError {
+                description: err.to_string(),
+            }
+            .into(),
+        })
+    }
+
+    fn supported_configs(
+        &self,
+        input: bool,
+    ) -> Result<std::vec::IntoIter<SupportedStreamConfigRange>, SupportedStreamConfigsError> {
+        let default_config = match self.default_config(input) {
+            Ok(config) => config,
+            Err(DefaultStreamConfigError::StreamTypeNotSupported) => {
+                return Ok(Vec::new().into_iter())
+            }
+            Err(DefaultStreamConfigError::DeviceNotAvailable) => {
+                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
+            }
+            Err(DefaultStreamConfigError::BackendSpecific { err }) => return Err(err.into()),
+        };
+        let mut channel_counts = COMMON_CHANNEL_COUNTS.to_vec();
+        if !channel_counts.contains(&default_config.channels) {
+            channel_counts.push(default_config.channels);
+        }
+        let configs: Vec<_> = channel_counts
+            .into_iter()
+            .map(|channels| SupportedStreamConfigRange {
+                channels,
+                min_sample_rate: default_config.sample_rate,
+                max_sample_rate: default_config.sample_rate,
+                buffer_size: SupportedBufferSize::Unknown,
+                sample_format: SampleFormat::F32,
+            })
+            .collect();
+        Ok(configs.into_iter())
+    }
+
+    // The format of the frames exchanged with the graph for streams with `config`.
+    fn encoding(
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<AudioEncodingProperties, BuildStreamError> {
+        if sample_format != SampleFormat::F32 {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let encoding =
+            AudioEncodingProperties::CreatePcm(config.sample_rate.0, config.channels as u32, 32)
+                .and_then(|encoding| {
+                    encoding.SetSubtype(&HSTRING::from("Float"))?;
+                    Ok(encoding)
+                })
+                .map_err(BackendSpecificError::from)?;
+        Ok(encoding)
+    }
+}
+
+impl From<AudioDeviceNodeCreationStatus> for BuildStreamError {
+    fn from(status: AudioDeviceNodeCreationStatus) -> Self {
+        match status {
+            AudioDeviceNodeCreationStatus::DeviceNotAvailable => {
+                BuildStreamError::DeviceNotAvailable
+            }
+            AudioDeviceNodeCreationStatus::FormatNotSupported => {
+                BuildStreamError::StreamConfigNotSupported
+            }
+            status => {
+                let description = format!("failed to create the device node: {:?}", status);
+                BackendSpecificError { description }.into()
+            }
+        }
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        let name = self.info.Name().map_err(BackendSpecificError::from)?;
+        Ok(name.to_string())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(true)
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        self.supported_configs(false)
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(true)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(false)
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let encoding = Device::encoding(config, sample_format)?;
+        let graph = self.graph(&config.buffer_size)?;
+        let (watchdog, data_callback, error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
+        Stream::new_input(
+            graph,
+            &self.info,
+            &encoding,
+            config,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if self.input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let encoding = Device::encoding(config, sample_format)?;
+        let graph = self.graph(&config.buffer_size)?;
+        let (watchdog, data_callback, error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+        Stream::new_output(
+            graph,
+            &encoding,
+            config,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+}
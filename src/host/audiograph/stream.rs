@@ -0,0 +1,281 @@
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat,
+    StreamConfig, StreamError, StreamInstant,
+};
+use std::ptr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use windows::core::ComInterface;
+use windows::Devices::Enumeration::DeviceInformation;
+use windows::Foundation::TypedEventHandler;
+use windows::Media::Audio::{
+    AudioDeviceNodeCreationStatus, AudioFrameInputNode, AudioGraph, AudioGraphUnrecoverableError,
+    AudioGraphUnrecoverableErrorOccurredEventArgs, FrameInputNodeQuantumStartedEventArgs,
+};
+use windows::Media::Capture::MediaCategory;
+use windows::Media::MediaProperties::AudioEncodingProperties;
+use windows::Media::{AudioBufferAccessMode, AudioFrame};
+use windows::Win32::System::WinRT::IMemoryBufferByteAccess;
+
+pub struct Stream {
+    // Owns the nodes of the stream, whose event handlers hold the callbacks, until closed.
+    graph: AudioGraph,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+}
+
+// Counts the frames exchanged by a stream to produce its stream instants, which start at zero
+// when the graph is first started.
+struct Clock {
+    frames: u64,
+    sample_rate: u32,
+    latency: Duration,
+}
+
+impl Clock {
+    fn new(graph: &AudioGraph, config: &StreamConfig) -> Result<Self, BuildStreamError> {
+        let latency = graph
+            .LatencyInSamples()
+            .map_err(BackendSpecificError::from)?;
+        let sample_rate = config.sample_rate.0;
+        Ok(Clock {
+            frames: 0,
+            sample_rate,
+            latency: Duration::from_secs_f64(latency.max(0) as f64 / sample_rate as f64),
+        })
+    }
+
+    // The instant of the first of `frames` frames, counting them as exchanged.
+    fn advance(&mut self, frames: usize) -> StreamInstant {
+        let secs = self.frames as f64 / self.sample_rate as f64;
+        self.frames += frames as u64;
+        StreamInstant::from_secs_f64(secs)
+    }
+}
+
+// Call `f` with the bytes of `frame`, locked with `mode`.
+fn with_frame_bytes<R>(
+    frame: &AudioFrame,
+    mode: AudioBufferAccessMode,
+    f: impl FnOnce(*mut u8, usize) -> R,
+) -> windows::core::Result<R> {
+    let buffer = frame.LockBuffer(mode)?;
+    let reference = buffer.CreateReference()?;
+    let byte_access = reference.cast::<IMemoryBufferByteAccess>()?;
+    let (mut bytes, mut len) = (ptr::null_mut(), 0);
+    unsafe { byte_access.GetBuffer(&mut bytes, &mut len)? };
+    Ok(f(bytes, len as usize))
+}
+
+impl Stream {
+    // Wrapped first, so that the graph is closed if connecting its nodes fails.
+    fn new(graph: AudioGraph, watchdog: Option<Watchdog>) -> Self {
+        Stream { graph, watchdog }
+    }
+
+    pub(crate) fn new_input<D, E>(
+        graph: AudioGraph,
+        device: &DeviceInformation,
+        encoding: &AudioEncodingProperties,
+        config: &StreamConfig,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let stream = Stream::new(graph, watchdog);
+        let graph = &stream.graph;
+        let error_callback = report_unrecoverable_errors(graph, error_callback)?;
+
+        let result = graph
+            .CreateDeviceInputNodeWithFormatOnDeviceAsync(MediaCategory::Media, encoding, device)
+            .and_then(|operation| operation.get())
+            .map_err(BackendSpecificError::from)?;
+        let status = result.Status().map_err(BackendSpecificError::from)?;
+        if status != AudioDeviceNodeCreationStatus::Success {
+            return Err(status.into());
+        }
+        let device_node = result
+            .DeviceInputNode()
+            .map_err(BackendSpecificError::from)?;
+        let frame_node = graph
+            .CreateFrameOutputNodeWithFormat(encoding)
+            .map_err(BackendSpecificError::from)?;
+        device_node
+            .AddOutgoingConnection(&frame_node)
+            .map_err(BackendSpecificError::from)?;
+
+        let mut clock = Clock::new(graph, config)?;
+        let channels = config.channels as usize;
+        let handler = TypedEventHandler::new(move |graph: &Option<AudioGraph>, _| {
+            let frame = frame_node.GetFrame()?;
+            let result = with_frame_bytes(&frame, AudioBufferAccessMode::Read, |bytes, len| {
+                let len = len / SampleFormat::F32.sample_size();
+                let data = unsafe { Data::from_parts(bytes as *mut (), len, SampleFormat::F32) };
+                let capture = clock.advance(len / channels);
+                // The captured frames are delivered once the graph has processed them.
+                let callback = capture.add(clock.latency).unwrap_or(capture);
+                let timestamp = InputStreamTimestamp { callback, capture };
+                data_callback(
+                    &data,
+                    &InputCallbackInfo {
+                        timestamp,
+                        transport: None,
+                    },
+                );
+            });
+            if let Err(err) = result {
+                (error_callback.lock().unwrap())(BackendSpecificError::from(err).into());
+                if let Some(graph) = graph {
+                    graph.Stop()?;
+                }
+            }
+            Ok(())
+        });
+        graph
+            .QuantumStarted(&handler)
+            .map_err(BackendSpecificError::from)?;
+        Ok(stream)
+    }
+
+    pub(crate) fn new_output<D, E>(
+        graph: AudioGraph,
+        encoding: &AudioEncodingProperties,
+        config: &StreamConfig,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let stream = Stream::new(graph, watchdog);
+        let graph = &stream.graph;
+        let error_callback = report_unrecoverable_errors(graph, error_callback)?;
+
+        let result = graph
+            .CreateDeviceOutputNodeAsync()
+            .and_then(|operation| operation.get())
+            .map_err(BackendSpecificError::from)?;
+        let status = result.Status().map_err(BackendSpecificError::from)?;
+        if status != AudioDeviceNodeCreationStatus::Success {
+            return Err(status.into());
+        }
+        let device_node = result
+            .DeviceOutputNode()
+            .map_err(BackendSpecificError::from)?;
+        let frame_node = graph
+            .CreateFrameInputNodeWithFormat(encoding)
+            .map_err(BackendSpecificError::from)?;
+        frame_node
+            .AddOutgoingConnection(&device_node)
+            .map_err(BackendSpecificError::from)?;
+
+        let mut clock = Clock::new(graph, config)?;
+        let channels = config.channels as usize;
+        let handler = TypedEventHandler::new(
+            move |node: &Option<AudioFrameInputNode>,
+                  args: &Option<FrameInputNodeQuantumStartedEventArgs>| {
+                let (node, args) = match (node, args) {
+                    (Some(node), Some(args)) => (node, args),
+                    _ => return Ok(()),
+                };
+                let frames = args.RequiredSamples()?.max(0) as usize;
+                if frames == 0 {
+                    return Ok(());
+                }
+                let len = frames * channels;
+                let frame = AudioFrame::Create((len * SampleFormat::F32.sample_size()) as u32)?;
+                let result = with_frame_bytes(&frame, AudioBufferAccessMode::Write, |bytes, _| {
+                    let mut data =
+                        unsafe { Data::from_parts(bytes as *mut (), len, SampleFormat::F32) };
+                    let callback = clock.advance(frames);
+                    let playback = callback.add(clock.latency).unwrap_or(callback);
+                    let timestamp = OutputStreamTimestamp { callback, playback };
+                    data_callback(
+                        &mut data,
+                        &OutputCallbackInfo {
+                            timestamp,
+                            transport: None,
+                        },
+                    );
+                });
+                match result {
+                    Ok(()) => node.AddFrame(&frame),
+                    Err(err) => {
+                        (error_callback.lock().unwrap())(BackendSpecificError::from(err).into());
+                        node.Stop()
+                    }
+                }
+            },
+        );
+        frame_node
+            .QuantumStarted(&handler)
+            .map_err(BackendSpecificError::from)?;
+        Ok(stream)
+    }
+}
+
+// Share `error_callback` between the event handlers of the stream, reporting the unrecoverable
+// errors of `graph`, e.g. the removal of its device, through it.
+fn report_unrecoverable_errors<E>(
+    graph: &AudioGraph,
+    error_callback: E,
+) -> Result<Arc<Mutex<E>>, BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let error_callback = Arc::new(Mutex::new(error_callback));
+    let handler_error_callback = error_callback.clone();
+    let handler = TypedEventHandler::new(
+        move |_, args: &Option<AudioGraphUnrecoverableErrorOccurredEventArgs>| {
+            let error = match args {
+                Some(args) => args.Error()?,
+                None => return Ok(()),
+            };
+            let err = if error == AudioGraphUnrecoverableError::AudioDeviceLost {
+                StreamError::DeviceNotAvailable
+            } else {
+                let description = format!("the audio graph failed: {:?}", error);
+                BackendSpecificError { description }.into()
+            };
+            (handler_error_callback.lock().unwrap())(err);
+            Ok(())
+        },
+    );
+    graph
+        .UnrecoverableErrorOccurred(&handler)
+        .map_err(BackendSpecificError::from)?;
+    Ok(error_callback)
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.graph.Close();
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.graph.Start().map_err(BackendSpecificError::from)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.graph.Stop().map_err(BackendSpecificError::from)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        Ok(())
+    }
+}
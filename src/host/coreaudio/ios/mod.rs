@@ -2,15 +2,19 @@
 //! coreaudio on iOS looks a bit different from macOS. A lot of configuration needs to use
 //! the AVAudioSession objc API which doesn't exist on macOS.
 //!
+//! tvOS and watchOS share the audio unit and audio session APIs of iOS, so they use this host as
+//! well.
+//!
 //! TODO:
-//! - Use AVAudioSession to enumerate buffer size / sample rate / number of channels and set
-//!   buffer size.
+//! - Use AVAudioSession to enumerate buffer size / sample rate / number of channels.
 //!
 
 extern crate core_foundation_sys;
 extern crate coreaudio;
 
 use std::cell::RefCell;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
 
 use self::coreaudio::audio_unit::render_callback::data;
 use self::coreaudio::audio_unit::{render_callback, AudioUnit, Element, Scope};
@@ -21,22 +25,28 @@ use self::coreaudio::sys::{
 
 use super::{asbd_from_config, frames_to_duration, host_time_to_stream_instant};
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::watchdog::{self, Watchdog, WatchdogHandle};
 
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
     DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
-    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+    PlayStreamError, SampleFormat, SampleRate, ShareMode, StreamConfig, StreamError, StreamEvent,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
 };
 
 use self::enumerate::{
     default_input_device, default_output_device, Devices, SupportedInputConfigs,
     SupportedOutputConfigs,
 };
+use self::notifications::{SessionListener, SessionNotification};
+use self::session::SessionConfig;
 use std::slice;
 use std::time::Duration;
 
 pub mod enumerate;
+mod notifications;
+pub mod session;
 
 // These days the default of iOS is now F32 and no longer I16
 const SUPPORTED_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
@@ -50,6 +60,14 @@ impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
     }
+
+    /// Configure the `AVAudioSession` of the application and activate it.
+    ///
+    /// The preferred sample rate and buffer duration are only hints, which the system may not
+    /// honour, so the configuration of the streams built afterwards should be queried again.
+    pub fn configure_session(&self, config: &SessionConfig) -> Result<(), BackendSpecificError> {
+        session::configure(config)
+    }
 }
 
 impl HostTrait for Host {
@@ -173,19 +191,53 @@ impl DeviceTrait for Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
-        mut error_callback: E,
-        _timeout: Option<Duration>,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
+        let (watchdog, mut data_callback, mut error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
+
         // The scope and element for working with a device's input stream.
         let scope = Scope::Output;
         let element = Element::Input;
 
-        let mut audio_unit = create_audio_unit()?;
+        let mut audio_unit = create_audio_unit(options.voice_processing)?;
         audio_unit.uninitialize()?;
         configure_for_recording(&mut audio_unit)?;
         audio_unit.initialize()?;
@@ -238,16 +290,31 @@ impl DeviceTrait for Device {
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
             data_callback(&data, &info);
             Ok(())
         })?;
 
         audio_unit.start()?;
+        if let Some(watchdog) = &watchdog {
+            watchdog.set_playing(true);
+        }
 
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let session_listener = listen_to_session(
+            options,
+            interrupted.clone(),
+            watchdog.as_ref().map(Watchdog::handle),
+        );
         Ok(Stream::new(StreamInner {
             playing: true,
+            interrupted,
             audio_unit,
+            watchdog,
+            _session_listener: session_listener,
         }))
     }
 
@@ -256,14 +323,45 @@ impl DeviceTrait for Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
-        mut error_callback: E,
-        _timeout: Option<Duration>,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
         match config.buffer_size {
             BufferSize::Fixed(_) => {
                 return Err(BuildStreamError::StreamConfigNotSupported);
@@ -271,7 +369,10 @@ impl DeviceTrait for Device {
             BufferSize::Default => (),
         };
 
-        let mut audio_unit = create_audio_unit()?;
+        let (watchdog, mut data_callback, mut error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+
+        let mut audio_unit = create_audio_unit(options.voice_processing)?;
 
         // The scope and element for working with a device's output stream.
         let scope = Scope::Input;
@@ -315,16 +416,31 @@ impl DeviceTrait for Device {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
 
-            let info = OutputCallbackInfo { timestamp };
+            let info = OutputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
             data_callback(&mut data, &info);
             Ok(())
         })?;
 
         audio_unit.start()?;
+        if let Some(watchdog) = &watchdog {
+            watchdog.set_playing(true);
+        }
 
+        let interrupted = Arc::new(AtomicBool::new(false));
+        let session_listener = listen_to_session(
+            options,
+            interrupted.clone(),
+            watchdog.as_ref().map(Watchdog::handle),
+        );
         Ok(Stream::new(StreamInner {
             playing: true,
+            interrupted,
             audio_unit,
+            watchdog,
+            _session_listener: session_listener,
         }))
     }
 }
@@ -345,13 +461,22 @@ impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
         let mut stream = self.inner.borrow_mut();
 
-        if !stream.playing {
+        // The system stops interrupted streams without them being paused.
+        let interrupted = stream.interrupted.swap(false, Ordering::Relaxed);
+        if !stream.playing || interrupted {
+            if interrupted {
+                // And deactivates the session.
+                session::activate()?;
+            }
             if let Err(e) = stream.audio_unit.start() {
                 let description = format!("{}", e);
                 let err = BackendSpecificError { description };
                 return Err(err.into());
             }
             stream.playing = true;
+            if let Some(watchdog) = &stream.watchdog {
+                watchdog.set_playing(true);
+            }
         }
         Ok(())
     }
@@ -367,6 +492,9 @@ impl StreamTrait for Stream {
             }
 
             stream.playing = false;
+            if let Some(watchdog) = &stream.watchdog {
+                watchdog.set_playing(false);
+            }
         }
         Ok(())
     }
@@ -374,11 +502,48 @@ impl StreamTrait for Stream {
 
 struct StreamInner {
     playing: bool,
+    // Set by the system stopping the stream for an interruption.
+    interrupted: Arc<AtomicBool>,
     audio_unit: AudioUnit,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+    // Tracks the interruptions of the stream, and reports the notifications of the audio session
+    // to the event callback, if any.
+    _session_listener: SessionListener,
+}
+
+fn listen_to_session(
+    options: &StreamOptions,
+    interrupted: Arc<AtomicBool>,
+    watchdog: Option<WatchdogHandle>,
+) -> SessionListener {
+    let event_callback = options.event_callback.clone();
+    notifications::listen(move |notification| {
+        let event = match notification {
+            SessionNotification::RouteChanged(reason) => StreamEvent::RouteChanged { reason },
+            SessionNotification::InterruptionBegan => {
+                interrupted.store(true, Ordering::Relaxed);
+                if let Some(watchdog) = &watchdog {
+                    watchdog.set_playing(false);
+                }
+                StreamEvent::Interrupted
+            }
+            SessionNotification::InterruptionEnded { should_resume } => {
+                StreamEvent::InterruptionEnded { should_resume }
+            }
+        };
+        if let Some(event_callback) = &event_callback {
+            event_callback(event);
+        }
+    })
 }
 
-fn create_audio_unit() -> Result<AudioUnit, coreaudio::Error> {
-    AudioUnit::new(coreaudio::audio_unit::IOType::RemoteIO)
+fn create_audio_unit(voice_processing: bool) -> Result<AudioUnit, coreaudio::Error> {
+    AudioUnit::new(if voice_processing {
+        coreaudio::audio_unit::IOType::VoiceProcessingIO
+    } else {
+        coreaudio::audio_unit::IOType::RemoteIO
+    })
 }
 
 fn configure_for_recording(audio_unit: &mut AudioUnit) -> Result<(), coreaudio::Error> {
@@ -404,7 +569,7 @@ fn configure_for_recording(audio_unit: &mut AudioUnit) -> Result<(), coreaudio::
 }
 
 fn default_output_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error> {
-    let audio_unit = create_audio_unit()?;
+    let audio_unit = create_audio_unit(false)?;
     let id = kAudioUnitProperty_StreamFormat;
     let asbd: AudioStreamBasicDescription =
         audio_unit.get_property(id, Scope::Output, Element::Output)?;
@@ -412,7 +577,7 @@ fn default_output_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error
 }
 
 fn default_input_asbd() -> Result<AudioStreamBasicDescription, coreaudio::Error> {
-    let mut audio_unit = create_audio_unit()?;
+    let mut audio_unit = create_audio_unit(false)?;
     audio_unit.uninitialize()?;
     configure_for_recording(&mut audio_unit)?;
     audio_unit.initialize()?;
@@ -0,0 +1,153 @@
+//! The notifications of the application's `AVAudioSession`, observed once per process and
+//! dispatched to the streams listening to them.
+
+use super::super::objc::{self, Id, Sel};
+use crate::RouteChangeReason;
+use std::ffi::c_void;
+use std::ptr::null_mut;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, Once};
+
+/// A notification of the audio session.
+#[derive(Clone, Copy, Debug)]
+pub(crate) enum SessionNotification {
+    RouteChanged(RouteChangeReason),
+    /// The system stopped the audio of the application, e.g. for a phone call.
+    InterruptionBegan,
+    InterruptionEnded {
+        should_resume: bool,
+    },
+}
+
+type Listener = Arc<dyn Fn(SessionNotification) + Send + Sync>;
+
+static LISTENERS: Mutex<Vec<(usize, Listener)>> = Mutex::new(Vec::new());
+static NEXT_LISTENER_ID: AtomicUsize = AtomicUsize::new(0);
+static OBSERVE: Once = Once::new();
+
+/// Stops calling its listener when dropped.
+pub(crate) struct SessionListener(usize);
+
+/// Call `listener` with the notifications of the audio session, on the thread posting them, until
+/// the returned [`SessionListener`] is dropped.
+pub(crate) fn listen(
+    listener: impl Fn(SessionNotification) + Send + Sync + 'static,
+) -> SessionListener {
+    OBSERVE.call_once(|| objc::autoreleasepool(|| unsafe { observe() }));
+    let id = NEXT_LISTENER_ID.fetch_add(1, Ordering::Relaxed);
+    LISTENERS.lock().unwrap().push((id, Arc::new(listener)));
+    SessionListener(id)
+}
+
+impl Drop for SessionListener {
+    fn drop(&mut self) {
+        LISTENERS.lock().unwrap().retain(|(id, _)| *id != self.0);
+    }
+}
+
+#[link(name = "AVFoundation", kind = "framework")]
+extern "C" {
+    static AVAudioSessionRouteChangeNotification: Id;
+    static AVAudioSessionRouteChangeReasonKey: Id;
+    static AVAudioSessionInterruptionNotification: Id;
+    static AVAudioSessionInterruptionTypeKey: Id;
+    static AVAudioSessionInterruptionOptionKey: Id;
+}
+
+// `AVAudioSessionRouteChangeReason`.
+const ROUTE_CHANGE_REASON_NEW_DEVICE_AVAILABLE: usize = 1;
+const ROUTE_CHANGE_REASON_OLD_DEVICE_UNAVAILABLE: usize = 2;
+const ROUTE_CHANGE_REASON_CATEGORY_CHANGE: usize = 3;
+const ROUTE_CHANGE_REASON_OVERRIDE: usize = 4;
+const ROUTE_CHANGE_REASON_WAKE_FROM_SLEEP: usize = 6;
+const ROUTE_CHANGE_REASON_NO_SUITABLE_ROUTE_FOR_CATEGORY: usize = 7;
+const ROUTE_CHANGE_REASON_ROUTE_CONFIGURATION_CHANGE: usize = 8;
+
+// `AVAudioSessionInterruptionType` and `AVAudioSessionInterruptionOptions`.
+const INTERRUPTION_TYPE_BEGAN: usize = 1;
+const INTERRUPTION_OPTION_SHOULD_RESUME: usize = 0x1;
+
+// Observe the notifications of the audio session with the default notification center, for the
+// lifetime of the process.
+unsafe fn observe() {
+    let center = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(
+        objc::class(c"NSNotificationCenter"),
+        objc::sel(c"defaultCenter"),
+    );
+    let add_observer = objc::msg_send::<unsafe extern "C" fn(Id, Sel, Id, Id, Id, Id) -> Id>();
+    add_observer(
+        center,
+        objc::sel(c"addObserverForName:object:queue:usingBlock:"),
+        AVAudioSessionRouteChangeNotification,
+        null_mut(),
+        null_mut(),
+        objc::global_block(route_changed),
+    );
+    add_observer(
+        center,
+        objc::sel(c"addObserverForName:object:queue:usingBlock:"),
+        AVAudioSessionInterruptionNotification,
+        null_mut(),
+        null_mut(),
+        objc::global_block(interrupted),
+    );
+}
+
+unsafe extern "C" fn route_changed(_block: *const c_void, notification: Id) {
+    let reason = match user_info_integer(notification, AVAudioSessionRouteChangeReasonKey) {
+        Some(ROUTE_CHANGE_REASON_NEW_DEVICE_AVAILABLE) => RouteChangeReason::NewDeviceAvailable,
+        Some(ROUTE_CHANGE_REASON_OLD_DEVICE_UNAVAILABLE) => RouteChangeReason::OldDeviceUnavailable,
+        Some(ROUTE_CHANGE_REASON_CATEGORY_CHANGE) => RouteChangeReason::CategoryChanged,
+        Some(ROUTE_CHANGE_REASON_OVERRIDE) => RouteChangeReason::Override,
+        Some(ROUTE_CHANGE_REASON_WAKE_FROM_SLEEP) => RouteChangeReason::WakeFromSleep,
+        Some(ROUTE_CHANGE_REASON_NO_SUITABLE_ROUTE_FOR_CATEGORY) => {
+            RouteChangeReason::NoSuitableRoute
+        }
+        Some(ROUTE_CHANGE_REASON_ROUTE_CONFIGURATION_CHANGE) => {
+            RouteChangeReason::ConfigurationChanged
+        }
+        _ => RouteChangeReason::Unknown,
+    };
+    dispatch(SessionNotification::RouteChanged(reason));
+}
+
+unsafe extern "C" fn interrupted(_block: *const c_void, notification: Id) {
+    let notification = match user_info_integer(notification, AVAudioSessionInterruptionTypeKey) {
+        Some(INTERRUPTION_TYPE_BEGAN) => SessionNotification::InterruptionBegan,
+        _ => {
+            let options = user_info_integer(notification, AVAudioSessionInterruptionOptionKey);
+            let should_resume = options.unwrap_or(0) & INTERRUPTION_OPTION_SHOULD_RESUME != 0;
+            SessionNotification::InterruptionEnded { should_resume }
+        }
+    };
+    dispatch(notification);
+}
+
+// The `NSNumber` stored under `key` in the user info of `notification`, as an integer.
+unsafe fn user_info_integer(notification: Id, key: Id) -> Option<usize> {
+    let get = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>();
+    let user_info = get(notification, objc::sel(c"userInfo"));
+    if user_info.is_null() {
+        return None;
+    }
+    let object_for_key = objc::msg_send::<unsafe extern "C" fn(Id, Sel, Id) -> Id>();
+    let number = object_for_key(user_info, objc::sel(c"objectForKey:"), key);
+    if number.is_null() {
+        return None;
+    }
+    let value = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> usize>();
+    Some(value(number, objc::sel(c"unsignedIntegerValue")))
+}
+
+fn dispatch(notification: SessionNotification) {
+    // Listeners may be added or dropped by the listeners themselves.
+    let listeners: Vec<Listener> = LISTENERS
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|(_, listener)| listener.clone())
+        .collect();
+    for listener in listeners {
+        listener(notification);
+    }
+}
@@ -0,0 +1,236 @@
+//! Configuration of the application's `AVAudioSession`, which decides how its audio is routed and
+//! mixed with the audio of other applications, and the rate and buffer size of the hardware.
+
+use super::super::objc::{self, Id, Sel};
+use crate::{BackendSpecificError, SampleRate};
+use std::ffi::CStr;
+use std::ptr::null_mut;
+use std::time::Duration;
+
+/// The configuration of the audio session, applied with
+/// [`Host::configure_session`](super::Host::configure_session).
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionConfig {
+    pub category: SessionCategory,
+    pub mode: SessionMode,
+    /// Mix the audio of the application with the audio of other applications rather than
+    /// interrupting them. Ignored by the categories which always mix.
+    pub mix_with_others: bool,
+    /// Lower the volume of other applications while the session is active.
+    pub duck_others: bool,
+    /// Allow recording from and playing to Bluetooth hands-free devices.
+    pub allow_bluetooth: bool,
+    /// Allow playing to Bluetooth A2DP devices, e.g. headphones, while recording.
+    pub allow_bluetooth_a2dp: bool,
+    pub allow_air_play: bool,
+    /// Play to the speaker rather than the receiver when no other route is connected, in the
+    /// [`PlayAndRecord`](SessionCategory::PlayAndRecord) category.
+    pub default_to_speaker: bool,
+    /// The sample rate to run the hardware at, if possible.
+    pub preferred_sample_rate: Option<SampleRate>,
+    /// The duration of the IO buffer of the hardware, if possible, which is about 23 ms by
+    /// default.
+    pub preferred_buffer_duration: Option<Duration>,
+}
+
+/// Whether the application plays, records or both, and how its audio is mixed with the audio of
+/// other applications, mirroring `AVAudioSessionCategory`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SessionCategory {
+    /// Playback mixed with the audio of other applications, silenced by the ringer switch and
+    /// when the screen locks.
+    Ambient,
+    /// Playback interrupting the audio of other applications, silenced by the ringer switch and
+    /// when the screen locks. The category of sessions that are not configured.
+    #[default]
+    SoloAmbient,
+    /// Playback which is not silenced by the ringer switch, e.g. of music.
+    Playback,
+    /// Recording, silencing all playback.
+    Record,
+    /// Simultaneous recording and playback, e.g. for voice calls.
+    PlayAndRecord,
+    /// Recording from and playing to several routes at once, e.g. USB and headphones.
+    MultiRoute,
+}
+
+/// The use of the audio of the application, which tunes the processing and routing of the
+/// category, mirroring `AVAudioSessionMode`.
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+pub enum SessionMode {
+    #[default]
+    Default,
+    /// Voice calls, with voice processing, in the
+    /// [`PlayAndRecord`](SessionCategory::PlayAndRecord) category.
+    VoiceChat,
+    /// Video calls, in the [`PlayAndRecord`](SessionCategory::PlayAndRecord) category.
+    VideoChat,
+    /// Voice chat in games, set by Game Kit.
+    GameChat,
+    VideoRecording,
+    /// Measuring audio, with the least processing of the signal.
+    Measurement,
+    MoviePlayback,
+    /// Long-form spoken audio, e.g. podcasts and audio books.
+    SpokenAudio,
+    /// Short spoken prompts, e.g. navigation instructions.
+    VoicePrompt,
+}
+
+#[link(name = "AVFoundation", kind = "framework")]
+extern "C" {
+    static AVAudioSessionCategoryAmbient: Id;
+    static AVAudioSessionCategorySoloAmbient: Id;
+    static AVAudioSessionCategoryPlayback: Id;
+    static AVAudioSessionCategoryRecord: Id;
+    static AVAudioSessionCategoryPlayAndRecord: Id;
+    static AVAudioSessionCategoryMultiRoute: Id;
+    static AVAudioSessionModeDefault: Id;
+    static AVAudioSessionModeVoiceChat: Id;
+    static AVAudioSessionModeVideoChat: Id;
+    static AVAudioSessionModeGameChat: Id;
+    static AVAudioSessionModeVideoRecording: Id;
+    static AVAudioSessionModeMeasurement: Id;
+    static AVAudioSessionModeMoviePlayback: Id;
+    static AVAudioSessionModeSpokenAudio: Id;
+    static AVAudioSessionModeVoicePrompt: Id;
+}
+
+// `AVAudioSessionCategoryOptions`.
+const MIX_WITH_OTHERS: usize = 0x1;
+const DUCK_OTHERS: usize = 0x2;
+const ALLOW_BLUETOOTH: usize = 0x4;
+const DEFAULT_TO_SPEAKER: usize = 0x8;
+const ALLOW_BLUETOOTH_A2DP: usize = 0x20;
+const ALLOW_AIR_PLAY: usize = 0x40;
+
+impl SessionCategory {
+    unsafe fn name(self) -> Id {
+        match self {
+            SessionCategory::Ambient => AVAudioSessionCategoryAmbient,
+            SessionCategory::SoloAmbient => AVAudioSessionCategorySoloAmbient,
+            SessionCategory::Playback => AVAudioSessionCategoryPlayback,
+            SessionCategory::Record => AVAudioSessionCategoryRecord,
+            SessionCategory::PlayAndRecord => AVAudioSessionCategoryPlayAndRecord,
+            SessionCategory::MultiRoute => AVAudioSessionCategoryMultiRoute,
+        }
+    }
+}
+
+impl SessionMode {
+    unsafe fn name(self) -> Id {
+        match self {
+            SessionMode::Default => AVAudioSessionModeDefault,
+            SessionMode::VoiceChat => AVAudioSessionModeVoiceChat,
+            SessionMode::VideoChat => AVAudioSessionModeVideoChat,
+            SessionMode::GameChat => AVAudioSessionModeGameChat,
+            SessionMode::VideoRecording => AVAudioSessionModeVideoRecording,
+            SessionMode::Measurement => AVAudioSessionModeMeasurement,
+            SessionMode::MoviePlayback => AVAudioSessionModeMoviePlayback,
+            SessionMode::SpokenAudio => AVAudioSessionModeSpokenAudio,
+            SessionMode::VoicePrompt => AVAudioSessionModeVoicePrompt,
+        }
+    }
+}
+
+/// Apply `config` to the shared audio session and activate it.
+pub(crate) fn configure(config: &SessionConfig) -> Result<(), BackendSpecificError> {
+    objc::autoreleasepool(|| unsafe {
+        let session = shared_session();
+        let options = [
+            (config.mix_with_others, MIX_WITH_OTHERS),
+            (config.duck_others, DUCK_OTHERS),
+            (config.allow_bluetooth, ALLOW_BLUETOOTH),
+            (config.default_to_speaker, DEFAULT_TO_SPEAKER),
+            (config.allow_bluetooth_a2dp, ALLOW_BLUETOOTH_A2DP),
+            (config.allow_air_play, ALLOW_AIR_PLAY),
+        ]
+        .iter()
+        .filter(|(enabled, _)| *enabled)
+        .fold(0, |options, (_, option)| options | option);
+
+        let mut error: Id = null_mut();
+        let set_category =
+            objc::msg_send::<unsafe extern "C" fn(Id, Sel, Id, Id, usize, *mut Id) -> bool>();
+        if !set_category(
+            session,
+            objc::sel(c"setCategory:mode:options:error:"),
+            config.category.name(),
+            config.mode.name(),
+            options,
+            &mut error,
+        ) {
+            return Err(session_error("failed to set the category", error));
+        }
+
+        let set_preference =
+            objc::msg_send::<unsafe extern "C" fn(Id, Sel, f64, *mut Id) -> bool>();
+        if let Some(sample_rate) = config.preferred_sample_rate {
+            if !set_preference(
+                session,
+                objc::sel(c"setPreferredSampleRate:error:"),
+                sample_rate.0 as f64,
+                &mut error,
+            ) {
+                return Err(session_error("failed to set the sample rate", error));
+            }
+        }
+        if let Some(duration) = config.preferred_buffer_duration {
+            if !set_preference(
+                session,
+                objc::sel(c"setPreferredIOBufferDuration:error:"),
+                duration.as_secs_f64(),
+                &mut error,
+            ) {
+                return Err(session_error("failed to set the buffer duration", error));
+            }
+        }
+
+        set_active(session)
+    })
+}
+
+/// Activate the shared audio session, which the system deactivates when interrupting it.
+pub(crate) fn activate() -> Result<(), BackendSpecificError> {
+    objc::autoreleasepool(|| unsafe { set_active(shared_session()) })
+}
+
+unsafe fn shared_session() -> Id {
+    objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(
+        objc::class(c"AVAudioSession"),
+        objc::sel(c"sharedInstance"),
+    )
+}
+
+unsafe fn set_active(session: Id) -> Result<(), BackendSpecificError> {
+    let mut error: Id = null_mut();
+    let set_active = objc::msg_send::<unsafe extern "C" fn(Id, Sel, bool, *mut Id) -> bool>();
+    if !set_active(session, objc::sel(c"setActive:error:"), true, &mut error) {
+        return Err(session_error("failed to activate the session", error));
+    }
+    Ok(())
+}
+
+// The error reported by the session, described by the `NSError` `error`.
+unsafe fn session_error(context: &str, error: Id) -> BackendSpecificError {
+    let description = if error.is_null() {
+        context.to_string()
+    } else {
+        let localized_description = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(
+            error,
+            objc::sel(c"localizedDescription"),
+        );
+        let utf8 = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> *const std::os::raw::c_char>()(
+            localized_description,
+            objc::sel(c"UTF8String"),
+        );
+        if utf8.is_null() {
+            context.to_string()
+        } else {
+            format!("{}: {}", context, CStr::from_ptr(utf8).to_string_lossy())
+        }
+    };
+    BackendSpecificError {
+        description: format!("AVAudioSession {}", description),
+    }
+}
@@ -0,0 +1,60 @@
+//! Activities of the process, which keep the system from sleeping while idle and from throttling
+//! the process with App Nap.
+
+use super::super::objc::{self, Id, Sel};
+use super::cf::CfObject;
+
+/// An activity begun with `NSProcessInfo`, ended when dropped.
+#[derive(Debug)]
+pub(crate) struct Activity(Id);
+
+// `NSProcessInfo` can begin and end activities on any thread.
+unsafe impl Send for Activity {}
+
+// `NSActivityOptions`: user initiated, which includes keeping the system from sleeping while idle,
+// and latency critical, which keeps timers and threads from being coalesced.
+const ACTIVITY_USER_INITIATED: u64 = 0x00FF_FFFF;
+const ACTIVITY_LATENCY_CRITICAL: u64 = 0xFF_0000_0000;
+
+impl Activity {
+    /// Begin an activity, `reason` being shown to the user, e.g. by `pmset -g assertions`.
+    pub(crate) fn begin(reason: &str) -> Option<Self> {
+        objc::autoreleasepool(|| unsafe {
+            let process_info = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(
+                objc::class(c"NSProcessInfo"),
+                objc::sel(c"processInfo"),
+            );
+            // Strings are toll-free bridged to `NSString`.
+            let reason = CfObject::string(reason);
+            let activity = objc::msg_send::<unsafe extern "C" fn(Id, Sel, u64, Id) -> Id>()(
+                process_info,
+                objc::sel(c"beginActivityWithOptions:reason:"),
+                ACTIVITY_USER_INITIATED | ACTIVITY_LATENCY_CRITICAL,
+                reason.as_ptr() as Id,
+            );
+            if activity.is_null() {
+                return None;
+            }
+            // The activity is autoreleased.
+            objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(activity, objc::sel(c"retain"));
+            Some(Activity(activity))
+        })
+    }
+}
+
+impl Drop for Activity {
+    fn drop(&mut self) {
+        objc::autoreleasepool(|| unsafe {
+            let process_info = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>()(
+                objc::class(c"NSProcessInfo"),
+                objc::sel(c"processInfo"),
+            );
+            objc::msg_send::<unsafe extern "C" fn(Id, Sel, Id)>()(
+                process_info,
+                objc::sel(c"endActivity:"),
+                self.0,
+            );
+            objc::msg_send::<unsafe extern "C" fn(Id, Sel)>()(self.0, objc::sel(c"release"));
+        })
+    }
+}
@@ -0,0 +1,119 @@
+//! Creation of aggregate devices, which combine the channels of several devices into a single
+//! device, as Audio MIDI Setup does.
+
+use super::cf::CfObject;
+use super::coreaudio::sys::{
+    AudioHardwareCreateAggregateDevice, AudioHardwareDestroyAggregateDevice, AudioObjectID,
+};
+use super::{check_os_status, device_uid, Device};
+use crate::BackendSpecificError;
+
+/// An aggregate device to create with
+/// [`Device::create_aggregate`](super::Device::create_aggregate).
+#[derive(Clone, Debug, Default)]
+pub struct AggregateDeviceConfig {
+    /// The name of the device, as shown by Audio MIDI Setup.
+    pub name: String,
+    /// The devices whose channels are combined, in this order. The first device clocks the
+    /// aggregate device.
+    pub sub_devices: Vec<Device>,
+    /// Resample the other devices to the clock of the first device, which is required unless all
+    /// devices are driven by the same word clock.
+    pub drift_compensation: bool,
+    /// Play the same channels on every device, as a multi-output device does, instead of
+    /// combining their channels.
+    pub stacked: bool,
+    /// Only show the device to this process, which destroys it when exiting. Other aggregate
+    /// devices persist until destroyed with
+    /// [`Device::destroy_aggregate`](super::Device::destroy_aggregate).
+    pub private: bool,
+}
+
+// The keys of the descriptions of aggregate devices, sub-devices and sub-taps.
+const AGGREGATE_NAME_KEY: &str = "name";
+const AGGREGATE_UID_KEY: &str = "uid";
+const AGGREGATE_MAIN_SUB_DEVICE_KEY: &str = "master";
+const AGGREGATE_IS_PRIVATE_KEY: &str = "private";
+const AGGREGATE_IS_STACKED_KEY: &str = "stacked";
+const AGGREGATE_TAP_AUTO_START_KEY: &str = "tapautostart";
+const AGGREGATE_SUB_DEVICE_LIST_KEY: &str = "subdevices";
+const AGGREGATE_TAP_LIST_KEY: &str = "taps";
+const SUB_DEVICE_UID_KEY: &str = "uid";
+const SUB_DEVICE_DRIFT_COMPENSATION_KEY: &str = "drift";
+const SUB_TAP_UID_KEY: &str = "uid";
+const SUB_TAP_DRIFT_COMPENSATION_KEY: &str = "drift";
+
+/// Create the aggregate device described by `config`, also recording the process taps with the
+/// UIDs `tap_uids`.
+pub(crate) fn create(
+    config: &AggregateDeviceConfig,
+    tap_uids: &[CfObject],
+) -> Result<AudioObjectID, BackendSpecificError> {
+    let sub_device_uids = config
+        .sub_devices
+        .iter()
+        .map(|device| device_uid(device.audio_device_id))
+        .collect::<Result<Vec<_>, _>>()?;
+    let main_uid = sub_device_uids.first().ok_or_else(|| {
+        let description = "an aggregate device requires at least one sub-device".to_string();
+        BackendSpecificError { description }
+    })?;
+    let drift_compensation = CfObject::number(config.drift_compensation as i32);
+    let sub_devices: Vec<CfObject> = sub_device_uids
+        .iter()
+        .enumerate()
+        .map(|(i, uid)| {
+            // The clock device is never resampled.
+            let drift_compensation = CfObject::number((i > 0 && config.drift_compensation) as i32);
+            CfObject::dictionary(&[
+                (SUB_DEVICE_UID_KEY, uid),
+                (SUB_DEVICE_DRIFT_COMPENSATION_KEY, &drift_compensation),
+            ])
+        })
+        .collect();
+    let sub_taps: Vec<CfObject> = tap_uids
+        .iter()
+        .map(|uid| {
+            CfObject::dictionary(&[
+                (SUB_TAP_UID_KEY, uid),
+                (SUB_TAP_DRIFT_COMPENSATION_KEY, &drift_compensation),
+            ])
+        })
+        .collect();
+
+    let description = CfObject::dictionary(&[
+        (AGGREGATE_NAME_KEY, &CfObject::string(&config.name)),
+        (AGGREGATE_UID_KEY, &CfObject::uuid_string()),
+        (AGGREGATE_MAIN_SUB_DEVICE_KEY, main_uid),
+        (
+            AGGREGATE_IS_PRIVATE_KEY,
+            &CfObject::number(config.private as i32),
+        ),
+        (
+            AGGREGATE_IS_STACKED_KEY,
+            &CfObject::number(config.stacked as i32),
+        ),
+        (
+            AGGREGATE_TAP_AUTO_START_KEY,
+            &CfObject::number(!tap_uids.is_empty() as i32),
+        ),
+        (
+            AGGREGATE_SUB_DEVICE_LIST_KEY,
+            &CfObject::array(&sub_devices.iter().collect::<Vec<_>>()),
+        ),
+        (
+            AGGREGATE_TAP_LIST_KEY,
+            &CfObject::array(&sub_taps.iter().collect::<Vec<_>>()),
+        ),
+    ]);
+    let mut aggregate_id: AudioObjectID = 0;
+    let status =
+        unsafe { AudioHardwareCreateAggregateDevice(description.as_ptr() as _, &mut aggregate_id) };
+    check_os_status(status)?;
+    Ok(aggregate_id)
+}
+
+/// Destroy the aggregate device `aggregate_id`.
+pub(crate) fn destroy(aggregate_id: AudioObjectID) -> Result<(), BackendSpecificError> {
+    check_os_status(unsafe { AudioHardwareDestroyAggregateDevice(aggregate_id) })
+}
@@ -0,0 +1,126 @@
+//! Owned Core Foundation objects, for the dictionaries through which devices are created and the
+//! strings identifying them.
+
+use super::core_foundation_sys::array::{kCFTypeArrayCallBacks, CFArrayCreate};
+use super::core_foundation_sys::base::{kCFAllocatorDefault, CFIndex, CFRelease, CFTypeRef};
+use super::core_foundation_sys::dictionary::{
+    kCFTypeDictionaryKeyCallBacks, kCFTypeDictionaryValueCallBacks, CFDictionaryCreate,
+};
+use super::core_foundation_sys::number::{kCFNumberSInt32Type, CFNumberCreate};
+use super::core_foundation_sys::string::{
+    kCFStringEncodingUTF8, CFStringCreateWithBytes, CFStringGetCString, CFStringGetLength,
+    CFStringGetMaximumSizeForEncoding, CFStringRef,
+};
+use super::core_foundation_sys::uuid::{CFUUIDCreate, CFUUIDCreateString};
+use std::ffi::CStr;
+use std::os::raw::c_char;
+
+/// A Core Foundation object owned by CPAL, released when dropped.
+#[derive(Debug)]
+pub struct CfObject(CFTypeRef);
+
+// The objects created by CPAL are immutable, which Core Foundation allows to share between threads.
+unsafe impl Send for CfObject {}
+unsafe impl Sync for CfObject {}
+
+impl CfObject {
+    /// Take ownership of `object`, returned by a function following the create rule, or `None` if
+    /// it is null.
+    pub unsafe fn from_created(object: CFTypeRef) -> Option<Self> {
+        (!object.is_null()).then_some(CfObject(object))
+    }
+
+    // Core Foundation only fails to create small objects when out of memory.
+    unsafe fn created(object: CFTypeRef) -> Self {
+        Self::from_created(object).expect("failed to create a Core Foundation object")
+    }
+
+    pub fn string(string: &str) -> Self {
+        unsafe {
+            Self::created(CFStringCreateWithBytes(
+                kCFAllocatorDefault,
+                string.as_ptr(),
+                string.len() as CFIndex,
+                kCFStringEncodingUTF8,
+                false as _,
+            ) as CFTypeRef)
+        }
+    }
+
+    pub fn number(number: i32) -> Self {
+        unsafe {
+            Self::created(CFNumberCreate(
+                kCFAllocatorDefault,
+                kCFNumberSInt32Type,
+                &number as *const i32 as *const _,
+            ) as CFTypeRef)
+        }
+    }
+
+    pub fn array(values: &[&CfObject]) -> Self {
+        let values: Vec<CFTypeRef> = values.iter().map(|value| value.0).collect();
+        unsafe {
+            Self::created(CFArrayCreate(
+                kCFAllocatorDefault,
+                values.as_ptr(),
+                values.len() as CFIndex,
+                &kCFTypeArrayCallBacks,
+            ) as CFTypeRef)
+        }
+    }
+
+    /// A dictionary with string keys.
+    pub fn dictionary(entries: &[(&str, &CfObject)]) -> Self {
+        let keys: Vec<CfObject> = entries.iter().map(|(key, _)| Self::string(key)).collect();
+        let keys: Vec<CFTypeRef> = keys.iter().map(|key| key.0).collect();
+        let values: Vec<CFTypeRef> = entries.iter().map(|(_, value)| value.0).collect();
+        unsafe {
+            Self::created(CFDictionaryCreate(
+                kCFAllocatorDefault,
+                keys.as_ptr(),
+                values.as_ptr(),
+                entries.len() as CFIndex,
+                &kCFTypeDictionaryKeyCallBacks,
+                &kCFTypeDictionaryValueCallBacks,
+            ) as CFTypeRef)
+        }
+    }
+
+    /// A newly generated UUID, as a string.
+    pub fn uuid_string() -> Self {
+        unsafe {
+            let uuid = Self::created(CFUUIDCreate(kCFAllocatorDefault) as CFTypeRef);
+            Self::created(CFUUIDCreateString(kCFAllocatorDefault, uuid.0 as _) as CFTypeRef)
+        }
+    }
+
+    pub fn as_ptr(&self) -> CFTypeRef {
+        self.0
+    }
+
+    /// The contents of the object if it is a string.
+    pub fn to_string(&self) -> Option<String> {
+        unsafe {
+            let string = self.0 as CFStringRef;
+            let len =
+                CFStringGetMaximumSizeForEncoding(CFStringGetLength(string), kCFStringEncodingUTF8);
+            let mut buf = vec![0 as c_char; len as usize + 1];
+            if CFStringGetCString(
+                string,
+                buf.as_mut_ptr(),
+                buf.len() as CFIndex,
+                kCFStringEncodingUTF8,
+            ) == 0
+            {
+                return None;
+            }
+            Some(CStr::from_ptr(buf.as_ptr()).to_string_lossy().into_owned())
+        }
+    }
+}
+
+impl Drop for CfObject {
+    fn drop(&mut self) {
+        unsafe { CFRelease(self.0) }
+    }
+}
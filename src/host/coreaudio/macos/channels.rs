@@ -0,0 +1,192 @@
+//! The names and roles of the channels of devices, as shown by Audio MIDI Setup.
+
+use super::cf::CfObject;
+use super::coreaudio::sys::{
+    kAudioChannelLayoutTag_UseChannelDescriptions, kAudioDevicePropertyPreferredChannelLayout,
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyElementName, AudioChannelDescription,
+    AudioChannelLayout, AudioDeviceID, AudioObjectGetPropertyData, AudioObjectGetPropertyDataSize,
+    AudioObjectPropertyAddress, AudioObjectPropertyScope,
+};
+use crate::ChannelCount;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::null;
+use std::slice;
+
+/// A channel of a device, retrieved with
+/// [`Device::input_channels`](super::Device::input_channels) or
+/// [`Device::output_channels`](super::Device::output_channels).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub struct ChannelInfo {
+    /// The name of the channel given by the driver, e.g. "Mic 1 / DI 2", if any.
+    pub name: Option<String>,
+    /// The role of the channel in the preferred channel layout of the device, if the device has
+    /// one.
+    pub label: Option<ChannelLabel>,
+}
+
+/// The role of a channel in a channel layout, mirroring `AudioChannelLabel`.
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ChannelLabel {
+    /// The channel is not used, e.g. until the user assigns it in Audio MIDI Setup.
+    Unused,
+    Left,
+    Right,
+    Center,
+    LowFrequencyEffects,
+    LeftSurround,
+    RightSurround,
+    LeftCenter,
+    RightCenter,
+    CenterSurround,
+    LeftSideSurround,
+    RightSideSurround,
+    LeftRearSurround,
+    RightRearSurround,
+    Mono,
+    /// A channel with no spatial role, with the given index.
+    Discrete(u16),
+    /// Another `AudioChannelLabel`.
+    Other(u32),
+}
+
+// `AudioChannelLabel`s.
+const LABEL_UNKNOWN: u32 = 0xFFFF_FFFF;
+const LABEL_UNUSED: u32 = 0;
+const LABEL_LEFT: u32 = 1;
+const LABEL_RIGHT: u32 = 2;
+const LABEL_CENTER: u32 = 3;
+const LABEL_LFE_SCREEN: u32 = 4;
+const LABEL_LEFT_SURROUND: u32 = 5;
+const LABEL_RIGHT_SURROUND: u32 = 6;
+const LABEL_LEFT_CENTER: u32 = 7;
+const LABEL_RIGHT_CENTER: u32 = 8;
+const LABEL_CENTER_SURROUND: u32 = 9;
+const LABEL_LEFT_SURROUND_DIRECT: u32 = 10;
+const LABEL_RIGHT_SURROUND_DIRECT: u32 = 11;
+const LABEL_REAR_SURROUND_LEFT: u32 = 33;
+const LABEL_REAR_SURROUND_RIGHT: u32 = 34;
+const LABEL_MONO: u32 = 42;
+const LABEL_DISCRETE: u32 = 1 << 16;
+
+impl ChannelLabel {
+    fn from_label(label: u32) -> Option<Self> {
+        Some(match label {
+            LABEL_UNKNOWN => return None,
+            LABEL_UNUSED => ChannelLabel::Unused,
+            LABEL_LEFT => ChannelLabel::Left,
+            LABEL_RIGHT => ChannelLabel::Right,
+            LABEL_CENTER => ChannelLabel::Center,
+            LABEL_LFE_SCREEN => ChannelLabel::LowFrequencyEffects,
+            LABEL_LEFT_SURROUND => ChannelLabel::LeftSurround,
+            LABEL_RIGHT_SURROUND => ChannelLabel::RightSurround,
+            LABEL_LEFT_CENTER => ChannelLabel::LeftCenter,
+            LABEL_RIGHT_CENTER => ChannelLabel::RightCenter,
+            LABEL_CENTER_SURROUND => ChannelLabel::CenterSurround,
+            LABEL_LEFT_SURROUND_DIRECT => ChannelLabel::LeftSideSurround,
+            LABEL_RIGHT_SURROUND_DIRECT => ChannelLabel::RightSideSurround,
+            LABEL_REAR_SURROUND_LEFT => ChannelLabel::LeftRearSurround,
+            LABEL_REAR_SURROUND_RIGHT => ChannelLabel::RightRearSurround,
+            LABEL_MONO => ChannelLabel::Mono,
+            label if label & !0xFFFF == LABEL_DISCRETE => ChannelLabel::Discrete(label as u16),
+            label => ChannelLabel::Other(label),
+        })
+    }
+}
+
+/// The `n_channels` channels of the device in `scope`.
+pub(crate) fn channels(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+    n_channels: ChannelCount,
+) -> Vec<ChannelInfo> {
+    let mut labels = preferred_layout(device_id, scope).into_iter();
+    // The elements of the channels start at 1, the main element being 0.
+    (1..=n_channels as u32)
+        .map(|element| ChannelInfo {
+            name: element_name(device_id, scope, element),
+            label: labels.next().flatten(),
+        })
+        .collect()
+}
+
+fn element_name(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+    element: u32,
+) -> Option<String> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioObjectPropertyElementName,
+        mScope: scope,
+        mElement: element,
+    };
+    let name: *const c_void = null();
+    let data_size = mem::size_of::<*const c_void>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &name as *const _ as *mut _,
+        )
+    };
+    if status != 0 {
+        return None;
+    }
+    let name = unsafe { CfObject::from_created(name) }?.to_string()?;
+    (!name.is_empty()).then_some(name)
+}
+
+// The labels of the channels in the preferred layout of the device, which is empty if the device
+// only describes its layout with a tag.
+#[allow(clippy::cast_ptr_alignment)]
+fn preferred_layout(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+) -> Vec<Option<ChannelLabel>> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyPreferredChannelLayout,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    unsafe {
+        let data_size = 0u32;
+        let status = AudioObjectGetPropertyDataSize(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+        );
+        if status != 0 || (data_size as usize) < mem::size_of::<AudioChannelLayout>() {
+            return vec![];
+        }
+        // Aligned for the layout, whose size depends on its number of descriptions.
+        let mut layout: Vec<u64> = vec![0; (data_size as usize).div_ceil(mem::size_of::<u64>())];
+        let status = AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            layout.as_mut_ptr() as *mut _,
+        );
+        let layout = layout.as_ptr() as *const AudioChannelLayout;
+        if status != 0
+            || (*layout).mChannelLayoutTag != kAudioChannelLayoutTag_UseChannelDescriptions
+        {
+            return vec![];
+        }
+        let descriptions: &[AudioChannelDescription] = slice::from_raw_parts(
+            (*layout).mChannelDescriptions.as_ptr(),
+            (*layout).mNumberChannelDescriptions as usize,
+        );
+        descriptions
+            .iter()
+            .map(|description| ChannelLabel::from_label(description.mChannelLabel))
+            .collect()
+    }
+}
@@ -0,0 +1,20 @@
+//! Lookup of the functions of newer systems at runtime, so that applications built with them keep
+//! running on older systems.
+
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::os::raw::c_char;
+
+// The pseudo-handle searching every image loaded by the process.
+const RTLD_DEFAULT: *mut c_void = -2isize as *mut c_void;
+
+extern "C" {
+    fn dlsym(handle: *mut c_void, symbol: *const c_char) -> *mut c_void;
+}
+
+/// The function named `name`, cast to the function pointer type `F`, if the running system has
+/// it.
+pub unsafe fn function<F>(name: &CStr) -> Option<F> {
+    let function = dlsym(RTLD_DEFAULT, name.as_ptr());
+    (!function.is_null()).then(|| mem::transmute_copy(&function))
+}
@@ -9,23 +9,32 @@ use self::coreaudio::audio_unit::{AudioUnit, Element, Scope};
 use self::coreaudio::sys::{
     kAudioDevicePropertyAvailableNominalSampleRates, kAudioDevicePropertyBufferFrameSize,
     kAudioDevicePropertyBufferFrameSizeRange, kAudioDevicePropertyDeviceIsAlive,
-    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyNominalSampleRate,
+    kAudioDevicePropertyDeviceNameCFString, kAudioDevicePropertyDeviceUID,
+    kAudioDevicePropertyHogMode, kAudioDevicePropertyLatency,
+    kAudioDevicePropertyNominalSampleRate, kAudioDevicePropertySafetyOffset,
     kAudioDevicePropertyScopeOutput, kAudioDevicePropertyStreamConfiguration,
-    kAudioDevicePropertyStreamFormat, kAudioObjectPropertyElementMaster,
+    kAudioDevicePropertyStreamFormat, kAudioDevicePropertyStreams, kAudioFormatFlagIsFloat,
+    kAudioFormatFlagIsSignedInteger, kAudioFormatLinearPCM, kAudioObjectPropertyElementMaster,
     kAudioObjectPropertyScopeGlobal, kAudioObjectPropertyScopeInput,
-    kAudioObjectPropertyScopeOutput, kAudioOutputUnitProperty_CurrentDevice,
-    kAudioOutputUnitProperty_EnableIO, kAudioUnitProperty_StreamFormat, kCFStringEncodingUTF8,
+    kAudioObjectPropertyScopeOutput, kAudioOutputUnitProperty_ChannelMap,
+    kAudioOutputUnitProperty_CurrentDevice, kAudioOutputUnitProperty_EnableIO,
+    kAudioStreamPropertyAvailablePhysicalFormats, kAudioStreamPropertyLatency,
+    kAudioStreamPropertyPhysicalFormat, kAudioUnitProperty_StreamFormat, kCFStringEncodingUTF8,
     AudioBuffer, AudioBufferList, AudioDeviceID, AudioObjectGetPropertyData,
     AudioObjectGetPropertyDataSize, AudioObjectID, AudioObjectPropertyAddress,
     AudioObjectPropertyScope, AudioObjectSetPropertyData, AudioStreamBasicDescription,
-    AudioValueRange, OSStatus,
+    AudioStreamID, AudioStreamRangedDescription, AudioValueRange, OSStatus,
 };
+use crate::channel_subset;
+use crate::dsd;
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::watchdog::{self, Watchdog};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, ChannelCount, Data,
-    DefaultStreamConfigError, DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo,
-    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    DefaultStreamConfigError, DeviceNameError, DevicesError, DisconnectReason, DsdMode,
+    HardwareFormat, InputCallbackInfo, OutputCallbackInfo, PauseStreamError, PlayStreamError,
+    SampleFormat, SampleRate, ShareMode, SignalPath, StreamConfig, StreamError, StreamEvent,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
     SupportedStreamConfigsError,
 };
 use parking_lot::Mutex;
@@ -36,18 +45,32 @@ use std::os::raw::c_char;
 use std::ptr::null;
 use std::slice;
 use std::sync::mpsc::{channel, RecvTimeoutError};
-use std::sync::Arc;
+use std::sync::{Arc, Weak};
 use std::time::{Duration, Instant};
 
+pub use self::aggregate::AggregateDeviceConfig;
+pub use self::channels::{ChannelInfo, ChannelLabel};
 pub use self::enumerate::{
     default_input_device, default_output_device, Devices, SupportedInputConfigs,
     SupportedOutputConfigs,
 };
+pub use self::tap::TapProcesses;
+pub use self::workgroup::{Workgroup, WorkgroupMembership};
 
+use self::activity::Activity;
+use self::cf::CfObject;
+use self::tap::ProcessTap;
 use property_listener::AudioObjectPropertyListener;
 
+mod activity;
+mod aggregate;
+mod cf;
+mod channels;
+mod dl;
 pub mod enumerate;
 mod property_listener;
+mod tap;
+mod workgroup;
 
 /// Coreaudio host, the default host on macOS.
 #[derive(Debug)]
@@ -126,6 +149,62 @@ impl DeviceTrait for Device {
             self,
             config,
             sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.device_channels.is_some() {
+            let device_channel_count = self.channel_count(kAudioObjectPropertyScopeInput)?;
+            let device_channels =
+                options.checked_device_channels(config.channels, device_channel_count)?;
+            // The voice processing unit doesn't take a channel map and longer maps can't be set,
+            // so the selected channels are copied from the buffers of all of the device's instead.
+            let copied = options.voice_processing || config.channels as usize > MAX_CHANNEL_MAP;
+            if let Some(device_channels) = device_channels.filter(|_| copied) {
+                let data_callback = channel_subset::select_input_channels(
+                    device_channels,
+                    device_channel_count,
+                    sample_format,
+                    data_callback,
+                );
+                return Device::build_input_stream_raw(
+                    self,
+                    &StreamConfig {
+                        channels: device_channel_count,
+                        ..config.clone()
+                    },
+                    sample_format,
+                    &StreamOptions {
+                        device_channels: None,
+                        ..options.clone()
+                    },
+                    data_callback,
+                    error_callback,
+                    timeout,
+                );
+            }
+        }
+        Device::build_input_stream_raw(
+            self,
+            config,
+            sample_format,
+            options,
             data_callback,
             error_callback,
             timeout,
@@ -148,6 +227,74 @@ impl DeviceTrait for Device {
             self,
             config,
             sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if options.device_channels.is_some() {
+            let device_channel_count = self.channel_count(kAudioObjectPropertyScopeOutput)?;
+            let device_channels =
+                options.checked_device_channels(config.channels, device_channel_count)?;
+            // The voice processing unit doesn't take a channel map and longer maps can't be set,
+            // so the selected channels are copied from the buffers of all of the device's instead.
+            let copied =
+                options.voice_processing || device_channel_count as usize > MAX_CHANNEL_MAP;
+            if let Some(device_channels) = device_channels.filter(|_| copied) {
+                let data_callback = channel_subset::select_output_channels(
+                    device_channels,
+                    device_channel_count,
+                    sample_format,
+                    data_callback,
+                );
+                return Device::build_output_stream_raw(
+                    self,
+                    &StreamConfig {
+                        channels: device_channel_count,
+                        ..config.clone()
+                    },
+                    sample_format,
+                    &StreamOptions {
+                        device_channels: None,
+                        ..options.clone()
+                    },
+                    data_callback,
+                    error_callback,
+                    timeout,
+                );
+            }
+        }
+        Device::build_output_stream_raw(
+            self,
+            config,
+            sample_format,
+            options,
             data_callback,
             error_callback,
             timeout,
@@ -159,9 +306,128 @@ impl DeviceTrait for Device {
 pub struct Device {
     pub(crate) audio_device_id: AudioDeviceID,
     is_default: bool,
+    // The process tap recorded by the device, if it was created with `Device::tap`.
+    tap: Option<Arc<ProcessTap>>,
 }
 
 impl Device {
+    /// Create a device recording the audio played by `processes`, mixed down to stereo, through a
+    /// Core Audio process tap, e.g. to capture the system output.
+    ///
+    /// Process taps are available since macOS 14.2, and the user is asked for permission to
+    /// record other applications the first time a stream is built, which requires the
+    /// `NSAudioCaptureUsageDescription` key in the `Info.plist` of the application. On older
+    /// systems, other processes can only be recorded through a loopback driver such as BlackHole,
+    /// by playing to it, or to an aggregate device combining it with the speakers, and building
+    /// input streams on it.
+    ///
+    /// Only input streams can be built on the device, which is private to this process, and the
+    /// tap is destroyed once the device and its streams have been dropped.
+    pub fn tap(processes: &TapProcesses) -> Result<Self, BackendSpecificError> {
+        let tap = ProcessTap::new(processes)?;
+        Ok(Device {
+            audio_device_id: tap.aggregate_id,
+            is_default: false,
+            tap: Some(Arc::new(tap)),
+        })
+    }
+
+    /// Create an aggregate device combining the channels of several devices, e.g. to record and
+    /// play through several interfaces in sync.
+    ///
+    /// The device may take a moment to appear in the devices of the host. Unless it is private,
+    /// it persists after this process exits, until destroyed with
+    /// [`Device::destroy_aggregate`].
+    pub fn create_aggregate(config: &AggregateDeviceConfig) -> Result<Self, BackendSpecificError> {
+        Ok(Device {
+            audio_device_id: aggregate::create(config, &[])?,
+            is_default: false,
+            tap: None,
+        })
+    }
+
+    /// Destroy the aggregate device, which may have been created by another process or in Audio
+    /// MIDI Setup. Fails if the device is not an aggregate device.
+    pub fn destroy_aggregate(self) -> Result<(), BackendSpecificError> {
+        if self.tap.is_some() {
+            let description = "process tap devices are destroyed when dropped".to_string();
+            return Err(BackendSpecificError { description });
+        }
+        aggregate::destroy(self.audio_device_id)
+    }
+
+    /// The UID of the device, which is unique and persists across reboots, unlike its name and
+    /// `AudioDeviceID`, so that a device can be remembered and found again with
+    /// [`Device::from_uid`].
+    pub fn uid(&self) -> Result<String, BackendSpecificError> {
+        device_uid(self.audio_device_id)?
+            .to_string()
+            .ok_or_else(|| {
+                let description = "the UID of the device is not a string".to_string();
+                BackendSpecificError { description }
+            })
+    }
+
+    /// The device with the UID `uid`, or `None` if no such device is connected.
+    pub fn from_uid(uid: &str) -> Option<Self> {
+        Devices::new()
+            .ok()?
+            .find(|device| device.uid().is_ok_and(|device_uid| device_uid == uid))
+    }
+
+    /// Switch the device to run at `sample_rate`, waiting for the change to take effect, so that
+    /// the streams built at that rate are not resampled by the system.
+    ///
+    /// The rate of a device is shared by all applications, and the streams of this process
+    /// running at another rate are disconnected with
+    /// [`DisconnectReason::FormatChanged`](crate::DisconnectReason::FormatChanged). Fails if the
+    /// device doesn't support the rate.
+    pub fn set_sample_rate(&self, sample_rate: SampleRate) -> Result<(), BackendSpecificError> {
+        set_sample_rate(self.audio_device_id, sample_rate, None).map_err(|err| match err {
+            BuildStreamError::BackendSpecific { err } => err,
+            BuildStreamError::StreamConfigNotSupported => BackendSpecificError {
+                description: format!(
+                    "the device doesn't support a sample rate of {} Hz",
+                    sample_rate.0
+                ),
+            },
+            err => BackendSpecificError {
+                description: err.to_string(),
+            },
+        })
+    }
+
+    /// The workgroup of the thread on which the device processes IO, and on which the data
+    /// callbacks of its streams run.
+    ///
+    /// The threads doing audio work for the data callbacks, e.g. mixing or decoding in parallel,
+    /// should [`join`](Workgroup::join) it, so that they are scheduled alongside the IO thread.
+    /// Available since macOS 11.
+    pub fn io_workgroup(&self) -> Result<Workgroup, BackendSpecificError> {
+        Workgroup::of_device(self.audio_device_id)
+    }
+
+    /// The input channels of the device, with the names the driver gives them, in order.
+    pub fn input_channels(&self) -> Result<Vec<ChannelInfo>, BackendSpecificError> {
+        let n_channels = self.channel_count(kAudioObjectPropertyScopeInput)?;
+        Ok(channels::channels(
+            self.audio_device_id,
+            kAudioObjectPropertyScopeInput,
+            n_channels,
+        ))
+    }
+
+    /// The output channels of the device, with the names the driver gives them and their roles
+    /// in the speaker configuration set in Audio MIDI Setup, in order.
+    pub fn output_channels(&self) -> Result<Vec<ChannelInfo>, BackendSpecificError> {
+        let n_channels = self.channel_count(kAudioObjectPropertyScopeOutput)?;
+        Ok(channels::channels(
+            self.audio_device_id,
+            kAudioObjectPropertyScopeOutput,
+            n_channels,
+        ))
+    }
+
     fn name(&self) -> Result<String, DeviceNameError> {
         let property_address = AudioObjectPropertyAddress {
             mSelector: kAudioDevicePropertyDeviceNameCFString,
@@ -213,13 +479,13 @@ impl Device {
         Ok(c_str.to_string_lossy().into_owned())
     }
 
-    // Logic re-used between `supported_input_configs` and `supported_output_configs`.
+    /// The total number of channels of the device in `scope`.
     #[allow(clippy::cast_ptr_alignment)]
-    fn supported_configs(
+    fn channel_count(
         &self,
         scope: AudioObjectPropertyScope,
-    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
-        let mut property_address = AudioObjectPropertyAddress {
+    ) -> Result<ChannelCount, BackendSpecificError> {
+        let property_address = AudioObjectPropertyAddress {
             mSelector: kAudioDevicePropertyStreamConfiguration,
             mScope: scope,
             mElement: kAudioObjectPropertyElementMaster,
@@ -251,12 +517,8 @@ impl Device {
 
             let audio_buffer_list = audio_buffer_list.as_mut_ptr() as *mut AudioBufferList;
 
-            // If there's no buffers, skip.
-            if (*audio_buffer_list).mNumberBuffers == 0 {
-                return Ok(vec![].into_iter());
-            }
-
-            // Count the number of channels as the sum of all channels in all output buffers.
+            // Count the number of channels as the sum of all channels in all buffers, as devices
+            // with many channels often split them between several streams.
             let n_buffers = (*audio_buffer_list).mNumberBuffers as usize;
             let first: *const AudioBuffer = (*audio_buffer_list).mBuffers.as_ptr();
             let buffers: &'static [AudioBuffer] = slice::from_raw_parts(first, n_buffers);
@@ -264,13 +526,35 @@ impl Device {
             for buffer in buffers {
                 n_channels += buffer.mNumberChannels as usize;
             }
+            Ok(n_channels as ChannelCount)
+        }
+    }
 
+    // Logic re-used between `supported_input_configs` and `supported_output_configs`.
+    #[allow(clippy::cast_ptr_alignment)]
+    fn supported_configs(
+        &self,
+        scope: AudioObjectPropertyScope,
+    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
+        let n_channels = self.channel_count(scope)?;
+
+        // If there's no channels, skip.
+        if n_channels == 0 {
+            return Ok(vec![].into_iter());
+        }
+
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyAvailableNominalSampleRates,
+            mScope: scope,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+
+        unsafe {
             // TODO: macOS should support U8, I16, I32, F32 and F64. This should allow for using
             // I16 but just use F32 for now as it's the default anyway.
             let sample_format = SampleFormat::F32;
 
             // Get available sample rate ranges.
-            property_address.mSelector = kAudioDevicePropertyAvailableNominalSampleRates;
             let data_size = 0u32;
             let status = AudioObjectGetPropertyDataSize(
                 self.audio_device_id,
@@ -297,14 +581,14 @@ impl Device {
             let ranges: *mut AudioValueRange = ranges.as_mut_ptr() as *mut _;
             let ranges: &'static [AudioValueRange] = slice::from_raw_parts(ranges, n_ranges);
 
-            let audio_unit = audio_unit_from_device(self, true)?;
+            let audio_unit = audio_unit_from_device(self, true, false)?;
             let buffer_size = get_io_buffer_frame_size_range(&audio_unit)?;
 
             // Collect the supported formats for the device.
             let mut fmts = vec![];
             for range in ranges {
                 let fmt = SupportedStreamConfigRange {
-                    channels: n_channels as ChannelCount,
+                    channels: n_channels,
                     min_sample_rate: SampleRate(range.mMinimum as _),
                     max_sample_rate: SampleRate(range.mMaximum as _),
                     buffer_size: buffer_size.clone(),
@@ -399,12 +683,13 @@ impl Device {
                 }
             };
 
-            let audio_unit = audio_unit_from_device(self, true)?;
+            let audio_unit = audio_unit_from_device(self, true, false)?;
             let buffer_size = get_io_buffer_frame_size_range(&audio_unit)?;
 
+            // The stream format only describes the first of the device's streams.
             let config = SupportedStreamConfig {
                 sample_rate: SampleRate(asbd.mSampleRate as _),
-                channels: asbd.mChannelsPerFrame as _,
+                channels: self.channel_count(scope)?,
                 buffer_size,
                 sample_format,
             };
@@ -435,12 +720,28 @@ struct StreamInner {
     audio_unit: AudioUnit,
     /// Manage the lifetime of the closure that handles device disconnection.
     _disconnect_listener: Option<AudioObjectPropertyListener>,
+    /// The listeners reporting changes to the format of the device.
+    _format_listeners: Vec<AudioObjectPropertyListener>,
     // Track the device with which the audio unit was spawned.
     //
     // We must do this so that we can avoid changing the device sample rate if there is already
     // a stream associated with the device.
     #[allow(dead_code)]
     device_id: AudioDeviceID,
+    /// Exclusive access to the device, if requested. Declared after `audio_unit` so that it is
+    /// only released once the audio unit has been disposed of.
+    _hog_mode: Option<Arc<HogMode>>,
+    /// The physical format the device was switched from, restored before exclusive access is
+    /// released.
+    _physical_format: Option<PhysicalFormatChange>,
+    /// The process tap recorded by the stream, if any, which must outlive the audio unit.
+    _tap: Option<Arc<ProcessTap>>,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+    signal_path: SignalPath,
+    latency: Duration,
+    /// Keeps the system awake while the stream exists, if requested.
+    _activity: Option<Activity>,
 }
 
 /// Register the on-disconnect callback.
@@ -470,8 +771,126 @@ where
     Ok(())
 }
 
-fn audio_unit_from_device(device: &Device, input: bool) -> Result<AudioUnit, coreaudio::Error> {
-    let output_type = if device.is_default && !input {
+/// Register the listeners reporting changes to the format of the device in `scope` through the
+/// event callback, if any, and stopping the stream if the device stops running at the stream's
+/// `sample_rate`, as the stream would then be played or recorded at the wrong pitch. Streams
+/// built at another rate than the device's are resampled by the audio unit instead.
+fn add_format_listeners<E>(
+    stream: &Stream,
+    scope: AudioObjectPropertyScope,
+    sample_rate: SampleRate,
+    event_callback: Option<Arc<dyn Fn(StreamEvent) + Send + Sync>>,
+    error_callback: Arc<Mutex<E>>,
+) -> Result<(), BuildStreamError>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    // The listeners are owned by the stream, so they must not keep it alive.
+    let weak_inner = Arc::downgrade(&stream.inner);
+    let mut stream_inner = stream.inner.lock();
+    let device_id = stream_inner.device_id;
+    let mut last_rate = nominal_sample_rate(device_id)? as u32;
+    stream_inner
+        ._format_listeners
+        .push(AudioObjectPropertyListener::new(
+            device_id,
+            AudioObjectPropertyAddress {
+                mSelector: kAudioDevicePropertyNominalSampleRate,
+                mScope: kAudioObjectPropertyScopeGlobal,
+                mElement: kAudioObjectPropertyElementMaster,
+            },
+            move || {
+                let rate = match nominal_sample_rate(device_id) {
+                    Ok(rate) => rate as u32,
+                    Err(_) => return,
+                };
+                if last_rate == sample_rate.0 && rate != sample_rate.0 {
+                    if let Some(inner) = weak_inner.upgrade() {
+                        let _ = Stream { inner }.pause();
+                    }
+                    let reason = DisconnectReason::FormatChanged;
+                    (error_callback.lock())(StreamError::Disconnected { reason });
+                }
+                last_rate = rate;
+            },
+        )?);
+
+    // The physical format also changes with the sample rate.
+    if let Some(event_callback) = event_callback {
+        for stream_id in streams(device_id, scope)? {
+            let event_callback = event_callback.clone();
+            stream_inner
+                ._format_listeners
+                .push(AudioObjectPropertyListener::new(
+                    stream_id,
+                    PHYSICAL_FORMAT_PROPERTY_ADDRESS,
+                    move || {
+                        if let Ok(Some(format)) = physical_format(device_id, scope) {
+                            event_callback(StreamEvent::FormatChanged { format });
+                        }
+                    },
+                )?);
+        }
+    }
+    Ok(())
+}
+
+// The longest channel map that can be set on an audio unit, whose properties are set from values
+// of a fixed size.
+const MAX_CHANNEL_MAP: usize = 64;
+
+/// The `kAudioOutputUnitProperty_ChannelMap` of a stream on `device_channels`, of a device with
+/// `device_channel_count` channels.
+///
+/// The map of an input stream holds the device channel of each of its channels, and the map of
+/// an output stream the channel of the stream played by each device channel, or -1 for none.
+fn channel_map(device_channels: &[u16], device_channel_count: u16, input: bool) -> Vec<i32> {
+    if input {
+        return device_channels.iter().map(|&c| c as i32).collect();
+    }
+    let mut map = vec![-1; device_channel_count as usize];
+    for (channel, &device_channel) in device_channels.iter().enumerate() {
+        map[device_channel as usize] = channel as i32;
+    }
+    map
+}
+
+/// Set the channel map of `audio_unit`, so that only the mapped channels of the device are
+/// transferred. `map` is at most `MAX_CHANNEL_MAP` long.
+fn set_channel_map(
+    audio_unit: &mut AudioUnit,
+    scope: Scope,
+    element: Element,
+    map: &[i32],
+) -> Result<(), coreaudio::Error> {
+    macro_rules! set_channel_map {
+        ($($len:literal)*) => {
+            match map.len() {
+                0 => Ok(()),
+                $($len => {
+                    let map: &[i32; $len] = map.try_into().unwrap();
+                    audio_unit.set_property(
+                        kAudioOutputUnitProperty_ChannelMap,
+                        scope,
+                        element,
+                        Some(map),
+                    )
+                })*
+                len => unreachable!("a channel map of {} channels", len),
+            }
+        };
+    }
+    set_channel_map!(1 2 3 4 5 6 7 8 9 10 11 12 13 14 15 16 17 18 19 20 21 22 23 24 25 26 27 28 29 30 31 32 33 34 35 36 37 38 39 40 41 42 43 44 45 46 47 48 49 50 51 52 53 54 55 56 57 58 59 60 61 62 63 64)
+}
+
+fn audio_unit_from_device(
+    device: &Device,
+    input: bool,
+    voice_processing: bool,
+) -> Result<AudioUnit, coreaudio::Error> {
+    let output_type = if voice_processing {
+        coreaudio::audio_unit::IOType::VoiceProcessingIO
+    } else if device.is_default && !input {
         coreaudio::audio_unit::IOType::DefaultOutput
     } else {
         coreaudio::audio_unit::IOType::HalOutput
@@ -516,26 +935,73 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
+        options: &StreamOptions,
+        data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        if options.passthrough.is_some() || options.dsd.is_some() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
         // The scope and element for working with a device's input stream.
         let scope = Scope::Output;
         let element = Element::Input;
 
+        let channel_map = match &options.device_channels {
+            Some(device_channels) => Some(channel_map(
+                device_channels,
+                self.channel_count(kAudioObjectPropertyScopeInput)?,
+                true,
+            )),
+            None => None,
+        };
+
+        // Take exclusive access to the device before changing its format and sample rate.
+        let (hog_mode, physical_format) = match options.share_mode {
+            ShareMode::Shared => (None, None),
+            ShareMode::Exclusive => {
+                let hog_mode = HogMode::take(self.audio_device_id)?;
+                // The format is left to the stream of this process that took exclusive access.
+                let physical_format = match &hog_mode {
+                    Some((hog_mode, true)) => PhysicalFormatChange::apply(
+                        hog_mode,
+                        kAudioObjectPropertyScopeInput,
+                        config,
+                        sample_format,
+                    )?,
+                    _ => None,
+                };
+                let hog_mode = hog_mode.map(|(hog_mode, _)| hog_mode);
+                (hog_mode, physical_format)
+            }
+        };
+
         // Potentially change the device sample rate to match the config.
-        set_sample_rate(self.audio_device_id, config.sample_rate)?;
+        set_sample_rate(self.audio_device_id, config.sample_rate, timeout)?;
+        let signal_path = signal_path(
+            self.audio_device_id,
+            kAudioObjectPropertyScopeInput,
+            config,
+            sample_format,
+            options.share_mode,
+        );
+
+        let (watchdog, mut data_callback, error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
 
-        let mut audio_unit = audio_unit_from_device(self, true)?;
+        let mut audio_unit = audio_unit_from_device(self, true, options.voice_processing)?;
 
         // Set the stream in interleaved mode.
         let asbd = asbd_from_config(config, sample_format);
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
+        if let Some(channel_map) = &channel_map {
+            set_channel_map(&mut audio_unit, scope, element, channel_map)?;
+        }
 
         // Set the buffersize
         match config.buffer_size {
@@ -559,6 +1025,8 @@ impl Device {
             }
             BufferSize::Default => (),
         }
+        let (hardware_latency, latency) =
+            device_latency(self.audio_device_id, kAudioObjectPropertyScopeInput);
 
         let error_callback = Arc::new(Mutex::new(error_callback));
         let error_callback_disconnect = error_callback.clone();
@@ -584,7 +1052,6 @@ impl Device {
             let len = (data_byte_size as usize / bytes_per_channel) as usize;
             let data = Data::from_parts(data, len, sample_format);
 
-            // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
             let callback = match host_time_to_stream_instant(args.time_stamp.mHostTime) {
                 Err(err) => {
                     (error_callback.lock())(err.into());
@@ -592,14 +1059,19 @@ impl Device {
                 }
                 Ok(cb) => cb,
             };
+            // The frames were captured while the previous buffer was delivered, and reach the
+            // buffer through the hardware.
             let buffer_frames = len / channels as usize;
-            let delay = frames_to_duration(buffer_frames, sample_rate);
+            let delay = frames_to_duration(buffer_frames, sample_rate) + hardware_latency;
             let capture = callback
                 .sub(delay)
                 .expect("`capture` occurs before origin of alsa `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
 
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
             data_callback(&data, &info);
             Ok(())
         })?;
@@ -607,17 +1079,42 @@ impl Device {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _format_listeners: Vec::new(),
             audio_unit,
             device_id: self.audio_device_id,
+            _hog_mode: hog_mode,
+            _physical_format: physical_format,
+            _tap: self.tap.clone(),
+            watchdog,
+            signal_path,
+            latency,
+            _activity: options
+                .keep_awake
+                .then(|| Activity::begin("Recording audio"))
+                .flatten(),
         });
 
+        add_format_listeners(
+            &stream,
+            kAudioObjectPropertyScopeInput,
+            config.sample_rate,
+            options.event_callback.clone(),
+            error_callback_disconnect.clone(),
+        )?;
+
         // If we didn't request the default device, stop the stream if the
         // device disconnects.
         if !self.is_default {
             add_disconnect_listener(&stream, error_callback_disconnect)?;
         }
 
-        stream.inner.lock().audio_unit.start()?;
+        {
+            let mut inner = stream.inner.lock();
+            inner.audio_unit.start()?;
+            if let Some(watchdog) = &inner.watchdog {
+                watchdog.set_playing(true);
+            }
+        }
 
         Ok(stream)
     }
@@ -626,15 +1123,64 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
-        mut data_callback: D,
+        options: &StreamOptions,
+        data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let mut audio_unit = audio_unit_from_device(self, false)?;
+        // Passing a bitstream through or playing native DSD would require switching the physical
+        // format of the device to a non-mixable one, which is not implemented. DoP streams are
+        // built as PCM streams.
+        if options.passthrough.is_some() || options.dsd.is_some() || self.tap.is_some() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
+        let channel_map = match &options.device_channels {
+            Some(device_channels) => Some(channel_map(
+                device_channels,
+                self.channel_count(kAudioObjectPropertyScopeOutput)?,
+                false,
+            )),
+            None => None,
+        };
+
+        let (watchdog, mut data_callback, error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+
+        // Bit-perfect playback requires the device to run at the rate of the stream, which can
+        // only be enforced once no other application can use the device.
+        let (hog_mode, physical_format) = match options.share_mode {
+            ShareMode::Shared => (None, None),
+            ShareMode::Exclusive => {
+                let hog_mode = HogMode::take(self.audio_device_id)?;
+                // The format is left to the stream of this process that took exclusive access.
+                let physical_format = match &hog_mode {
+                    Some((hog_mode, true)) => PhysicalFormatChange::apply(
+                        hog_mode,
+                        kAudioObjectPropertyScopeOutput,
+                        config,
+                        sample_format,
+                    )?,
+                    _ => None,
+                };
+                let hog_mode = hog_mode.map(|(hog_mode, _)| hog_mode);
+                set_sample_rate(self.audio_device_id, config.sample_rate, timeout)?;
+                (hog_mode, physical_format)
+            }
+        };
+        let signal_path = signal_path(
+            self.audio_device_id,
+            kAudioObjectPropertyScopeOutput,
+            config,
+            sample_format,
+            options.share_mode,
+        );
+
+        let mut audio_unit = audio_unit_from_device(self, false, options.voice_processing)?;
 
         // The scope and element for working with a device's output stream.
         let scope = Scope::Input;
@@ -643,6 +1189,9 @@ impl Device {
         // Set the stream in interleaved mode.
         let asbd = asbd_from_config(config, sample_format);
         audio_unit.set_property(kAudioUnitProperty_StreamFormat, scope, element, Some(&asbd))?;
+        if let Some(channel_map) = &channel_map {
+            set_channel_map(&mut audio_unit, scope, element, channel_map)?;
+        }
 
         // Set the buffersize
         match config.buffer_size {
@@ -666,6 +1215,8 @@ impl Device {
             }
             BufferSize::Default => (),
         }
+        let (hardware_latency, latency) =
+            device_latency(self.audio_device_id, kAudioObjectPropertyScopeOutput);
 
         let error_callback = Arc::new(Mutex::new(error_callback));
         let error_callback_disconnect = error_callback.clone();
@@ -696,15 +1247,19 @@ impl Device {
                 }
                 Ok(cb) => cb,
             };
-            // TODO: Need a better way to get delay, for now we assume a double-buffer offset.
+            // The frames are played once the previous buffer has been, and reach the speakers
+            // through the hardware.
             let buffer_frames = len / channels as usize;
-            let delay = frames_to_duration(buffer_frames, sample_rate);
+            let delay = frames_to_duration(buffer_frames, sample_rate) + hardware_latency;
             let playback = callback
                 .add(delay)
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
 
-            let info = OutputCallbackInfo { timestamp };
+            let info = OutputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
             data_callback(&mut data, &info);
             Ok(())
         })?;
@@ -712,27 +1267,511 @@ impl Device {
         let stream = Stream::new(StreamInner {
             playing: true,
             _disconnect_listener: None,
+            _format_listeners: Vec::new(),
             audio_unit,
             device_id: self.audio_device_id,
+            _hog_mode: hog_mode,
+            _physical_format: physical_format,
+            _tap: self.tap.clone(),
+            watchdog,
+            signal_path,
+            latency,
+            _activity: options
+                .keep_awake
+                .then(|| Activity::begin("Playing audio"))
+                .flatten(),
         });
 
+        add_format_listeners(
+            &stream,
+            kAudioObjectPropertyScopeOutput,
+            config.sample_rate,
+            options.event_callback.clone(),
+            error_callback_disconnect.clone(),
+        )?;
+
         // If we didn't request the default device, stop the stream if the
         // device disconnects.
         if !self.is_default {
             add_disconnect_listener(&stream, error_callback_disconnect)?;
         }
 
-        stream.inner.lock().audio_unit.start()?;
+        {
+            let mut inner = stream.inner.lock();
+            inner.audio_unit.start()?;
+            if let Some(watchdog) = &inner.watchdog {
+                watchdog.set_playing(true);
+            }
+        }
 
         Ok(stream)
     }
 }
 
-/// Attempt to set the device sample rate to the provided rate.
+/// The UID of the device, which identifies it across reboots.
+fn device_uid(device_id: AudioDeviceID) -> Result<CfObject, BackendSpecificError> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyDeviceUID,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let uid: CFStringRef = null();
+    let data_size = mem::size_of::<CFStringRef>() as u32;
+    unsafe {
+        let status = AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &uid as *const _ as *mut _,
+        );
+        check_os_status(status)?;
+        CfObject::from_created(uid as _).ok_or_else(|| {
+            let description = "the device has no UID".to_string();
+            BackendSpecificError { description }
+        })
+    }
+}
+
+/// Exclusive access of this process to a device, shared by the streams holding it and released
+/// when the last of them is dropped.
+struct HogMode {
+    device_id: AudioDeviceID,
+}
+
+// The exclusive access to devices held by the streams of this process.
+static HOG_MODES: Mutex<Vec<(AudioDeviceID, Weak<HogMode>)>> = Mutex::new(Vec::new());
+
+impl HogMode {
+    /// Take exclusive access to the device, or share it with the streams already holding it.
+    ///
+    /// Returns whether the access was taken rather than shared, or `None` if this process was
+    /// given exclusive access to the device by other means.
+    fn take(device_id: AudioDeviceID) -> Result<Option<(Arc<Self>, bool)>, BuildStreamError> {
+        let mut hog_modes = HOG_MODES.lock();
+        let held = hog_modes
+            .iter()
+            .find(|(id, _)| *id == device_id)
+            .and_then(|(_, hog_mode)| hog_mode.upgrade());
+        if let Some(hog_mode) = held {
+            return Ok(Some((hog_mode, false)));
+        }
+        let pid = std::process::id() as i32;
+        match hog_mode_owner(device_id)? {
+            owner if owner == pid => return Ok(None),
+            -1 => (),
+            // Another process has exclusive access to the device.
+            _ => return Err(BuildStreamError::DeviceNotAvailable),
+        }
+        toggle_hog_mode(device_id)?;
+        if hog_mode_owner(device_id)? != pid {
+            return Err(BuildStreamError::DeviceNotAvailable);
+        }
+        let hog_mode = Arc::new(HogMode { device_id });
+        hog_modes.push((device_id, Arc::downgrade(&hog_mode)));
+        Ok(Some((hog_mode, true)))
+    }
+}
+
+impl Drop for HogMode {
+    fn drop(&mut self) {
+        // Released while locked, so that no stream finds the device hogged by this process
+        // without a `HogMode` for it.
+        let mut hog_modes = HOG_MODES.lock();
+        hog_modes.retain(|(_, hog_mode)| hog_mode.strong_count() > 0);
+        let _ = toggle_hog_mode(self.device_id);
+    }
+}
+
+const HOG_MODE_PROPERTY_ADDRESS: AudioObjectPropertyAddress = AudioObjectPropertyAddress {
+    mSelector: kAudioDevicePropertyHogMode,
+    mScope: kAudioObjectPropertyScopeGlobal,
+    mElement: kAudioObjectPropertyElementMaster,
+};
+
+/// The `pid_t` of the process with exclusive access to the device, or -1 if there is none.
+fn hog_mode_owner(device_id: AudioDeviceID) -> Result<i32, coreaudio::Error> {
+    let owner: i32 = -1;
+    let data_size = mem::size_of::<i32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &HOG_MODE_PROPERTY_ADDRESS as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &owner as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(owner)
+}
+
+/// Take exclusive access to the device if no process has it, or release it if this process has.
+/// The value written is ignored by the HAL.
+fn toggle_hog_mode(device_id: AudioDeviceID) -> Result<(), coreaudio::Error> {
+    let pid: i32 = -1;
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            device_id,
+            &HOG_MODE_PROPERTY_ADDRESS as *const _,
+            0,
+            null(),
+            mem::size_of::<i32>() as u32,
+            &pid as *const _ as *const _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)
+}
+
+/// Describe how the samples of a stream reach the hardware of the device.
+///
+/// Only streams with exclusive access to the device can be bit-perfect, as the HAL mixes the
+/// streams of all applications otherwise. Even then it converts the samples if the physical format
+/// of the device differs from the format of the stream.
+fn signal_path(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    share_mode: ShareMode,
+) -> SignalPath {
+    let hardware_format = physical_format(device_id, scope).ok().flatten();
+    let stream_format = HardwareFormat {
+        channels: config.channels,
+        sample_rate: config.sample_rate,
+        sample_format: Some(sample_format),
+    };
+    SignalPath {
+        bit_perfect: share_mode == ShareMode::Exclusive && hardware_format == Some(stream_format),
+        hardware_format,
+    }
+}
+
+/// The physical format of the streams of the device in `scope`, whose channels add up, or `None`
+/// if the device has no stream in `scope`. The sample format is only reported if all streams
+/// share it.
+fn physical_format(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+) -> Result<Option<HardwareFormat>, coreaudio::Error> {
+    let mut format: Option<HardwareFormat> = None;
+    for stream_id in streams(device_id, scope)? {
+        let stream_format = hardware_format(&stream_physical_format(stream_id)?);
+        format = Some(match format {
+            None => stream_format,
+            Some(format) => HardwareFormat {
+                channels: format.channels + stream_format.channels,
+                sample_rate: format.sample_rate,
+                sample_format: format
+                    .sample_format
+                    .filter(|&sample_format| stream_format.sample_format == Some(sample_format)),
+            },
+        });
+    }
+    Ok(format)
+}
+
+/// The streams of the device in `scope`, between which devices with many channels often split
+/// them, in the order of their channels.
+fn streams(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+) -> Result<Vec<AudioStreamID>, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyStreams,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let data_size = 0u32;
+    let status = unsafe {
+        AudioObjectGetPropertyDataSize(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    let mut streams: Vec<AudioStreamID> =
+        vec![0; data_size as usize / mem::size_of::<AudioStreamID>()];
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            streams.as_mut_ptr() as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(streams)
+}
+
+const PHYSICAL_FORMAT_PROPERTY_ADDRESS: AudioObjectPropertyAddress = AudioObjectPropertyAddress {
+    mSelector: kAudioStreamPropertyPhysicalFormat,
+    mScope: kAudioObjectPropertyScopeGlobal,
+    mElement: kAudioObjectPropertyElementMaster,
+};
+
+/// The format in which the hardware exchanges the samples of the stream.
+fn stream_physical_format(
+    stream_id: AudioStreamID,
+) -> Result<AudioStreamBasicDescription, coreaudio::Error> {
+    let asbd: AudioStreamBasicDescription = unsafe { mem::zeroed() };
+    let data_size = mem::size_of::<AudioStreamBasicDescription>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            stream_id,
+            &PHYSICAL_FORMAT_PROPERTY_ADDRESS as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &asbd as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(asbd)
+}
+
+fn set_stream_physical_format(
+    stream_id: AudioStreamID,
+    asbd: &AudioStreamBasicDescription,
+) -> Result<(), coreaudio::Error> {
+    let status = unsafe {
+        AudioObjectSetPropertyData(
+            stream_id,
+            &PHYSICAL_FORMAT_PROPERTY_ADDRESS as *const _,
+            0,
+            null(),
+            mem::size_of::<AudioStreamBasicDescription>() as u32,
+            asbd as *const _ as *const _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)
+}
+
+/// The physical formats the stream can be switched to.
+fn available_physical_formats(
+    stream_id: AudioStreamID,
+) -> Result<Vec<AudioStreamRangedDescription>, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioStreamPropertyAvailablePhysicalFormats,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let data_size = 0u32;
+    let status = unsafe {
+        AudioObjectGetPropertyDataSize(
+            stream_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    let n_formats = data_size as usize / mem::size_of::<AudioStreamRangedDescription>();
+    let mut formats: Vec<AudioStreamRangedDescription> = vec![unsafe { mem::zeroed() }; n_formats];
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            stream_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            formats.as_mut_ptr() as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(formats)
+}
+
+/// Describe a physical format through the CPAL sample format of its samples, if any.
+fn hardware_format(asbd: &AudioStreamBasicDescription) -> HardwareFormat {
+    let is_float = asbd.mFormatFlags & kAudioFormatFlagIsFloat as u32 != 0;
+    let is_signed = asbd.mFormatFlags & kAudioFormatFlagIsSignedInteger as u32 != 0;
+    let sample_format = match (is_float, is_signed, asbd.mBitsPerChannel) {
+        _ if asbd.mFormatID != kAudioFormatLinearPCM => None,
+        (true, _, 32) => Some(SampleFormat::F32),
+        (true, _, 64) => Some(SampleFormat::F64),
+        (false, true, 8) => Some(SampleFormat::I8),
+        (false, true, 16) => Some(SampleFormat::I16),
+        (false, true, 32) => Some(SampleFormat::I32),
+        (false, false, 8) => Some(SampleFormat::U8),
+        // E.g. 24-bit integers, which CPAL has no sample format for.
+        _ => None,
+    };
+    HardwareFormat {
+        channels: asbd.mChannelsPerFrame as ChannelCount,
+        sample_rate: SampleRate(asbd.mSampleRate as u32),
+        sample_format,
+    }
+}
+
+/// A change of the physical format of a device to the format of a stream, so that its samples
+/// reach the hardware unconverted, undone when dropped.
+struct PhysicalFormatChange {
+    // The streams that were switched, with their original formats.
+    originals: Vec<(AudioStreamID, AudioStreamBasicDescription)>,
+}
+
+impl PhysicalFormatChange {
+    /// Switch the streams in `scope` of the device hogged by `hog_mode` to the physical format
+    /// matching `config` and `sample_format`.
+    ///
+    /// Returns `None` if the streams already use that format or do not offer it, in which case
+    /// the HAL converts the samples.
+    fn apply(
+        hog_mode: &HogMode,
+        scope: AudioObjectPropertyScope,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+    ) -> Result<Option<Self>, coreaudio::Error> {
+        let stream_ids = streams(hog_mode.device_id, scope)?;
+        let rate = config.sample_rate.0 as f64;
+        let mut change = PhysicalFormatChange { originals: vec![] };
+        for &stream_id in &stream_ids {
+            let original = stream_physical_format(stream_id)?;
+            // The streams of devices splitting their channels keep their share of the channels.
+            let channels = if stream_ids.len() == 1 {
+                config.channels
+            } else {
+                original.mChannelsPerFrame as ChannelCount
+            };
+            let target = HardwareFormat {
+                channels,
+                sample_rate: config.sample_rate,
+                sample_format: Some(sample_format),
+            };
+            if hardware_format(&original) == target {
+                continue;
+            }
+            let format = available_physical_formats(stream_id)?
+                .into_iter()
+                .filter(|ranged| {
+                    ranged.mSampleRateRange.mMinimum <= rate
+                        && rate <= ranged.mSampleRateRange.mMaximum
+                })
+                .map(|ranged| AudioStreamBasicDescription {
+                    mSampleRate: rate,
+                    ..ranged.mFormat
+                })
+                .find(|format| hardware_format(format) == target);
+            if let Some(format) = format {
+                // Dropping the change on error restores the streams switched so far.
+                set_stream_physical_format(stream_id, &format)?;
+                change.originals.push((stream_id, original));
+            }
+        }
+        Ok((!change.originals.is_empty()).then_some(change))
+    }
+}
+
+impl Drop for PhysicalFormatChange {
+    fn drop(&mut self) {
+        for (stream_id, original) in &self.originals {
+            let _ = set_stream_physical_format(*stream_id, original);
+        }
+    }
+}
+
+/// The latency of the hardware of the device in `scope`, i.e. the latency of the device and of
+/// its slowest stream plus the safety offset the HAL keeps from the position of the hardware, and
+/// the total latency of streams on the device, which adds the IO buffer of the device.
+///
+/// Latencies the device does not report are left out.
+fn device_latency(
+    device_id: AudioDeviceID,
+    scope: AudioObjectPropertyScope,
+) -> (Duration, Duration) {
+    let property =
+        |object_id, selector, scope| u32_property(object_id, selector, scope).unwrap_or(0) as usize;
+    let mut hardware_frames = property(device_id, kAudioDevicePropertyLatency, scope)
+        + property(device_id, kAudioDevicePropertySafetyOffset, scope);
+    hardware_frames += streams(device_id, scope)
+        .unwrap_or_default()
+        .into_iter()
+        .map(|stream_id| {
+            property(
+                stream_id,
+                kAudioStreamPropertyLatency,
+                kAudioObjectPropertyScopeGlobal,
+            )
+        })
+        .max()
+        .unwrap_or(0);
+    let buffer_frames = property(device_id, kAudioDevicePropertyBufferFrameSize, scope);
+    let rate = match nominal_sample_rate(device_id) {
+        Ok(rate) if rate > 0.0 => SampleRate(rate as u32),
+        _ => return (Duration::ZERO, Duration::ZERO),
+    };
+    let hardware_latency = frames_to_duration(hardware_frames, rate);
+    (
+        hardware_latency,
+        hardware_latency + frames_to_duration(buffer_frames, rate),
+    )
+}
+
+fn u32_property(
+    object_id: AudioObjectID,
+    selector: u32,
+    scope: AudioObjectPropertyScope,
+) -> Result<u32, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: selector,
+        mScope: scope,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let value = 0u32;
+    let data_size = mem::size_of::<u32>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            object_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &value as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(value)
+}
+
+/// The rate at which the device is running.
+fn nominal_sample_rate(device_id: AudioDeviceID) -> Result<f64, coreaudio::Error> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioDevicePropertyNominalSampleRate,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let sample_rate: f64 = 0.0;
+    let data_size = mem::size_of::<f64>() as u32;
+    let status = unsafe {
+        AudioObjectGetPropertyData(
+            device_id,
+            &property_address as *const _,
+            0,
+            null(),
+            &data_size as *const _ as *mut _,
+            &sample_rate as *const _ as *mut _,
+        )
+    };
+    coreaudio::Error::from_os_status(status)?;
+    Ok(sample_rate)
+}
+
+/// Attempt to set the device sample rate to the provided rate, waiting at most `timeout` (one
+/// second by default) for the device to apply it.
 /// Return an error if the requested sample rate is not supported by the device.
 fn set_sample_rate(
     audio_device_id: AudioObjectID,
     target_sample_rate: SampleRate,
+    timeout: Option<Duration>,
 ) -> Result<(), BuildStreamError> {
     // Get the current sample rate.
     let mut property_address = AudioObjectPropertyAddress {
@@ -846,11 +1885,12 @@ fn set_sample_rate(
         // This should not take longer than a few ms, but we timeout after 1 sec just in case.
         // We loop over potentially several events from the channel to ensure
         // that we catch the expected change in sample rate.
-        let mut timeout = Duration::from_secs(1);
+        let timeout = timeout.unwrap_or(Duration::from_secs(1));
         let start = Instant::now();
 
         loop {
-            match recv.recv_timeout(timeout) {
+            let remaining = timeout.saturating_sub(start.elapsed());
+            match recv.recv_timeout(remaining) {
                 Err(err) => {
                     let description = match err {
                         RecvTimeoutError::Disconnected => {
@@ -872,9 +1912,6 @@ fn set_sample_rate(
                     // TODO: should we consider collecting this error?
                 }
             };
-            timeout = timeout
-                .checked_sub(start.elapsed())
-                .unwrap_or(Duration::ZERO);
         }
         listener.remove()?;
     }
@@ -905,6 +1942,9 @@ impl StreamTrait for Stream {
                 return Err(err.into());
             }
             stream.playing = true;
+            if let Some(watchdog) = &stream.watchdog {
+                watchdog.set_playing(true);
+            }
         }
         Ok(())
     }
@@ -920,9 +1960,20 @@ impl StreamTrait for Stream {
             }
 
             stream.playing = false;
+            if let Some(watchdog) = &stream.watchdog {
+                watchdog.set_playing(false);
+            }
         }
         Ok(())
     }
+
+    fn signal_path(&self) -> Option<SignalPath> {
+        Some(self.inner.lock().signal_path.clone())
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        Some(self.inner.lock().latency)
+    }
 }
 
 fn get_io_buffer_frame_size_range(
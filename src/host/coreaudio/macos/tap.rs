@@ -0,0 +1,183 @@
+//! Capture of the audio played by other processes through the process taps of macOS 14.2 and
+//! later, which are recorded through a private aggregate device.
+//!
+//! The tapping API is not part of older SDKs, so its functions and the `CATapDescription` class
+//! are looked up at runtime, which also lets applications keep running on older systems.
+
+use super::super::objc::{self, Id, Sel};
+use super::aggregate::{self, AggregateDeviceConfig};
+use super::cf::CfObject;
+use super::coreaudio::sys::{
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyScopeGlobal, kAudioObjectSystemObject,
+    AudioObjectGetPropertyData, AudioObjectID, AudioObjectPropertyAddress, OSStatus,
+};
+use super::dl::function;
+use super::{check_os_status, default_output_device};
+use crate::BackendSpecificError;
+use std::ffi::c_void;
+use std::mem;
+use std::ptr::null;
+
+/// The processes whose audio is captured by a tap, created with [`Device::tap`](super::Device::tap).
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum TapProcesses {
+    /// Capture the audio played by all processes except those with the given process IDs, e.g.
+    /// by all other processes with `AllExcept(vec![std::process::id() as i32])`.
+    AllExcept(Vec<i32>),
+    /// Capture the audio played by the processes with the given process IDs.
+    Only(Vec<i32>),
+}
+
+/// A process tap and the private aggregate device recording it, destroyed when dropped.
+#[derive(Debug, PartialEq, Eq)]
+pub struct ProcessTap {
+    pub(crate) aggregate_id: AudioObjectID,
+    tap_id: AudioObjectID,
+}
+
+// Not yet part of the bindings generated from older SDKs.
+#[allow(non_upper_case_globals)]
+const kAudioHardwarePropertyTranslatePIDToProcessObject: u32 = u32::from_be_bytes(*b"id2p");
+#[allow(non_upper_case_globals)]
+const kAudioTapPropertyUID: u32 = u32::from_be_bytes(*b"tuid");
+
+type CreateProcessTap =
+    unsafe extern "C" fn(description: *const c_void, tap_id: *mut AudioObjectID) -> OSStatus;
+type DestroyProcessTap = unsafe extern "C" fn(tap_id: AudioObjectID) -> OSStatus;
+
+impl ProcessTap {
+    pub(crate) fn new(processes: &TapProcesses) -> Result<Self, BackendSpecificError> {
+        unsafe {
+            let class = objc::class(c"CATapDescription");
+            let create = function::<CreateProcessTap>(c"AudioHardwareCreateProcessTap");
+            let create = match create {
+                Some(create) if !class.is_null() => create,
+                _ => {
+                    let description = "process taps require macOS 14.2 or later, older systems \
+                        can only record other processes through a loopback driver"
+                        .to_string();
+                    return Err(BackendSpecificError { description });
+                }
+            };
+
+            let (selector, pids) = match processes {
+                TapProcesses::AllExcept(pids) => (c"initStereoGlobalTapButExcludeProcesses:", pids),
+                TapProcesses::Only(pids) => (c"initStereoMixdownOfProcesses:", pids),
+            };
+            let process_objects = pids
+                .iter()
+                .map(|&pid| Ok(CfObject::number(process_object(pid)? as i32)))
+                .collect::<Result<Vec<_>, BackendSpecificError>>()?;
+            let process_objects = CfObject::array(&process_objects.iter().collect::<Vec<_>>());
+
+            // Core Foundation objects are toll-free bridged to their Foundation counterparts, and
+            // Objective-C objects can be released with `CFRelease`.
+            let alloc = objc::msg_send::<unsafe extern "C" fn(Id, Sel) -> Id>();
+            let init = objc::msg_send::<unsafe extern "C" fn(Id, Sel, *const c_void) -> Id>();
+            let set_private = objc::msg_send::<unsafe extern "C" fn(Id, Sel, bool)>();
+            let tap_description = init(
+                alloc(class, objc::sel(c"alloc")),
+                objc::sel(selector),
+                process_objects.as_ptr(),
+            );
+            let tap_description = CfObject::from_created(tap_description).ok_or_else(|| {
+                let description = "failed to describe the process tap".to_string();
+                BackendSpecificError { description }
+            })?;
+            // Hide the tap from other processes.
+            set_private(
+                tap_description.as_ptr() as _,
+                objc::sel(c"setPrivate:"),
+                true,
+            );
+
+            let mut tap = ProcessTap {
+                aggregate_id: 0,
+                tap_id: 0,
+            };
+            check_os_status(create(tap_description.as_ptr(), &mut tap.tap_id))?;
+
+            // The aggregate device is clocked by the default output device, whose output is
+            // included in most taps.
+            let output = default_output_device().ok_or_else(|| {
+                let description = "no default output device to clock the tap".to_string();
+                BackendSpecificError { description }
+            })?;
+            let config = AggregateDeviceConfig {
+                name: "CPAL Process Tap".to_string(),
+                sub_devices: vec![output],
+                drift_compensation: true,
+                stacked: false,
+                private: true,
+            };
+            tap.aggregate_id = aggregate::create(&config, &[tap_uid(tap.tap_id)?])?;
+            Ok(tap)
+        }
+    }
+}
+
+impl Drop for ProcessTap {
+    fn drop(&mut self) {
+        unsafe {
+            if self.aggregate_id != 0 {
+                let _ = aggregate::destroy(self.aggregate_id);
+            }
+            if self.tap_id != 0 {
+                if let Some(destroy) =
+                    function::<DestroyProcessTap>(c"AudioHardwareDestroyProcessTap")
+                {
+                    destroy(self.tap_id);
+                }
+            }
+        }
+    }
+}
+
+// The audio object of the process with ID `pid`, which must have used Core Audio.
+unsafe fn process_object(pid: i32) -> Result<AudioObjectID, BackendSpecificError> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioHardwarePropertyTranslatePIDToProcessObject,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let object: AudioObjectID = 0;
+    let data_size = mem::size_of::<AudioObjectID>() as u32;
+    let status = AudioObjectGetPropertyData(
+        kAudioObjectSystemObject,
+        &property_address as *const _,
+        mem::size_of::<i32>() as u32,
+        &pid as *const _ as *const _,
+        &data_size as *const _ as *mut _,
+        &object as *const _ as *mut _,
+    );
+    check_os_status(status)?;
+    if object == 0 {
+        let description = format!("no process with ID {} has used Core Audio", pid);
+        return Err(BackendSpecificError { description });
+    }
+    Ok(object)
+}
+
+// The UID of the tap, through which aggregate devices refer to it.
+unsafe fn tap_uid(tap_id: AudioObjectID) -> Result<CfObject, BackendSpecificError> {
+    let property_address = AudioObjectPropertyAddress {
+        mSelector: kAudioTapPropertyUID,
+        mScope: kAudioObjectPropertyScopeGlobal,
+        mElement: kAudioObjectPropertyElementMaster,
+    };
+    let uid: *const c_void = null();
+    let data_size = mem::size_of::<*const c_void>() as u32;
+    let status = AudioObjectGetPropertyData(
+        tap_id,
+        &property_address as *const _,
+        0,
+        null(),
+        &data_size as *const _ as *mut _,
+        &uid as *const _ as *mut _,
+    );
+    check_os_status(status)?;
+    CfObject::from_created(uid).ok_or_else(|| {
+        let description = "the process tap has no UID".to_string();
+        BackendSpecificError { description }
+    })
+}
@@ -0,0 +1,127 @@
+//! The `os_workgroup` of the IO thread of a device, which threads processing audio for its streams
+//! join so that the scheduler runs them alongside the IO thread, on the same cores and at the same
+//! performance, as Apple silicon otherwise schedules them independently.
+
+use super::check_os_status;
+use super::coreaudio::sys::{
+    kAudioObjectPropertyElementMaster, kAudioObjectPropertyScopeGlobal, AudioDeviceID,
+    AudioObjectGetPropertyData, AudioObjectPropertyAddress,
+};
+use super::dl::function;
+use crate::BackendSpecificError;
+use std::ffi::c_void;
+use std::io;
+use std::marker::PhantomData;
+use std::mem;
+use std::os::raw::{c_char, c_int};
+use std::ptr::{null, null_mut};
+
+/// The workgroup of the IO thread of a device, retrieved with
+/// [`Device::io_workgroup`](super::Device::io_workgroup).
+#[derive(Debug)]
+pub struct Workgroup(*mut c_void);
+
+// Workgroups can be joined from any thread.
+unsafe impl Send for Workgroup {}
+unsafe impl Sync for Workgroup {}
+
+/// The membership of the current thread in a [`Workgroup`], which the thread leaves when dropped.
+#[derive(Debug)]
+pub struct WorkgroupMembership<'a> {
+    workgroup: &'a Workgroup,
+    token: JoinToken,
+    // Threads must leave the workgroups they joined themselves.
+    _not_send: PhantomData<*const ()>,
+}
+
+// `os_workgroup_join_token_s`.
+#[derive(Debug)]
+#[repr(C)]
+struct JoinToken {
+    sig: u32,
+    opaque: [c_char; 36],
+}
+
+// Not yet part of the bindings generated from older SDKs.
+#[allow(non_upper_case_globals)]
+const kAudioDevicePropertyIOThreadOSWorkgroup: u32 = u32::from_be_bytes(*b"oswg");
+
+type Join = unsafe extern "C" fn(workgroup: *mut c_void, token: *mut JoinToken) -> c_int;
+type Leave = unsafe extern "C" fn(workgroup: *mut c_void, token: *mut JoinToken);
+
+extern "C" {
+    fn os_release(object: *mut c_void);
+}
+
+impl Workgroup {
+    pub(crate) fn of_device(device_id: AudioDeviceID) -> Result<Self, BackendSpecificError> {
+        let property_address = AudioObjectPropertyAddress {
+            mSelector: kAudioDevicePropertyIOThreadOSWorkgroup,
+            mScope: kAudioObjectPropertyScopeGlobal,
+            mElement: kAudioObjectPropertyElementMaster,
+        };
+        let workgroup: *mut c_void = null_mut();
+        let data_size = mem::size_of::<*mut c_void>() as u32;
+        let status = unsafe {
+            AudioObjectGetPropertyData(
+                device_id,
+                &property_address as *const _,
+                0,
+                null(),
+                &data_size as *const _ as *mut _,
+                &workgroup as *const _ as *mut _,
+            )
+        };
+        check_os_status(status)?;
+        if workgroup.is_null() {
+            let description = "workgroups require macOS 11 or later".to_string();
+            return Err(BackendSpecificError { description });
+        }
+        Ok(Workgroup(workgroup))
+    }
+
+    /// Add the current thread to the workgroup, until the returned membership is dropped.
+    ///
+    /// Only threads with real-time priority should join, and they should finish their work
+    /// within each IO cycle of the device, as the data callbacks of its streams do.
+    pub fn join(&self) -> Result<WorkgroupMembership<'_>, BackendSpecificError> {
+        let join = unsafe { function::<Join>(c"os_workgroup_join") }.ok_or_else(|| {
+            let description = "workgroups require macOS 11 or later".to_string();
+            BackendSpecificError { description }
+        })?;
+        let mut membership = WorkgroupMembership {
+            workgroup: self,
+            token: JoinToken {
+                sig: 0,
+                opaque: [0; 36],
+            },
+            _not_send: PhantomData,
+        };
+        let status = unsafe { join(self.0, &mut membership.token) };
+        if status != 0 {
+            // The thread did not join, so must not leave.
+            mem::forget(membership);
+            let description = format!(
+                "failed to join the workgroup: {}",
+                io::Error::from_raw_os_error(status)
+            );
+            return Err(BackendSpecificError { description });
+        }
+        Ok(membership)
+    }
+}
+
+impl Drop for Workgroup {
+    fn drop(&mut self) {
+        unsafe { os_release(self.0) }
+    }
+}
+
+impl Drop for WorkgroupMembership<'_> {
+    fn drop(&mut self) {
+        // Joining succeeded, so the system has the function.
+        if let Some(leave) = unsafe { function::<Leave>(c"os_workgroup_leave") } {
+            unsafe { leave(self.workgroup.0, &mut self.token) }
+        }
+    }
+}
@@ -11,21 +11,24 @@ use crate::{BuildStreamError, SupportedStreamConfigsError};
 
 use crate::{BackendSpecificError, SampleFormat, StreamConfig};
 
-#[cfg(target_os = "ios")]
+#[cfg(any(target_os = "ios", target_os = "tvos", target_os = "watchos"))]
 mod ios;
 #[cfg(target_os = "macos")]
 mod macos;
+mod objc;
 
-#[cfg(target_os = "ios")]
+#[cfg(any(target_os = "ios", target_os = "tvos", target_os = "watchos"))]
 pub use self::ios::{
     enumerate::{Devices, SupportedInputConfigs, SupportedOutputConfigs},
+    session::{SessionCategory, SessionConfig, SessionMode},
     Device, Host, Stream,
 };
 
 #[cfg(target_os = "macos")]
 pub use self::macos::{
     enumerate::{Devices, SupportedInputConfigs, SupportedOutputConfigs},
-    Device, Host, Stream,
+    AggregateDeviceConfig, ChannelInfo, ChannelLabel, Device, Host, Stream, TapProcesses,
+    Workgroup, WorkgroupMembership,
 };
 
 /// Common helper methods used by both macOS and iOS
@@ -0,0 +1,90 @@
+//! The few parts of the Objective-C runtime needed to call the Objective-C APIs of the host, e.g.
+//! `AVAudioSession` on iOS.
+
+use std::ffi::{c_void, CStr};
+use std::mem;
+use std::os::raw::c_char;
+
+pub type Id = *mut c_void;
+pub type Sel = *mut c_void;
+
+#[link(name = "objc", kind = "dylib")]
+extern "C" {
+    fn objc_getClass(name: *const c_char) -> Id;
+    fn sel_registerName(name: *const c_char) -> Sel;
+    fn objc_msgSend();
+    fn objc_autoreleasePoolPush() -> *mut c_void;
+    fn objc_autoreleasePoolPop(pool: *mut c_void);
+}
+
+/// The class named `name`, or null if the running system has no such class.
+pub unsafe fn class(name: &CStr) -> Id {
+    objc_getClass(name.as_ptr())
+}
+
+pub unsafe fn sel(name: &CStr) -> Sel {
+    sel_registerName(name.as_ptr())
+}
+
+/// `objc_msgSend`, cast to `F`: an `unsafe extern "C" fn` taking the receiver, the selector and
+/// the arguments of the method, and returning its result.
+///
+/// Methods returning floating-point numbers or structs can not be called this way on x86_64.
+pub unsafe fn msg_send<F: Copy>() -> F {
+    let msg_send = objc_msgSend as unsafe extern "C" fn();
+    mem::transmute_copy(&msg_send)
+}
+
+/// Run `f` in an autorelease pool, which releases the objects autoreleased by the methods `f`
+/// calls, e.g. errors, once it returns. Threads not created by the system have no pool otherwise.
+pub fn autoreleasepool<R>(f: impl FnOnce() -> R) -> R {
+    let pool = unsafe { objc_autoreleasePoolPush() };
+    let result = f();
+    unsafe { objc_autoreleasePoolPop(pool) };
+    result
+}
+
+#[repr(C)]
+struct Block {
+    isa: *const c_void,
+    flags: i32,
+    reserved: i32,
+    invoke: BlockInvoke,
+    descriptor: *const BlockDescriptor,
+}
+
+#[repr(C)]
+struct BlockDescriptor {
+    reserved: usize,
+    size: usize,
+}
+
+// Blocks with no captured variables, which are never copied or released.
+const BLOCK_IS_GLOBAL: i32 = 1 << 28;
+
+static BLOCK_DESCRIPTOR: BlockDescriptor = BlockDescriptor {
+    reserved: 0,
+    size: mem::size_of::<Block>(),
+};
+
+#[link(name = "System", kind = "dylib")]
+extern "C" {
+    static _NSConcreteGlobalBlock: c_void;
+}
+
+/// The function implementing a block taking an object argument, called with the block and the
+/// argument.
+pub type BlockInvoke = unsafe extern "C" fn(block: *const c_void, argument: Id);
+
+/// A block, taking an object argument, implemented by `invoke`. The block is never freed, so this
+/// is meant for the blocks registered once per process, e.g. notification observers.
+pub fn global_block(invoke: BlockInvoke) -> Id {
+    let block = Box::new(Block {
+        isa: unsafe { &_NSConcreteGlobalBlock },
+        flags: BLOCK_IS_GLOBAL,
+        reserved: 0,
+        invoke,
+        descriptor: &BLOCK_DESCRIPTOR,
+    });
+    Box::into_raw(block) as Id
+}
@@ -1,14 +1,17 @@
 use crate::traits::DeviceTrait;
+use crate::watchdog;
 use crate::{
-    BackendSpecificError, BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError,
-    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    BackendSpecificError, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError,
+    DeviceNameError, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, ShareMode,
+    StreamConfig, StreamError, StreamOptions, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
+use std::fmt;
 use std::hash::{Hash, Hasher};
+use std::sync::{Arc, Mutex};
 use std::time::Duration;
 
-use super::stream::Stream;
+use super::stream::{ProcessCallback, Stream, StreamParams};
 use super::JACK_SAMPLE_FORMAT;
 
 pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
@@ -24,6 +27,21 @@ pub enum DeviceType {
     InputDevice,
     OutputDevice,
 }
+
+/// What the ports of the streams built from a device are connected to, set with
+/// [`Device::set_connections`].
+#[derive(Clone, Debug, Eq, Hash, PartialEq)]
+pub enum Connections {
+    /// The capture or playback ports of the system, i.e. of the sound card. The default, unless
+    /// disabled with `Host::set_connect_automatically`.
+    System,
+    /// The ports with the given full names, e.g. `"ardour:Audio 1/audio_in 1"`, the `n`th port
+    /// of the stream to the `n`th of them.
+    Ports(Vec<String>),
+    /// No ports, leaving the connections to the user or their session manager.
+    None,
+}
+
 #[derive(Clone, Debug)]
 pub struct Device {
     name: String,
@@ -31,7 +49,27 @@ pub struct Device {
     buffer_size: SupportedBufferSize,
     device_type: DeviceType,
     start_server_automatically: bool,
-    connect_ports_automatically: bool,
+    connections: Connections,
+    client_name: Option<String>,
+    port_names: Vec<String>,
+    process_callback: SharedProcessCallback,
+}
+
+// Shared by the clones of a device, and taken by the first stream built from one of them.
+#[derive(Clone, Default)]
+struct SharedProcessCallback(Arc<Mutex<Option<ProcessCallback>>>);
+
+impl fmt::Debug for SharedProcessCallback {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("SharedProcessCallback")
+            .finish_non_exhaustive()
+    }
+}
+
+impl SharedProcessCallback {
+    fn take(&self) -> Option<ProcessCallback> {
+        self.0.lock().ok()?.take()
+    }
 }
 
 impl Device {
@@ -58,7 +96,14 @@ impl Device {
                 },
                 device_type,
                 start_server_automatically,
-                connect_ports_automatically,
+                connections: if connect_ports_automatically {
+                    Connections::System
+                } else {
+                    Connections::None
+                },
+                client_name: None,
+                port_names: vec![],
+                process_callback: SharedProcessCallback::default(),
             }),
             Err(e) => Err(e),
         }
@@ -135,6 +180,64 @@ impl Device {
     pub fn is_output(&self) -> bool {
         matches!(self.device_type, DeviceType::OutputDevice)
     }
+
+    /// Set what the ports of the streams built from this device afterwards are connected to.
+    pub fn set_connections(&mut self, connections: Connections) {
+        self.connections = connections;
+    }
+
+    /// Set the name of the JACK clients of the streams built from this device afterwards, the name
+    /// of the device by default. JACK appends a number to names already taken by other clients.
+    pub fn set_client_name(&mut self, name: &str) {
+        self.client_name = Some(name.to_owned());
+    }
+
+    /// Set the short names of the ports of the streams built from this device afterwards, e.g.
+    /// `"drums_L"` for `"myapp:drums_L"`, the `n`th port getting the `n`th name. Ports without a
+    /// name are called `in_{n}` or `out_{n}`.
+    pub fn set_port_names(&mut self, names: Vec<String>) {
+        self.port_names = names;
+    }
+
+    /// Set a callback invoked at the start of every cycle of the JACK client of the next stream
+    /// built from this device or its clones, e.g. to process MIDI ports registered on
+    /// [`Stream::client`], in addition to the data callback.
+    ///
+    /// It runs on the real-time thread of JACK, so it mustn't block.
+    pub fn set_process_callback<F>(&mut self, process_callback: F)
+    where
+        F: FnMut(&jack::Client, &jack::ProcessScope) + Send + 'static,
+    {
+        if let Ok(mut shared) = self.process_callback.0.lock() {
+            *shared = Some(Box::new(process_callback));
+        }
+    }
+
+    // Connect the ports of `stream`, which has just been activated, as set with
+    // `set_connections`.
+    fn connect(
+        &self,
+        stream: &mut Stream,
+        channels: ChannelCount,
+        options: &StreamOptions,
+    ) -> Result<(), BuildStreamError> {
+        match &self.connections {
+            Connections::System => {
+                let kind = if self.is_input() {
+                    "capture"
+                } else {
+                    "playback"
+                };
+                let system_ports = stream.system_ports(kind);
+                let device_channels = options
+                    .checked_device_channels(channels, system_ports.len() as ChannelCount)?;
+                stream.connect_to_ports(&system_ports, device_channels);
+            }
+            Connections::Ports(ports) => stream.connect_to_ports(ports, None),
+            Connections::None => (),
+        }
+        Ok(())
+    }
 }
 
 impl DeviceTrait for Device {
@@ -178,12 +281,43 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            // Device channels are those of the system.
+            || (options.device_channels.is_some() && self.connections != Connections::System)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         if let DeviceType::OutputDevice = &self.device_type {
             // Trying to create an input stream from an output device
             return Err(BuildStreamError::StreamConfigNotSupported);
@@ -194,7 +328,8 @@ impl DeviceTrait for Device {
         // The settings should be fine, create a Client
         let client_options = super::get_client_options(self.start_server_automatically);
         let client;
-        match super::get_client(&self.name, client_options) {
+        let client_name = self.client_name.as_ref().unwrap_or(&self.name);
+        match super::get_client(client_name, client_options) {
             Ok(c) => client = c,
             Err(e) => {
                 return Err(BuildStreamError::BackendSpecific {
@@ -202,11 +337,17 @@ impl DeviceTrait for Device {
                 })
             }
         };
-        let mut stream = Stream::new_input(client, conf.channels, data_callback, error_callback);
+        let (watchdog, data_callback, error_callback) =
+            watchdog::watch_input(timeout, data_callback, error_callback)?;
+        let params = StreamParams {
+            channels: conf.channels,
+            port_names: &self.port_names,
+            process_callback: self.process_callback.take(),
+            event_callback: options.event_callback.clone(),
+        };
+        let mut stream = Stream::new_input(client, params, watchdog, data_callback, error_callback);
 
-        if self.connect_ports_automatically {
-            stream.connect_to_system_inputs();
-        }
+        self.connect(&mut stream, conf.channels, options)?;
 
         Ok(stream)
     }
@@ -217,12 +358,43 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            // Device channels are those of the system.
+            || (options.device_channels.is_some() && self.connections != Connections::System)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         if let DeviceType::InputDevice = &self.device_type {
             // Trying to create an output stream from an input device
             return Err(BuildStreamError::StreamConfigNotSupported);
@@ -234,7 +406,8 @@ impl DeviceTrait for Device {
         // The settings should be fine, create a Client
         let client_options = super::get_client_options(self.start_server_automatically);
         let client;
-        match super::get_client(&self.name, client_options) {
+        let client_name = self.client_name.as_ref().unwrap_or(&self.name);
+        match super::get_client(client_name, client_options) {
             Ok(c) => client = c,
             Err(e) => {
                 return Err(BuildStreamError::BackendSpecific {
@@ -242,11 +415,18 @@ impl DeviceTrait for Device {
                 })
             }
         };
-        let mut stream = Stream::new_output(client, conf.channels, data_callback, error_callback);
+        let (watchdog, data_callback, error_callback) =
+            watchdog::watch_output(timeout, data_callback, error_callback)?;
+        let params = StreamParams {
+            channels: conf.channels,
+            port_names: &self.port_names,
+            process_callback: self.process_callback.take(),
+            event_callback: options.event_callback.clone(),
+        };
+        let mut stream =
+            Stream::new_output(client, params, watchdog, data_callback, error_callback);
 
-        if self.connect_ports_automatically {
-            stream.connect_to_system_outputs();
-        }
+        self.connect(&mut stream, conf.channels, options)?;
 
         Ok(stream)
     }
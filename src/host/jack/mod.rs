@@ -4,8 +4,8 @@ use crate::traits::HostTrait;
 use crate::{DevicesError, SampleFormat, SupportedStreamConfigRange};
 
 mod device;
-pub use self::device::Device;
-pub use self::stream::Stream;
+pub use self::device::{Connections, Device};
+pub use self::stream::{ProcessCallback, Stream};
 mod stream;
 
 const JACK_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
@@ -131,6 +131,11 @@ impl HostTrait for Host {
     }
 }
 
+/// Whether a JACK server is running, which a client can connect to without starting one.
+pub(crate) fn is_server_running() -> bool {
+    get_client("cpal_probe", get_client_options(false)).is_ok()
+}
+
 fn get_client_options(start_server_automatically: bool) -> jack::ClientOptions {
     let mut client_options = jack::ClientOptions::empty();
     client_options.set(
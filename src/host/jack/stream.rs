@@ -1,16 +1,35 @@
 use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
 use crate::ChannelCount;
-use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use crate::{
     BackendSpecificError, Data, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleRate, StreamError,
+    PlayStreamError, SampleRate, StreamError, StreamEvent,
 };
 
 use super::JACK_SAMPLE_FORMAT;
 
 type ErrorCallbackPtr = Arc<Mutex<dyn FnMut(StreamError) + Send + 'static>>;
+type EventCallback = Arc<dyn Fn(StreamEvent) + Send + Sync>;
+
+/// Invoked on the process thread of the JACK client of a stream at the start of every cycle, set
+/// with [`Device::set_process_callback`](super::Device::set_process_callback).
+pub type ProcessCallback = Box<dyn FnMut(&jack::Client, &jack::ProcessScope) + Send + 'static>;
+
+// Stored as the latency of streams that have not run yet.
+const UNKNOWN_LATENCY: u64 = u64::MAX;
+
+/// What a stream is built with, besides its data and error callbacks.
+pub(crate) struct StreamParams<'a> {
+    pub(crate) channels: ChannelCount,
+    /// The short names of the ports, see `Device::set_port_names`.
+    pub(crate) port_names: &'a [String],
+    pub(crate) process_callback: Option<ProcessCallback>,
+    pub(crate) event_callback: Option<EventCallback>,
+}
 
 pub struct Stream {
     // TODO: It might be faster to send a message when playing/pausing than to check this every iteration
@@ -19,13 +38,20 @@ pub struct Stream {
     // Port names are stored in order to connect them to other ports in jack automatically
     input_port_names: Vec<String>,
     output_port_names: Vec<String>,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+    // The latency of the ports in the last cycle, in nanoseconds.
+    latency: Arc<AtomicU64>,
+    // Whether JACK is in freewheel mode, as last notified.
+    freewheeling: Arc<AtomicBool>,
 }
 
 impl Stream {
     // TODO: Return error messages
-    pub fn new_input<D, E>(
+    pub(crate) fn new_input<D, E>(
         client: jack::Client,
-        channels: ChannelCount,
+        params: StreamParams,
+        watchdog: Option<Watchdog>,
         data_callback: D,
         mut error_callback: E,
     ) -> Stream
@@ -36,8 +62,13 @@ impl Stream {
         let mut ports = vec![];
         let mut port_names: Vec<String> = vec![];
         // Create ports
-        for i in 0..channels {
-            let port_try = client.register_port(&format!("in_{}", i), jack::AudioIn::default());
+        for i in 0..params.channels {
+            let name = params
+                .port_names
+                .get(i as usize)
+                .cloned()
+                .unwrap_or_else(|| format!("in_{}", i));
+            let port_try = client.register_port(&name, jack::AudioIn::default());
             match port_try {
                 Ok(port) => {
                     // Get the port name in order to later connect it automatically
@@ -60,10 +91,13 @@ impl Stream {
         }
 
         let playing = Arc::new(AtomicBool::new(true));
+        if let Some(watchdog) = &watchdog {
+            watchdog.set_playing(true);
+        }
 
         let error_callback_ptr = Arc::new(Mutex::new(error_callback)) as ErrorCallbackPtr;
 
-        let input_process_handler = LocalProcessHandler::new(
+        let mut input_process_handler = LocalProcessHandler::new(
             vec![],
             ports,
             SampleRate(client.sample_rate() as u32),
@@ -74,7 +108,11 @@ impl Stream {
             Arc::clone(&error_callback_ptr),
         );
 
-        let notification_handler = JackNotificationHandler::new(error_callback_ptr);
+        input_process_handler.process_callback = params.process_callback;
+        let latency = input_process_handler.latency.clone();
+        let notification_handler =
+            JackNotificationHandler::new(error_callback_ptr, params.event_callback);
+        let freewheeling = notification_handler.freewheeling.clone();
 
         let async_client = client
             .activate_async(notification_handler, input_process_handler)
@@ -85,12 +123,16 @@ impl Stream {
             async_client,
             input_port_names: port_names,
             output_port_names: vec![],
+            watchdog,
+            latency,
+            freewheeling,
         }
     }
 
-    pub fn new_output<D, E>(
+    pub(crate) fn new_output<D, E>(
         client: jack::Client,
-        channels: ChannelCount,
+        params: StreamParams,
+        watchdog: Option<Watchdog>,
         data_callback: D,
         mut error_callback: E,
     ) -> Stream
@@ -101,8 +143,13 @@ impl Stream {
         let mut ports = vec![];
         let mut port_names: Vec<String> = vec![];
         // Create ports
-        for i in 0..channels {
-            let port_try = client.register_port(&format!("out_{}", i), jack::AudioOut::default());
+        for i in 0..params.channels {
+            let name = params
+                .port_names
+                .get(i as usize)
+                .cloned()
+                .unwrap_or_else(|| format!("out_{}", i));
+            let port_try = client.register_port(&name, jack::AudioOut::default());
             match port_try {
                 Ok(port) => {
                     // Get the port name in order to later connect it automatically
@@ -125,10 +172,13 @@ impl Stream {
         }
 
         let playing = Arc::new(AtomicBool::new(true));
+        if let Some(watchdog) = &watchdog {
+            watchdog.set_playing(true);
+        }
 
         let error_callback_ptr = Arc::new(Mutex::new(error_callback)) as ErrorCallbackPtr;
 
-        let output_process_handler = LocalProcessHandler::new(
+        let mut output_process_handler = LocalProcessHandler::new(
             ports,
             vec![],
             SampleRate(client.sample_rate() as u32),
@@ -139,7 +189,11 @@ impl Stream {
             Arc::clone(&error_callback_ptr),
         );
 
-        let notification_handler = JackNotificationHandler::new(error_callback_ptr);
+        output_process_handler.process_callback = params.process_callback;
+        let latency = output_process_handler.latency.clone();
+        let notification_handler =
+            JackNotificationHandler::new(error_callback_ptr, params.event_callback);
+        let freewheeling = notification_handler.freewheeling.clone();
 
         let async_client = client
             .activate_async(notification_handler, output_process_handler)
@@ -150,57 +204,84 @@ impl Stream {
             async_client,
             input_port_names: vec![],
             output_port_names: port_names,
+            watchdog,
+            latency,
+            freewheeling,
+        }
+    }
+
+    /// The JACK client of this stream, e.g. to register more ports on it, or to read its transport.
+    ///
+    /// This ties the code using it to the version of the `jack` crate used by cpal.
+    pub fn client(&self) -> &jack::Client {
+        self.async_client.as_client()
+    }
+
+    /// Whether JACK is in freewheel mode, running the process callbacks of its clients as fast as
+    /// possible rather than in real time, e.g. to render a session to a file. The toggling of the
+    /// mode is reported as [`StreamEvent::FreewheelChanged`].
+    pub fn is_freewheeling(&self) -> bool {
+        self.freewheeling.load(Ordering::SeqCst)
+    }
+
+    /// Enter or leave freewheel mode, for all the clients of JACK.
+    pub fn set_freewheel(&self, enabled: bool) -> Result<(), BackendSpecificError> {
+        // The `jack` crate doesn't wrap `jack_set_freewheel`.
+        let client = self.async_client.as_client().raw();
+        match unsafe { jack::jack_sys::jack_set_freewheel(client, enabled as _) } {
+            0 => Ok(()),
+            _ => Err(BackendSpecificError {
+                description: String::from("Unable to set freewheel mode"),
+            }),
         }
     }
 
     /// Connect to the standard system outputs in jack, system:playback_1 and system:playback_2
     /// This has to be done after the client is activated, doing it just after creating the ports doesn't work.
     pub fn connect_to_system_outputs(&mut self) {
-        // Get the system ports
-        let system_ports = self.async_client.as_client().ports(
-            Some("system:playback_.*"),
-            None,
-            jack::PortFlags::empty(),
-        );
-
-        // Connect outputs from this client to the system playback inputs
-        for i in 0..self.output_port_names.len() {
-            if i >= system_ports.len() {
-                break;
-            }
-            match self
-                .async_client
-                .as_client()
-                .connect_ports_by_name(&self.output_port_names[i], &system_ports[i])
-            {
-                Ok(_) => (),
-                Err(e) => println!("Unable to connect to port with error {}", e),
-            }
-        }
+        let system_ports = self.system_ports("playback");
+        self.connect_to_ports(&system_ports, None);
     }
 
     /// Connect to the standard system outputs in jack, system:capture_1 and system:capture_2
     /// This has to be done after the client is activated, doing it just after creating the ports doesn't work.
     pub fn connect_to_system_inputs(&mut self) {
-        // Get the system ports
-        let system_ports = self.async_client.as_client().ports(
-            Some("system:capture_.*"),
+        let system_ports = self.system_ports("capture");
+        self.connect_to_ports(&system_ports, None);
+    }
+
+    /// The names of the system capture or playback ports, depending on `kind`.
+    pub(crate) fn system_ports(&self, kind: &str) -> Vec<String> {
+        self.async_client.as_client().ports(
+            Some(&format!("system:{}_.*", kind)),
             None,
             jack::PortFlags::empty(),
-        );
+        )
+    }
 
-        // Connect outputs from this client to the system playback inputs
-        for i in 0..self.input_port_names.len() {
-            if i >= system_ports.len() {
-                break;
-            }
-            match self
-                .async_client
-                .as_client()
-                .connect_ports_by_name(&system_ports[i], &self.input_port_names[i])
-            {
-                Ok(_) => (),
-                Err(e) => println!("Unable to connect to port with error {}", e),
+    /// Connect the ports of this client to `ports`, the `n`th port to the port at index
+    /// `device_channels[n]`, or to the `n`th port if `device_channels` is `None`.
+    pub(crate) fn connect_to_ports(
+        &mut self,
+        ports: &[String],
+        device_channels: Option<&[ChannelCount]>,
+    ) {
+        let client = self.async_client.as_client();
+        let port_names = self.input_port_names.iter().chain(&self.output_port_names);
+        for (i, port_name) in port_names.enumerate() {
+            let index = device_channels.map_or(i, |channels| channels[i] as usize);
+            let port = match ports.get(index) {
+                Some(port) => port,
+                None => break,
+            };
+            // Inputs of this client connect from the ports, outputs to them.
+            let result = if self.input_port_names.is_empty() {
+                client.connect_ports_by_name(port_name, port)
+            } else {
+                client.connect_ports_by_name(port, port_name)
+            };
+            if let Err(e) = result {
+                println!("Unable to connect to port with error {}", e);
             }
         }
     }
@@ -209,13 +290,27 @@ impl Stream {
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
         self.playing.store(true, Ordering::SeqCst);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
         Ok(())
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
         self.playing.store(false, Ordering::SeqCst);
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
         Ok(())
     }
+
+    /// The maximum of the latency ranges of the ports, as computed by JACK for their connections.
+    fn latency(&self) -> Option<Duration> {
+        match self.latency.load(Ordering::Relaxed) {
+            UNKNOWN_LATENCY => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
 }
 
 struct LocalProcessHandler {
@@ -233,6 +328,9 @@ struct LocalProcessHandler {
     temp_output_buffer: Vec<f32>,
     playing: Arc<AtomicBool>,
     creation_timestamp: std::time::Instant,
+    /// The latency of the ports in the last cycle, in nanoseconds, read by `Stream::latency`.
+    latency: Arc<AtomicU64>,
+    process_callback: Option<ProcessCallback>,
     /// This should not be called on `process`, only on `buffer_size` because it can block.
     error_callback_ptr: ErrorCallbackPtr,
 }
@@ -265,9 +363,29 @@ impl LocalProcessHandler {
             temp_output_buffer,
             playing,
             creation_timestamp: std::time::Instant::now(),
+            latency: Arc::new(AtomicU64::new(UNKNOWN_LATENCY)),
+            process_callback: None,
             error_callback_ptr,
         }
     }
+
+    /// The maximum of the capture latency ranges of the input ports, or of the playback latency
+    /// ranges of the output ports, in frames.
+    ///
+    /// JACK recomputes the ranges when the connections of the ports change. The `jack` crate
+    /// doesn't expose the latency callback telling about that, so they are read every cycle,
+    /// which is real-time safe.
+    fn port_latency(&self) -> usize {
+        let capture = self
+            .in_ports
+            .iter()
+            .map(|port| port.get_latency_range(jack::LatencyType::Capture).1);
+        let playback = self
+            .out_ports
+            .iter()
+            .map(|port| port.get_latency_range(jack::LatencyType::Playback).1);
+        capture.chain(playback).max().unwrap_or(0) as usize
+    }
 }
 
 fn temp_buffer_to_data(temp_input_buffer: &mut Vec<f32>, total_buffer_size: usize) -> Data {
@@ -279,7 +397,16 @@ fn temp_buffer_to_data(temp_input_buffer: &mut Vec<f32>, total_buffer_size: usiz
 }
 
 impl jack::ProcessHandler for LocalProcessHandler {
-    fn process(&mut self, _: &jack::Client, process_scope: &jack::ProcessScope) -> jack::Control {
+    fn process(
+        &mut self,
+        client: &jack::Client,
+        process_scope: &jack::ProcessScope,
+    ) -> jack::Control {
+        // Run even when paused, e.g. to keep serving the ports registered by it.
+        if let Some(process_callback) = &mut self.process_callback {
+            process_callback(client, process_scope);
+        }
+
         if !self.playing.load(Ordering::SeqCst) {
             return jack::Control::Continue;
         }
@@ -307,6 +434,10 @@ impl jack::ProcessHandler for LocalProcessHandler {
                 self.sample_rate,
             ))
             .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+        let latency = frames_to_duration(self.port_latency(), self.sample_rate);
+        self.latency
+            .store(latency.as_nanos() as u64, Ordering::Relaxed);
+        let transport = transport(client);
 
         if let Some(input_callback) = &mut self.input_data_callback {
             // Let's get the data from the input ports and run the callback
@@ -333,9 +464,14 @@ impl jack::ProcessHandler for LocalProcessHandler {
             let callback = start_callback_instant
                 .add(duration_since_cycle_start)
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
-            let capture = start_callback_instant;
+            let capture = start_callback_instant
+                .sub(latency)
+                .expect("`capture` occurs before representation supported by `StreamInstant`");
             let timestamp = crate::InputStreamTimestamp { callback, capture };
-            let info = crate::InputCallbackInfo { timestamp };
+            let info = crate::InputCallbackInfo {
+                timestamp,
+                transport,
+            };
             input_callback(&data, &info);
         }
 
@@ -356,10 +492,13 @@ impl jack::ProcessHandler for LocalProcessHandler {
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let buffer_duration = frames_to_duration(current_frame_count, self.sample_rate);
             let playback = start_cycle_instant
-                .add(buffer_duration)
+                .add(buffer_duration + latency)
                 .expect("`playback` occurs beyond representation supported by `StreamInstant`");
             let timestamp = crate::OutputStreamTimestamp { callback, playback };
-            let info = crate::OutputCallbackInfo { timestamp };
+            let info = crate::OutputCallbackInfo {
+                timestamp,
+                transport,
+            };
             output_callback(&mut data, &info);
 
             // Deinterlace
@@ -396,6 +535,42 @@ impl jack::ProcessHandler for LocalProcessHandler {
     }
 }
 
+// The transport at the start of the current cycle, when called on the process thread.
+fn transport(client: &jack::Client) -> Option<crate::Transport> {
+    // Querying the transport is real-time safe.
+    let jack::TransportStatePosition { pos, state } = client.transport().query().ok()?;
+    let state = match state {
+        jack::TransportState::Stopped => crate::TransportState::Stopped,
+        jack::TransportState::Rolling => crate::TransportState::Rolling,
+        jack::TransportState::Starting => crate::TransportState::Starting,
+    };
+    let bbt = pos
+        .bbt()
+        .map(|bbt| crate::Bbt {
+            bar: bbt.bar as u32,
+            beat: bbt.beat as u32,
+            tick: bbt.tick as u32,
+            bar_start_tick: bbt.bar_start_tick,
+            beats_per_bar: bbt.sig_num,
+            beat_type: bbt.sig_denom,
+            ticks_per_beat: bbt.ticks_per_beat,
+            beats_per_minute: bbt.bpm,
+        })
+        // The position is set by any client, which may leave garbage in it.
+        .filter(|bbt| {
+            bbt.bar_start_tick.is_finite()
+                && bbt.beats_per_bar.is_finite()
+                && bbt.beat_type.is_finite()
+                && bbt.ticks_per_beat.is_finite()
+                && bbt.beats_per_minute.is_finite()
+        });
+    Some(crate::Transport {
+        state,
+        frame: pos.frame() as u64,
+        bbt,
+    })
+}
+
 fn micros_to_stream_instant(micros: u64) -> crate::StreamInstant {
     let nanos = micros * 1000;
     let secs = micros / 1_000_000;
@@ -415,14 +590,21 @@ fn frames_to_duration(frames: usize, rate: crate::SampleRate) -> std::time::Dura
 /// so it needs to be Sync.
 struct JackNotificationHandler {
     error_callback_ptr: ErrorCallbackPtr,
+    event_callback: Option<EventCallback>,
     init_sample_rate_flag: Arc<AtomicBool>,
+    freewheeling: Arc<AtomicBool>,
 }
 
 impl JackNotificationHandler {
-    pub fn new(error_callback_ptr: ErrorCallbackPtr) -> Self {
+    pub fn new(
+        error_callback_ptr: ErrorCallbackPtr,
+        event_callback: Option<EventCallback>,
+    ) -> Self {
         JackNotificationHandler {
             error_callback_ptr,
+            event_callback,
             init_sample_rate_flag: Arc::new(AtomicBool::new(false)),
+            freewheeling: Arc::new(AtomicBool::new(false)),
         }
     }
 
@@ -456,6 +638,16 @@ impl jack::NotificationHandler for JackNotificationHandler {
         }
     }
 
+    fn freewheel(&mut self, _: &jack::Client, is_freewheel_enabled: bool) {
+        self.freewheeling
+            .store(is_freewheel_enabled, Ordering::SeqCst);
+        if let Some(event_callback) = &self.event_callback {
+            event_callback(StreamEvent::FreewheelChanged {
+                enabled: is_freewheel_enabled,
+            });
+        }
+    }
+
     fn xrun(&mut self, _: &jack::Client) -> jack::Control {
         self.send_error(String::from("xrun (buffer over or under run)"));
         jack::Control::Continue
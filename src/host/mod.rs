@@ -1,3 +1,5 @@
+#[cfg(all(target_os = "android", feature = "aaudio"))]
+pub(crate) mod aaudio;
 #[cfg(any(
     target_os = "linux",
     target_os = "dragonfly",
@@ -5,9 +7,20 @@
     target_os = "netbsd"
 ))]
 pub(crate) mod alsa;
+// The audio focus is only requested by the Oboe host.
+#[cfg(all(target_os = "android", any(feature = "aaudio", feature = "oboe")))]
+#[cfg_attr(not(feature = "oboe"), allow(dead_code))]
+pub(crate) mod android_media;
 #[cfg(all(windows, feature = "asio"))]
 pub(crate) mod asio;
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(all(windows, feature = "audiograph"))]
+pub(crate) mod audiograph;
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "tvos",
+    target_os = "watchos"
+))]
 pub(crate) mod coreaudio;
 #[cfg(target_os = "emscripten")]
 pub(crate) mod emscripten;
@@ -22,9 +35,49 @@ pub(crate) mod emscripten;
 ))]
 pub(crate) mod jack;
 pub(crate) mod null;
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", feature = "oboe"))]
 pub(crate) mod oboe;
+#[cfg(any(target_os = "dragonfly", target_os = "freebsd"))]
+pub(crate) mod oss;
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ),
+    feature = "pipewire"
+))]
+pub(crate) mod pipewire;
+#[cfg(all(
+    any(
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    ),
+    feature = "pulseaudio"
+))]
+pub(crate) mod pulseaudio;
+#[cfg(any(
+    target_os = "openbsd",
+    all(
+        any(
+            target_os = "linux",
+            target_os = "dragonfly",
+            target_os = "freebsd",
+            target_os = "netbsd"
+        ),
+        feature = "sndio"
+    )
+))]
+pub(crate) mod sndio;
 #[cfg(windows)]
 pub(crate) mod wasapi;
-#[cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
+#[cfg(all(target_os = "wasi", feature = "wasi"))]
+pub(crate) mod wasi;
+#[cfg(any(
+    target_os = "emscripten",
+    all(target_arch = "wasm32", feature = "wasm-bindgen")
+))]
 pub(crate) mod webaudio;
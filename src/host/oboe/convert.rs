@@ -1,13 +1,16 @@
 use std::convert::TryInto;
+use std::sync::Arc;
 use std::time::Duration;
 
 extern crate oboe;
 
 use crate::{
-    BackendSpecificError, BuildStreamError, PauseStreamError, PlayStreamError, StreamError,
-    StreamInstant,
+    BackendSpecificError, BuildStreamError, DisconnectReason, PauseStreamError, PlayStreamError,
+    RouteChangeReason, StreamError, StreamEvent, StreamInstant,
 };
 
+pub type EventCallback = Arc<dyn Fn(StreamEvent) + Send + Sync>;
+
 pub fn to_stream_instant(duration: Duration) -> StreamInstant {
     StreamInstant::new(
         duration.as_secs().try_into().unwrap(),
@@ -26,6 +29,40 @@ pub fn stream_instant<T: oboe::AudioStreamSafe + ?Sized>(stream: &mut T) -> Stre
     to_stream_instant(Duration::from_nanos(ts.timestamp as u64))
 }
 
+/// The error of a stream on the device `device_id` that stopped with `error`. AAudio disconnects
+/// streams whenever their route changes, which is reported to `event_callback` first, the stream
+/// having been routed away from its device if that device is gone.
+pub fn stream_error(
+    error: oboe::Error,
+    device_id: i32,
+    direction: oboe::AudioDeviceDirection,
+    event_callback: Option<&EventCallback>,
+) -> StreamError {
+    if error != oboe::Error::Disconnected {
+        return error.into();
+    }
+    let device_present = oboe::AudioDeviceInfo::request(direction)
+        .map(|devices| devices.iter().any(|device| device.id == device_id))
+        .unwrap_or(true);
+    let (route_change, reason) = if device_present {
+        (
+            RouteChangeReason::NewDeviceAvailable,
+            DisconnectReason::RouteChanged,
+        )
+    } else {
+        (
+            RouteChangeReason::OldDeviceUnavailable,
+            DisconnectReason::DeviceRemoved,
+        )
+    };
+    if let Some(event_callback) = event_callback {
+        event_callback(StreamEvent::RouteChanged {
+            reason: route_change,
+        });
+    }
+    StreamError::Disconnected { reason }
+}
+
 impl From<oboe::Error> for StreamError {
     fn from(error: oboe::Error) -> Self {
         use self::oboe::Error::*;
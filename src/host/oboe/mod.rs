@@ -1,47 +1,72 @@
-use std::cell::RefCell;
+use std::cell::{Cell, Ref, RefCell};
 use std::cmp;
 use std::convert::TryInto;
+use std::sync::Arc;
 use std::time::Duration;
 use std::vec::IntoIter as VecIntoIter;
 
 extern crate oboe;
 
+use crate::dsd;
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::watchdog::{self, Watchdog};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
-    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, SampleRate, SizedSample, StreamConfig, StreamError,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError,
+    DeviceNameError, DevicesError, DsdMode, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PerformanceMode, PlayStreamError, SampleFormat, SampleRate, ShareMode,
+    SizedSample, StreamConfig, StreamError, StreamOptions, StreamRole, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
 
-mod android_media;
 mod convert;
 mod input_callback;
 mod output_callback;
+mod reopen;
 
-use self::android_media::{get_audio_record_min_buffer_size, get_audio_track_min_buffer_size};
 use self::input_callback::CpalInputCallback;
 use self::oboe::{AudioInputStream, AudioOutputStream};
 use self::output_callback::CpalOutputCallback;
+use self::reopen::{OpenStream, Reopen};
+pub use crate::host::android_media::AudioFocus;
+use crate::host::android_media::{
+    self, abandon_audio_focus, buffer_size_range_for_params, default_supported_configs,
+    request_audio_focus, CHANNEL_MASKS, SAMPLE_RATES,
+};
 
-// Android Java API supports up to 8 channels, but oboe API
-// only exposes mono and stereo.
-const CHANNEL_MASKS: [i32; 2] = [
-    android_media::CHANNEL_OUT_MONO,
-    android_media::CHANNEL_OUT_STEREO,
-];
-
-const SAMPLE_RATES: [i32; 13] = [
-    5512, 8000, 11025, 16000, 22050, 32000, 44100, 48000, 64000, 88200, 96000, 176_400, 192_000,
-];
+pub use self::oboe::{AudioDeviceType as DeviceType, ContentType, InputPreset, Usage};
 
 pub struct Host;
-pub struct Device(Option<oboe::AudioDeviceInfo>);
-pub enum Stream {
+#[derive(Clone)]
+pub struct Device {
+    /// The device streams are routed to, `None` for the default one chosen by the system.
+    info: Option<oboe::AudioDeviceInfo>,
+    /// The attributes of the streams built from this device, overriding those of their role.
+    usage: Option<oboe::Usage>,
+    content_type: Option<oboe::ContentType>,
+    /// The input preset of the input streams, overriding that of their role.
+    input_preset: Option<oboe::InputPreset>,
+    /// The capacity of the buffer of the streams, in frames, left to AAudio if `None`.
+    buffer_capacity: Option<u32>,
+    audio_focus: Option<AudioFocus>,
+    reopen_on_disconnect: bool,
+}
+pub struct Stream {
+    inner: RefCell<StreamInner>,
+    // Hands over the stream opened in place of `inner` when disconnected, if it is reopened.
+    reopen: Option<Arc<Reopen>>,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+    // The audio focus requested while playing, and whether it is held.
+    audio_focus: Option<AudioFocus>,
+    focused: Cell<bool>,
+}
+enum StreamInner {
     Input(Box<RefCell<dyn AudioInputStream>>),
     Output(Box<RefCell<dyn AudioOutputStream>>),
 }
+// Oboe streams may be started, paused and closed from any thread, which streams reopened from the
+// error callback of the disconnected one are handed over across.
+unsafe impl Send for StreamInner {}
 pub type SupportedInputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = VecIntoIter<SupportedStreamConfigRange>;
 pub type Devices = VecIntoIter<Device>;
@@ -65,77 +90,92 @@ impl HostTrait for Host {
         {
             Ok(devices
                 .into_iter()
-                .map(|d| Device(Some(d)))
+                .map(|d| Device::new(Some(d)))
                 .collect::<Vec<_>>()
                 .into_iter())
         } else {
-            Ok(vec![Device(None)].into_iter())
+            Ok(vec![Device::new(None)].into_iter())
         }
     }
 
     fn default_input_device(&self) -> Option<Self::Device> {
-        Some(Device(None))
+        Some(Device::new(None))
     }
 
     fn default_output_device(&self) -> Option<Self::Device> {
-        Some(Device(None))
+        Some(Device::new(None))
     }
 }
 
-fn buffer_size_range_for_params(
-    is_output: bool,
-    sample_rate: i32,
-    channel_mask: i32,
-    android_format: i32,
-) -> SupportedBufferSize {
-    let min_buffer_size = if is_output {
-        get_audio_track_min_buffer_size(sample_rate, channel_mask, android_format)
-    } else {
-        get_audio_record_min_buffer_size(sample_rate, channel_mask, android_format)
-    };
-    if min_buffer_size > 0 {
-        SupportedBufferSize::Range {
-            min: min_buffer_size as u32,
-            max: i32::MAX as u32,
+impl Device {
+    fn new(info: Option<oboe::AudioDeviceInfo>) -> Self {
+        Device {
+            info,
+            usage: None,
+            content_type: None,
+            input_preset: None,
+            buffer_capacity: None,
+            audio_focus: None,
+            reopen_on_disconnect: false,
         }
-    } else {
-        SupportedBufferSize::Unknown
     }
-}
 
-fn default_supported_configs(is_output: bool) -> VecIntoIter<SupportedStreamConfigRange> {
-    // Have to "brute force" the parameter combinations with getMinBufferSize
-    const FORMATS: [SampleFormat; 2] = [SampleFormat::I16, SampleFormat::F32];
+    /// The type of the device, e.g. [`DeviceType::BuiltinSpeaker`], [`DeviceType::WiredHeadset`],
+    /// [`DeviceType::UsbDevice`] or [`DeviceType::BluetoothA2DP`], `None` for the default device,
+    /// whose streams are routed by the system.
+    pub fn device_type(&self) -> Option<DeviceType> {
+        self.info.as_ref().map(|info| info.device_type)
+    }
 
-    let mut output = Vec::with_capacity(SAMPLE_RATES.len() * CHANNEL_MASKS.len() * FORMATS.len());
-    for sample_format in &FORMATS {
-        let android_format = if *sample_format == SampleFormat::I16 {
-            android_media::ENCODING_PCM_16BIT
-        } else {
-            android_media::ENCODING_PCM_FLOAT
-        };
-        for (mask_idx, channel_mask) in CHANNEL_MASKS.iter().enumerate() {
-            let channel_count = mask_idx + 1;
-            for sample_rate in &SAMPLE_RATES {
-                if let SupportedBufferSize::Range { min, max } = buffer_size_range_for_params(
-                    is_output,
-                    *sample_rate,
-                    *channel_mask,
-                    android_format,
-                ) {
-                    output.push(SupportedStreamConfigRange {
-                        channels: channel_count as u16,
-                        min_sample_rate: SampleRate(*sample_rate as u32),
-                        max_sample_rate: SampleRate(*sample_rate as u32),
-                        buffer_size: SupportedBufferSize::Range { min, max },
-                        sample_format: *sample_format,
-                    });
-                }
-            }
-        }
+    /// Set the usage of the streams built from this device afterwards, e.g.
+    /// [`Usage::VoiceCommunication`] or [`Usage::Game`], overriding the one mapped from
+    /// `StreamOptions::role`. Android chooses the volume, routing and effects of output streams by
+    /// their usage.
+    pub fn set_usage(&mut self, usage: Option<oboe::Usage>) {
+        self.usage = usage;
     }
 
-    output.into_iter()
+    /// Set the content type of the streams built from this device afterwards, overriding the one
+    /// mapped from `StreamOptions::role`.
+    pub fn set_content_type(&mut self, content_type: Option<oboe::ContentType>) {
+        self.content_type = content_type;
+    }
+
+    /// Set the input preset of the input streams built from this device afterwards, e.g.
+    /// [`InputPreset::VoiceCommunication`], which enables the echo canceler and noise suppressor
+    /// of the platform, [`InputPreset::VoiceRecognition`] or [`InputPreset::Unprocessed`],
+    /// overriding the one mapped from `StreamOptions::role`.
+    pub fn set_input_preset(&mut self, input_preset: Option<oboe::InputPreset>) {
+        self.input_preset = input_preset;
+    }
+
+    /// Set the capacity of the buffer of the streams built from this device afterwards, in
+    /// frames, which bounds how far their latency can grow to absorb underruns, rather than leaving
+    /// it to AAudio. The capacity granted is reported by [`Stream::buffer_capacity`].
+    pub fn set_buffer_capacity(&mut self, frames: Option<u32>) {
+        self.buffer_capacity = frames;
+    }
+
+    /// Have the streams built from this device afterwards request `audio_focus` when played, and
+    /// abandon it when paused or dropped. Playing them fails if the focus isn't granted, e.g.
+    /// during a phone call.
+    ///
+    /// The focus is that of the whole application, shared by all its streams. Losing it to
+    /// another application isn't reported, as that requires a listener implemented in Java.
+    pub fn set_audio_focus(&mut self, audio_focus: Option<AudioFocus>) {
+        self.audio_focus = audio_focus;
+    }
+
+    /// Have the streams built from this device afterwards reopened when AAudio disconnects them,
+    /// e.g. as headphones are plugged in or unplugged, as Oboe recommends. The reopened stream
+    /// calls the same callbacks, and keeps playing if the disconnected one was.
+    ///
+    /// The route change is still reported to `StreamOptions::event_callback`, but the error
+    /// callback is only called if the stream can't be reopened, e.g. as this device is a specific
+    /// one that was removed.
+    pub fn set_reopen_on_disconnect(&mut self, reopen: bool) {
+        self.reopen_on_disconnect = reopen;
+    }
 }
 
 fn device_supported_configs(
@@ -205,24 +245,101 @@ fn configure_for_device<D, C, I>(
     builder: oboe::AudioStreamBuilder<D, C, I>,
     device: &Device,
     config: &StreamConfig,
+    options: &StreamOptions,
 ) -> oboe::AudioStreamBuilder<D, C, I> {
-    let mut builder = if let Some(info) = &device.0 {
+    let mut builder = if let Some(info) = &device.info {
         builder.set_device_id(info.id)
     } else {
         builder
     };
+    if options.share_mode == ShareMode::Exclusive {
+        builder = builder.set_exclusive();
+    }
+    builder = match options.performance_mode {
+        Some(PerformanceMode::LowLatency) => {
+            builder.set_performance_mode(oboe::PerformanceMode::LowLatency)
+        }
+        Some(PerformanceMode::PowerSaving) => {
+            builder.set_performance_mode(oboe::PerformanceMode::PowerSaving)
+        }
+        None => builder,
+    };
     builder = builder.set_sample_rate(config.sample_rate.0.try_into().unwrap());
+    if let Some(frames) = device.buffer_capacity {
+        builder = builder.set_buffer_capacity_in_frames(frames as i32);
+    }
+    // Oboe splits and joins the bursts of the device into callbacks of the size asked for, if
+    // AAudio doesn't.
     match &config.buffer_size {
         BufferSize::Default => builder,
-        BufferSize::Fixed(size) => builder.set_buffer_capacity_in_frames(*size as i32),
+        BufferSize::Fixed(size) => builder.set_frames_per_callback(*size as i32),
+    }
+}
+
+fn configure_input_role<C, I>(
+    builder: oboe::AudioStreamBuilder<oboe::Input, C, I>,
+    device: &Device,
+    options: &StreamOptions,
+) -> oboe::AudioStreamBuilder<oboe::Input, C, I> {
+    if let Some(input_preset) = device.input_preset {
+        return builder.set_input_preset(input_preset);
+    }
+    match options.role {
+        Some(StreamRole::Communications | StreamRole::GameChat) => {
+            builder.set_input_preset(oboe::InputPreset::VoiceCommunication)
+        }
+        Some(StreamRole::Speech) => builder.set_input_preset(oboe::InputPreset::VoiceRecognition),
+        _ => builder,
+    }
+}
+
+fn configure_output_role<C, I>(
+    builder: oboe::AudioStreamBuilder<oboe::Output, C, I>,
+    device: &Device,
+    options: &StreamOptions,
+) -> oboe::AudioStreamBuilder<oboe::Output, C, I> {
+    let (usage, content_type) = match options.role {
+        Some(StreamRole::Media) => (oboe::Usage::Media, oboe::ContentType::Music),
+        Some(StreamRole::Communications | StreamRole::GameChat) => {
+            (oboe::Usage::VoiceCommunication, oboe::ContentType::Speech)
+        }
+        Some(StreamRole::Game) => (oboe::Usage::Game, oboe::ContentType::Sonification),
+        Some(StreamRole::GameMedia) => (oboe::Usage::Game, oboe::ContentType::Music),
+        Some(StreamRole::Movie) => (oboe::Usage::Media, oboe::ContentType::Movie),
+        Some(StreamRole::Speech) => (oboe::Usage::Assistant, oboe::ContentType::Speech),
+        Some(StreamRole::SoundEffects) => (
+            oboe::Usage::AssistanceSonification,
+            oboe::ContentType::Sonification,
+        ),
+        Some(StreamRole::Alert) => (oboe::Usage::Notification, oboe::ContentType::Sonification),
+        None => (oboe::Usage::Media, oboe::ContentType::Music),
+    };
+    if options.role.is_none() && device.usage.is_none() && device.content_type.is_none() {
+        return builder;
+    }
+    builder
+        .set_usage(device.usage.unwrap_or(usage))
+        .set_content_type(device.content_type.unwrap_or(content_type))
+}
+
+// The options the streams reopened when disconnected are configured with, which their callbacks
+// don't need.
+fn reopen_options(options: &StreamOptions) -> StreamOptions {
+    StreamOptions {
+        share_mode: options.share_mode,
+        role: options.role,
+        performance_mode: options.performance_mode,
+        ..StreamOptions::default()
     }
 }
 
 fn build_input_stream<D, E, C, T>(
     device: &Device,
     config: &StreamConfig,
+    options: &StreamOptions,
     data_callback: D,
     error_callback: E,
+    timeout: Option<Duration>,
     builder: oboe::AudioStreamBuilder<oboe::Input, C, T>,
 ) -> Result<Stream, BuildStreamError>
 where
@@ -232,21 +349,57 @@ where
     D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
     E: FnMut(StreamError) + Send + 'static,
 {
-    let builder = configure_for_device(builder, device, config);
+    let (watchdog, data_callback, error_callback) =
+        watchdog::watch_input(timeout, data_callback, error_callback)?;
+    let reopen = device.reopen_on_disconnect.then(|| {
+        let device = device.clone();
+        let config = config.clone();
+        let options = reopen_options(options);
+        let open: OpenStream<CpalInputCallback<T, C>> = Arc::new(move |callback| {
+            let builder = oboe::AudioStreamBuilder::default()
+                .set_input()
+                .set_format::<T>()
+                .set_channel_count::<C>();
+            let stream = configure_input_role(
+                configure_for_device(builder, &device, &config, &options),
+                &device,
+                &options,
+            )
+            .set_callback(callback)
+            .open_stream()?;
+            Ok(StreamInner::Input(Box::new(RefCell::new(stream))))
+        });
+        (Arc::new(Reopen::default()), open)
+    });
+    let builder = configure_input_role(
+        configure_for_device(builder, device, config, options),
+        device,
+        options,
+    );
     let stream = builder
         .set_callback(CpalInputCallback::<T, C>::new(
             data_callback,
             error_callback,
+            options.event_callback.clone(),
+            reopen.clone(),
         ))
         .open_stream()?;
-    Ok(Stream::Input(Box::new(RefCell::new(stream))))
+    Ok(Stream {
+        inner: RefCell::new(StreamInner::Input(Box::new(RefCell::new(stream)))),
+        reopen: reopen.map(|(reopen, _)| reopen),
+        watchdog,
+        audio_focus: device.audio_focus,
+        focused: Cell::new(false),
+    })
 }
 
 fn build_output_stream<D, E, C, T>(
     device: &Device,
     config: &StreamConfig,
+    options: &StreamOptions,
     data_callback: D,
     error_callback: E,
+    timeout: Option<Duration>,
     builder: oboe::AudioStreamBuilder<oboe::Output, C, T>,
 ) -> Result<Stream, BuildStreamError>
 where
@@ -256,14 +409,48 @@ where
     D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
     E: FnMut(StreamError) + Send + 'static,
 {
-    let builder = configure_for_device(builder, device, config);
+    let (watchdog, data_callback, error_callback) =
+        watchdog::watch_output(timeout, data_callback, error_callback)?;
+    let reopen = device.reopen_on_disconnect.then(|| {
+        let device = device.clone();
+        let config = config.clone();
+        let options = reopen_options(options);
+        let open: OpenStream<CpalOutputCallback<T, C>> = Arc::new(move |callback| {
+            let builder = oboe::AudioStreamBuilder::default()
+                .set_output()
+                .set_format::<T>()
+                .set_channel_count::<C>();
+            let stream = configure_output_role(
+                configure_for_device(builder, &device, &config, &options),
+                &device,
+                &options,
+            )
+            .set_callback(callback)
+            .open_stream()?;
+            Ok(StreamInner::Output(Box::new(RefCell::new(stream))))
+        });
+        (Arc::new(Reopen::default()), open)
+    });
+    let builder = configure_output_role(
+        configure_for_device(builder, device, config, options),
+        device,
+        options,
+    );
     let stream = builder
         .set_callback(CpalOutputCallback::<T, C>::new(
             data_callback,
             error_callback,
+            options.event_callback.clone(),
+            reopen.clone(),
         ))
         .open_stream()?;
-    Ok(Stream::Output(Box::new(RefCell::new(stream))))
+    Ok(Stream {
+        inner: RefCell::new(StreamInner::Output(Box::new(RefCell::new(stream)))),
+        reopen: reopen.map(|(reopen, _)| reopen),
+        watchdog,
+        audio_focus: device.audio_focus,
+        focused: Cell::new(false),
+    })
 }
 
 impl DeviceTrait for Device {
@@ -272,7 +459,7 @@ impl DeviceTrait for Device {
     type Stream = Stream;
 
     fn name(&self) -> Result<String, DeviceNameError> {
-        match &self.0 {
+        match &self.info {
             None => Ok("default".to_owned()),
             Some(info) => Ok(info.product_name.clone()),
         }
@@ -281,7 +468,7 @@ impl DeviceTrait for Device {
     fn supported_input_configs(
         &self,
     ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
-        if let Some(info) = &self.0 {
+        if let Some(info) = &self.info {
             Ok(device_supported_configs(info, false))
         } else {
             Ok(default_supported_configs(false))
@@ -291,7 +478,7 @@ impl DeviceTrait for Device {
     fn supported_output_configs(
         &self,
     ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
-        if let Some(info) = &self.0 {
+        if let Some(info) = &self.info {
             Ok(device_supported_configs(info, true))
         } else {
             Ok(default_supported_configs(true))
@@ -326,12 +513,41 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         match sample_format {
             SampleFormat::I16 => {
                 let builder = oboe::AudioStreamBuilder::default()
@@ -341,16 +557,20 @@ impl DeviceTrait for Device {
                     build_input_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_mono(),
                     )
                 } else if config.channels == 2 {
                     build_input_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_stereo(),
                     )
                 } else {
@@ -368,16 +588,20 @@ impl DeviceTrait for Device {
                     build_input_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_mono(),
                     )
                 } else if config.channels == 2 {
                     build_input_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_stereo(),
                     )
                 } else {
@@ -400,12 +624,52 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
         match sample_format {
             SampleFormat::I16 => {
                 let builder = oboe::AudioStreamBuilder::default()
@@ -415,16 +679,20 @@ impl DeviceTrait for Device {
                     build_output_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_mono(),
                     )
                 } else if config.channels == 2 {
                     build_output_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_stereo(),
                     )
                 } else {
@@ -442,16 +710,20 @@ impl DeviceTrait for Device {
                     build_output_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_mono(),
                     )
                 } else if config.channels == 2 {
                     build_output_stream(
                         self,
                         config,
+                        options,
                         data_callback,
                         error_callback,
+                        timeout,
                         builder.set_stereo(),
                     )
                 } else {
@@ -469,30 +741,164 @@ impl DeviceTrait for Device {
     }
 }
 
+impl Stream {
+    /// The performance mode AAudio granted the stream, `None` if it has no particular one, e.g.
+    /// when the device has no low latency path or the stream runs on OpenSL ES.
+    pub fn performance_mode(&self) -> Option<PerformanceMode> {
+        let mode = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow().get_performance_mode(),
+            StreamInner::Output(stream) => stream.borrow().get_performance_mode(),
+        };
+        match mode {
+            oboe::PerformanceMode::LowLatency => Some(PerformanceMode::LowLatency),
+            oboe::PerformanceMode::PowerSaving => Some(PerformanceMode::PowerSaving),
+            oboe::PerformanceMode::None => None,
+        }
+    }
+
+    /// The sharing mode AAudio granted the stream, [`ShareMode::Shared`] if the device couldn't
+    /// give it the exclusive access requested through `StreamOptions::share_mode`.
+    pub fn share_mode(&self) -> ShareMode {
+        let mode = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow().get_sharing_mode(),
+            StreamInner::Output(stream) => stream.borrow().get_sharing_mode(),
+        };
+        match mode {
+            oboe::SharingMode::Exclusive => ShareMode::Exclusive,
+            oboe::SharingMode::Shared => ShareMode::Shared,
+        }
+    }
+
+    /// Whether the stream runs on the MMAP path of AAudio, exchanging samples with the buffer of
+    /// the DSP directly, which has the lowest latency. `None` if that can't be told: neither
+    /// AAudio nor Oboe report it, but exclusive streams only run on that path, and
+    /// streams on OpenSL ES or without low latency never do.
+    pub fn mmap_used(&self) -> Option<bool> {
+        let uses_aaudio = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow().uses_aaudio(),
+            StreamInner::Output(stream) => stream.borrow().uses_aaudio(),
+        };
+        if !uses_aaudio || self.performance_mode() != Some(PerformanceMode::LowLatency) {
+            Some(false)
+        } else if self.share_mode() == ShareMode::Exclusive {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    /// The number of frames the device consumes or produces at a time, which buffer sizes are
+    /// best made a multiple of. `None` if the stream doesn't report it.
+    pub fn frames_per_burst(&self) -> Option<u32> {
+        let frames = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow_mut().get_frames_per_burst(),
+            StreamInner::Output(stream) => stream.borrow_mut().get_frames_per_burst(),
+        };
+        (frames > 0).then_some(frames as u32)
+    }
+
+    /// The number of frames of every data callback, those of a `BufferSize::Fixed` buffer size,
+    /// `None` if their number varies, as with `BufferSize::Default`.
+    pub fn frames_per_callback(&self) -> Option<u32> {
+        let frames = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow().get_frames_per_callback(),
+            StreamInner::Output(stream) => stream.borrow().get_frames_per_callback(),
+        };
+        (frames > 0).then_some(frames as u32)
+    }
+
+    /// The capacity AAudio granted the buffer of the stream, in frames, `None` if the stream
+    /// doesn't report it.
+    pub fn buffer_capacity(&self) -> Option<u32> {
+        let frames = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow().get_buffer_capacity_in_frames(),
+            StreamInner::Output(stream) => stream.borrow().get_buffer_capacity_in_frames(),
+        };
+        (frames > 0).then_some(frames as u32)
+    }
+
+    // The stream, after taking over the one it was reopened as since, if any.
+    fn inner(&self) -> Ref<'_, StreamInner> {
+        if let Some(stream) = self.reopen.as_ref().and_then(|reopen| reopen.take()) {
+            *self.inner.borrow_mut() = stream;
+        }
+        self.inner.borrow()
+    }
+
+    fn request_focus(&self) -> Result<(), BackendSpecificError> {
+        let focus = match self.audio_focus {
+            Some(focus) if !self.focused.get() => focus,
+            _ => return Ok(()),
+        };
+        match request_audio_focus(focus) {
+            Ok(true) => {
+                self.focused.set(true);
+                Ok(())
+            }
+            Ok(false) => Err(BackendSpecificError {
+                description: "The audio focus was not granted.".to_owned(),
+            }),
+            Err(err) => Err(BackendSpecificError {
+                description: format!("Failed to request the audio focus: {}", err),
+            }),
+        }
+    }
+
+    fn abandon_focus(&self) {
+        if self.focused.replace(false) {
+            let _ = abandon_audio_focus();
+        }
+    }
+}
+
 impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
-        match self {
-            Self::Input(stream) => stream
-                .borrow_mut()
-                .request_start()
-                .map_err(PlayStreamError::from),
-            Self::Output(stream) => stream
-                .borrow_mut()
-                .request_start()
-                .map_err(PlayStreamError::from),
+        self.request_focus()?;
+        let started = match &*self.inner() {
+            StreamInner::Input(stream) => stream.borrow_mut().request_start(),
+            StreamInner::Output(stream) => stream.borrow_mut().request_start(),
+        };
+        if let Err(err) = started {
+            self.abandon_focus();
+            return Err(err.into());
         }
+        if let Some(reopen) = &self.reopen {
+            reopen.set_playing(true);
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
     }
 
     fn pause(&self) -> Result<(), PauseStreamError> {
-        match self {
-            Self::Input(_) => Err(BackendSpecificError {
+        match &*self.inner() {
+            StreamInner::Input(_) => Err(BackendSpecificError {
                 description: "Pause called on the input stream.".to_owned(),
             }
             .into()),
-            Self::Output(stream) => stream
+            StreamInner::Output(stream) => stream
                 .borrow_mut()
                 .request_pause()
                 .map_err(PauseStreamError::from),
+        }?;
+        if let Some(reopen) = &self.reopen {
+            reopen.set_playing(false);
+        }
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        self.abandon_focus();
+        Ok(())
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        self.abandon_focus();
+        // The stream reopened last holds the callbacks referring to `reopen` until dropped here.
+        if let Some(reopen) = &self.reopen {
+            drop(reopen.close());
         }
     }
 }
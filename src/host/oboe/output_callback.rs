@@ -1,28 +1,45 @@
 use std::marker::PhantomData;
+use std::mem;
+use std::sync::{Arc, Mutex};
 use std::time::Instant;
 
 extern crate oboe;
 
-use super::convert::{stream_instant, to_stream_instant};
+use super::convert::{stream_error, stream_instant, to_stream_instant, EventCallback};
+use super::reopen::{ErrorCallback, OpenStream, Reopen};
 use crate::{Data, OutputCallbackInfo, OutputStreamTimestamp, SizedSample, StreamError};
 
 pub struct CpalOutputCallback<I, C> {
     data_cb: Box<dyn FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static>,
-    error_cb: Box<dyn FnMut(StreamError) + Send + 'static>,
+    // Shared with the callbacks of the streams it is reopened as, to report failing to reopen it.
+    error_cb: ErrorCallback,
+    event_cb: Option<EventCallback>,
+    // How the stream is reopened when disconnected, if it is.
+    reopen: Option<(Arc<Reopen>, OpenStream<Self>)>,
+    // The error of the stream, disconnected, reported if it can't be reopened.
+    disconnected: Option<StreamError>,
     created: Instant,
     phantom_channel: PhantomData<C>,
     phantom_input: PhantomData<I>,
 }
 
 impl<I, C> CpalOutputCallback<I, C> {
-    pub fn new<D, E>(data_cb: D, error_cb: E) -> Self
+    pub fn new<D, E>(
+        data_cb: D,
+        error_cb: E,
+        event_cb: Option<EventCallback>,
+        reopen: Option<(Arc<Reopen>, OpenStream<Self>)>,
+    ) -> Self
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
         Self {
             data_cb: Box::new(data_cb),
-            error_cb: Box::new(error_cb),
+            error_cb: Arc::new(Mutex::new(Box::new(error_cb))),
+            event_cb,
+            reopen,
+            disconnected: None,
             created: Instant::now(),
             phantom_channel: PhantomData,
             phantom_input: PhantomData,
@@ -38,6 +55,7 @@ impl<I, C> CpalOutputCallback<I, C> {
                 callback: to_stream_instant(self.created.elapsed()),
                 playback: stream_instant(audio_stream),
             },
+            transport: None,
         }
     }
 }
@@ -50,18 +68,47 @@ where
 
     fn on_error_before_close(
         &mut self,
-        _audio_stream: &mut dyn oboe::AudioOutputStreamSafe,
+        audio_stream: &mut dyn oboe::AudioOutputStreamSafe,
         error: oboe::Error,
     ) {
-        (self.error_cb)(StreamError::from(error))
+        let device_id = audio_stream.get_device_id();
+        let error = stream_error(
+            error,
+            device_id,
+            oboe::AudioDeviceDirection::Output,
+            self.event_cb.as_ref(),
+        );
+        if self.reopen.is_some() && matches!(error, StreamError::Disconnected { .. }) {
+            self.disconnected = Some(error);
+        } else {
+            (self.error_cb.lock().unwrap())(error)
+        }
     }
 
     fn on_error_after_close(
         &mut self,
         _audio_stream: &mut dyn oboe::AudioOutputStreamSafe,
-        error: oboe::Error,
+        _error: oboe::Error,
     ) {
-        (self.error_cb)(StreamError::from(error))
+        // Other errors were reported before closing the stream.
+        let error = match self.disconnected.take() {
+            Some(error) => error,
+            None => return,
+        };
+        let (reopen, open) = self.reopen.clone().unwrap();
+        let callback = Self {
+            data_cb: mem::replace(&mut self.data_cb, Box::new(|_, _| {})),
+            error_cb: self.error_cb.clone(),
+            event_cb: self.event_cb.clone(),
+            reopen: self.reopen.clone(),
+            disconnected: None,
+            created: self.created,
+            phantom_channel: PhantomData,
+            phantom_input: PhantomData,
+        };
+        if reopen.reopen(|| open(callback)).is_err() {
+            (self.error_cb.lock().unwrap())(error)
+        }
     }
 
     fn on_audio_ready(
@@ -0,0 +1,65 @@
+use std::sync::{Arc, Mutex};
+
+extern crate oboe;
+
+use super::StreamInner;
+use crate::StreamError;
+
+pub type ErrorCallback = Arc<Mutex<Box<dyn FnMut(StreamError) + Send + 'static>>>;
+
+/// Opens the stream again with the callback of the disconnected one.
+pub type OpenStream<F> = Arc<dyn Fn(F) -> Result<StreamInner, oboe::Error> + Send + Sync>;
+
+/// Shared by a stream that is reopened when disconnected, and the callbacks of its streams.
+#[derive(Default)]
+pub struct Reopen {
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    // The stream opened in place of the disconnected one, until the stream takes it.
+    reopened: Option<StreamInner>,
+    playing: bool,
+    closed: bool,
+}
+
+impl Reopen {
+    /// Open the stream again through `open`, starting it if it was playing, unless it was dropped
+    /// in the meantime.
+    pub fn reopen(
+        &self,
+        open: impl FnOnce() -> Result<StreamInner, oboe::Error>,
+    ) -> Result<(), oboe::Error> {
+        let mut state = self.state.lock().unwrap();
+        if state.closed {
+            return Ok(());
+        }
+        let stream = open()?;
+        if state.playing {
+            match &stream {
+                StreamInner::Input(stream) => stream.borrow_mut().request_start()?,
+                StreamInner::Output(stream) => stream.borrow_mut().request_start()?,
+            }
+        }
+        state.reopened = Some(stream);
+        Ok(())
+    }
+
+    /// The stream opened in place of the disconnected one, if it was reopened since.
+    pub fn take(&self) -> Option<StreamInner> {
+        self.state.lock().unwrap().reopened.take()
+    }
+
+    pub fn set_playing(&self, playing: bool) {
+        self.state.lock().unwrap().playing = playing;
+    }
+
+    /// Stop reopening the stream, which was dropped, returning the stream opened last if it
+    /// wasn't taken.
+    pub fn close(&self) -> Option<StreamInner> {
+        let mut state = self.state.lock().unwrap();
+        state.closed = true;
+        state.reopened.take()
+    }
+}
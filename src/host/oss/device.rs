@@ -0,0 +1,388 @@
+use crate::thread;
+use crate::traits::DeviceTrait;
+use crate::watchdog;
+use crate::{
+    BufferSize, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError, DeviceNameError,
+    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use libc::c_int;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use super::stream::{Stream, StreamParams};
+use super::DEFAULT_DEVICE;
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+// The period the watchdog assumes for streams without a fixed buffer size, whose fragments are
+// left to the device.
+const DEFAULT_PERIOD: u32 = 512;
+const DEFAULT_NUM_CHANNELS: ChannelCount = 2;
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+// The virtual channels resample every stream to the rate of their device.
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(192_000);
+const MAX_CHANNELS: ChannelCount = 8;
+// The formats the virtual channels convert to and from that of their device.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 6] = [
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::I8,
+    SampleFormat::U8,
+    SampleFormat::U16,
+    SampleFormat::U32,
+];
+
+/// An OSS device, `/dev/dspN` for the `pcmN` sound card, or `/dev/dsp` for the default one.
+#[derive(Clone, Debug)]
+pub struct Device {
+    path: String,
+    description: String,
+    is_input: bool,
+    is_output: bool,
+    /// Whether this is the default unit, for the entries of `/dev/sndstat`.
+    pub(crate) is_default: bool,
+}
+
+impl Device {
+    /// The device of a `pcmN: <description> (play/rec) default` line of `/dev/sndstat`.
+    pub(crate) fn from_sndstat(line: &str) -> Option<Self> {
+        let (unit, rest) = line.trim().strip_prefix("pcm")?.split_once(": ")?;
+        let unit: u32 = unit.parse().ok()?;
+        // The description may contain parentheses, but no `>`.
+        let (description, rest) = rest.strip_prefix('<')?.split_once('>')?;
+        let (capabilities, rest) = rest.trim_start().strip_prefix('(')?.split_once(')')?;
+        let capabilities: Vec<_> = capabilities.split('/').collect();
+        Some(Device {
+            path: format!("/dev/dsp{}", unit),
+            description: description.to_owned(),
+            is_input: capabilities.contains(&"rec"),
+            is_output: capabilities.contains(&"play"),
+            is_default: rest.split_whitespace().any(|word| word == "default"),
+        })
+    }
+
+    /// The device following the default unit, with the capabilities of this one.
+    pub(crate) fn default_device(&self) -> Self {
+        Device {
+            path: DEFAULT_DEVICE.to_owned(),
+            description: "default".to_owned(),
+            is_default: false,
+            ..self.clone()
+        }
+    }
+
+    /// The path of the device, e.g. `/dev/dsp0`.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.is_input
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.is_output
+    }
+
+    fn default_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: DEFAULT_NUM_CHANNELS,
+            sample_rate: DEFAULT_SAMPLE_RATE,
+            buffer_size: SupportedBufferSize::Unknown,
+            sample_format: SampleFormat::I16,
+        }
+    }
+
+    fn supported_configs(&self) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=MAX_CHANNELS {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Unknown,
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    // The parameters of a stream with `conf`, if the device can take them.
+    fn stream_params(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+    ) -> Result<StreamParams, BuildStreamError> {
+        if !options.is_shared_pcm() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let format = afmt(sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        if conf.channels == 0
+            || conf.channels > MAX_CHANNELS
+            || conf.sample_rate < MIN_SAMPLE_RATE
+            || conf.sample_rate > MAX_SAMPLE_RATE
+            || conf.buffer_size == BufferSize::Fixed(0)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        Ok(StreamParams {
+            path: self.path.clone(),
+            config: conf.clone(),
+            sample_format,
+            format,
+        })
+    }
+}
+
+// The `AFMT_*` format of samples in `sample_format`, in native byte order.
+fn afmt(sample_format: SampleFormat) -> Option<c_int> {
+    let little_endian = cfg!(target_endian = "little");
+    let format = match sample_format {
+        SampleFormat::U8 => 0x0000_0008,
+        SampleFormat::I8 => 0x0000_0040,
+        SampleFormat::I16 if little_endian => 0x0000_0010,
+        SampleFormat::I16 => 0x0000_0020,
+        SampleFormat::U16 if little_endian => 0x0000_0080,
+        SampleFormat::U16 => 0x0000_0100,
+        SampleFormat::I32 if little_endian => 0x0000_1000,
+        SampleFormat::I32 => 0x0000_2000,
+        SampleFormat::U32 if little_endian => 0x0000_4000,
+        SampleFormat::U32 => 0x0000_8000,
+        _ => return None,
+    };
+    Some(format)
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.description.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        if !self.is_input {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        if !self.is_output {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    /// Returns the default input config
+    /// Two channels at 48 kHz in 16-bit samples, which the virtual channels of any device take.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_input {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    /// Returns the default output config
+    /// Two channels at 48 kHz in 16-bit samples, which the virtual channels of any device take.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_output {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_input(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_input(
+            params,
+            options,
+            thread::thread_name(options, "cpal_oss_in")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_output {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_output(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_output(
+            params,
+            options,
+            thread::thread_name(options, "cpal_oss_out")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.path == other.path
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.path.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{afmt, Device};
+    use crate::SampleFormat;
+
+    #[test]
+    fn from_sndstat_play_rec() {
+        let device =
+            Device::from_sndstat("pcm0: <Realtek ALC892 (Analog)> (play/rec) default").unwrap();
+        assert_eq!(device.path, "/dev/dsp0");
+        assert_eq!(device.description, "Realtek ALC892 (Analog)");
+        assert!(device.is_input && device.is_output && device.is_default);
+    }
+
+    #[test]
+    fn from_sndstat_play_or_rec_only() {
+        let device = Device::from_sndstat("pcm1: <Realtek ALC892 (Rear Analog)> (play)").unwrap();
+        assert_eq!(device.path, "/dev/dsp1");
+        assert_eq!(device.description, "Realtek ALC892 (Rear Analog)");
+        assert!(device.is_output && !device.is_input && !device.is_default);
+
+        let device = Device::from_sndstat("  pcm12: <USB audio> (rec)  ").unwrap();
+        assert_eq!(device.path, "/dev/dsp12");
+        assert!(device.is_input && !device.is_output && !device.is_default);
+    }
+
+    #[test]
+    fn from_sndstat_malformed() {
+        for line in [
+            "Installed devices:",
+            "FreeBSD Audio Driver (64bit 2009061500/amd64)",
+            "",
+            "pcm: <USB audio> (play)",
+            "pcmX: <USB audio> (play)",
+            "pcm0 <USB audio> (play)",
+            "pcm0: USB audio (play)",
+            "pcm0: <USB audio (play)",
+            "pcm0: <USB audio> play",
+            "pcm0: <USB audio> (play",
+        ] {
+            assert!(Device::from_sndstat(line).is_none(), "{:?}", line);
+        }
+    }
+
+    #[test]
+    fn afmt_native_endian() {
+        assert_eq!(afmt(SampleFormat::U8), Some(0x0000_0008));
+        assert_eq!(afmt(SampleFormat::I8), Some(0x0000_0040));
+        let (s16, s32) = if cfg!(target_endian = "little") {
+            (0x0000_0010, 0x0000_1000)
+        } else {
+            (0x0000_0020, 0x0000_2000)
+        };
+        assert_eq!(afmt(SampleFormat::I16), Some(s16));
+        assert_eq!(afmt(SampleFormat::I32), Some(s32));
+        assert_eq!(afmt(SampleFormat::F32), None);
+    }
+}
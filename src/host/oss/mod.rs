@@ -0,0 +1,222 @@
+//! An OSS host, playing and recording through the `/dev/dsp` devices of FreeBSD and DragonFly.
+//!
+//! OSS is the native API of their sound system, whose virtual channels mix streams and convert
+//! them to the format of the hardware, so any channel count, sample rate and integer format is
+//! accepted.
+
+use crate::traits::HostTrait;
+use crate::DevicesError;
+use libc::{c_int, c_ulong};
+use std::ffi::CString;
+use std::fs;
+use std::io;
+
+mod device;
+pub use self::device::{Device, SupportedInputConfigs, SupportedOutputConfigs};
+pub use self::stream::Stream;
+mod stream;
+
+pub type Devices = std::vec::IntoIter<Device>;
+
+// The device that follows the default unit, `hw.snd.default_unit`.
+const DEFAULT_DEVICE: &str = "/dev/dsp";
+// Lists the installed devices, one `pcmN: <description> (play/rec) default` line per device.
+const SNDSTAT: &str = "/dev/sndstat";
+
+// The ioctls of `sys/soundcard.h`, encoded as by `_IOWR` and friends.
+const SNDCTL_DSP_HALT: c_ulong = 0x2000_5000;
+const SNDCTL_DSP_SPEED: c_ulong = 0xc004_5002;
+const SNDCTL_DSP_SETFMT: c_ulong = 0xc004_5005;
+const SNDCTL_DSP_CHANNELS: c_ulong = 0xc004_5006;
+const SNDCTL_DSP_SETFRAGMENT: c_ulong = 0xc004_500a;
+const SNDCTL_DSP_GETOSPACE: c_ulong = 0x4010_500c;
+const SNDCTL_DSP_GETISPACE: c_ulong = 0x4010_500d;
+const SNDCTL_DSP_GETODELAY: c_ulong = 0x4004_5017;
+
+/// The `audio_buf_info` of `sys/soundcard.h`.
+#[repr(C)]
+#[derive(Default)]
+struct AudioBufInfo {
+    fragments: c_int,
+    fragstotal: c_int,
+    /// The size of a fragment, the unit the device is written and read in, in bytes.
+    fragsize: c_int,
+    /// The bytes that can be written or read without blocking.
+    bytes: c_int,
+}
+
+/// The OSS Host type
+#[derive(Debug)]
+pub struct Host;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    /// OSS is available if a sound card is installed.
+    fn is_available() -> bool {
+        !installed_devices().is_empty()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let mut devices = installed_devices();
+        if let Some(default) = devices.iter().find(|device| device.is_default) {
+            devices.insert(0, default.default_device());
+        }
+        Ok(devices.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        installed_devices()
+            .into_iter()
+            .find(|device| device.is_default && device.is_input())
+            .map(|device| device.default_device())
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        installed_devices()
+            .into_iter()
+            .find(|device| device.is_default && device.is_output())
+            .map(|device| device.default_device())
+    }
+}
+
+// The devices listed by `/dev/sndstat`.
+fn installed_devices() -> Vec<Device> {
+    let sndstat = match fs::read_to_string(SNDSTAT) {
+        Ok(sndstat) => sndstat,
+        Err(_) => return vec![],
+    };
+    sndstat.lines().filter_map(Device::from_sndstat).collect()
+}
+
+/// A device opened for playback or recording, in blocking mode.
+pub(crate) struct Handle {
+    fd: c_int,
+}
+
+impl Handle {
+    pub(crate) fn open(path: &str, output: bool) -> io::Result<Self> {
+        let path = CString::new(path).map_err(|_| io::Error::from(io::ErrorKind::InvalidInput))?;
+        let flags = if output {
+            libc::O_WRONLY
+        } else {
+            libc::O_RDONLY
+        };
+        match unsafe { libc::open(path.as_ptr(), flags | libc::O_CLOEXEC) } {
+            -1 => Err(io::Error::last_os_error()),
+            fd => Ok(Handle { fd }),
+        }
+    }
+
+    fn ioctl<T>(&self, request: c_ulong, arg: &mut T) -> io::Result<()> {
+        match unsafe { libc::ioctl(self.fd, request as _, arg as *mut T) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    // Set the integer parameter of `request` to `value`, returning the value granted.
+    fn set(&self, request: c_ulong, mut value: c_int) -> io::Result<c_int> {
+        self.ioctl(request, &mut value)?;
+        Ok(value)
+    }
+
+    /// Set the format of the samples to an `AFMT_*` value, returning the one granted.
+    pub(crate) fn set_format(&self, format: c_int) -> io::Result<c_int> {
+        self.set(SNDCTL_DSP_SETFMT, format)
+    }
+
+    pub(crate) fn set_channels(&self, channels: c_int) -> io::Result<c_int> {
+        self.set(SNDCTL_DSP_CHANNELS, channels)
+    }
+
+    pub(crate) fn set_rate(&self, rate: c_int) -> io::Result<c_int> {
+        self.set(SNDCTL_DSP_SPEED, rate)
+    }
+
+    /// Ask for fragments of `2^size_log2` bytes, of which at most `count` are buffered.
+    pub(crate) fn set_fragments(&self, count: c_int, size_log2: c_int) -> io::Result<()> {
+        self.set(SNDCTL_DSP_SETFRAGMENT, (count << 16) | size_log2)
+            .map(|_| ())
+    }
+
+    /// The size of a fragment, in bytes.
+    pub(crate) fn fragment_size(&self, output: bool) -> io::Result<usize> {
+        Ok(self.space(output)?.fragsize as usize)
+    }
+
+    fn space(&self, output: bool) -> io::Result<AudioBufInfo> {
+        let mut info = AudioBufInfo::default();
+        let request = if output {
+            SNDCTL_DSP_GETOSPACE
+        } else {
+            SNDCTL_DSP_GETISPACE
+        };
+        self.ioctl(request, &mut info)?;
+        Ok(info)
+    }
+
+    /// The bytes written but not played yet, or recorded but not read yet.
+    pub(crate) fn delay(&self, output: bool) -> io::Result<usize> {
+        if output {
+            let mut delay: c_int = 0;
+            self.ioctl(SNDCTL_DSP_GETODELAY, &mut delay)?;
+            Ok(delay as usize)
+        } else {
+            Ok(self.space(false)?.bytes as usize)
+        }
+    }
+
+    /// Stop playing or recording, dropping the buffered samples. The device restarts on the next
+    /// write or read.
+    pub(crate) fn halt(&self) -> io::Result<()> {
+        match unsafe { libc::ioctl(self.fd, SNDCTL_DSP_HALT as _) } {
+            -1 => Err(io::Error::last_os_error()),
+            _ => Ok(()),
+        }
+    }
+
+    pub(crate) fn write(&self, mut bytes: &[u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            match unsafe { libc::write(self.fd, bytes.as_ptr() as _, bytes.len()) } {
+                -1 => retry_on_interrupt()?,
+                0 => return Err(io::ErrorKind::WriteZero.into()),
+                n => bytes = &bytes[n as usize..],
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn read(&self, mut bytes: &mut [u8]) -> io::Result<()> {
+        while !bytes.is_empty() {
+            match unsafe { libc::read(self.fd, bytes.as_mut_ptr() as _, bytes.len()) } {
+                -1 => retry_on_interrupt()?,
+                0 => return Err(io::ErrorKind::UnexpectedEof.into()),
+                n => bytes = &mut bytes[n as usize..],
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { libc::close(self.fd) };
+    }
+}
+
+// Return the error of the last call, unless it was interrupted by a signal and can be retried.
+fn retry_on_interrupt() -> io::Result<()> {
+    let err = io::Error::last_os_error();
+    match err.kind() {
+        io::ErrorKind::Interrupted => Ok(()),
+        _ => Err(err),
+    }
+}
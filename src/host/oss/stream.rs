@@ -0,0 +1,193 @@
+use crate::stream_thread::blocking::{self, BlockingStream};
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
+    OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, StreamConfig, StreamError,
+    StreamOptions,
+};
+use libc::c_int;
+use std::io;
+use std::time::Duration;
+
+use super::Handle;
+
+/// The device file and format of a stream, checked by its device.
+pub(crate) struct StreamParams {
+    pub(crate) path: String,
+    pub(crate) config: StreamConfig,
+    pub(crate) sample_format: SampleFormat,
+    /// The `AFMT_*` format of `sample_format`.
+    pub(crate) format: c_int,
+}
+
+pub struct Stream(BlockingStream);
+
+impl Stream {
+    pub(crate) fn new_input<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        blocking::new_input(
+            "OSS",
+            params.sample_format,
+            params.config.sample_rate,
+            options,
+            thread_name,
+            watchdog,
+            move || open(false, &params),
+            data_callback,
+            error_callback,
+        )
+        .map(Stream)
+    }
+
+    pub(crate) fn new_output<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        blocking::new_output(
+            "OSS",
+            params.sample_format,
+            params.config.sample_rate,
+            options,
+            thread_name,
+            watchdog,
+            move || open(true, &params),
+            data_callback,
+            error_callback,
+        )
+        .map(Stream)
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.0.play()
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.0.pause()
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.0.latency()
+    }
+}
+
+// The device of a stream, with what it takes to count its delay in frames.
+struct Pcm {
+    handle: Handle,
+    output: bool,
+    frame_size: usize,
+}
+
+impl blocking::Handle for Pcm {
+    // The device starts on the first write or read.
+    fn start(&mut self) -> Result<(), BackendSpecificError> {
+        Ok(())
+    }
+
+    // Halting drops the samples buffered so far.
+    fn stop(&mut self) -> Result<(), BackendSpecificError> {
+        self.handle
+            .halt()
+            .map_err(|err| error(&format!("failed to halt the device: {}", err)))
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), StreamError> {
+        self.handle.read(buffer).map_err(stream_error)
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), StreamError> {
+        self.handle.write(buffer).map_err(stream_error)
+    }
+
+    fn delay(&mut self) -> Result<u64, StreamError> {
+        let bytes = self.handle.delay(self.output).map_err(stream_error)?;
+        Ok((bytes / self.frame_size) as u64)
+    }
+}
+
+// Open the device of a stream and set its parameters, returning it with a buffer of a period.
+fn open(output: bool, params: &StreamParams) -> Result<(Pcm, Vec<u8>), BuildStreamError> {
+    let handle = Handle::open(&params.path, output).map_err(|err| {
+        if device_gone(&err) {
+            BuildStreamError::DeviceNotAvailable
+        } else {
+            error(&format!("failed to open {}: {}", params.path, err)).into()
+        }
+    })?;
+    let failed = |err: io::Error| error(&format!("failed to set up the device: {}", err));
+    let frame_size = params.config.channels as usize * params.sample_format.sample_size();
+    // The fragments must be asked for before the format, and are rounded to a power of two:
+    // keep two periods in the buffer of the stream.
+    if let BufferSize::Fixed(frames) = params.config.buffer_size {
+        let size = (frames as usize * frame_size).next_power_of_two();
+        handle
+            .set_fragments(2, size.trailing_zeros() as c_int)
+            .map_err(failed)?;
+    }
+    let channels = params.config.channels as c_int;
+    let rate = params.config.sample_rate.0 as c_int;
+    if handle.set_format(params.format).map_err(failed)? != params.format
+        || handle.set_channels(channels).map_err(failed)? != channels
+        || handle.set_rate(rate).map_err(failed)? != rate
+    {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    let fragment_size = handle.fragment_size(output).map_err(failed)?;
+    if let BufferSize::Fixed(frames) = params.config.buffer_size {
+        if fragment_size != frames as usize * frame_size {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+    }
+    // Read and write whole frames, even if the fragments hold part of one.
+    let period = (fragment_size / frame_size).max(1) * frame_size;
+    let pcm = Pcm {
+        handle,
+        output,
+        frame_size,
+    };
+    Ok((pcm, vec![0; period]))
+}
+
+// Whether `err` reports the device having been removed.
+fn device_gone(err: &io::Error) -> bool {
+    err.kind() == io::ErrorKind::NotFound
+        || matches!(err.raw_os_error(), Some(libc::ENXIO) | Some(libc::ENODEV))
+}
+
+fn stream_error(err: io::Error) -> StreamError {
+    if device_gone(&err) {
+        StreamError::DeviceNotAvailable
+    } else {
+        error(&format!(
+            "failed to exchange samples with the device: {}",
+            err
+        ))
+        .into()
+    }
+}
+
+fn error(description: &str) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("OSS: {}", description),
+    }
+}
@@ -0,0 +1,378 @@
+use pipewire as pw;
+
+use crate::thread;
+use crate::traits::DeviceTrait;
+use crate::watchdog;
+use crate::{
+    BufferSize, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError, DeviceNameError,
+    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use pw::spa::param::audio::AudioFormat;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use super::stream::{Stream, StreamParams};
+use super::{Graph, Node};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+const DEFAULT_NUM_CHANNELS: ChannelCount = 2;
+// The quantum the watchdog of streams without a fixed buffer size expects, the graph's usual
+// default.
+const DEFAULT_QUANTUM: u32 = 1024;
+// The adapter of every stream resamples it to the rate of the graph.
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(384_000);
+// The adapter also converts the samples to and from the format of the graph.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 8] = [
+    SampleFormat::F32,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::F64,
+    SampleFormat::I8,
+    SampleFormat::U8,
+    SampleFormat::U16,
+    SampleFormat::U32,
+];
+
+/// A PipeWire node that streams can be connected to, or the default node chosen by the session
+/// manager.
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// The name of the nodes of the streams built from this device.
+    stream_name: String,
+    /// `None` for the default device, whose streams follow the default nodes as they change.
+    node_name: Option<String>,
+    description: String,
+    is_input: bool,
+    is_output: bool,
+    // Input streams of sinks record their monitor.
+    is_sink: bool,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+    buffer_size: SupportedBufferSize,
+}
+
+impl Device {
+    pub(crate) fn default_device(stream_name: &str, graph: &Graph) -> Self {
+        Device {
+            stream_name: stream_name.to_owned(),
+            node_name: None,
+            description: "default".to_owned(),
+            is_input: true,
+            is_output: true,
+            is_sink: false,
+            channels: DEFAULT_NUM_CHANNELS,
+            sample_rate: graph.rate,
+            buffer_size: supported_buffer_size(graph),
+        }
+    }
+
+    pub(crate) fn from_node(stream_name: &str, graph: &Graph, node: &Node) -> Self {
+        Device {
+            stream_name: stream_name.to_owned(),
+            node_name: Some(node.name.clone()),
+            description: node
+                .description
+                .clone()
+                .unwrap_or_else(|| node.name.clone()),
+            // Streams record from sources, and from the monitors of sinks.
+            is_input: true,
+            is_output: node.media_class != "Audio/Source",
+            is_sink: node.media_class == "Audio/Sink",
+            channels: node
+                .channels
+                .filter(|&c| c > 0)
+                .unwrap_or(DEFAULT_NUM_CHANNELS),
+            sample_rate: node.rate.map_or(graph.rate, SampleRate),
+            buffer_size: supported_buffer_size(graph),
+        }
+    }
+
+    /// The `node.name` of the node the streams of this device are connected to, or `None` for
+    /// the default device.
+    pub fn node_name(&self) -> Option<&str> {
+        self.node_name.as_deref()
+    }
+
+    /// Whether this is a sink, whose input streams record its monitor, i.e. what is played to it,
+    /// e.g. to record the audio of other applications.
+    pub fn is_loopback(&self) -> bool {
+        self.is_sink
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.is_input
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.is_output
+    }
+
+    fn default_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            buffer_size: self.buffer_size.clone(),
+            sample_format: SampleFormat::F32,
+        }
+    }
+
+    fn supported_configs(&self) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=self.channels {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: self.buffer_size.clone(),
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    // The parameters of a stream with `conf`, if the device can take them.
+    fn stream_params(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        is_input: bool,
+    ) -> Result<StreamParams, BuildStreamError> {
+        if !options.is_shared_pcm() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let format =
+            audio_format(sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        if conf.channels == 0
+            || conf.channels as usize > pw::spa::param::audio::MAX_CHANNELS
+            || conf.sample_rate < MIN_SAMPLE_RATE
+            || conf.sample_rate > MAX_SAMPLE_RATE
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if let (BufferSize::Fixed(frames), SupportedBufferSize::Range { min, max }) =
+            (conf.buffer_size, &self.buffer_size)
+        {
+            if frames < *min || frames > *max {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+        }
+        Ok(StreamParams {
+            name: self.stream_name.clone(),
+            target: self.node_name.clone(),
+            config: conf.clone(),
+            sample_format,
+            format,
+            role: options.role,
+            properties: options.properties.clone(),
+            capture_sink: is_input && self.is_sink,
+        })
+    }
+}
+
+fn supported_buffer_size(graph: &Graph) -> SupportedBufferSize {
+    SupportedBufferSize::Range {
+        min: graph.min_quantum,
+        max: graph.max_quantum,
+    }
+}
+
+// The PipeWire format of samples in `sample_format`, in native byte order.
+fn audio_format(sample_format: SampleFormat) -> Option<AudioFormat> {
+    let native = |le, be| {
+        if cfg!(target_endian = "little") {
+            le
+        } else {
+            be
+        }
+    };
+    let format = match sample_format {
+        SampleFormat::I8 => AudioFormat::S8,
+        SampleFormat::U8 => AudioFormat::U8,
+        SampleFormat::I16 => native(AudioFormat::S16LE, AudioFormat::S16BE),
+        SampleFormat::U16 => native(AudioFormat::U16LE, AudioFormat::U16BE),
+        SampleFormat::I32 => native(AudioFormat::S32LE, AudioFormat::S32BE),
+        SampleFormat::U32 => native(AudioFormat::U32LE, AudioFormat::U32BE),
+        SampleFormat::F32 => native(AudioFormat::F32LE, AudioFormat::F32BE),
+        SampleFormat::F64 => native(AudioFormat::F64LE, AudioFormat::F64BE),
+        _ => return None,
+    };
+    Some(format)
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.description.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        if !self.is_input {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        if !self.is_output {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    /// Returns the default input config
+    /// The channels and sample rate are those of the node, or of the graph for the default
+    /// device, in 32-bit float samples.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_input {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    /// Returns the default output config
+    /// The channels and sample rate are those of the node, or of the graph for the default
+    /// device, in 32-bit float samples.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_output {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options, true)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_input(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_QUANTUM)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_input(
+            params,
+            options,
+            thread::thread_name(options, "cpal_pw_in")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_output {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options, false)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_output(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_QUANTUM)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_output(
+            params,
+            options,
+            thread::thread_name(options, "cpal_pw_out")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.node_name == other.node_name
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.node_name.hash(state);
+    }
+}
@@ -0,0 +1,229 @@
+//! A native PipeWire host, exchanging samples with the graph through `pw_stream`s.
+//!
+//! Unlike going through the ALSA `pipewire` plugin, every stream is a node of its own, routed per
+//! application by the session manager, with the latency of the graph reported to the callbacks.
+
+use pipewire as pw;
+
+use crate::traits::HostTrait;
+use crate::{BackendSpecificError, DevicesError, SampleRate, SupportedStreamConfigRange};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+
+mod device;
+pub use self::device::Device;
+pub use self::stream::Stream;
+mod stream;
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type Devices = std::vec::IntoIter<Device>;
+
+// Used when the graph doesn't report its clock.
+const DEFAULT_RATE: u32 = 48_000;
+const DEFAULT_MIN_QUANTUM: u32 = 32;
+const DEFAULT_MAX_QUANTUM: u32 = 8192;
+
+/// The PipeWire Host type
+#[derive(Debug)]
+pub struct Host {
+    /// The name of the nodes of the streams created from this host, shown by patchbays and mixers.
+    name: String,
+}
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        pw::init();
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "cpal".to_owned());
+        Ok(Host { name })
+    }
+
+    /// Set the name of the nodes of the streams built from the devices of this host, the name of
+    /// the executable by default.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_owned();
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    /// PipeWire is available if a PipeWire daemon can be connected to.
+    fn is_available() -> bool {
+        pw::init();
+        Connection::new().is_ok()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let graph = Connection::new()?.graph()?;
+        let mut devices = vec![Device::default_device(&self.name, &graph)];
+        devices.extend(
+            graph
+                .nodes
+                .iter()
+                .map(|node| Device::from_node(&self.name, &graph, node)),
+        );
+        Ok(devices.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        let graph = Connection::new().and_then(|c| c.graph()).ok()?;
+        Some(Device::default_device(&self.name, &graph))
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        self.default_input_device()
+    }
+}
+
+/// A connection to the PipeWire daemon, on a main loop owned by the current thread.
+pub(crate) struct Connection {
+    main_loop: pw::main_loop::MainLoop,
+    // Kept alive for as long as the core.
+    _context: pw::context::Context,
+    core: pw::core::Core,
+}
+
+/// The clock of the graph and the audio nodes that streams can be connected to.
+pub(crate) struct Graph {
+    rate: SampleRate,
+    min_quantum: u32,
+    max_quantum: u32,
+    nodes: Vec<Node>,
+}
+
+pub(crate) struct Node {
+    name: String,
+    description: Option<String>,
+    media_class: String,
+    channels: Option<u16>,
+    rate: Option<u32>,
+}
+
+impl Connection {
+    pub(crate) fn new() -> Result<Self, BackendSpecificError> {
+        let main_loop = pw::main_loop::MainLoop::new(None).map_err(pw_error)?;
+        let context = pw::context::Context::new(&main_loop).map_err(pw_error)?;
+        let core = context.connect(None).map_err(pw_error)?;
+        Ok(Connection {
+            main_loop,
+            _context: context,
+            core,
+        })
+    }
+
+    /// Ask the daemon for its clock settings and audio nodes, waiting for a roundtrip.
+    pub(crate) fn graph(&self) -> Result<Graph, BackendSpecificError> {
+        let registry = self.core.get_registry().map_err(pw_error)?;
+        let graph = Rc::new(RefCell::new(Graph {
+            rate: SampleRate(DEFAULT_RATE),
+            min_quantum: DEFAULT_MIN_QUANTUM,
+            max_quantum: DEFAULT_MAX_QUANTUM,
+            nodes: vec![],
+        }));
+        let done = Rc::new(Cell::new(false));
+        let error = Rc::new(RefCell::new(None));
+
+        let pending = self.core.sync(0).map_err(pw_error)?;
+        let core_listener = self
+            .core
+            .add_listener_local()
+            .info({
+                let graph = graph.clone();
+                move |info| {
+                    let props = match info.props() {
+                        Some(props) => props,
+                        None => return,
+                    };
+                    let number = |key| props.get(key).and_then(|value| value.parse().ok());
+                    let mut graph = graph.borrow_mut();
+                    if let Some(rate) = number("default.clock.rate") {
+                        graph.rate = SampleRate(rate);
+                    }
+                    if let Some(min_quantum) = number("default.clock.min-quantum") {
+                        graph.min_quantum = min_quantum;
+                    }
+                    if let Some(max_quantum) = number("default.clock.max-quantum") {
+                        graph.max_quantum = max_quantum;
+                    }
+                }
+            })
+            .done({
+                let done = done.clone();
+                let main_loop = self.main_loop.clone();
+                move |id, seq| {
+                    if id == pw::core::PW_ID_CORE && seq == pending {
+                        done.set(true);
+                        main_loop.quit();
+                    }
+                }
+            })
+            .error({
+                let error = error.clone();
+                let main_loop = self.main_loop.clone();
+                move |_, _, _, message| {
+                    *error.borrow_mut() = Some(message.to_owned());
+                    main_loop.quit();
+                }
+            })
+            .register();
+        let registry_listener = registry
+            .add_listener_local()
+            .global({
+                let graph = graph.clone();
+                move |global| {
+                    if global.type_ != pw::types::ObjectType::Node {
+                        return;
+                    }
+                    let props = match global.props {
+                        Some(props) => props,
+                        None => return,
+                    };
+                    let media_class = match props.get(*pw::keys::MEDIA_CLASS) {
+                        Some(class @ ("Audio/Sink" | "Audio/Source" | "Audio/Duplex")) => class,
+                        _ => return,
+                    };
+                    let name = match props.get(*pw::keys::NODE_NAME) {
+                        Some(name) => name,
+                        None => return,
+                    };
+                    graph.borrow_mut().nodes.push(Node {
+                        name: name.to_owned(),
+                        description: props.get(*pw::keys::NODE_DESCRIPTION).map(str::to_owned),
+                        media_class: media_class.to_owned(),
+                        channels: props
+                            .get(*pw::keys::AUDIO_CHANNELS)
+                            .and_then(|channels| channels.parse().ok()),
+                        rate: props
+                            .get(*pw::keys::AUDIO_RATE)
+                            .and_then(|rate| rate.parse().ok()),
+                    });
+                }
+            })
+            .register();
+
+        while !done.get() {
+            if let Some(description) = error.borrow_mut().take() {
+                return Err(BackendSpecificError { description });
+            }
+            self.main_loop.run();
+        }
+        // Release the graph held by the callbacks.
+        drop(registry_listener);
+        drop(core_listener);
+        Ok(Rc::try_unwrap(graph)
+            .ok()
+            .expect("the listeners holding the graph were dropped")
+            .into_inner())
+    }
+}
+
+pub(crate) fn pw_error(err: pw::Error) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("PipeWire: {}", err),
+    }
+}
@@ -0,0 +1,498 @@
+use pipewire as pw;
+
+use crate::stream_thread::{self, input_info, output_info, Built};
+use crate::thread::AudioThread;
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
+    OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamConfig,
+    StreamError, StreamInstant, StreamOptions, StreamRole,
+};
+use pw::spa::param::audio::{AudioFormat, AudioInfoRaw, MAX_CHANNELS};
+use pw::spa::pod::{serialize::PodSerializer, Object, Pod, Value};
+use pw::spa::sys as spa_sys;
+use pw::spa::utils::Direction;
+use pw::stream::{StreamFlags, StreamRef, StreamState};
+use std::io::Cursor;
+use std::mem;
+use std::rc::Rc;
+use std::sync::mpsc;
+use std::time::Duration;
+
+use super::{pw_error, Connection};
+
+/// The node properties and format of a stream, checked by its device.
+pub(crate) struct StreamParams {
+    /// The `node.name` of the stream.
+    pub(crate) name: String,
+    /// The `node.name` of the node to connect to, or `None` to follow the default node.
+    pub(crate) target: Option<String>,
+    pub(crate) config: StreamConfig,
+    pub(crate) sample_format: SampleFormat,
+    pub(crate) format: AudioFormat,
+    pub(crate) role: Option<StreamRole>,
+    /// Set after those derived from the other parameters, overriding them.
+    pub(crate) properties: Vec<(String, String)>,
+    /// Record the monitor of the target sink.
+    pub(crate) capture_sink: bool,
+}
+
+// Sent to the thread running the main loop of the stream.
+enum Command {
+    SetActive(bool, mpsc::Sender<Result<(), pw::Error>>),
+    Quit,
+}
+
+pub struct Stream {
+    commands: pw::channel::Sender<Command>,
+    thread: Option<AudioThread>,
+    latency: stream_thread::Latency,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+}
+
+impl Stream {
+    pub(crate) fn new_input<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let sample_format = params.sample_format;
+        let channels = params.config.channels as usize;
+        let sample_rate = params.config.sample_rate;
+        let latency = stream_thread::Latency::new();
+        let process_latency = latency.clone();
+        let process = move |stream: &StreamRef| {
+            let mut buffer = match stream.dequeue_buffer() {
+                Some(buffer) => buffer,
+                None => return,
+            };
+            let (callback, delay) = cycle_time(stream, sample_rate);
+            process_latency.set(delay);
+            let data = match buffer.datas_mut().first_mut() {
+                Some(data) => data,
+                None => return,
+            };
+            let chunk = data.chunk();
+            let (offset, size) = (chunk.offset() as usize, chunk.size() as usize);
+            let bytes = match data.data() {
+                Some(bytes) => bytes,
+                None => return,
+            };
+            let end = (offset + size).min(bytes.len());
+            let bytes = &mut bytes[offset.min(end)..end];
+            let frames = bytes.len() / (sample_format.sample_size() * channels);
+            let data = unsafe {
+                Data::from_parts(bytes.as_mut_ptr() as _, frames * channels, sample_format)
+            };
+            data_callback(&data, &input_info(callback, delay));
+        };
+        Stream::new(
+            Direction::Input,
+            params,
+            options,
+            thread_name,
+            watchdog,
+            latency,
+            error_callback,
+            process,
+        )
+    }
+
+    pub(crate) fn new_output<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let sample_format = params.sample_format;
+        let channels = params.config.channels as usize;
+        let sample_rate = params.config.sample_rate;
+        let frame_size = sample_format.sample_size() * channels;
+        let latency = stream_thread::Latency::new();
+        let process_latency = latency.clone();
+        let process = move |stream: &StreamRef| {
+            let mut buffer = match stream.dequeue_buffer() {
+                Some(buffer) => buffer,
+                None => return,
+            };
+            let (callback, delay) = cycle_time(stream, sample_rate);
+            process_latency.set(delay);
+            // The number of frames the graph asks for in this cycle, 0 if it doesn't tell.
+            let requested = buffer.requested() as usize;
+            let data = match buffer.datas_mut().first_mut() {
+                Some(data) => data,
+                None => return,
+            };
+            let bytes = match data.data() {
+                Some(bytes) => bytes,
+                None => return,
+            };
+            let mut frames = bytes.len() / frame_size;
+            if requested > 0 {
+                frames = frames.min(requested);
+            }
+            let mut output = unsafe {
+                Data::from_parts(bytes.as_mut_ptr() as _, frames * channels, sample_format)
+            };
+            data_callback(&mut output, &output_info(callback, delay));
+
+            let chunk = data.chunk_mut();
+            *chunk.offset_mut() = 0;
+            *chunk.stride_mut() = frame_size as _;
+            *chunk.size_mut() = (frames * frame_size) as _;
+        };
+        Stream::new(
+            Direction::Output,
+            params,
+            options,
+            thread_name,
+            watchdog,
+            latency,
+            error_callback,
+            process,
+        )
+    }
+
+    // Run the stream on a thread of its own, returning once it is connected.
+    #[allow(clippy::too_many_arguments)]
+    fn new<E, P>(
+        direction: Direction,
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        latency: stream_thread::Latency,
+        error_callback: E,
+        process: P,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+        P: FnMut(&StreamRef) + Send + 'static,
+    {
+        let (commands, commands_rx) = pw::channel::channel();
+        let thread = stream_thread::spawn("PipeWire", options, thread_name, move |built| {
+            run(
+                direction,
+                &params,
+                built,
+                commands_rx,
+                error_callback,
+                process,
+            );
+        })?;
+        Ok(Stream {
+            commands,
+            thread: Some(thread),
+            latency,
+            watchdog,
+        })
+    }
+
+    fn set_active(&self, active: bool) -> Result<(), BackendSpecificError> {
+        let (result_tx, result_rx) = mpsc::channel();
+        let exited = || BackendSpecificError {
+            description: "the PipeWire thread of the stream has exited".to_owned(),
+        };
+        if self
+            .commands
+            .send(Command::SetActive(active, result_tx))
+            .is_err()
+        {
+            return Err(exited());
+        }
+        result_rx.recv().map_err(|_| exited())?.map_err(pw_error)
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.set_active(true)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.set_active(false)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        Ok(())
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.latency.get()
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        self.thread.take().unwrap().join();
+    }
+}
+
+// The body of the thread of the stream: connect it, report the result through `built`, then run
+// the main loop until told to quit.
+fn run<E, P>(
+    direction: Direction,
+    params: &StreamParams,
+    built: Built,
+    commands: pw::channel::Receiver<Command>,
+    error_callback: E,
+    process: P,
+) where
+    E: FnMut(StreamError) + 'static,
+    P: FnMut(&StreamRef) + 'static,
+{
+    let connection = match Connection::new() {
+        Ok(connection) => connection,
+        Err(err) => {
+            let _ = built.send(Err(err.into()));
+            return;
+        }
+    };
+    let (stream, listener) = match connect(&connection, direction, params, error_callback, process)
+    {
+        Ok(stream) => stream,
+        Err(err) => {
+            let _ = built.send(Err(err));
+            return;
+        }
+    };
+    let main_loop = connection.main_loop.clone();
+    let command_stream = stream.clone();
+    let commands = commands.attach(connection.main_loop.loop_(), move |command| match command {
+        Command::SetActive(active, result) => {
+            let _ = result.send(command_stream.set_active(active));
+        }
+        Command::Quit => main_loop.quit(),
+    });
+    let _ = built.send(Ok(()));
+
+    connection.main_loop.run();
+
+    drop(commands);
+    drop(listener);
+    let _ = stream.disconnect();
+}
+
+type Listener = pw::stream::StreamListener<()>;
+
+fn connect<E, P>(
+    connection: &Connection,
+    direction: Direction,
+    params: &StreamParams,
+    mut error_callback: E,
+    mut process: P,
+) -> Result<(Rc<pw::stream::Stream>, Listener), BuildStreamError>
+where
+    E: FnMut(StreamError) + 'static,
+    P: FnMut(&StreamRef) + 'static,
+{
+    let category = if direction == Direction::Output {
+        "Playback"
+    } else {
+        "Capture"
+    };
+    let mut props = pw::properties::properties! {
+        *pw::keys::MEDIA_TYPE => "Audio",
+        *pw::keys::MEDIA_CATEGORY => category,
+        *pw::keys::APP_NAME => params.name.as_str(),
+        *pw::keys::NODE_NAME => params.name.as_str(),
+    };
+    if let Some(role) = params.role {
+        props.insert(*pw::keys::MEDIA_ROLE, media_role(role));
+    }
+    // Quantum negotiation: ask the graph to run at the requested buffer size. The graph runs at
+    // the lowest latency requested by its nodes, within its limits.
+    if let BufferSize::Fixed(frames) = params.config.buffer_size {
+        let latency = format!("{}/{}", frames, params.config.sample_rate.0);
+        props.insert(*pw::keys::NODE_LATENCY, latency);
+    }
+    if let Some(target) = &params.target {
+        props.insert(*pw::keys::TARGET_OBJECT, target.as_str());
+        // Report the removal of the node rather than moving to another one.
+        props.insert("node.dont-reconnect", "true");
+    }
+    if params.capture_sink {
+        props.insert("stream.capture.sink", "true");
+    }
+    for (key, value) in &params.properties {
+        props.insert(key.as_str(), value.as_str());
+    }
+
+    let stream =
+        pw::stream::Stream::new(&connection.core, &params.name, props).map_err(pw_error)?;
+    let listener = stream
+        .add_local_listener_with_user_data(())
+        .state_changed(move |_, _, old, new| match new {
+            StreamState::Error(description) => {
+                error_callback(BackendSpecificError { description }.into())
+            }
+            StreamState::Unconnected
+                if matches!(old, StreamState::Paused | StreamState::Streaming) =>
+            {
+                error_callback(StreamError::DeviceNotAvailable)
+            }
+            _ => {}
+        })
+        .process(move |stream, _| process(stream))
+        .register()
+        .map_err(pw_error)?;
+
+    let mut info = AudioInfoRaw::new();
+    info.set_format(params.format);
+    info.set_rate(params.config.sample_rate.0);
+    info.set_channels(params.config.channels as u32);
+    info.set_position(channel_positions(params.config.channels as usize));
+    let format = PodSerializer::serialize(
+        Cursor::new(Vec::new()),
+        &Value::Object(Object {
+            type_: spa_sys::SPA_TYPE_OBJECT_Format,
+            id: spa_sys::SPA_PARAM_EnumFormat,
+            properties: info.into(),
+        }),
+    )
+    .map_err(|err| BackendSpecificError {
+        description: format!("failed to serialize the format of the stream: {:?}", err),
+    })?
+    .0
+    .into_inner();
+    let mut pods = [Pod::from_bytes(&format).expect("the format was just serialized")];
+
+    // Streams are paused until played.
+    stream
+        .connect(
+            direction,
+            None,
+            StreamFlags::AUTOCONNECT
+                | StreamFlags::MAP_BUFFERS
+                | StreamFlags::RT_PROCESS
+                | StreamFlags::INACTIVE,
+            &mut pods,
+        )
+        .map_err(pw_error)?;
+    Ok((Rc::new(stream), listener))
+}
+
+// The `media.role` the session manager routes and sets the volume of streams by.
+fn media_role(role: StreamRole) -> &'static str {
+    match role {
+        StreamRole::Media => "Music",
+        StreamRole::Communications | StreamRole::GameChat => "Communication",
+        StreamRole::Game | StreamRole::GameMedia => "Game",
+        StreamRole::Movie => "Movie",
+        StreamRole::Speech => "Accessibility",
+        StreamRole::SoundEffects | StreamRole::Alert => "Notification",
+    }
+}
+
+// The usual positions of the channels of `channels`-channel interleaved audio.
+fn channel_positions(channels: usize) -> [u32; MAX_CHANNELS] {
+    let layout: &[u32] = match channels {
+        1 => &[spa_sys::SPA_AUDIO_CHANNEL_MONO],
+        2 => &[spa_sys::SPA_AUDIO_CHANNEL_FL, spa_sys::SPA_AUDIO_CHANNEL_FR],
+        4 => &[
+            spa_sys::SPA_AUDIO_CHANNEL_FL,
+            spa_sys::SPA_AUDIO_CHANNEL_FR,
+            spa_sys::SPA_AUDIO_CHANNEL_RL,
+            spa_sys::SPA_AUDIO_CHANNEL_RR,
+        ],
+        6 => &[
+            spa_sys::SPA_AUDIO_CHANNEL_FL,
+            spa_sys::SPA_AUDIO_CHANNEL_FR,
+            spa_sys::SPA_AUDIO_CHANNEL_FC,
+            spa_sys::SPA_AUDIO_CHANNEL_LFE,
+            spa_sys::SPA_AUDIO_CHANNEL_RL,
+            spa_sys::SPA_AUDIO_CHANNEL_RR,
+        ],
+        8 => &[
+            spa_sys::SPA_AUDIO_CHANNEL_FL,
+            spa_sys::SPA_AUDIO_CHANNEL_FR,
+            spa_sys::SPA_AUDIO_CHANNEL_FC,
+            spa_sys::SPA_AUDIO_CHANNEL_LFE,
+            spa_sys::SPA_AUDIO_CHANNEL_RL,
+            spa_sys::SPA_AUDIO_CHANNEL_RR,
+            spa_sys::SPA_AUDIO_CHANNEL_SL,
+            spa_sys::SPA_AUDIO_CHANNEL_SR,
+        ],
+        _ => &[],
+    };
+    let mut positions = [0; MAX_CHANNELS];
+    for (i, position) in positions.iter_mut().take(channels).enumerate() {
+        *position = layout
+            .get(i)
+            .copied()
+            .unwrap_or(spa_sys::SPA_AUDIO_CHANNEL_AUX0 + i as u32);
+    }
+    positions
+}
+
+// The time of the current cycle of the graph, and the delay of the samples of the stream, i.e.
+// until they are played or since they were captured.
+fn cycle_time(stream: &StreamRef, sample_rate: SampleRate) -> (StreamInstant, Duration) {
+    let mut time: pw::sys::pw_time = unsafe { mem::zeroed() };
+    unsafe {
+        pw::sys::pw_stream_get_time_n(stream.as_raw_ptr(), &mut time, mem::size_of_val(&time));
+    }
+    let now = if time.now > 0 {
+        StreamInstant::from_nanos(time.now)
+    } else {
+        stream_thread::now()
+    };
+    // The delay of the graph is counted in ticks of its clock, `time.rate` seconds each, and that
+    // of the resampler of the stream in frames.
+    let graph_delay = if time.rate.denom > 0 {
+        time.delay.max(0) as f64 * time.rate.num as f64 / time.rate.denom as f64
+    } else {
+        0.0
+    };
+    let buffered = time.buffered as f64 / sample_rate.0 as f64;
+    (now, Duration::from_secs_f64(graph_delay + buffered))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{channel_positions, spa_sys};
+
+    #[test]
+    fn channel_positions_of_layouts() {
+        let positions = channel_positions(2);
+        assert_eq!(
+            positions[..2],
+            [spa_sys::SPA_AUDIO_CHANNEL_FL, spa_sys::SPA_AUDIO_CHANNEL_FR]
+        );
+        assert!(positions[2..].iter().all(|&position| position == 0));
+        assert_eq!(channel_positions(6)[3], spa_sys::SPA_AUDIO_CHANNEL_LFE);
+    }
+
+    #[test]
+    fn channel_positions_without_layout() {
+        let positions = channel_positions(3);
+        assert_eq!(
+            positions[..3],
+            [
+                spa_sys::SPA_AUDIO_CHANNEL_AUX0,
+                spa_sys::SPA_AUDIO_CHANNEL_AUX0 + 1,
+                spa_sys::SPA_AUDIO_CHANNEL_AUX0 + 2,
+            ]
+        );
+    }
+}
@@ -0,0 +1,399 @@
+use libpulse_binding as pulse;
+
+use crate::thread;
+use crate::traits::DeviceTrait;
+use crate::watchdog;
+use crate::{
+    BufferSize, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError, DeviceNameError,
+    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use pulse::sample::Format;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use super::stream::{Stream, StreamParams};
+use super::{Endpoint, Server};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+const DEFAULT_NUM_CHANNELS: ChannelCount = 2;
+const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(48_000);
+// The period streams without a fixed buffer size are written and read in, which the watchdog
+// expects.
+pub(crate) const DEFAULT_PERIOD: u32 = 1024;
+// The server resamples every stream to the rate of its sink or source.
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(384_000);
+// The formats the server converts to and from that of its sinks and sources.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 4] = [
+    SampleFormat::F32,
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::U8,
+];
+
+/// A PulseAudio sink or source, or the default ones chosen by the server.
+#[derive(Clone, Debug)]
+pub struct Device {
+    /// The application name of the streams built from this device.
+    stream_name: String,
+    /// `None` for the default device, whose streams follow the default sink and source as they
+    /// change.
+    name: Option<String>,
+    description: String,
+    is_input: bool,
+    is_output: bool,
+    is_monitor: bool,
+    channels: ChannelCount,
+    sample_rate: SampleRate,
+}
+
+impl Device {
+    pub(crate) fn default_device(stream_name: &str, server: &Server) -> Self {
+        Device {
+            stream_name: stream_name.to_owned(),
+            name: None,
+            description: "default".to_owned(),
+            is_input: true,
+            is_output: true,
+            is_monitor: false,
+            channels: channels(server.channels),
+            sample_rate: sample_rate(server.rate.0),
+        }
+    }
+
+    pub(crate) fn from_sink(stream_name: &str, sink: &Endpoint) -> Self {
+        Device {
+            is_input: false,
+            is_output: true,
+            ..Device::from_endpoint(stream_name, sink)
+        }
+    }
+
+    pub(crate) fn from_source(stream_name: &str, source: &Endpoint) -> Self {
+        Device {
+            is_input: true,
+            is_output: false,
+            ..Device::from_endpoint(stream_name, source)
+        }
+    }
+
+    fn from_endpoint(stream_name: &str, endpoint: &Endpoint) -> Self {
+        Device {
+            stream_name: stream_name.to_owned(),
+            name: Some(endpoint.name.clone()),
+            description: endpoint
+                .description
+                .clone()
+                .unwrap_or_else(|| endpoint.name.clone()),
+            is_input: false,
+            is_output: false,
+            is_monitor: endpoint.is_monitor,
+            channels: channels(endpoint.channels),
+            sample_rate: sample_rate(endpoint.rate),
+        }
+    }
+
+    /// The name of the sink or source the streams of this device are connected to, or `None` for
+    /// the default device.
+    pub fn pulse_name(&self) -> Option<&str> {
+        self.name.as_deref()
+    }
+
+    /// Whether this is the monitor source of a sink, whose input streams record what is played to
+    /// the sink, e.g. to record the audio of other applications.
+    pub fn is_loopback(&self) -> bool {
+        self.is_monitor
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.is_input
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.is_output
+    }
+
+    fn default_config(&self) -> SupportedStreamConfig {
+        SupportedStreamConfig {
+            channels: self.channels,
+            sample_rate: self.sample_rate,
+            buffer_size: SupportedBufferSize::Unknown,
+            sample_format: SampleFormat::F32,
+        }
+    }
+
+    fn supported_configs(&self) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=self.channels {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Unknown,
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    // The parameters of a stream with `conf`, if the device can take them.
+    fn stream_params(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+    ) -> Result<StreamParams, BuildStreamError> {
+        if !options.is_shared_pcm() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let format =
+            sample_spec_format(sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+        if conf.channels == 0
+            || conf.channels > pulse::sample::Spec::CHANNELS_MAX as ChannelCount
+            || conf.sample_rate < MIN_SAMPLE_RATE
+            || conf.sample_rate > MAX_SAMPLE_RATE
+            || conf.buffer_size == BufferSize::Fixed(0)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        Ok(StreamParams {
+            name: self.stream_name.clone(),
+            device: self.name.clone(),
+            config: conf.clone(),
+            sample_format,
+            format,
+            role: options.role,
+            properties: options.properties.clone(),
+        })
+    }
+}
+
+fn channels(channels: u8) -> ChannelCount {
+    match channels {
+        0 => DEFAULT_NUM_CHANNELS,
+        channels => channels as ChannelCount,
+    }
+}
+
+fn sample_rate(rate: u32) -> SampleRate {
+    match rate {
+        0 => DEFAULT_SAMPLE_RATE,
+        rate => SampleRate(rate),
+    }
+}
+
+// The PulseAudio format of samples in `sample_format`, in native byte order.
+fn sample_spec_format(sample_format: SampleFormat) -> Option<Format> {
+    let format = match sample_format {
+        SampleFormat::U8 => Format::U8,
+        SampleFormat::I16 => Format::S16NE,
+        SampleFormat::I32 => Format::S32NE,
+        SampleFormat::F32 => Format::FLOAT32NE,
+        _ => return None,
+    };
+    Some(format)
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.description.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        if !self.is_input {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        if !self.is_output {
+            return Ok(vec![].into_iter());
+        }
+        Ok(self.supported_configs().into_iter())
+    }
+
+    /// Returns the default input config
+    /// The channels and sample rate are those of the source, or of the server for the default
+    /// device, in 32-bit float samples.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_input {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    /// Returns the default output config
+    /// The channels and sample rate are those of the sink, or of the server for the default
+    /// device, in 32-bit float samples.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_output {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        Ok(self.default_config())
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_input(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_input(
+            params,
+            options,
+            thread::thread_name(options, "cpal_pulse_in")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_output {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_output(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_output(
+            params,
+            options,
+            thread::thread_name(options, "cpal_pulse_out")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{channels, sample_rate, sample_spec_format, Format};
+    use super::{DEFAULT_NUM_CHANNELS, DEFAULT_SAMPLE_RATE};
+    use crate::{SampleFormat, SampleRate};
+
+    #[test]
+    fn unset_sample_spec_defaults() {
+        assert_eq!(channels(0), DEFAULT_NUM_CHANNELS);
+        assert_eq!(channels(6), 6);
+        assert_eq!(sample_rate(0), DEFAULT_SAMPLE_RATE);
+        assert_eq!(sample_rate(96_000), SampleRate(96_000));
+    }
+
+    #[test]
+    fn sample_spec_format_native_endian() {
+        assert_eq!(sample_spec_format(SampleFormat::U8), Some(Format::U8));
+        let (s16, f32) = if cfg!(target_endian = "little") {
+            (Format::S16le, Format::F32le)
+        } else {
+            (Format::S16be, Format::F32be)
+        };
+        assert_eq!(sample_spec_format(SampleFormat::I16), Some(s16));
+        assert_eq!(sample_spec_format(SampleFormat::F32), Some(f32));
+        assert_eq!(sample_spec_format(SampleFormat::U16), None);
+    }
+}
@@ -0,0 +1,237 @@
+//! A native PulseAudio host, playing and recording through streams of the asynchronous API.
+//!
+//! Unlike going through the ALSA `pulse` plugin, streams are connected to the sinks and sources of
+//! the server by name, monitors of sinks included, carry the properties of the application,
+//! negotiate their buffer with the server and report the latency measured by it.
+
+use libpulse_binding as pulse;
+
+use crate::traits::HostTrait;
+use crate::{BackendSpecificError, DevicesError, SampleRate, SupportedStreamConfigRange};
+use pulse::callbacks::ListResult;
+use pulse::context::{Context, FlagSet as ContextFlagSet, State as ContextState};
+use pulse::mainloop::standard::{IterateResult, Mainloop};
+use pulse::operation::{Operation, State as OperationState};
+use pulse::proplist::{properties, Proplist};
+use std::cell::RefCell;
+use std::rc::Rc;
+
+mod device;
+pub use self::device::Device;
+pub use self::stream::Stream;
+mod stream;
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type Devices = std::vec::IntoIter<Device>;
+
+/// The PulseAudio Host type
+#[derive(Debug)]
+pub struct Host {
+    /// The `application.name` of the streams created from this host, shown by mixers.
+    name: String,
+}
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        let name = std::env::current_exe()
+            .ok()
+            .and_then(|exe| Some(exe.file_stem()?.to_string_lossy().into_owned()))
+            .unwrap_or_else(|| "cpal".to_owned());
+        Ok(Host { name })
+    }
+
+    /// Set the application name of the streams built from the devices of this host, the name of
+    /// the executable by default.
+    pub fn set_name(&mut self, name: &str) {
+        self.name = name.to_owned();
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    /// PulseAudio is available if a PulseAudio server, or PipeWire's replacement of it, can be
+    /// connected to.
+    fn is_available() -> bool {
+        Connection::new("cpal").is_ok()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let server = Connection::new(&self.name)?.server()?;
+        let mut devices = vec![Device::default_device(&self.name, &server)];
+        devices.extend(
+            server
+                .sinks
+                .iter()
+                .map(|sink| Device::from_sink(&self.name, sink)),
+        );
+        devices.extend(
+            server
+                .sources
+                .iter()
+                .map(|source| Device::from_source(&self.name, source)),
+        );
+        Ok(devices.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        let server = Connection::new(&self.name)
+            .and_then(|mut c| c.server())
+            .ok()?;
+        Some(Device::default_device(&self.name, &server))
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        self.default_input_device()
+    }
+}
+
+/// A context connected to the PulseAudio server, on a main loop iterated by the current thread.
+pub(crate) struct Connection {
+    mainloop: Mainloop,
+    context: Context,
+}
+
+/// The sample spec of the server and the sinks and sources that streams can be connected to.
+pub(crate) struct Server {
+    rate: SampleRate,
+    channels: u8,
+    sinks: Vec<Endpoint>,
+    sources: Vec<Endpoint>,
+}
+
+/// A sink or a source.
+pub(crate) struct Endpoint {
+    name: String,
+    description: Option<String>,
+    channels: u8,
+    rate: u32,
+    /// Whether this is the monitor source of a sink, recording what is played to it.
+    is_monitor: bool,
+}
+
+impl Connection {
+    /// Connect a context called `name` to the server, waiting until it is ready.
+    pub(crate) fn new(name: &str) -> Result<Self, BackendSpecificError> {
+        let mut proplist = Proplist::new().ok_or_else(|| error("failed to create a proplist"))?;
+        let _ = proplist.set_str(properties::APPLICATION_NAME, name);
+        let mut mainloop = Mainloop::new().ok_or_else(|| error("failed to create a main loop"))?;
+        let mut context = Context::new_with_proplist(&mainloop, name, &proplist)
+            .ok_or_else(|| error("failed to create a context"))?;
+        // Don't start a server just to find out that there is none.
+        context
+            .connect(None, ContextFlagSet::NOAUTOSPAWN, None)
+            .map_err(pa_error)?;
+        loop {
+            match context.get_state() {
+                ContextState::Ready => break,
+                ContextState::Failed | ContextState::Terminated => {
+                    return Err(pa_error(context.errno()))
+                }
+                _ => iterate(&mut mainloop)?,
+            }
+        }
+        Ok(Connection { mainloop, context })
+    }
+
+    /// Ask the server for its sample spec, sinks and sources, waiting for the replies.
+    pub(crate) fn server(&mut self) -> Result<Server, BackendSpecificError> {
+        let server = Rc::new(RefCell::new(Server {
+            rate: SampleRate(0),
+            channels: 0,
+            sinks: vec![],
+            sources: vec![],
+        }));
+        let introspect = self.context.introspect();
+
+        let info = introspect.get_server_info({
+            let server = server.clone();
+            move |info| {
+                let mut server = server.borrow_mut();
+                server.rate = SampleRate(info.sample_spec.rate);
+                server.channels = info.sample_spec.channels;
+            }
+        });
+        self.wait_for(&info)?;
+
+        let sinks = introspect.get_sink_info_list({
+            let server = server.clone();
+            move |result| {
+                if let ListResult::Item(sink) = result {
+                    if let Some(name) = &sink.name {
+                        server.borrow_mut().sinks.push(Endpoint {
+                            name: name.to_string(),
+                            description: sink.description.as_ref().map(|d| d.to_string()),
+                            channels: sink.sample_spec.channels,
+                            rate: sink.sample_spec.rate,
+                            is_monitor: false,
+                        });
+                    }
+                }
+            }
+        });
+        self.wait_for(&sinks)?;
+
+        let sources = introspect.get_source_info_list({
+            let server = server.clone();
+            move |result| {
+                if let ListResult::Item(source) = result {
+                    if let Some(name) = &source.name {
+                        server.borrow_mut().sources.push(Endpoint {
+                            name: name.to_string(),
+                            description: source.description.as_ref().map(|d| d.to_string()),
+                            channels: source.sample_spec.channels,
+                            rate: source.sample_spec.rate,
+                            is_monitor: source.monitor_of_sink.is_some(),
+                        });
+                    }
+                }
+            }
+        });
+        self.wait_for(&sources)?;
+
+        // Release the server held by the callbacks.
+        drop((info, sinks, sources));
+        Ok(Rc::try_unwrap(server)
+            .ok()
+            .expect("the callbacks holding the server were dropped")
+            .into_inner())
+    }
+
+    /// Iterate the main loop until `operation` is done.
+    pub(crate) fn wait_for<C: ?Sized>(
+        &mut self,
+        operation: &Operation<C>,
+    ) -> Result<(), BackendSpecificError> {
+        loop {
+            match operation.get_state() {
+                OperationState::Done => return Ok(()),
+                OperationState::Cancelled => return Err(pa_error(self.context.errno())),
+                OperationState::Running => iterate(&mut self.mainloop)?,
+            }
+        }
+    }
+}
+
+/// Run one iteration of `mainloop`, blocking until there is something to dispatch.
+pub(crate) fn iterate(mainloop: &mut Mainloop) -> Result<(), BackendSpecificError> {
+    match mainloop.iterate(true) {
+        IterateResult::Success(_) => Ok(()),
+        IterateResult::Quit(_) => Err(error("the main loop was quit")),
+        IterateResult::Err(err) => Err(pa_error(err)),
+    }
+}
+
+pub(crate) fn pa_error(err: pulse::error::PAErr) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("PulseAudio: {}", err),
+    }
+}
+
+fn error(description: &str) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("PulseAudio: {}", description),
+    }
+}
@@ -0,0 +1,527 @@
+use libpulse_binding as pulse;
+use libpulse_sys as pulse_sys;
+
+use crate::stream_thread::{self, input_info, now, output_info, Built};
+use crate::thread::AudioThread;
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
+    OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, StreamConfig, StreamError,
+    StreamOptions, StreamRole,
+};
+use parking_lot::Mutex;
+use pulse::channelmap::{Map, MapDef};
+use pulse::def::BufferAttr;
+use pulse::error::{Code, PAErr};
+use pulse::mainloop::api::MainloopInnerType;
+use pulse::mainloop::standard::MainloopInternal;
+use pulse::proplist::{properties, Proplist};
+use pulse::sample::{Format, Spec};
+use pulse::stream::{
+    Direction, FlagSet as StreamFlagSet, Latency, PeekResult, SeekMode, State as StreamState,
+};
+use pulse::volume::{ChannelVolumes, Volume, VolumeLinear};
+use std::cell::{Cell, RefCell};
+use std::rc::Rc;
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+use super::device::DEFAULT_PERIOD;
+use super::{error, iterate, pa_error, Connection};
+
+/// The server-side properties and format of a stream, checked by its device.
+pub(crate) struct StreamParams {
+    /// The `application.name` and `media.name` of the stream.
+    pub(crate) name: String,
+    /// The name of the sink or source to connect to, or `None` to follow the default one.
+    pub(crate) device: Option<String>,
+    pub(crate) config: StreamConfig,
+    pub(crate) sample_format: SampleFormat,
+    pub(crate) format: Format,
+    pub(crate) role: Option<StreamRole>,
+    /// Set after those derived from the other parameters, overriding them.
+    pub(crate) properties: Vec<(String, String)>,
+}
+
+type Reply = mpsc::Sender<Result<(), BackendSpecificError>>;
+
+// Sent to the thread running the main loop of the stream.
+enum Command {
+    SetCorked(bool, Reply),
+    SetVolume(f32, Reply),
+    Quit,
+}
+
+// Interrupts the blocking iteration of the main loop of a stream.
+struct Waker(*mut MainloopInternal);
+
+// `pa_mainloop_wakeup` may be called from any thread, for as long as the main loop is alive.
+unsafe impl Send for Waker {}
+
+type PaStream = pulse::stream::Stream;
+
+pub struct Stream {
+    commands: mpsc::Sender<Command>,
+    // Taken by the thread of the stream before it frees the main loop.
+    waker: Arc<Mutex<Option<Waker>>>,
+    thread: Option<AudioThread>,
+    latency: stream_thread::Latency,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+}
+
+impl Stream {
+    pub(crate) fn new_input<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let sample_format = params.sample_format;
+        let channels = params.config.channels as usize;
+        let frame_size = sample_format.sample_size() * channels;
+        let latency = stream_thread::Latency::new();
+        let process_latency = latency.clone();
+        let process = move |stream: &mut PaStream, _: usize| loop {
+            match stream.peek()? {
+                PeekResult::Empty => return Ok(()),
+                PeekResult::Hole(_) => {}
+                PeekResult::Data(bytes) => {
+                    let delay = stream_delay(stream);
+                    process_latency.set(delay);
+                    let frames = bytes.len() / frame_size;
+                    let data = unsafe {
+                        Data::from_parts(bytes.as_ptr() as _, frames * channels, sample_format)
+                    };
+                    data_callback(&data, &input_info(now(), delay));
+                }
+            }
+            stream.discard()?;
+        };
+        Stream::new(
+            Direction::Record,
+            params,
+            options,
+            thread_name,
+            watchdog,
+            latency,
+            error_callback,
+            process,
+        )
+    }
+
+    pub(crate) fn new_output<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        mut data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let sample_format = params.sample_format;
+        let channels = params.config.channels as usize;
+        let frame_size = sample_format.sample_size() * channels;
+        let latency = stream_thread::Latency::new();
+        let process_latency = latency.clone();
+        let process = move |stream: &mut PaStream, nbytes: usize| {
+            let bytes = match stream.begin_write(Some(nbytes))? {
+                Some(bytes) => bytes,
+                None => return Ok(()),
+            };
+            let frames = bytes.len() / frame_size;
+            let bytes = &mut bytes[..frames * frame_size];
+            let delay = stream_delay(stream);
+            process_latency.set(delay);
+            let mut data = unsafe {
+                Data::from_parts(bytes.as_mut_ptr() as _, frames * channels, sample_format)
+            };
+            data_callback(&mut data, &output_info(now(), delay));
+            stream.write(bytes, None, 0, SeekMode::Relative)
+        };
+        Stream::new(
+            Direction::Playback,
+            params,
+            options,
+            thread_name,
+            watchdog,
+            latency,
+            error_callback,
+            process,
+        )
+    }
+
+    // Run the stream on a thread of its own, returning once it is connected.
+    #[allow(clippy::too_many_arguments)]
+    fn new<E, P>(
+        direction: Direction,
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        latency: stream_thread::Latency,
+        error_callback: E,
+        process: P,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+        P: FnMut(&mut PaStream, usize) -> Result<(), PAErr> + Send + 'static,
+    {
+        let (commands, commands_rx) = mpsc::channel();
+        let waker = Arc::new(Mutex::new(None));
+        let thread_waker = waker.clone();
+        let thread = stream_thread::spawn("PulseAudio", options, thread_name, move |built| {
+            run(
+                direction,
+                &params,
+                built,
+                commands_rx,
+                &thread_waker,
+                error_callback,
+                process,
+            );
+        })?;
+        Ok(Stream {
+            commands,
+            waker,
+            thread: Some(thread),
+            latency,
+            watchdog,
+        })
+    }
+
+    // Send `command` to the thread of the stream and wait for its reply.
+    fn request(&self, command: impl FnOnce(Reply) -> Command) -> Result<(), BackendSpecificError> {
+        let (result_tx, result_rx) = mpsc::channel();
+        let exited = || error("the thread of the stream has exited");
+        {
+            let waker = self.waker.lock();
+            let waker = waker.as_ref().ok_or_else(exited)?;
+            self.commands
+                .send(command(result_tx))
+                .map_err(|_| exited())?;
+            unsafe { pulse_sys::pa_mainloop_wakeup(waker.0) };
+        }
+        result_rx.recv().map_err(|_| exited())?
+    }
+
+    /// Set the volume of this stream in the mixer of the server as a linear factor applied to all
+    /// of its channels, from 0.0 (muted) through 1.0 (unchanged) to amplification above.
+    pub fn set_volume(&self, volume: f32) -> Result<(), BackendSpecificError> {
+        self.request(|reply| Command::SetVolume(volume, reply))
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.request(|reply| Command::SetCorked(false, reply))?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.request(|reply| Command::SetCorked(true, reply))?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        Ok(())
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.latency.get()
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        if let Some(waker) = self.waker.lock().as_ref() {
+            let _ = self.commands.send(Command::Quit);
+            unsafe { pulse_sys::pa_mainloop_wakeup(waker.0) };
+        }
+        self.thread.take().unwrap().join();
+    }
+}
+
+// The body of the thread of the stream: connect it, report the result through `built`, then
+// iterate the main loop until told to quit.
+fn run<E, P>(
+    direction: Direction,
+    params: &StreamParams,
+    built: Built,
+    commands: mpsc::Receiver<Command>,
+    waker: &Mutex<Option<Waker>>,
+    error_callback: E,
+    process: P,
+) where
+    E: FnMut(StreamError) + 'static,
+    P: FnMut(&mut PaStream, usize) -> Result<(), PAErr> + 'static,
+{
+    let mut connection = match Connection::new(&params.name) {
+        Ok(connection) => connection,
+        Err(err) => {
+            let _ = built.send(Err(err.into()));
+            return;
+        }
+    };
+    let error_callback = Rc::new(RefCell::new(error_callback));
+    let process = Rc::new(RefCell::new(process));
+    // Output streams are asked for data as soon as they are created, which is only given once
+    // they are played.
+    let playing = Rc::new(Cell::new(false));
+    let stream = match connect(
+        &mut connection,
+        direction,
+        params,
+        &error_callback,
+        &process,
+        &playing,
+    ) {
+        Ok(stream) => stream,
+        Err(err) => {
+            let _ = built.send(Err(err));
+            return;
+        }
+    };
+    *waker.lock() = Some(Waker(connection.mainloop._inner.get_ptr()));
+    let _ = built.send(Ok(()));
+
+    let mut failed = false;
+    'run: loop {
+        while let Ok(command) = commands.try_recv() {
+            match command {
+                Command::SetCorked(corked, reply) => {
+                    if !corked && !playing.get() {
+                        playing.set(true);
+                        // Fill the buffer before it starts playing.
+                        let mut stream = stream.borrow_mut();
+                        match stream.writable_size() {
+                            Some(nbytes) if nbytes > 0 && direction == Direction::Playback => {
+                                if let Err(err) = (process.borrow_mut())(&mut stream, nbytes) {
+                                    (error_callback.borrow_mut())(pa_error(err).into());
+                                }
+                            }
+                            _ => {}
+                        }
+                    }
+                    if corked {
+                        playing.set(false);
+                    }
+                    stream.borrow_mut().set_corked_state(
+                        corked,
+                        Some(Box::new(move |success| {
+                            let _ = reply.send(if success {
+                                Ok(())
+                            } else {
+                                Err(error("failed to change the corked state of the stream"))
+                            });
+                        })),
+                    );
+                }
+                Command::SetVolume(volume, reply) => {
+                    let index = match stream.borrow().get_index() {
+                        Some(index) => index,
+                        None => {
+                            let _ = reply.send(Err(error("the stream has no index")));
+                            continue;
+                        }
+                    };
+                    let mut volumes = ChannelVolumes::default();
+                    volumes.set(
+                        params.config.channels as u8,
+                        Volume::from(VolumeLinear(volume.max(0.0) as f64)),
+                    );
+                    let callback: Box<dyn FnMut(bool)> = Box::new(move |success| {
+                        let _ = reply.send(if success {
+                            Ok(())
+                        } else {
+                            Err(error("failed to set the volume of the stream"))
+                        });
+                    });
+                    let mut introspect = connection.context.introspect();
+                    if direction == Direction::Playback {
+                        introspect.set_sink_input_volume(index, &volumes, Some(callback));
+                    } else {
+                        introspect.set_source_output_volume(index, &volumes, Some(callback));
+                    }
+                }
+                Command::Quit => break 'run,
+            }
+        }
+
+        if let Err(err) = iterate(&mut connection.mainloop) {
+            (error_callback.borrow_mut())(err.into());
+            break;
+        }
+        if !failed && stream.borrow().get_state() == StreamState::Failed {
+            failed = true;
+            // Streams that don't follow the default sink or source are killed when theirs is
+            // removed.
+            let err = match connection.context.errno() {
+                err if err == PAErr::from(Code::Killed) => StreamError::DeviceNotAvailable,
+                err => pa_error(err).into(),
+            };
+            (error_callback.borrow_mut())(err);
+        }
+    }
+
+    *waker.lock() = None;
+    let mut stream = stream.borrow_mut();
+    stream.set_write_callback(None);
+    stream.set_read_callback(None);
+    let _ = stream.disconnect();
+}
+
+fn connect<E, P>(
+    connection: &mut Connection,
+    direction: Direction,
+    params: &StreamParams,
+    error_callback: &Rc<RefCell<E>>,
+    process: &Rc<RefCell<P>>,
+    playing: &Rc<Cell<bool>>,
+) -> Result<Rc<RefCell<PaStream>>, BuildStreamError>
+where
+    E: FnMut(StreamError) + 'static,
+    P: FnMut(&mut PaStream, usize) -> Result<(), PAErr> + 'static,
+{
+    let spec = Spec {
+        format: params.format,
+        rate: params.config.sample_rate.0,
+        channels: params.config.channels as u8,
+    };
+    if !spec.is_valid() {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    // The channel order of WAVEFORMATEXTENSIBLE, that of the other hosts.
+    let mut map = Map::default();
+    map.init_extend(spec.channels, MapDef::WAVEEx);
+
+    let mut proplist = Proplist::new().ok_or_else(|| error("failed to create a proplist"))?;
+    let _ = proplist.set_str(properties::APPLICATION_NAME, &params.name);
+    let _ = proplist.set_str(properties::MEDIA_NAME, &params.name);
+    if let Some(role) = params.role {
+        let _ = proplist.set_str(properties::MEDIA_ROLE, media_role(role));
+    }
+    for (key, value) in &params.properties {
+        proplist
+            .set_str(key, value)
+            .map_err(|()| error(&format!("invalid stream property {}", key)))?;
+    }
+    let stream = PaStream::new_with_proplist(
+        &mut connection.context,
+        &params.name,
+        &spec,
+        Some(&map),
+        &mut proplist,
+    )
+    .ok_or_else(|| pa_error(connection.context.errno()))?;
+    let stream = Rc::new(RefCell::new(stream));
+
+    // The callbacks hold the stream weakly, as it owns them.
+    let callback = {
+        let stream = Rc::downgrade(&stream);
+        let error_callback = error_callback.clone();
+        let process = process.clone();
+        let playing = playing.clone();
+        move |nbytes| {
+            let stream = match stream.upgrade() {
+                Some(stream) if playing.get() => stream,
+                _ => return,
+            };
+            let result = (process.borrow_mut())(&mut stream.borrow_mut(), nbytes);
+            if let Err(err) = result {
+                (error_callback.borrow_mut())(pa_error(err).into());
+            }
+        }
+    };
+
+    // The server sizes its buffer by the period the stream is written or read in, only keeping
+    // two of them queued for playback.
+    let frames = match params.config.buffer_size {
+        BufferSize::Fixed(frames) => frames,
+        BufferSize::Default => DEFAULT_PERIOD,
+    };
+    let period = (frames as usize * spec.frame_size()).min(u32::MAX as usize / 2) as u32;
+    let mut flags = StreamFlagSet::INTERPOLATE_TIMING
+        | StreamFlagSet::AUTO_TIMING_UPDATE
+        | StreamFlagSet::ADJUST_LATENCY
+        | StreamFlagSet::START_CORKED;
+    if params.device.is_some() {
+        // Report the removal of the sink or source rather than moving to another one.
+        flags |= StreamFlagSet::DONT_MOVE;
+    }
+    {
+        let mut stream = stream.borrow_mut();
+        let result = if direction == Direction::Playback {
+            stream.set_write_callback(Some(Box::new(callback)));
+            let attr = BufferAttr {
+                maxlength: u32::MAX,
+                tlength: period * 2,
+                prebuf: u32::MAX,
+                minreq: period,
+                fragsize: u32::MAX,
+            };
+            stream.connect_playback(params.device.as_deref(), Some(&attr), flags, None, None)
+        } else {
+            stream.set_read_callback(Some(Box::new(callback)));
+            let attr = BufferAttr {
+                maxlength: u32::MAX,
+                tlength: u32::MAX,
+                prebuf: u32::MAX,
+                minreq: u32::MAX,
+                fragsize: period,
+            };
+            stream.connect_record(params.device.as_deref(), Some(&attr), flags)
+        };
+        result.map_err(pa_error)?;
+    }
+
+    loop {
+        match stream.borrow().get_state() {
+            StreamState::Ready => break,
+            StreamState::Failed | StreamState::Terminated => {
+                let err = connection.context.errno();
+                return Err(if err == PAErr::from(Code::NoEntity) {
+                    BuildStreamError::DeviceNotAvailable
+                } else {
+                    pa_error(err).into()
+                });
+            }
+            _ => {}
+        }
+        iterate(&mut connection.mainloop)?;
+    }
+    Ok(stream)
+}
+
+// The `media.role` the server routes and sets the volume of streams by.
+fn media_role(role: StreamRole) -> &'static str {
+    match role {
+        StreamRole::Media => "music",
+        StreamRole::Communications | StreamRole::GameChat => "phone",
+        StreamRole::Game | StreamRole::GameMedia => "game",
+        StreamRole::Movie => "video",
+        StreamRole::Speech => "a11y",
+        StreamRole::SoundEffects | StreamRole::Alert => "event",
+    }
+}
+
+// The delay of the samples of the stream, i.e. until they are played or since they were captured,
+// as measured by the server.
+fn stream_delay(stream: &PaStream) -> Duration {
+    match stream.get_latency() {
+        Ok(Latency::Positive(latency)) => Duration::from_micros(latency.0),
+        _ => Duration::ZERO,
+    }
+}
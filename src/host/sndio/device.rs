@@ -0,0 +1,340 @@
+use crate::thread;
+use crate::traits::DeviceTrait;
+use crate::watchdog;
+use crate::{
+    BufferSize, BuildStreamError, ChannelCount, Data, DefaultStreamConfigError, DeviceNameError,
+    InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig, StreamError,
+    StreamOptions, SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
+};
+use libc::c_uint;
+use std::hash::{Hash, Hasher};
+use std::time::Duration;
+
+use super::stream::{Stream, StreamParams};
+use super::{ffi, Handle};
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+// The period streams without a fixed buffer size are written and read in, which the watchdog
+// expects.
+pub(crate) const DEFAULT_PERIOD: u32 = 960;
+// The server resamples every stream to the rate of its device.
+const MIN_SAMPLE_RATE: SampleRate = SampleRate(4_000);
+const MAX_SAMPLE_RATE: SampleRate = SampleRate(192_000);
+const MAX_CHANNELS: ChannelCount = 64;
+// The formats the server converts to and from that of its devices.
+const SUPPORTED_SAMPLE_FORMATS: [SampleFormat; 6] = [
+    SampleFormat::I16,
+    SampleFormat::I32,
+    SampleFormat::I8,
+    SampleFormat::U8,
+    SampleFormat::U16,
+    SampleFormat::U32,
+];
+
+/// A sndio device, e.g. `snd/0` of the server.
+#[derive(Clone, Debug)]
+pub struct Device {
+    name: String,
+    /// The default configs of the device for recording and playback, `None` if it can't be opened
+    /// for them.
+    input: Option<SupportedStreamConfig>,
+    output: Option<SupportedStreamConfig>,
+}
+
+impl Device {
+    /// Open the device called `name` for recording and playback, `None` if it can be opened for
+    /// neither.
+    pub(crate) fn open(name: &str) -> Option<Self> {
+        let input = default_config(name, ffi::SIO_REC);
+        let output = default_config(name, ffi::SIO_PLAY);
+        if input.is_none() && output.is_none() {
+            return None;
+        }
+        Some(Device {
+            name: name.to_owned(),
+            input,
+            output,
+        })
+    }
+
+    pub fn is_input(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn is_output(&self) -> bool {
+        self.output.is_some()
+    }
+
+    fn supported_configs(
+        &self,
+        default: &SupportedStreamConfig,
+    ) -> Vec<SupportedStreamConfigRange> {
+        let mut supported_configs = vec![];
+        for channels in 1..=default.channels {
+            for &sample_format in SUPPORTED_SAMPLE_FORMATS.iter() {
+                supported_configs.push(SupportedStreamConfigRange {
+                    channels,
+                    min_sample_rate: MIN_SAMPLE_RATE,
+                    max_sample_rate: MAX_SAMPLE_RATE,
+                    buffer_size: SupportedBufferSize::Unknown,
+                    sample_format,
+                });
+            }
+        }
+        supported_configs
+    }
+
+    // The parameters of a stream with `conf`, if the device can take them.
+    fn stream_params(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+    ) -> Result<StreamParams, BuildStreamError> {
+        if !options.is_shared_pcm() || encoding(sample_format).is_none() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if conf.channels == 0
+            || conf.channels > MAX_CHANNELS
+            || conf.sample_rate < MIN_SAMPLE_RATE
+            || conf.sample_rate > MAX_SAMPLE_RATE
+            || conf.buffer_size == BufferSize::Fixed(0)
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        Ok(StreamParams {
+            device: self.name.clone(),
+            config: conf.clone(),
+            sample_format,
+        })
+    }
+}
+
+// The config the device called `name` is opened with for `mode`.
+fn default_config(name: &str, mode: c_uint) -> Option<SupportedStreamConfig> {
+    let par = Handle::open(name, mode)?.par()?;
+    let channels = if mode == ffi::SIO_REC {
+        par.rchan
+    } else {
+        par.pchan
+    };
+    Some(SupportedStreamConfig {
+        channels: channels.clamp(1, MAX_CHANNELS as c_uint) as ChannelCount,
+        sample_rate: SampleRate(par.rate),
+        buffer_size: SupportedBufferSize::Unknown,
+        sample_format: SUPPORTED_SAMPLE_FORMATS
+            .iter()
+            .copied()
+            .find(|&sample_format| encoding(sample_format) == Some((par.bits, par.sig)))
+            .unwrap_or(SampleFormat::I16),
+    })
+}
+
+/// The bits per sample and signedness of samples in `sample_format`, in native byte order.
+pub(crate) fn encoding(sample_format: SampleFormat) -> Option<(c_uint, c_uint)> {
+    let encoding = match sample_format {
+        SampleFormat::I8 => (8, 1),
+        SampleFormat::U8 => (8, 0),
+        SampleFormat::I16 => (16, 1),
+        SampleFormat::U16 => (16, 0),
+        SampleFormat::I32 => (32, 1),
+        SampleFormat::U32 => (32, 0),
+        _ => return None,
+    };
+    Some(encoding)
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(self.name.clone())
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        let configs = match &self.input {
+            Some(default) => self.supported_configs(default),
+            None => vec![],
+        };
+        Ok(configs.into_iter())
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        let configs = match &self.output {
+            Some(default) => self.supported_configs(default),
+            None => vec![],
+        };
+        Ok(configs.into_iter())
+    }
+
+    /// Returns the default input config
+    /// The channels, sample rate and sample format the device records with when opened.
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.input
+            .clone()
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    /// Returns the default output config
+    /// The channels, sample rate and sample format the device plays with when opened.
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.output
+            .clone()
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_input() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_input(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_input(
+            params,
+            options,
+            thread::thread_name(options, "cpal_sndio_in")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_raw_with_options(
+            conf,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        conf: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.is_output() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let params = self.stream_params(conf, sample_format, options)?;
+        let (watchdog, data_callback, error_callback) = watchdog::watch_output(
+            timeout.or_else(|| options.watchdog_timeout(conf, DEFAULT_PERIOD)),
+            data_callback,
+            error_callback,
+        )?;
+        Stream::new_output(
+            params,
+            options,
+            thread::thread_name(options, "cpal_sndio_out")?,
+            watchdog,
+            data_callback,
+            error_callback,
+        )
+    }
+}
+
+impl PartialEq for Device {
+    fn eq(&self, other: &Self) -> bool {
+        self.name == other.name
+    }
+}
+
+impl Eq for Device {}
+
+impl Hash for Device {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.name.hash(state);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::encoding;
+    use crate::SampleFormat;
+
+    #[test]
+    fn encoding_of_integer_formats() {
+        for sample_format in [
+            SampleFormat::I8,
+            SampleFormat::U8,
+            SampleFormat::I16,
+            SampleFormat::U16,
+            SampleFormat::I32,
+            SampleFormat::U32,
+        ] {
+            let (bits, sig) = encoding(sample_format).unwrap();
+            assert_eq!(bits as usize, sample_format.sample_size() * 8);
+            assert_eq!(sig == 1, sample_format.is_int());
+        }
+        assert_eq!(encoding(SampleFormat::F32), None);
+        assert_eq!(encoding(SampleFormat::F64), None);
+    }
+}
@@ -0,0 +1,61 @@
+//! Bindings to the parts of `libsndio` used by the host, from `sndio.h`.
+
+#![allow(non_camel_case_types)]
+
+use libc::{c_char, c_int, c_uint, c_void, size_t};
+
+/// The device chosen by the `AUDIODEVICE` environment variable, or the first one of the server.
+pub(crate) const SIO_DEVANY: &str = "default";
+pub(crate) const SIO_PLAY: c_uint = 1;
+pub(crate) const SIO_REC: c_uint = 2;
+/// Pause the stream on overruns and underruns, resuming it where it was.
+pub(crate) const SIO_IGNORE: c_uint = 0;
+
+#[repr(C)]
+pub(crate) struct sio_hdl {
+    _private: [u8; 0],
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+pub(crate) struct sio_par {
+    /// The bits per sample.
+    pub(crate) bits: c_uint,
+    /// The bytes per sample.
+    pub(crate) bps: c_uint,
+    pub(crate) sig: c_uint,
+    pub(crate) le: c_uint,
+    /// Whether samples with fewer bits than bytes are aligned to the most significant bit.
+    pub(crate) msb: c_uint,
+    pub(crate) rchan: c_uint,
+    pub(crate) pchan: c_uint,
+    pub(crate) rate: c_uint,
+    /// The size of the buffer between the stream and the device, in frames.
+    pub(crate) bufsz: c_uint,
+    pub(crate) xrun: c_uint,
+    /// The number of frames the device is read and written in.
+    pub(crate) round: c_uint,
+    /// The size of the buffer the stream writes to or reads from, in frames.
+    pub(crate) appbufsz: c_uint,
+    __pad: [c_int; 3],
+    __magic: c_uint,
+}
+
+#[link(name = "sndio")]
+extern "C" {
+    pub(crate) fn sio_open(name: *const c_char, mode: c_uint, nbio: c_int) -> *mut sio_hdl;
+    pub(crate) fn sio_close(hdl: *mut sio_hdl);
+    pub(crate) fn sio_initpar(par: *mut sio_par);
+    pub(crate) fn sio_setpar(hdl: *mut sio_hdl, par: *mut sio_par) -> c_int;
+    pub(crate) fn sio_getpar(hdl: *mut sio_hdl, par: *mut sio_par) -> c_int;
+    pub(crate) fn sio_onmove(
+        hdl: *mut sio_hdl,
+        cb: Option<unsafe extern "C" fn(arg: *mut c_void, delta: c_int)>,
+        arg: *mut c_void,
+    );
+    pub(crate) fn sio_write(hdl: *mut sio_hdl, addr: *const c_void, nbytes: size_t) -> size_t;
+    pub(crate) fn sio_read(hdl: *mut sio_hdl, addr: *mut c_void, nbytes: size_t) -> size_t;
+    pub(crate) fn sio_start(hdl: *mut sio_hdl) -> c_int;
+    pub(crate) fn sio_stop(hdl: *mut sio_hdl) -> c_int;
+    pub(crate) fn sio_eof(hdl: *mut sio_hdl) -> c_int;
+}
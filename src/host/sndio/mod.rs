@@ -0,0 +1,184 @@
+//! A sndio host, playing and recording through `libsndio`, the native API of OpenBSD.
+//!
+//! Streams are opened on the devices of the `sndiod` server, which mixes them and converts them to
+//! the format of the hardware, so any channel count, sample rate and integer format is accepted.
+
+use crate::traits::HostTrait;
+use crate::DevicesError;
+use libc::{c_int, c_uint, c_void};
+use std::cell::Cell;
+use std::ffi::CString;
+use std::mem;
+use std::ptr::NonNull;
+
+mod device;
+mod ffi;
+pub use self::device::{Device, SupportedInputConfigs, SupportedOutputConfigs};
+pub use self::stream::Stream;
+mod stream;
+
+pub type Devices = std::vec::IntoIter<Device>;
+
+// The devices listed: the default one, followed by the first devices of the server.
+const DEVICE_NAMES: [&str; 5] = [ffi::SIO_DEVANY, "snd/0", "snd/1", "snd/2", "snd/3"];
+
+/// The sndio Host type
+#[derive(Debug)]
+pub struct Host;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    /// sndio is available if its default device can be opened, i.e. if `sndiod` is running.
+    fn is_available() -> bool {
+        Handle::open(ffi::SIO_DEVANY, ffi::SIO_PLAY).is_some()
+            || Handle::open(ffi::SIO_DEVANY, ffi::SIO_REC).is_some()
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let devices: Vec<_> = DEVICE_NAMES
+            .iter()
+            .filter_map(|name| Device::open(name))
+            .collect();
+        Ok(devices.into_iter())
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        Device::open(ffi::SIO_DEVANY).filter(Device::is_input)
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        Device::open(ffi::SIO_DEVANY).filter(Device::is_output)
+    }
+}
+
+/// A device opened for playback or recording, in blocking mode.
+pub(crate) struct Handle {
+    hdl: NonNull<ffi::sio_hdl>,
+    /// The frames played or recorded by the device since the stream was started, counted by the
+    /// `onmove` callback. Boxed, as the callback holds a pointer to it.
+    moved: Box<Cell<i64>>,
+    /// The frames written or read by the stream since it was started.
+    transferred: i64,
+    frame_size: usize,
+}
+
+// A handle is only used by one thread at a time.
+unsafe impl Send for Handle {}
+
+impl Handle {
+    pub(crate) fn open(name: &str, mode: c_uint) -> Option<Self> {
+        let name = CString::new(name).ok()?;
+        let hdl = NonNull::new(unsafe { ffi::sio_open(name.as_ptr(), mode, 0) })?;
+        let moved = Box::new(Cell::new(0));
+        unsafe {
+            ffi::sio_onmove(
+                hdl.as_ptr(),
+                Some(on_move),
+                &*moved as *const Cell<i64> as *mut c_void,
+            )
+        };
+        Some(Handle {
+            hdl,
+            moved,
+            transferred: 0,
+            frame_size: 0,
+        })
+    }
+
+    /// The parameters the device is set to.
+    pub(crate) fn par(&self) -> Option<ffi::sio_par> {
+        let mut par = new_par();
+        match unsafe { ffi::sio_getpar(self.hdl.as_ptr(), &mut par) } {
+            0 => None,
+            _ => Some(par),
+        }
+    }
+
+    /// Ask for `par`, returning the parameters the device granted.
+    pub(crate) fn set_par(&mut self, mut par: ffi::sio_par) -> Option<ffi::sio_par> {
+        if unsafe { ffi::sio_setpar(self.hdl.as_ptr(), &mut par) } == 0 {
+            return None;
+        }
+        let par = self.par()?;
+        self.frame_size = par.bps as usize * par.pchan.max(par.rchan) as usize;
+        Some(par)
+    }
+
+    pub(crate) fn start(&mut self) -> bool {
+        self.moved.set(0);
+        self.transferred = 0;
+        unsafe { ffi::sio_start(self.hdl.as_ptr()) != 0 }
+    }
+
+    /// Stop the device, after playing the samples written to it.
+    pub(crate) fn stop(&mut self) -> bool {
+        unsafe { ffi::sio_stop(self.hdl.as_ptr()) != 0 }
+    }
+
+    /// Write all of `bytes`, returning `false` if the device failed.
+    pub(crate) fn write(&mut self, bytes: &[u8]) -> bool {
+        let mut written = 0;
+        while written < bytes.len() {
+            let rest = &bytes[written..];
+            let n = unsafe { ffi::sio_write(self.hdl.as_ptr(), rest.as_ptr() as _, rest.len()) };
+            if n == 0 {
+                return false;
+            }
+            written += n;
+        }
+        self.transferred += (bytes.len() / self.frame_size) as i64;
+        true
+    }
+
+    /// Fill all of `bytes`, returning `false` if the device failed.
+    pub(crate) fn read(&mut self, bytes: &mut [u8]) -> bool {
+        let mut read = 0;
+        while read < bytes.len() {
+            let rest = &mut bytes[read..];
+            let n = unsafe { ffi::sio_read(self.hdl.as_ptr(), rest.as_mut_ptr() as _, rest.len()) };
+            if n == 0 {
+                return false;
+            }
+            read += n;
+        }
+        self.transferred += (bytes.len() / self.frame_size) as i64;
+        true
+    }
+
+    /// The frames written but not played yet, or recorded but not read yet: the stream is ahead
+    /// of the device for playback, and behind it for recording.
+    pub(crate) fn delay(&self) -> u64 {
+        (self.transferred - self.moved.get()).unsigned_abs()
+    }
+
+    /// Whether the device failed, e.g. because the server was stopped.
+    pub(crate) fn eof(&self) -> bool {
+        unsafe { ffi::sio_eof(self.hdl.as_ptr()) != 0 }
+    }
+}
+
+impl Drop for Handle {
+    fn drop(&mut self) {
+        unsafe { ffi::sio_close(self.hdl.as_ptr()) };
+    }
+}
+
+unsafe extern "C" fn on_move(arg: *mut c_void, delta: c_int) {
+    let moved = &*(arg as *const Cell<i64>);
+    moved.set(moved.get() + delta as i64);
+}
+
+/// Parameters with every field unset, leaving them to the device.
+pub(crate) fn new_par() -> ffi::sio_par {
+    let mut par: ffi::sio_par = unsafe { mem::zeroed() };
+    unsafe { ffi::sio_initpar(&mut par) };
+    par
+}
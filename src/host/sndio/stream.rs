@@ -0,0 +1,193 @@
+use crate::stream_thread::blocking::{self, BlockingStream};
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, InputCallbackInfo,
+    OutputCallbackInfo, PauseStreamError, PlayStreamError, SampleFormat, StreamConfig, StreamError,
+    StreamOptions,
+};
+use libc::c_uint;
+use std::time::Duration;
+
+use super::device::{encoding, DEFAULT_PERIOD};
+use super::{ffi, new_par, Handle};
+
+/// The sndio device and format of a stream, checked by its device.
+pub(crate) struct StreamParams {
+    pub(crate) device: String,
+    pub(crate) config: StreamConfig,
+    pub(crate) sample_format: SampleFormat,
+}
+
+pub struct Stream(BlockingStream);
+
+impl Stream {
+    pub(crate) fn new_input<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        blocking::new_input(
+            "sndio",
+            params.sample_format,
+            params.config.sample_rate,
+            options,
+            thread_name,
+            watchdog,
+            move || open(ffi::SIO_REC, &params),
+            data_callback,
+            error_callback,
+        )
+        .map(Stream)
+    }
+
+    pub(crate) fn new_output<D, E>(
+        params: StreamParams,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        blocking::new_output(
+            "sndio",
+            params.sample_format,
+            params.config.sample_rate,
+            options,
+            thread_name,
+            watchdog,
+            move || open(ffi::SIO_PLAY, &params),
+            data_callback,
+            error_callback,
+        )
+        .map(Stream)
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.0.play()
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.0.pause()
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.0.latency()
+    }
+}
+
+impl blocking::Handle for Handle {
+    fn start(&mut self) -> Result<(), BackendSpecificError> {
+        match Handle::start(self) {
+            true => Ok(()),
+            false => Err(error("failed to start the device")),
+        }
+    }
+
+    // Stopping plays the samples written so far first.
+    fn stop(&mut self) -> Result<(), BackendSpecificError> {
+        match Handle::stop(self) {
+            true => Ok(()),
+            false => Err(error("failed to stop the device")),
+        }
+    }
+
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), StreamError> {
+        match Handle::read(self, buffer) {
+            true => Ok(()),
+            false => Err(self.failure()),
+        }
+    }
+
+    fn write(&mut self, buffer: &[u8]) -> Result<(), StreamError> {
+        match Handle::write(self, buffer) {
+            true => Ok(()),
+            false => Err(self.failure()),
+        }
+    }
+
+    fn delay(&mut self) -> Result<u64, StreamError> {
+        Ok(Handle::delay(self))
+    }
+}
+
+impl Handle {
+    // The error of a read or write that failed.
+    fn failure(&self) -> StreamError {
+        // The server stopped, or the device was removed.
+        if self.eof() {
+            StreamError::DeviceNotAvailable
+        } else {
+            error("failed to exchange samples with the device").into()
+        }
+    }
+}
+
+// Open the device of a stream for `mode` and set its parameters, returning it with a buffer of a
+// period.
+fn open(mode: c_uint, params: &StreamParams) -> Result<(Handle, Vec<u8>), BuildStreamError> {
+    let mut handle =
+        Handle::open(&params.device, mode).ok_or(BuildStreamError::DeviceNotAvailable)?;
+    let (bits, sig) =
+        encoding(params.sample_format).ok_or(BuildStreamError::StreamConfigNotSupported)?;
+    let channels = params.config.channels as c_uint;
+    let mut par = new_par();
+    par.bits = bits;
+    par.bps = params.sample_format.sample_size() as c_uint;
+    par.sig = sig;
+    par.le = cfg!(target_endian = "little") as c_uint;
+    if mode == ffi::SIO_REC {
+        par.rchan = channels;
+    } else {
+        par.pchan = channels;
+    }
+    par.rate = params.config.sample_rate.0;
+    par.xrun = ffi::SIO_IGNORE;
+    // Keep two periods in the buffer of the stream.
+    let frames = match params.config.buffer_size {
+        BufferSize::Fixed(frames) => frames,
+        BufferSize::Default => DEFAULT_PERIOD,
+    };
+    par.round = frames;
+    par.appbufsz = frames * 2;
+
+    let granted = handle
+        .set_par(par)
+        .ok_or_else(|| error("failed to set the parameters of the device"))?;
+    let granted_channels = if mode == ffi::SIO_REC {
+        granted.rchan
+    } else {
+        granted.pchan
+    };
+    if granted.bits != par.bits
+        || granted.bps != par.bps
+        || granted.sig != par.sig
+        || (granted.bps > 1 && granted.le != par.le)
+        || granted_channels != channels
+        || granted.rate != par.rate
+        || (matches!(params.config.buffer_size, BufferSize::Fixed(_)) && granted.round != frames)
+    {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    let period = granted.round as usize * granted.bps as usize * channels as usize;
+    Ok((handle, vec![0; period]))
+}
+
+fn error(description: &str) -> BackendSpecificError {
+    BackendSpecificError {
+        description: format!("sndio: {}", description),
+    }
+}
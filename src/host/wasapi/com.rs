@@ -1,9 +1,13 @@
-//! Handles COM initialization and cleanup.
+//! Handles COM initialization and cleanup, and the COM objects implemented by CPAL.
 
 use super::IoError;
+use std::ffi::c_void;
 use std::marker::PhantomData;
+use std::ptr;
+use std::sync::atomic::{AtomicU32, Ordering};
 
-use windows::Win32::Foundation::RPC_E_CHANGED_MODE;
+use windows::core::{ComInterface, IUnknown, Interface, GUID, HRESULT};
+use windows::Win32::Foundation::{E_NOINTERFACE, RPC_E_CHANGED_MODE, S_OK};
 use windows::Win32::System::Com::{CoInitializeEx, CoUninitialize, COINIT_APARTMENTTHREADED};
 
 thread_local!(static COM_INITIALIZED: ComInitialized = {
@@ -56,3 +60,56 @@ impl Drop for ComInitialized {
 pub fn com_initialized() {
     COM_INITIALIZED.with(|_| {});
 }
+
+/// A COM object implemented by hand, using [`query_interface`], [`add_ref`] and [`release`] in
+/// its vtable.
+///
+/// # Safety
+///
+/// The object must be `#[repr(C)]` with the pointer to its vtable as its first field, so that
+/// pointers to the object are pointers to the interfaces it implements.
+pub unsafe trait ComObject: Sized {
+    /// The interfaces implemented by the object besides `IUnknown`.
+    const IIDS: &'static [GUID];
+
+    /// The reference count of the object.
+    fn refs(&self) -> &AtomicU32;
+}
+
+/// Move `object` to the heap, returning the interface `I` implemented by it, which holds the
+/// object's single reference.
+pub unsafe fn into_interface<T: ComObject, I: Interface>(object: T) -> I {
+    object.refs().store(1, Ordering::Relaxed);
+    I::from_raw(Box::into_raw(Box::new(object)) as *mut c_void)
+}
+
+pub unsafe extern "system" fn query_interface<T: ComObject>(
+    this: *mut c_void,
+    iid: &GUID,
+    interface: *mut *const c_void,
+) -> HRESULT {
+    if *iid == IUnknown::IID || T::IIDS.contains(iid) {
+        add_ref::<T>(this);
+        *interface = this;
+        S_OK
+    } else {
+        *interface = ptr::null();
+        E_NOINTERFACE
+    }
+}
+
+pub unsafe extern "system" fn add_ref<T: ComObject>(this: *mut c_void) -> u32 {
+    let object = &*(this as *const T);
+    object.refs().fetch_add(1, Ordering::Relaxed) + 1
+}
+
+pub unsafe extern "system" fn release<T: ComObject>(this: *mut c_void) -> u32 {
+    let refs = {
+        let object = &*(this as *const T);
+        object.refs().fetch_sub(1, Ordering::AcqRel) - 1
+    };
+    if refs == 0 {
+        drop(Box::from_raw(this as *mut T));
+    }
+    refs
+}
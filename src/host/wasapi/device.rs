@@ -1,9 +1,11 @@
+use crate::dsd;
 use crate::FrameCount;
 use crate::{
-    BackendSpecificError, BufferSize, Data, DefaultStreamConfigError, DeviceNameError,
-    DevicesError, InputCallbackInfo, OutputCallbackInfo, SampleFormat, SampleRate, StreamConfig,
-    SupportedBufferSize, SupportedStreamConfig, SupportedStreamConfigRange,
-    SupportedStreamConfigsError, COMMON_SAMPLE_RATES,
+    BackendSpecificError, BufferSize, ConversionQuality, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, DsdMode, Ducking, HardwareFormat, InputCallbackInfo,
+    OutputCallbackInfo, PassthroughFormat, SampleFormat, SampleRate, ShareMode, SignalPath,
+    StreamConfig, StreamOptions, StreamRole, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError, COMMON_SAMPLE_RATES,
 };
 use once_cell::sync::Lazy;
 use std::ffi::OsString;
@@ -17,6 +19,10 @@ use std::sync::{Arc, Mutex, MutexGuard};
 use std::time::Duration;
 
 use super::com;
+use super::driver_info::{self, DriverInfo};
+use super::process_loopback::{self, ProcessLoopbackMode};
+use super::session_events::{self, DisconnectReasonSlot};
+use super::spatial::{self, SpatialConfig, SpatialObject, SpatialStream};
 use super::{windows_err_to_cpal_err, windows_err_to_cpal_err_message};
 use windows::core::ComInterface;
 use windows::core::GUID;
@@ -25,15 +31,24 @@ use windows::Win32::Foundation;
 use windows::Win32::Media::Audio::IAudioRenderClient;
 use windows::Win32::Media::{Audio, KernelStreaming, Multimedia};
 use windows::Win32::System::Com;
-use windows::Win32::System::Com::{StructuredStorage, STGM_READ, VT_LPWSTR};
+use windows::Win32::System::Com::{StructuredStorage, STGM_READ, VT_BLOB, VT_LPWSTR};
 use windows::Win32::System::Threading;
 
-use super::stream::{AudioClientFlow, Stream, StreamInner};
+use super::stream::{AudioClientFlow, Reactivate, Stream, StreamInner};
+use crate::stats::StreamStatsCollector;
 use crate::{traits::DeviceTrait, BuildStreamError, StreamError};
 
 pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
 pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
 
+type BuildStreamInner = fn(
+    &Device,
+    &StreamConfig,
+    SampleFormat,
+    &StreamOptions,
+    &DisconnectReasonSlot,
+) -> Result<StreamInner, BuildStreamError>;
+
 /// Wrapper because of that stupid decision to remove `Send` and `Sync` from raw pointers.
 #[derive(Clone)]
 struct IAudioClientWrapper(Audio::IAudioClient);
@@ -43,12 +58,22 @@ unsafe impl Sync for IAudioClientWrapper {}
 /// An opaque type that identifies an end point.
 #[derive(Clone)]
 pub struct Device {
-    device: Audio::IMMDevice,
+    kind: DeviceKind,
     /// We cache an uninitialized `IAudioClient` so that we can call functions from it without
     /// having to create/destroy audio clients all the time.
     future_audio_client: Arc<Mutex<Option<IAudioClientWrapper>>>, // TODO: add NonZero around the ptr
 }
 
+#[derive(Clone, Debug)]
+enum DeviceKind {
+    Endpoint(Audio::IMMDevice),
+    // Captures the audio played by processes rather than by a device.
+    ProcessLoopback {
+        process_id: u32,
+        mode: ProcessLoopbackMode,
+    },
+}
+
 impl DeviceTrait for Device {
     type SupportedInputConfigs = SupportedInputConfigs;
     type SupportedOutputConfigs = SupportedOutputConfigs;
@@ -84,18 +109,55 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let stream_inner = self.build_input_stream_raw_inner(config, sample_format)?;
-        Ok(Stream::new_input(
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+            || options.offload
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let stream_inner = self.build_input_stream_raw_inner(
+            config,
+            sample_format,
+            options,
+            &DisconnectReasonSlot::default(),
+        )?;
+        Stream::new_input(
             stream_inner,
+            options,
             data_callback,
             error_callback,
-        ))
+            timeout,
+        )
     }
 
     fn build_output_stream_raw<D, E>(
@@ -104,18 +166,66 @@ impl DeviceTrait for Device {
         sample_format: SampleFormat,
         data_callback: D,
         error_callback: E,
-        _timeout: Option<Duration>,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        let stream_inner = self.build_output_stream_raw_inner(config, sample_format)?;
-        Ok(Stream::new_output(
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        match options.dsd {
+            None => (),
+            Some(DsdMode::DoP) => {
+                return dsd::build_dop_output_stream(
+                    self,
+                    config,
+                    sample_format,
+                    options,
+                    data_callback,
+                    error_callback,
+                    timeout,
+                )
+            }
+            Some(DsdMode::Native) => return Err(BuildStreamError::StreamConfigNotSupported),
+        }
+        if options.device_channels.is_some() {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let stream_inner = self.build_output_stream_raw_inner(
+            config,
+            sample_format,
+            options,
+            &DisconnectReasonSlot::default(),
+        )?;
+        Stream::new_output(
             stream_inner,
+            options,
             data_callback,
             error_callback,
-        ))
+            timeout,
+        )
     }
 }
 
@@ -181,6 +291,234 @@ impl DerefMut for WaveFormat {
     }
 }
 
+impl From<ShareMode> for Audio::AUDCLNT_SHAREMODE {
+    fn from(share_mode: ShareMode) -> Self {
+        match share_mode {
+            ShareMode::Shared => Audio::AUDCLNT_SHAREMODE_SHARED,
+            ShareMode::Exclusive => Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+        }
+    }
+}
+
+impl From<StreamRole> for Audio::AUDIO_STREAM_CATEGORY {
+    fn from(role: StreamRole) -> Self {
+        match role {
+            StreamRole::Media => Audio::AudioCategory_Media,
+            StreamRole::Communications => Audio::AudioCategory_Communications,
+            StreamRole::Game => Audio::AudioCategory_GameEffects,
+            StreamRole::GameMedia => Audio::AudioCategory_GameMedia,
+            StreamRole::GameChat => Audio::AudioCategory_GameChat,
+            StreamRole::Movie => Audio::AudioCategory_Movie,
+            StreamRole::Speech => Audio::AudioCategory_Speech,
+            StreamRole::SoundEffects => Audio::AudioCategory_SoundEffects,
+            StreamRole::Alert => Audio::AudioCategory_Alerts,
+        }
+    }
+}
+
+// Set the properties of `audio_client` derived from `options`, which must happen before it is
+// initialized.
+//
+// Apart from offloading, these are only hints, so failures (e.g. before Windows 8, which lacks
+// `IAudioClient2`) are ignored.
+unsafe fn set_client_properties(
+    audio_client: &Audio::IAudioClient,
+    options: &StreamOptions,
+) -> Result<(), windows::core::Error> {
+    if options.role.is_none() && !options.raw && !options.offload {
+        return Ok(());
+    }
+    let audio_client = match audio_client.cast::<Audio::IAudioClient2>() {
+        Ok(audio_client) => audio_client,
+        Err(err) => return if options.offload { Err(err) } else { Ok(()) },
+    };
+    let mut properties = Audio::AudioClientProperties {
+        cbSize: mem::size_of::<Audio::AudioClientProperties>() as u32,
+        bIsOffload: options.offload.into(),
+        eCategory: stream_category(options),
+        Options: if options.raw {
+            Audio::AUDCLNT_STREAMOPTIONS_RAW
+        } else {
+            Audio::AUDCLNT_STREAMOPTIONS_NONE
+        },
+    };
+    // Devices that don't support raw mode reject it, in which case the role still applies.
+    let mut result = audio_client.SetClientProperties(&properties);
+    if result.is_err() && options.raw {
+        properties.Options = Audio::AUDCLNT_STREAMOPTIONS_NONE;
+        result = audio_client.SetClientProperties(&properties);
+    }
+    if options.offload {
+        result
+    } else {
+        Ok(())
+    }
+}
+
+// The audio category of streams built with `options`. Offloading is mostly supported for media.
+fn stream_category(options: &StreamOptions) -> Audio::AUDIO_STREAM_CATEGORY {
+    match options.role {
+        Some(role) => role.into(),
+        None if options.offload => Audio::AudioCategory_Media,
+        None => Audio::AudioCategory_Other,
+    }
+}
+
+// Whether the hardware of the device of the uninitialized `audio_client` can mix streams of
+// `category`.
+unsafe fn is_offload_capable(
+    audio_client: &Audio::IAudioClient,
+    category: Audio::AUDIO_STREAM_CATEGORY,
+) -> bool {
+    audio_client
+        .cast::<Audio::IAudioClient2>()
+        .and_then(|audio_client| audio_client.IsOffloadCapable(category))
+        .is_ok_and(|capable| capable.as_bool())
+}
+
+// The buffer duration of an offloaded stream on `audio_client` requesting `buffer_duration`, or
+// the longest one supported by default, to let the CPU sleep as long as possible.
+unsafe fn offload_buffer_duration(
+    audio_client: &Audio::IAudioClient,
+    format: &Audio::WAVEFORMATEX,
+    stream_flags: u32,
+    buffer_duration: i64,
+) -> Result<i64, windows::core::Error> {
+    let event_driven = stream_flags & Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK != 0;
+    let (mut min, mut max) = (0, 0);
+    audio_client
+        .cast::<Audio::IAudioClient2>()?
+        .GetBufferSizeLimits(format, event_driven, &mut min, &mut max)?;
+    Ok(match buffer_duration {
+        0 => max,
+        buffer_duration => buffer_duration.clamp(min, max),
+    })
+}
+
+// The flags of streams built with `options`, before those specific to their device.
+fn stream_flags(options: &StreamOptions) -> u32 {
+    let mut flags = if options.polling {
+        0
+    } else {
+        Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK
+    };
+    if converts_pcm(options) {
+        flags |= Audio::AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM;
+        if options.conversion == Some(ConversionQuality::Default) {
+            flags |= Audio::AUDCLNT_STREAMFLAGS_SRC_DEFAULT_QUALITY;
+        }
+    }
+    flags
+}
+
+// Whether the audio engine converts the samples of streams built with `options`, which can then
+// have any PCM format.
+fn converts_pcm(options: &StreamOptions) -> bool {
+    options.conversion.is_some()
+        && options.share_mode == ShareMode::Shared
+        && options.passthrough.is_none()
+}
+
+// Create the event waking the thread of a stream on the initialized `audio_client` whenever it
+// must exchange samples: signalled by the device, or by a periodic timer when polling.
+unsafe fn create_event(
+    audio_client: &Audio::IAudioClient,
+    options: &StreamOptions,
+    max_frames_in_buffer: u32,
+    config: &StreamConfig,
+) -> Result<Foundation::HANDLE, BuildStreamError> {
+    if options.polling {
+        let timer = Threading::CreateWaitableTimerW(None, false, None).map_err(|e| {
+            let description = format!("failed to create timer: {}", e);
+            BuildStreamError::from(BackendSpecificError { description })
+        })?;
+        // Poll four times per buffer, so that it is refilled well before running out.
+        let period_ms = max_frames_in_buffer as u64 * 1000 / config.sample_rate.0 as u64 / 4;
+        let period_ms = period_ms.clamp(1, i32::MAX as u64) as i32;
+        let due_time = -(period_ms as i64) * 10_000;
+        if !Threading::SetWaitableTimer(timer, &due_time, period_ms, None, None, false).as_bool() {
+            let description = format!(
+                "failed to call SetWaitableTimer: {}",
+                windows::core::Error::from_win32()
+            );
+            Foundation::CloseHandle(timer);
+            return Err(BackendSpecificError { description }.into());
+        }
+        return Ok(timer);
+    }
+
+    let event = Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
+        .map_err(|e| {
+            let description = format!("failed to create event: {}", e);
+            let err = BackendSpecificError { description };
+            BuildStreamError::from(err)
+        })?;
+
+    if let Err(e) = audio_client.SetEventHandle(event) {
+        let description = format!("failed to call SetEventHandle: {}", e);
+        let err = BackendSpecificError { description };
+        Foundation::CloseHandle(event);
+        return Err(err.into());
+    }
+
+    Ok(event)
+}
+
+// Set the properties of the session of the initialized `audio_client` derived from `options`.
+//
+// Like the client properties, these are only hints and failures are ignored.
+unsafe fn set_session_properties(audio_client: &Audio::IAudioClient, options: &StreamOptions) {
+    let ducking = match options.ducking {
+        Some(ducking) => ducking,
+        None => return,
+    };
+    let session_control = audio_client
+        .GetService::<Audio::IAudioSessionControl>()
+        .and_then(|session_control| session_control.cast::<Audio::IAudioSessionControl2>());
+    if let Ok(session_control) = session_control {
+        let _ = session_control.SetDuckingPreference(ducking == Ducking::OptOut);
+    }
+}
+
+// The engine period in frames closest to `buffer_duration` that a shared mode stream in `format`
+// can run at using the returned `IAudioClient3`, or `None` if it would be the default period.
+unsafe fn shared_mode_engine_period(
+    audio_client: &Audio::IAudioClient,
+    format: &Audio::WAVEFORMATEX,
+    buffer_duration: i64,
+) -> Option<(Audio::IAudioClient3, u32)> {
+    let audio_client3 = audio_client.cast::<Audio::IAudioClient3>().ok()?;
+    let (mut default, mut fundamental, mut min, mut max) = (0, 0, 0, 0);
+    audio_client3
+        .GetSharedModeEnginePeriod(format, &mut default, &mut fundamental, &mut min, &mut max)
+        .ok()?;
+    // Periods are multiples of the fundamental period between the minimum and maximum.
+    let frames = buffer_duration_to_frames(buffer_duration, format.nSamplesPerSec).clamp(min, max);
+    let fundamental = fundamental.max(1);
+    let period = (min + (frames - min).div_ceil(fundamental) * fundamental).min(max);
+    Some((audio_client3, period)).filter(|_| period != default)
+}
+
+// The buffer duration of process loopback streams with the default buffer size, in 100 ns units.
+const PROCESS_LOOPBACK_BUFFER_DURATION: i64 = 200_000;
+
+// The configs of process loopback streams, to which the captured audio is converted.
+fn process_loopback_configs() -> Vec<SupportedStreamConfigRange> {
+    let mut configs = Vec::new();
+    for sample_format in [SampleFormat::I16, SampleFormat::I32, SampleFormat::F32] {
+        for channels in 1..=2 {
+            configs.push(SupportedStreamConfigRange {
+                channels,
+                min_sample_rate: SampleRate(8_000),
+                max_sample_rate: SampleRate(192_000),
+                buffer_size: SupportedBufferSize::Unknown,
+                sample_format,
+            });
+        }
+    }
+    configs
+}
+
 unsafe fn immendpoint_from_immdevice(device: Audio::IMMDevice) -> Audio::IMMEndpoint {
     device
         .cast::<Audio::IMMEndpoint>()
@@ -193,18 +531,19 @@ unsafe fn data_flow_from_immendpoint(endpoint: &Audio::IMMEndpoint) -> Audio::ED
         .expect("could not get endpoint data_flow")
 }
 
-// Given the audio client and format, returns whether or not the format is supported.
+// Given the audio client, share mode and format, returns whether or not the format is supported.
 pub unsafe fn is_format_supported(
     client: &Audio::IAudioClient,
+    share_mode: Audio::AUDCLNT_SHAREMODE,
     waveformatex_ptr: *const Audio::WAVEFORMATEX,
 ) -> Result<bool, SupportedStreamConfigsError> {
     // Check if the given format is supported.
     let is_supported = |waveformatex_ptr, closest_waveformatex_ptr| {
-        let result = client.IsFormatSupported(
-            Audio::AUDCLNT_SHAREMODE_SHARED,
-            waveformatex_ptr,
-            Some(closest_waveformatex_ptr),
-        );
+        // No closest format is suggested in exclusive mode.
+        let closest_waveformatex_ptr = Some(closest_waveformatex_ptr)
+            .filter(|_| share_mode == Audio::AUDCLNT_SHAREMODE_SHARED);
+        let result =
+            client.IsFormatSupported(share_mode, waveformatex_ptr, closest_waveformatex_ptr);
         // `IsFormatSupported` can return `S_FALSE` (which means that a compatible format
         // has been found, but not an exact match) so we also treat this as unsupported.
         match result {
@@ -239,14 +578,81 @@ pub unsafe fn is_format_supported(
     }
 }
 
+fn cmp_guid(a: &GUID, b: &GUID) -> bool {
+    (a.data1, a.data2, a.data3, a.data4) == (b.data1, b.data2, b.data3, b.data4)
+}
+
+// The first of the formats that can describe a stream in `format` which is supported by `client`,
+// or `None` if there is none.
+//
+// In exclusive mode, streams of 32-bit integers fall back to 24 valid bits in each sample, as
+// many devices take 24-bit samples padded to 32 bits but not full 32-bit samples.
+unsafe fn checked_format(
+    client: &Audio::IAudioClient,
+    share_mode: ShareMode,
+    format: Audio::WAVEFORMATEXTENSIBLE,
+) -> Result<Option<Audio::WAVEFORMATEXTENSIBLE>, SupportedStreamConfigsError> {
+    let mut candidates = vec![format];
+    let sub_format = format.SubFormat;
+    if share_mode == ShareMode::Exclusive
+        && format.Format.wFormatTag as u32 == KernelStreaming::WAVE_FORMAT_EXTENSIBLE
+        && format.Format.wBitsPerSample == 32
+        && cmp_guid(&sub_format, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM)
+    {
+        let mut padded = format;
+        padded.Samples = Audio::WAVEFORMATEXTENSIBLE_0 {
+            wValidBitsPerSample: 24,
+        };
+        candidates.push(padded);
+    }
+    for candidate in candidates {
+        if is_format_supported(client, share_mode.into(), &candidate.Format)? {
+            return Ok(Some(candidate));
+        }
+    }
+    Ok(None)
+}
+
+// Get the format the hardware is running at from a WAVEFORMATEX.
+unsafe fn hardware_format_from_waveformatex_ptr(
+    waveformatex_ptr: *const Audio::WAVEFORMATEX,
+) -> HardwareFormat {
+    let is_float = match (*waveformatex_ptr).wFormatTag as u32 {
+        Audio::WAVE_FORMAT_PCM => Some(false),
+        Multimedia::WAVE_FORMAT_IEEE_FLOAT => Some(true),
+        KernelStreaming::WAVE_FORMAT_EXTENSIBLE => {
+            let waveformatextensible_ptr = waveformatex_ptr as *const Audio::WAVEFORMATEXTENSIBLE;
+            let sub = (*waveformatextensible_ptr).SubFormat;
+            if cmp_guid(&sub, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM) {
+                Some(false)
+            } else if cmp_guid(&sub, &Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
+                Some(true)
+            } else {
+                None
+            }
+        }
+        _ => None,
+    };
+    let sample_format = match (is_float, (*waveformatex_ptr).wBitsPerSample) {
+        (Some(false), 8) => Some(SampleFormat::U8),
+        (Some(false), 16) => Some(SampleFormat::I16),
+        (Some(false), 32) => Some(SampleFormat::I32),
+        (Some(true), 32) => Some(SampleFormat::F32),
+        (Some(true), 64) => Some(SampleFormat::F64),
+        _ => None,
+    };
+    HardwareFormat {
+        channels: (*waveformatex_ptr).nChannels,
+        sample_rate: SampleRate((*waveformatex_ptr).nSamplesPerSec),
+        sample_format,
+    }
+}
+
 // Get a cpal Format from a WAVEFORMATEX.
 unsafe fn format_from_waveformatex_ptr(
     waveformatex_ptr: *const Audio::WAVEFORMATEX,
     audio_client: &Audio::IAudioClient,
 ) -> Option<SupportedStreamConfig> {
-    fn cmp_guid(a: &GUID, b: &GUID) -> bool {
-        (a.data1, a.data2, a.data3, a.data4) == (b.data1, b.data2, b.data3, b.data4)
-    }
     let sample_format = match (
         (*waveformatex_ptr).wBitsPerSample,
         (*waveformatex_ptr).wFormatTag as u32,
@@ -258,6 +664,8 @@ unsafe fn format_from_waveformatex_ptr(
             let sub = (*waveformatextensible_ptr).SubFormat;
             if n_bits == 16 && cmp_guid(&sub, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM) {
                 SampleFormat::I16
+            } else if n_bits == 32 && cmp_guid(&sub, &KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM) {
+                SampleFormat::I32
             } else if n_bits == 32 && cmp_guid(&sub, &Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT) {
                 SampleFormat::F32
             } else {
@@ -317,12 +725,62 @@ unsafe fn format_from_waveformatex_ptr(
 unsafe impl Send for Device {}
 unsafe impl Sync for Device {}
 
+/// The periods of a device, retrieved via [`Device::periods`], in frames at the sample rate of
+/// its shared mode mix format.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct DevicePeriods {
+    /// The sample rate of the device's mix format.
+    pub sample_rate: SampleRate,
+    /// The period of shared mode streams, used with [`BufferSize::Default`].
+    pub default: FrameCount,
+    /// The minimum period of [exclusive mode](ShareMode::Exclusive) streams.
+    pub min_exclusive: FrameCount,
+    /// The periods shared mode streams with a [`BufferSize::Fixed`] buffer size can run at, or
+    /// `None` if the audio engine only supports the default period (before Windows 10).
+    pub engine: Option<EnginePeriods>,
+}
+
+/// The periods the audio engine can run shared mode streams at: multiples of `fundamental`
+/// between `min` and `max`, in frames.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct EnginePeriods {
+    pub min: FrameCount,
+    pub max: FrameCount,
+    pub fundamental: FrameCount,
+}
+
 impl Device {
+    /// A device capturing the audio played by the process with the given ID and its child
+    /// processes, or by all others, depending on `mode`.
+    ///
+    /// Only input streams can be built on the device, in shared mode. The audio is converted to
+    /// the stream's config, which can be any of the device's supported input configs. Requires
+    /// Windows 10 version 2004 or later, building streams fails on older versions.
+    pub fn process_loopback(process_id: u32, mode: ProcessLoopbackMode) -> Self {
+        Device {
+            kind: DeviceKind::ProcessLoopback { process_id, mode },
+            future_audio_client: Arc::new(Mutex::new(None)),
+        }
+    }
+
     pub fn name(&self) -> Result<String, DeviceNameError> {
+        let device = match self.kind {
+            DeviceKind::Endpoint(ref device) => device,
+            DeviceKind::ProcessLoopback { process_id, mode } => {
+                let name = match mode {
+                    ProcessLoopbackMode::IncludeProcessTree => {
+                        format!("Loopback of process {}", process_id)
+                    }
+                    ProcessLoopbackMode::ExcludeProcessTree => {
+                        format!("Loopback of all but process {}", process_id)
+                    }
+                };
+                return Ok(name);
+            }
+        };
         unsafe {
             // Open the device's property store.
-            let property_store = self
-                .device
+            let property_store = device
                 .OpenPropertyStore(STGM_READ)
                 .expect("could not open property store");
 
@@ -370,10 +828,38 @@ impl Device {
         }
     }
 
+    /// The audio adapter and driver the device belongs to, for diagnostics.
+    pub fn driver_info(&self) -> DriverInfo {
+        match self.kind {
+            DeviceKind::Endpoint(ref device) => unsafe { driver_info::driver_info(device) },
+            DeviceKind::ProcessLoopback { .. } => DriverInfo::default(),
+        }
+    }
+
+    /// Whether the audio hardware of the device can mix output streams with the given role,
+    /// see [`StreamOptions::offload`].
+    pub fn supports_offload(&self, role: Option<StreamRole>) -> bool {
+        if self.data_flow() != Audio::eRender {
+            return false;
+        }
+        let options = StreamOptions {
+            role,
+            offload: true,
+            ..StreamOptions::default()
+        };
+        match self.ensure_future_audio_client() {
+            Ok(audio_client) => unsafe {
+                let audio_client = &audio_client.as_ref().unwrap().0;
+                is_offload_capable(audio_client, stream_category(&options))
+            },
+            Err(_) => false,
+        }
+    }
+
     #[inline]
     fn from_immdevice(device: Audio::IMMDevice) -> Self {
         Device {
-            device,
+            kind: DeviceKind::Endpoint(device),
             future_audio_client: Arc::new(Mutex::new(None)),
         }
     }
@@ -388,9 +874,14 @@ impl Device {
         }
 
         let audio_client: Audio::IAudioClient = unsafe {
-            // can fail if the device has been disconnected since we enumerated it, or if
-            // the device doesn't support playback for some reason
-            self.device.Activate(Com::CLSCTX_ALL, None)?
+            match self.kind {
+                // can fail if the device has been disconnected since we enumerated it, or if
+                // the device doesn't support playback for some reason
+                DeviceKind::Endpoint(ref device) => device.Activate(Com::CLSCTX_ALL, None)?,
+                DeviceKind::ProcessLoopback { process_id, mode } => {
+                    process_loopback::activate_audio_client(process_id, mode)?
+                }
+            }
         };
 
         *lock = Some(IAudioClientWrapper(audio_client));
@@ -441,7 +932,11 @@ impl Device {
                 .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
 
             // If the default format can't succeed we have no hope of finding other formats.
-            assert!(is_format_supported(client, default_waveformatex_ptr.0)?);
+            assert!(is_format_supported(
+                client,
+                Audio::AUDCLNT_SHAREMODE_SHARED,
+                default_waveformatex_ptr.0
+            )?);
 
             // Copy the format to use as a test format (as to avoid mutating the original format).
             let mut test_format = {
@@ -463,7 +958,11 @@ impl Device {
                 test_format.nSamplesPerSec = rate;
                 test_format.nAvgBytesPerSec =
                     rate * u32::from((*default_waveformatex_ptr.0).nBlockAlign);
-                if is_format_supported(client, test_format.as_ptr())? {
+                if is_format_supported(
+                    client,
+                    Audio::AUDCLNT_SHAREMODE_SHARED,
+                    test_format.as_ptr(),
+                )? {
                     supported_sample_rates.push(rate);
                 }
             }
@@ -505,10 +1004,141 @@ impl Device {
         }
     }
 
+    /// The configs of streams on this device in [exclusive mode](ShareMode::Exclusive), in the
+    /// direction of the device.
+    ///
+    /// Unlike in shared mode, these depend on the driver. They are found by trialing common
+    /// sample rates and formats for mono, stereo and the channel count of the device, which is
+    /// slow.
+    pub fn supported_exclusive_configs(
+        &self,
+    ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
+        if self.is_process_loopback() {
+            return Ok(vec![].into_iter());
+        }
+
+        // initializing COM because we call `CoTaskMemFree` to release the format.
+        com::com_initialized();
+
+        let lock = match self.ensure_future_audio_client() {
+            Ok(lock) => lock,
+            Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
+                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
+            }
+            Err(e) => {
+                let description = format!("{}", e);
+                let err = BackendSpecificError { description };
+                return Err(err.into());
+            }
+        };
+        let client = &lock.as_ref().unwrap().0;
+
+        unsafe {
+            let device_channels = client
+                .GetMixFormat()
+                .map(WaveFormatExPtr)
+                .map(|format_ptr| (*format_ptr.0).nChannels)
+                .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+            let mut channel_counts = vec![1, 2];
+            if device_channels > 2 {
+                channel_counts.push(device_channels);
+            }
+
+            let mut supported_configs = Vec::new();
+            for sample_format in [SampleFormat::I16, SampleFormat::I32, SampleFormat::F32] {
+                for &channels in &channel_counts {
+                    for &sample_rate in COMMON_SAMPLE_RATES {
+                        let config = StreamConfig {
+                            channels,
+                            sample_rate,
+                            buffer_size: BufferSize::Default,
+                        };
+                        let format = match config_to_waveformatextensible(&config, sample_format) {
+                            Some(format) => format,
+                            None => continue,
+                        };
+                        if checked_format(client, ShareMode::Exclusive, format)?.is_some() {
+                            supported_configs.push(SupportedStreamConfigRange {
+                                channels,
+                                min_sample_rate: sample_rate,
+                                max_sample_rate: sample_rate,
+                                buffer_size: SupportedBufferSize::Unknown,
+                                sample_format,
+                            });
+                        }
+                    }
+                }
+            }
+            Ok(supported_configs.into_iter())
+        }
+    }
+
+    /// The periods at which the device processes audio, which bound the buffer sizes streams on
+    /// it can actually use.
+    pub fn periods(&self) -> Result<DevicePeriods, SupportedStreamConfigsError> {
+        // initializing COM because we call `CoTaskMemFree` to release the format.
+        com::com_initialized();
+
+        let lock = match self.ensure_future_audio_client() {
+            Ok(lock) => lock,
+            Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
+                return Err(SupportedStreamConfigsError::DeviceNotAvailable)
+            }
+            Err(e) => {
+                let description = format!("{}", e);
+                let err = BackendSpecificError { description };
+                return Err(err.into());
+            }
+        };
+        let client = &lock.as_ref().unwrap().0;
+
+        unsafe {
+            let format_ptr = client
+                .GetMixFormat()
+                .map(WaveFormatExPtr)
+                .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+            let sample_rate = (*format_ptr.0).nSamplesPerSec;
+
+            let (mut default_duration, mut min_duration) = (0, 0);
+            client
+                .GetDevicePeriod(Some(&mut default_duration), Some(&mut min_duration))
+                .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+
+            // Only available since Windows 10.
+            let engine = client
+                .cast::<Audio::IAudioClient3>()
+                .and_then(|client| {
+                    let (mut default, mut fundamental, mut min, mut max) = (0, 0, 0, 0);
+                    client.GetSharedModeEnginePeriod(
+                        format_ptr.0,
+                        &mut default,
+                        &mut fundamental,
+                        &mut min,
+                        &mut max,
+                    )?;
+                    Ok(EnginePeriods {
+                        min,
+                        max,
+                        fundamental,
+                    })
+                })
+                .ok();
+
+            Ok(DevicePeriods {
+                sample_rate: SampleRate(sample_rate),
+                default: buffer_duration_to_frames(default_duration, sample_rate),
+                min_exclusive: buffer_duration_to_frames(min_duration, sample_rate),
+                engine,
+            })
+        }
+    }
+
     pub fn supported_input_configs(
         &self,
     ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
-        if self.data_flow() == Audio::eCapture {
+        if self.is_process_loopback() {
+            Ok(process_loopback_configs().into_iter())
+        } else if self.data_flow() == Audio::eCapture {
             self.supported_formats()
         // If it's an output device, assume no input formats.
         } else {
@@ -560,11 +1190,23 @@ impl Device {
     }
 
     pub(crate) fn data_flow(&self) -> Audio::EDataFlow {
-        let endpoint = Endpoint::from(self.device.clone());
+        let device = match self.kind {
+            DeviceKind::Endpoint(ref device) => device,
+            DeviceKind::ProcessLoopback { .. } => return Audio::eCapture,
+        };
+        let endpoint = Endpoint::from(device.clone());
         endpoint.data_flow()
     }
 
     pub fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if self.is_process_loopback() {
+            return Ok(SupportedStreamConfig {
+                channels: 2,
+                sample_rate: SampleRate(48_000),
+                buffer_size: SupportedBufferSize::Unknown,
+                sample_format: SampleFormat::F32,
+            });
+        }
         if self.data_flow() == Audio::eCapture {
             self.default_format()
         } else {
@@ -572,6 +1214,19 @@ impl Device {
         }
     }
 
+    /// The config of loopback capture on this output device, which records the mix played by
+    /// the device in its shared mode format.
+    ///
+    /// It is kept apart from the input configs, so that output devices aren't enumerated as
+    /// input devices.
+    pub fn loopback_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if !self.is_process_loopback() && self.data_flow() == Audio::eRender {
+            self.default_format()
+        } else {
+            Err(DefaultStreamConfigError::StreamTypeNotSupported)
+        }
+    }
+
     pub fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
         let data_flow = self.data_flow();
         if data_flow == Audio::eRender {
@@ -581,18 +1236,253 @@ impl Device {
         }
     }
 
+    // Initialize `audio_client` for a stream of the given format.
+    //
+    // In shared mode, streams with a fixed buffer size run at the closest period supported by the
+    // audio engine where it supports other periods than its default (10 ms), falling back to
+    // the default period otherwise.
+    //
+    // In exclusive mode, the buffer duration defaults to the device period.
+    //
+    // `audio_client` is replaced by a new client if it can't be initialized again after a failed
+    // attempt.
+    unsafe fn initialize_audio_client(
+        &self,
+        audio_client: &mut Audio::IAudioClient,
+        options: &StreamOptions,
+        stream_flags: u32,
+        buffer_duration: i64,
+        format: &Audio::WAVEFORMATEX,
+    ) -> Result<(), windows::core::Error> {
+        set_client_properties(audio_client, options)?;
+        let session = options.session.map(|session| GUID::from_u128(session.0));
+        let session = session.as_ref().map(|guid| guid as *const GUID);
+        if options.share_mode == ShareMode::Shared {
+            let buffer_duration = if options.offload {
+                offload_buffer_duration(audio_client, format, stream_flags, buffer_duration)?
+            } else {
+                buffer_duration
+            };
+            // Loopback streams always run at the period of the render stream, and offloaded
+            // streams at the period of the hardware.
+            let engine_period = if buffer_duration != 0
+                && stream_flags & Audio::AUDCLNT_STREAMFLAGS_LOOPBACK == 0
+                && !options.offload
+            {
+                shared_mode_engine_period(audio_client, format, buffer_duration)
+            } else {
+                None
+            };
+            if let Some((audio_client3, period)) = engine_period {
+                let result = audio_client3.InitializeSharedAudioStream(
+                    stream_flags,
+                    period,
+                    format,
+                    session,
+                );
+                if result.is_ok() {
+                    return Ok(());
+                }
+                *audio_client = self.build_audioclient()?;
+                set_client_properties(audio_client, options)?;
+            }
+            return audio_client.Initialize(
+                Audio::AUDCLNT_SHAREMODE_SHARED,
+                stream_flags,
+                buffer_duration,
+                0,
+                format,
+                session,
+            );
+        }
+
+        let mut buffer_duration = buffer_duration;
+        if buffer_duration == 0 {
+            audio_client.GetDevicePeriod(Some(&mut buffer_duration), None)?;
+        }
+        // Event driven exclusive streams must use the buffer duration as their period.
+        let result = audio_client.Initialize(
+            Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+            stream_flags,
+            buffer_duration,
+            buffer_duration,
+            format,
+            session,
+        );
+        match result {
+            Err(ref e) if e.code() == Audio::AUDCLNT_E_BUFFER_SIZE_NOT_ALIGNED => {
+                // The failed client reports the closest aligned buffer size, but can't be
+                // initialized again.
+                let frames = audio_client.GetBufferSize()?;
+                let sample_rate = format.nSamplesPerSec as i64;
+                let buffer_duration = (frames as i64 * 10_000_000 + sample_rate / 2) / sample_rate;
+                *audio_client = self.build_audioclient()?;
+                set_client_properties(audio_client, options)?;
+                audio_client.Initialize(
+                    Audio::AUDCLNT_SHAREMODE_EXCLUSIVE,
+                    stream_flags,
+                    buffer_duration,
+                    buffer_duration,
+                    format,
+                    session,
+                )
+            }
+            result => result,
+        }
+    }
+
+    // Whether a stream with the given configuration is bit-perfect and the format of the hardware.
+    fn signal_path(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        share_mode: ShareMode,
+    ) -> SignalPath {
+        match share_mode {
+            // The samples are exchanged with the driver unmodified.
+            ShareMode::Exclusive => SignalPath {
+                bit_perfect: true,
+                hardware_format: Some(HardwareFormat {
+                    channels: config.channels,
+                    sample_rate: config.sample_rate,
+                    sample_format: Some(sample_format),
+                }),
+            },
+            // The audio engine mixes the samples in floating point and may resample them or apply
+            // effects.
+            ShareMode::Shared => SignalPath {
+                bit_perfect: false,
+                hardware_format: self.engine_device_format(),
+            },
+        }
+    }
+
+    /// The spatial audio capabilities of this output device.
+    ///
+    /// Spatial streams can only be built while a spatializer, such as Windows Sonic or Dolby
+    /// Atmos, is enabled for the device in the system's sound settings, which the user may change
+    /// at any time.
+    pub fn spatial_config(&self) -> Result<SpatialConfig, SupportedStreamConfigsError> {
+        match self.kind {
+            DeviceKind::Endpoint(ref device) if self.data_flow() == Audio::eRender => {
+                spatial::config(device)
+            }
+            _ => Ok(SpatialConfig {
+                sample_rate: SampleRate(0),
+                max_objects: 0,
+            }),
+        }
+    }
+
+    /// Build an output stream rendering `objects` dynamic audio objects through the spatializer
+    /// of this device, at most the `max_objects` of its [`spatial_config`](Self::spatial_config).
+    ///
+    /// Every period `data_callback` updates the samples and positions of the objects, which are
+    /// all inactive initially. The thread name, thread spawner and role of `options` apply.
+    pub fn build_spatial_output_stream<D, E>(
+        &self,
+        objects: u32,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+    ) -> Result<SpatialStream, BuildStreamError>
+    where
+        D: FnMut(&mut [SpatialObject]) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        match self.kind {
+            DeviceKind::Endpoint(ref device) if self.data_flow() == Audio::eRender => {
+                spatial::build_output_stream(
+                    device,
+                    objects,
+                    options,
+                    data_callback,
+                    error_callback,
+                )
+            }
+            _ => Err(BuildStreamError::StreamConfigNotSupported),
+        }
+    }
+
+    fn is_process_loopback(&self) -> bool {
+        matches!(self.kind, DeviceKind::ProcessLoopback { .. })
+    }
+
+    // The format the audio engine runs the device at in shared mode.
+    fn engine_device_format(&self) -> Option<HardwareFormat> {
+        let device = match self.kind {
+            DeviceKind::Endpoint(ref device) => device,
+            DeviceKind::ProcessLoopback { .. } => return None,
+        };
+        unsafe {
+            let property_store = device.OpenPropertyStore(STGM_READ).ok()?;
+            let mut property_value = property_store
+                .GetValue(&Audio::PKEY_AudioEngine_DeviceFormat)
+                .ok()?;
+
+            // The format is stored as a blob containing a `WAVEFORMATEX`.
+            let prop_variant = &property_value.Anonymous.Anonymous;
+            let blob = prop_variant.Anonymous.blob;
+            let format = if prop_variant.vt == VT_BLOB
+                && blob.cbSize as usize >= mem::size_of::<Audio::WAVEFORMATEX>()
+            {
+                Some(hardware_format_from_waveformatex_ptr(
+                    blob.pBlobData as *const Audio::WAVEFORMATEX,
+                ))
+            } else {
+                None
+            };
+
+            // Clean up the property.
+            StructuredStorage::PropVariantClear(&mut property_value).ok();
+
+            format
+        }
+    }
+
+    // Re-create the stream built by `build` with the given parameters, if requested, sharing its
+    // record of why it was disconnected.
+    fn reactivation(
+        &self,
+        build: BuildStreamInner,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        disconnect_reason: &DisconnectReasonSlot,
+    ) -> Option<Reactivate> {
+        if !options.reactivate {
+            return None;
+        }
+        let device = self.clone();
+        let config = config.clone();
+        let options = options.clone();
+        let disconnect_reason = disconnect_reason.clone();
+        Some(Box::new(move || {
+            build(
+                &device,
+                &config,
+                sample_format,
+                &options,
+                &disconnect_reason,
+            )
+        }))
+    }
+
     pub(crate) fn build_input_stream_raw_inner(
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
+        options: &StreamOptions,
+        disconnect_reason: &DisconnectReasonSlot,
     ) -> Result<StreamInner, BuildStreamError> {
+        let share_mode = options.share_mode;
         unsafe {
             // Making sure that COM is initialized.
             // It's not actually sure that this is required, but when in doubt do it.
             com::com_initialized();
 
             // Obtaining a `IAudioClient`.
-            let audio_client = match self.build_audioclient() {
+            let mut audio_client = match self.build_audioclient() {
                 Ok(client) => client,
                 Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
                     return Err(BuildStreamError::DeviceNotAvailable)
@@ -604,36 +1494,60 @@ impl Device {
                 }
             };
 
-            let buffer_duration =
+            let mut buffer_duration =
                 buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
 
-            let mut stream_flags = Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK;
+            let mut stream_flags = stream_flags(options);
 
-            if self.data_flow() == Audio::eRender {
+            let process_loopback = self.is_process_loopback();
+            if self.data_flow() == Audio::eRender || process_loopback {
+                // Loopback capture is only available in shared mode.
+                if share_mode == ShareMode::Exclusive {
+                    return Err(BuildStreamError::StreamConfigNotSupported);
+                }
                 stream_flags |= Audio::AUDCLNT_STREAMFLAGS_LOOPBACK;
             }
+            if process_loopback {
+                // Process loopback clients convert the audio to any PCM format, but can neither
+                // check formats nor report a default buffer duration.
+                stream_flags |= Audio::AUDCLNT_STREAMFLAGS_AUTOCONVERTPCM;
+                if buffer_duration == 0 {
+                    buffer_duration = PROCESS_LOOPBACK_BUFFER_DURATION;
+                }
+            }
 
             // Computing the format and initializing the device.
             let waveformatex = {
                 let format_attempt = config_to_waveformatextensible(config, sample_format)
                     .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
 
                 // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
-                    Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
+                let supported = if process_loopback {
+                    let supported = process_loopback_configs().iter().any(|range| {
+                        range.channels == config.channels
+                            && range.sample_format == sample_format
+                            && range.min_sample_rate <= config.sample_rate
+                            && config.sample_rate <= range.max_sample_rate
+                    });
+                    Ok(Some(format_attempt).filter(|_| supported))
+                } else if converts_pcm(options) {
+                    Ok(Some(format_attempt))
+                } else {
+                    checked_format(&audio_client, share_mode, format_attempt)
+                };
+                let format_attempt = match supported {
+                    Ok(Some(format)) => format,
+                    Ok(None) => return Err(BuildStreamError::StreamConfigNotSupported),
                     Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
-                    _ => (),
-                }
+                };
 
                 // Finally, initializing the audio client
-                let hresult = audio_client.Initialize(
-                    share_mode,
+                let hresult = self.initialize_audio_client(
+                    &mut audio_client,
+                    options,
                     stream_flags,
                     buffer_duration,
-                    0,
                     &format_attempt.Format,
-                    None,
                 );
                 match hresult {
                     Err(ref e) if e.code() == Audio::AUDCLNT_E_DEVICE_INVALIDATED => {
@@ -646,6 +1560,7 @@ impl Device {
                     }
                     Ok(()) => (),
                 };
+                set_session_properties(&audio_client, options);
 
                 format_attempt.Format
             };
@@ -656,23 +1571,7 @@ impl Device {
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
             // Creating the event that will be signalled whenever we need to submit some samples.
-            let event = {
-                let event =
-                    Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
-                        .map_err(|e| {
-                            let description = format!("failed to create event: {}", e);
-                            let err = BackendSpecificError { description };
-                            BuildStreamError::from(err)
-                        })?;
-
-                if let Err(e) = audio_client.SetEventHandle(event) {
-                    let description = format!("failed to call SetEventHandle: {}", e);
-                    let err = BackendSpecificError { description };
-                    return Err(err.into());
-                }
-
-                event
-            };
+            let event = create_event(&audio_client, options, max_frames_in_buffer, config)?;
 
             // Building a `IAudioCaptureClient` that will be used to read captured samples.
             let capture_client = audio_client
@@ -689,10 +1588,19 @@ impl Device {
             let client_flow = AudioClientFlow::Capture { capture_client };
 
             let audio_clock = get_audio_clock(&audio_client)?;
+            let clock_frequency = audio_clock
+                .GetFrequency()
+                .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+            // Polling streams are woken by their timer anyway.
+            let wake_event = Some(event).filter(|_| !options.polling);
+            let session_events =
+                session_events::register(&audio_client, options, wake_event, disconnect_reason);
 
             Ok(StreamInner {
                 audio_client,
                 audio_clock,
+                clock_frequency,
+                frames_written: 0,
                 client_flow,
                 event,
                 playing: false,
@@ -700,6 +1608,16 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                stats: Arc::new(StreamStatsCollector::new()),
+                signal_path: self.signal_path(config, sample_format, share_mode),
+                session_events,
+                reactivate: self.reactivation(
+                    Device::build_input_stream_raw_inner,
+                    config,
+                    sample_format,
+                    options,
+                    disconnect_reason,
+                ),
             })
         }
     }
@@ -708,67 +1626,73 @@ impl Device {
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
+        options: &StreamOptions,
+        disconnect_reason: &DisconnectReasonSlot,
     ) -> Result<StreamInner, BuildStreamError> {
+        let share_mode = options.share_mode;
         unsafe {
             // Making sure that COM is initialized.
             // It's not actually sure that this is required, but when in doubt do it.
             com::com_initialized();
 
+            // Process loopback devices only capture.
+            if self.is_process_loopback() {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+
             // Obtaining a `IAudioClient`.
-            let audio_client = self
+            let mut audio_client = self
                 .build_audioclient()
                 .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
 
+            // Only PCM is mixed by the hardware.
+            if options.offload
+                && (share_mode == ShareMode::Exclusive
+                    || options.passthrough.is_some()
+                    || !is_offload_capable(&audio_client, stream_category(options)))
+            {
+                return Err(BuildStreamError::StreamConfigNotSupported);
+            }
+
             let buffer_duration =
                 buffer_size_to_duration(&config.buffer_size, config.sample_rate.0);
 
             // Computing the format and initializing the device.
             let waveformatex = {
-                let format_attempt = config_to_waveformatextensible(config, sample_format)
-                    .ok_or(BuildStreamError::StreamConfigNotSupported)?;
-                let share_mode = Audio::AUDCLNT_SHAREMODE_SHARED;
+                let format_attempt = match options.passthrough {
+                    None => config_to_waveformatextensible(config, sample_format),
+                    Some(passthrough) => {
+                        passthrough_to_waveformatextensible(config, sample_format, passthrough)
+                    }
+                }
+                .ok_or(BuildStreamError::StreamConfigNotSupported)?;
 
                 // Ensure the format is supported.
-                match super::device::is_format_supported(&audio_client, &format_attempt.Format) {
-                    Ok(false) => return Err(BuildStreamError::StreamConfigNotSupported),
+                let supported = if converts_pcm(options) {
+                    Ok(Some(format_attempt))
+                } else {
+                    checked_format(&audio_client, share_mode, format_attempt)
+                };
+                let format_attempt = match supported {
+                    Ok(Some(format)) => format,
+                    Ok(None) => return Err(BuildStreamError::StreamConfigNotSupported),
                     Err(_) => return Err(BuildStreamError::DeviceNotAvailable),
-                    _ => (),
-                }
+                };
 
                 // Finally, initializing the audio client
-                audio_client
-                    .Initialize(
-                        share_mode,
-                        Audio::AUDCLNT_STREAMFLAGS_EVENTCALLBACK,
-                        buffer_duration,
-                        0,
-                        &format_attempt.Format,
-                        None,
-                    )
-                    .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                self.initialize_audio_client(
+                    &mut audio_client,
+                    options,
+                    stream_flags(options),
+                    buffer_duration,
+                    &format_attempt.Format,
+                )
+                .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+                set_session_properties(&audio_client, options);
 
                 format_attempt.Format
             };
 
-            // Creating the event that will be signalled whenever we need to submit some samples.
-            let event = {
-                let event =
-                    Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
-                        .map_err(|e| {
-                            let description = format!("failed to create event: {}", e);
-                            let err = BackendSpecificError { description };
-                            BuildStreamError::from(err)
-                        })?;
-
-                if let Err(e) = audio_client.SetEventHandle(event) {
-                    let description = format!("failed to call SetEventHandle: {}", e);
-                    let err = BackendSpecificError { description };
-                    return Err(err.into());
-                }
-
-                event
-            };
-
             // obtaining the size of the samples buffer in number of frames
             let max_frames_in_buffer = audio_client.GetBufferSize().map_err(|e| {
                 windows_err_to_cpal_err_message::<BuildStreamError>(
@@ -777,6 +1701,9 @@ impl Device {
                 )
             })?;
 
+            // Creating the event that will be signalled whenever we need to submit some samples.
+            let event = create_event(&audio_client, options, max_frames_in_buffer, config)?;
+
             // Building a `IAudioRenderClient` that will be used to fill the samples buffer.
             let render_client = audio_client
                 .GetService::<IAudioRenderClient>()
@@ -792,10 +1719,19 @@ impl Device {
             let client_flow = AudioClientFlow::Render { render_client };
 
             let audio_clock = get_audio_clock(&audio_client)?;
+            let clock_frequency = audio_clock
+                .GetFrequency()
+                .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+            // Polling streams are woken by their timer anyway.
+            let wake_event = Some(event).filter(|_| !options.polling);
+            let session_events =
+                session_events::register(&audio_client, options, wake_event, disconnect_reason);
 
             Ok(StreamInner {
                 audio_client,
                 audio_clock,
+                clock_frequency,
+                frames_written: 0,
                 client_flow,
                 event,
                 playing: false,
@@ -803,6 +1739,16 @@ impl Device {
                 bytes_per_frame: waveformatex.nBlockAlign,
                 config: config.clone(),
                 sample_format,
+                stats: Arc::new(StreamStatsCollector::new()),
+                signal_path: self.signal_path(config, sample_format, share_mode),
+                session_events,
+                reactivate: self.reactivation(
+                    Device::build_output_stream_raw_inner,
+                    config,
+                    sample_format,
+                    options,
+                    disconnect_reason,
+                ),
             })
         }
     }
@@ -811,6 +1757,17 @@ impl Device {
 impl PartialEq for Device {
     #[inline]
     fn eq(&self, other: &Device) -> bool {
+        let (device1, device2) = match (&self.kind, &other.kind) {
+            (DeviceKind::Endpoint(device1), DeviceKind::Endpoint(device2)) => (device1, device2),
+            (
+                &DeviceKind::ProcessLoopback { process_id, mode },
+                &DeviceKind::ProcessLoopback {
+                    process_id: other_process_id,
+                    mode: other_mode,
+                },
+            ) => return process_id == other_process_id && mode == other_mode,
+            _ => return false,
+        };
         // Use case: In order to check whether the default device has changed
         // the client code might need to compare the previous default device with the current one.
         // The pointer comparison (`self.device == other.device`) don't work there,
@@ -828,9 +1785,9 @@ impl PartialEq for Device {
             }
             // GetId only fails with E_OUTOFMEMORY and if it does, we're probably dead already.
             // Plus it won't do to change the device comparison logic unexpectedly.
-            let id1 = self.device.GetId().expect("cpal: GetId failure");
+            let id1 = device1.GetId().expect("cpal: GetId failure");
             let id1 = IdRAII(id1);
-            let id2 = other.device.GetId().expect("cpal: GetId failure");
+            let id2 = device2.GetId().expect("cpal: GetId failure");
             let id2 = IdRAII(id2);
             // 16-bit null-terminated comparison.
             let mut offset = 0;
@@ -854,7 +1811,7 @@ impl Eq for Device {}
 impl fmt::Debug for Device {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         f.debug_struct("Device")
-            .field("device", &self.device)
+            .field("device", &self.kind)
             .field("name", &self.name())
             .finish()
     }
@@ -952,23 +1909,28 @@ impl Iterator for Devices {
     }
 }
 
-fn default_device(data_flow: Audio::EDataFlow) -> Option<Device> {
+fn default_device(data_flow: Audio::EDataFlow, role: Audio::ERole) -> Option<Device> {
     unsafe {
-        let device = ENUMERATOR
-            .0
-            .GetDefaultAudioEndpoint(data_flow, Audio::eConsole)
-            .ok()?;
+        let device = ENUMERATOR.0.GetDefaultAudioEndpoint(data_flow, role).ok()?;
         // TODO: check specifically for `E_NOTFOUND`, and panic otherwise
         Some(Device::from_immdevice(device))
     }
 }
 
 pub fn default_input_device() -> Option<Device> {
-    default_device(Audio::eCapture)
+    default_device(Audio::eCapture, Audio::eConsole)
 }
 
 pub fn default_output_device() -> Option<Device> {
-    default_device(Audio::eRender)
+    default_device(Audio::eRender, Audio::eConsole)
+}
+
+pub fn default_communications_input_device() -> Option<Device> {
+    default_device(Audio::eCapture, Audio::eCommunications)
+}
+
+pub fn default_communications_output_device() -> Option<Device> {
+    default_device(Audio::eRender, Audio::eCommunications)
 }
 
 /// Get the audio clock used to produce `StreamInstant`s.
@@ -989,9 +1951,13 @@ fn config_to_waveformatextensible(
     config: &StreamConfig,
     sample_format: SampleFormat,
 ) -> Option<Audio::WAVEFORMATEXTENSIBLE> {
+    // `WAVE_FORMAT_PCM` can only describe mono and stereo streams of up to 16 bits.
+    let extensible = sample_format != SampleFormat::I16 || config.channels > 2;
     let format_tag = match sample_format {
+        SampleFormat::I16 | SampleFormat::I32 | SampleFormat::F32 if extensible => {
+            KernelStreaming::WAVE_FORMAT_EXTENSIBLE
+        }
         SampleFormat::I16 => Audio::WAVE_FORMAT_PCM,
-        SampleFormat::F32 => KernelStreaming::WAVE_FORMAT_EXTENSIBLE,
         _ => return None,
     } as u16;
     let channels = config.channels;
@@ -1000,14 +1966,12 @@ fn config_to_waveformatextensible(
     let avg_bytes_per_sec = u32::from(channels) * sample_rate * u32::from(sample_bytes);
     let block_align = channels * sample_bytes;
     let bits_per_sample = 8 * sample_bytes;
-    let cb_size = match sample_format {
-        SampleFormat::I16 => 0,
-        SampleFormat::F32 => {
-            let extensible_size = mem::size_of::<Audio::WAVEFORMATEXTENSIBLE>();
-            let ex_size = mem::size_of::<Audio::WAVEFORMATEX>();
-            (extensible_size - ex_size) as u16
-        }
-        _ => return None,
+    let cb_size = if extensible {
+        let extensible_size = mem::size_of::<Audio::WAVEFORMATEXTENSIBLE>();
+        let ex_size = mem::size_of::<Audio::WAVEFORMATEX>();
+        (extensible_size - ex_size) as u16
+    } else {
+        0
     };
     let waveformatex = Audio::WAVEFORMATEX {
         wFormatTag: format_tag,
@@ -1019,11 +1983,10 @@ fn config_to_waveformatextensible(
         cbSize: cb_size,
     };
 
-    // CPAL does not care about speaker positions, so pass audio ight through.
-    let channel_mask = KernelStreaming::KSAUDIO_SPEAKER_DIRECTOUT;
+    let channel_mask = channel_mask(channels);
 
     let sub_format = match sample_format {
-        SampleFormat::I16 => KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM,
+        SampleFormat::I16 | SampleFormat::I32 => KernelStreaming::KSDATAFORMAT_SUBTYPE_PCM,
         SampleFormat::F32 => Multimedia::KSDATAFORMAT_SUBTYPE_IEEE_FLOAT,
         _ => return None,
     };
@@ -1039,6 +2002,55 @@ fn config_to_waveformatextensible(
     Some(waveformatextensible)
 }
 
+/// The speaker positions of the channels of a stream with `channels` channels: the usual layout
+/// for up to 7.1 and none beyond, in which case the channels map to the device's channels in
+/// order, as expected by multichannel interfaces.
+fn channel_mask(channels: u16) -> u32 {
+    use KernelStreaming::{
+        SPEAKER_BACK_CENTER, SPEAKER_BACK_LEFT, SPEAKER_BACK_RIGHT, SPEAKER_FRONT_CENTER,
+        SPEAKER_FRONT_LEFT, SPEAKER_FRONT_RIGHT, SPEAKER_LOW_FREQUENCY, SPEAKER_SIDE_LEFT,
+        SPEAKER_SIDE_RIGHT,
+    };
+    let front = SPEAKER_FRONT_LEFT | SPEAKER_FRONT_RIGHT;
+    let back = SPEAKER_BACK_LEFT | SPEAKER_BACK_RIGHT;
+    let side = SPEAKER_SIDE_LEFT | SPEAKER_SIDE_RIGHT;
+    match channels {
+        1 => SPEAKER_FRONT_CENTER,
+        2 => front,
+        3 => front | SPEAKER_FRONT_CENTER,
+        4 => front | back,
+        5 => front | SPEAKER_FRONT_CENTER | back,
+        6 => front | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | back,
+        7 => front | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | SPEAKER_BACK_CENTER | side,
+        8 => front | SPEAKER_FRONT_CENTER | SPEAKER_LOW_FREQUENCY | back | side,
+        _ => KernelStreaming::KSAUDIO_SPEAKER_DIRECTOUT,
+    }
+}
+
+/// The IEC 61937 format carrying `passthrough` bursts packed into the `I16` samples of a stream.
+fn passthrough_to_waveformatextensible(
+    config: &StreamConfig,
+    sample_format: SampleFormat,
+    passthrough: PassthroughFormat,
+) -> Option<Audio::WAVEFORMATEXTENSIBLE> {
+    if sample_format != SampleFormat::I16 {
+        return None;
+    }
+    let mut waveformatextensible = config_to_waveformatextensible(config, sample_format)?;
+    let extensible_size = mem::size_of::<Audio::WAVEFORMATEXTENSIBLE>();
+    let ex_size = mem::size_of::<Audio::WAVEFORMATEX>();
+    waveformatextensible.Format.wFormatTag = KernelStreaming::WAVE_FORMAT_EXTENSIBLE as u16;
+    waveformatextensible.Format.cbSize = (extensible_size - ex_size) as u16;
+    waveformatextensible.SubFormat = match passthrough {
+        PassthroughFormat::Ac3 => KernelStreaming::KSDATAFORMAT_SUBTYPE_IEC61937_DOLBY_DIGITAL,
+        PassthroughFormat::Eac3 => {
+            KernelStreaming::KSDATAFORMAT_SUBTYPE_IEC61937_DOLBY_DIGITAL_PLUS
+        }
+        PassthroughFormat::Dts => KernelStreaming::KSDATAFORMAT_SUBTYPE_IEC61937_DTS,
+    };
+    Some(waveformatextensible)
+}
+
 fn buffer_size_to_duration(buffer_size: &BufferSize, sample_rate: u32) -> i64 {
     match buffer_size {
         BufferSize::Fixed(frames) => *frames as i64 * (1_000_000_000 / 100) / sample_rate as i64,
@@ -0,0 +1,125 @@
+//! Identification of the hardware and driver behind an endpoint, from the property stores of the
+//! endpoint and of the device node of its audio adapter.
+
+use std::ffi::OsString;
+use std::os::windows::ffi::OsStringExt;
+use std::slice;
+
+use windows::core::PCWSTR;
+use windows::Win32::Devices::DeviceAndDriverInstallation::{
+    CM_Get_DevNode_PropertyW, CM_Get_Parent, CM_Locate_DevNodeW, CM_LOCATE_DEVNODE_NORMAL,
+    CR_BUFFER_SMALL, CR_SUCCESS,
+};
+use windows::Win32::Devices::Properties::{self, DEVPROPKEY, DEVPROPTYPE, DEVPROP_TYPE_STRING};
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Com::{StructuredStorage, STGM_READ, VT_LPWSTR};
+use windows::Win32::UI::Shell::PropertiesSystem::{IPropertyStore, PROPERTYKEY};
+
+/// The audio adapter and driver an endpoint belongs to, as shown by Device Manager, returned by
+/// [`Device::driver_info`](super::Device::driver_info).
+///
+/// Each field is `None` if the property is not set, e.g. on process loopback devices, which have
+/// no adapter.
+#[derive(Clone, Debug, Default, Eq, Hash, PartialEq)]
+pub struct DriverInfo {
+    /// The name of the adapter the endpoint belongs to, e.g. "Realtek(R) Audio".
+    pub interface_name: Option<String>,
+    /// The description of the adapter given by its driver.
+    pub driver_description: Option<String>,
+    /// The manufacturer of the adapter given by its driver.
+    pub manufacturer: Option<String>,
+    /// The version of the adapter's driver, e.g. "6.0.9235.1".
+    pub driver_version: Option<String>,
+    /// The device instance path of the adapter, e.g. `HDAUDIO\FUNC_01&VEN_10EC&...`, which
+    /// identifies its bus, vendor and model.
+    pub instance_path: Option<String>,
+}
+
+pub(crate) unsafe fn driver_info(device: &Audio::IMMDevice) -> DriverInfo {
+    let property_store = match device.OpenPropertyStore(STGM_READ) {
+        Ok(property_store) => property_store,
+        Err(_) => return DriverInfo::default(),
+    };
+    let key = |key: &DEVPROPKEY| &*(key as *const DEVPROPKEY as *const PROPERTYKEY);
+    let interface_name = string_property(
+        &property_store,
+        key(&Properties::DEVPKEY_DeviceInterface_FriendlyName),
+    );
+
+    // The endpoint is a software device whose parent is the device node of its adapter.
+    let adapter = string_property(&property_store, key(&Properties::DEVPKEY_Device_InstanceId))
+        .and_then(|endpoint_id| {
+            let endpoint_id: Vec<u16> = endpoint_id.encode_utf16().chain(Some(0)).collect();
+            let mut endpoint = 0;
+            if CM_Locate_DevNodeW(
+                &mut endpoint,
+                PCWSTR(endpoint_id.as_ptr()),
+                CM_LOCATE_DEVNODE_NORMAL,
+            ) != CR_SUCCESS
+            {
+                return None;
+            }
+            let mut adapter = 0;
+            (CM_Get_Parent(&mut adapter, endpoint, 0) == CR_SUCCESS).then_some(adapter)
+        });
+    let adapter_property = |key| adapter.and_then(|adapter| devnode_string_property(adapter, key));
+
+    DriverInfo {
+        interface_name,
+        driver_description: adapter_property(&Properties::DEVPKEY_Device_DriverDesc),
+        manufacturer: adapter_property(&Properties::DEVPKEY_Device_Manufacturer),
+        driver_version: adapter_property(&Properties::DEVPKEY_Device_DriverVersion),
+        instance_path: adapter_property(&Properties::DEVPKEY_Device_InstanceId),
+    }
+}
+
+// The string value of the property with `key` in `property_store`.
+unsafe fn string_property(property_store: &IPropertyStore, key: &PROPERTYKEY) -> Option<String> {
+    let mut property_value = property_store.GetValue(key).ok()?;
+    let prop_variant = &property_value.Anonymous.Anonymous;
+    let string = if prop_variant.vt == VT_LPWSTR {
+        let ptr_utf16 = *(&prop_variant.Anonymous as *const _ as *const *const u16);
+        let mut len = 0;
+        while *ptr_utf16.add(len) != 0 {
+            len += 1;
+        }
+        Some(from_wide(slice::from_raw_parts(ptr_utf16, len)))
+    } else {
+        None
+    };
+    StructuredStorage::PropVariantClear(&mut property_value).ok();
+    string
+}
+
+// The string value of the property with `key` of the device node `devnode`.
+unsafe fn devnode_string_property(devnode: u32, key: &DEVPROPKEY) -> Option<String> {
+    let mut property_type = DEVPROPTYPE::default();
+    let mut size = 0;
+    if CM_Get_DevNode_PropertyW(devnode, key, &mut property_type, None, &mut size, 0)
+        != CR_BUFFER_SMALL
+    {
+        return None;
+    }
+    let mut buffer = vec![0u16; (size as usize).div_ceil(2)];
+    if CM_Get_DevNode_PropertyW(
+        devnode,
+        key,
+        &mut property_type,
+        Some(buffer.as_mut_ptr() as *mut u8),
+        &mut size,
+        0,
+    ) != CR_SUCCESS
+        || property_type != DEVPROP_TYPE_STRING
+    {
+        return None;
+    }
+    let len = buffer.iter().position(|&c| c == 0).unwrap_or(buffer.len());
+    Some(from_wide(&buffer[..len]))
+}
+
+fn from_wide(wide: &[u16]) -> String {
+    match OsString::from_wide(wide).into_string() {
+        Ok(string) => string,
+        Err(os_string) => os_string.to_string_lossy().into(),
+    }
+}
@@ -1,7 +1,11 @@
 pub use self::device::{
-    default_input_device, default_output_device, Device, Devices, SupportedInputConfigs,
-    SupportedOutputConfigs,
+    default_communications_input_device, default_communications_output_device,
+    default_input_device, default_output_device, Device, DevicePeriods, Devices, EnginePeriods,
+    SupportedInputConfigs, SupportedOutputConfigs,
 };
+pub use self::driver_info::DriverInfo;
+pub use self::process_loopback::ProcessLoopbackMode;
+pub use self::spatial::{SpatialConfig, SpatialObject, SpatialStream};
 pub use self::stream::Stream;
 use crate::traits::HostTrait;
 use crate::BackendSpecificError;
@@ -11,13 +15,19 @@ use windows::Win32::Media::Audio;
 
 mod com;
 mod device;
+mod driver_info;
+mod process_loopback;
+mod session_events;
+mod spatial;
 mod stream;
 
 /// The WASAPI host, the default windows host type.
 ///
 /// Note: If you use a WASAPI output device as an input device it will
 /// transparently enable loopback mode (see
-/// https://docs.microsoft.com/en-us/windows/win32/coreaudio/loopback-recording).
+/// https://docs.microsoft.com/en-us/windows/win32/coreaudio/loopback-recording). Such streams
+/// capture the mix played by the device, in the format returned by
+/// [`Device::loopback_config`], and are only supported in shared mode.
 #[derive(Debug)]
 pub struct Host;
 
@@ -25,6 +35,18 @@ impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
         Ok(Host)
     }
+
+    /// The default input device for communications, e.g. voice calls, which users may set apart
+    /// from the default input device.
+    pub fn default_communications_input_device(&self) -> Option<Device> {
+        default_communications_input_device()
+    }
+
+    /// The default output device for communications, e.g. voice calls, which users may set apart
+    /// from the default output device.
+    pub fn default_communications_output_device(&self) -> Option<Device> {
+        default_communications_output_device()
+    }
 }
 
 impl HostTrait for Host {
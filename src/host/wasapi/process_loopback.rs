@@ -0,0 +1,130 @@
+//! Activation of audio clients capturing the audio played by a process tree, available since
+//! Windows 10 version 2004.
+
+use std::ffi::c_void;
+use std::mem;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Condvar, Mutex};
+
+use super::com::{self, ComObject};
+use windows::core::{ComInterface, IUnknown_Vtbl, HRESULT};
+use windows::Win32::Foundation;
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Com::{IAgileObject, StructuredStorage, BLOB, VT_BLOB};
+
+/// The processes whose audio is captured by a process loopback device, created with
+/// [`Device::process_loopback`](super::Device::process_loopback).
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum ProcessLoopbackMode {
+    /// Capture the audio played by the process and its child processes.
+    IncludeProcessTree,
+    /// Capture the audio played by all processes except the process and its child processes.
+    ExcludeProcessTree,
+}
+
+impl From<ProcessLoopbackMode> for Audio::PROCESS_LOOPBACK_MODE {
+    fn from(mode: ProcessLoopbackMode) -> Self {
+        match mode {
+            ProcessLoopbackMode::IncludeProcessTree => {
+                Audio::PROCESS_LOOPBACK_MODE_INCLUDE_TARGET_PROCESS_TREE
+            }
+            ProcessLoopbackMode::ExcludeProcessTree => {
+                Audio::PROCESS_LOOPBACK_MODE_EXCLUDE_TARGET_PROCESS_TREE
+            }
+        }
+    }
+}
+
+/// Activate an uninitialized `IAudioClient` capturing the audio of the process with the given ID,
+/// blocking until the activation has completed.
+pub(crate) unsafe fn activate_audio_client(
+    process_id: u32,
+    mode: ProcessLoopbackMode,
+) -> Result<Audio::IAudioClient, windows::core::Error> {
+    let params = Audio::AUDIOCLIENT_ACTIVATION_PARAMS {
+        ActivationType: Audio::AUDIOCLIENT_ACTIVATION_TYPE_PROCESS_LOOPBACK,
+        Anonymous: Audio::AUDIOCLIENT_ACTIVATION_PARAMS_0 {
+            ProcessLoopbackParams: Audio::AUDIOCLIENT_PROCESS_LOOPBACK_PARAMS {
+                TargetProcessId: process_id,
+                ProcessLoopbackMode: mode.into(),
+            },
+        },
+    };
+    // The parameters are passed as a blob, which is borrowed and must not be cleared.
+    let mut activation_params = StructuredStorage::PROPVARIANT::default();
+    let prop_variant = &mut *activation_params.Anonymous.Anonymous;
+    prop_variant.vt = VT_BLOB;
+    prop_variant.Anonymous.blob = BLOB {
+        cbSize: mem::size_of::<Audio::AUDIOCLIENT_ACTIVATION_PARAMS>() as u32,
+        pBlobData: &params as *const _ as *mut u8,
+    };
+
+    let completed = Arc::new((Mutex::new(false), Condvar::new()));
+    let handler: Audio::IActivateAudioInterfaceCompletionHandler =
+        com::into_interface(CompletionHandler {
+            vtable: &VTABLE,
+            refs: AtomicU32::new(0),
+            completed: completed.clone(),
+        });
+    let operation = Audio::ActivateAudioInterfaceAsync(
+        Audio::VIRTUAL_AUDIO_DEVICE_PROCESS_LOOPBACK,
+        &Audio::IAudioClient::IID,
+        Some(&activation_params),
+        &handler,
+    )?;
+    let (lock, condvar) = &*completed;
+    let mut done = lock.lock().unwrap();
+    while !*done {
+        done = condvar.wait(done).unwrap();
+    }
+
+    let mut result = Foundation::S_OK;
+    let mut audio_client = None;
+    operation.GetActivateResult(&mut result, &mut audio_client)?;
+    result.ok()?;
+    audio_client
+        .ok_or_else(|| windows::core::Error::from(Foundation::E_FAIL))?
+        .cast::<Audio::IAudioClient>()
+}
+
+/// An `IActivateAudioInterfaceCompletionHandler` signalling the completion of an activation.
+#[repr(C)]
+struct CompletionHandler {
+    // Must come first, as pointers to the interface point to the pointer to its vtable.
+    vtable: &'static Audio::IActivateAudioInterfaceCompletionHandler_Vtbl,
+    refs: AtomicU32,
+    completed: Arc<(Mutex<bool>, Condvar)>,
+}
+
+unsafe impl ComObject for CompletionHandler {
+    // The handler is agile, as the activation completes on another thread.
+    const IIDS: &'static [windows::core::GUID] = &[
+        Audio::IActivateAudioInterfaceCompletionHandler::IID,
+        IAgileObject::IID,
+    ];
+
+    fn refs(&self) -> &AtomicU32 {
+        &self.refs
+    }
+}
+
+static VTABLE: Audio::IActivateAudioInterfaceCompletionHandler_Vtbl =
+    Audio::IActivateAudioInterfaceCompletionHandler_Vtbl {
+        base__: IUnknown_Vtbl {
+            QueryInterface: com::query_interface::<CompletionHandler>,
+            AddRef: com::add_ref::<CompletionHandler>,
+            Release: com::release::<CompletionHandler>,
+        },
+        ActivateCompleted: activate_completed,
+    };
+
+unsafe extern "system" fn activate_completed(
+    this: *mut c_void,
+    _operation: *mut c_void,
+) -> HRESULT {
+    let handler = &*(this as *const CompletionHandler);
+    let (lock, condvar) = &*handler.completed;
+    *lock.lock().unwrap() = true;
+    condvar.notify_all();
+    Foundation::S_OK
+}
@@ -0,0 +1,204 @@
+//! Notifications of changes to the audio session of a stream, reported as `StreamEvent`s and as
+//! the reason the stream was disconnected.
+
+use std::ffi::c_void;
+use std::sync::atomic::AtomicU32;
+use std::sync::{Arc, Mutex};
+
+use super::com::{self, ComObject};
+use crate::{DisconnectReason, StreamError, StreamEvent, StreamOptions};
+use windows::core::{ComInterface, IUnknown_Vtbl, GUID, HRESULT, PCWSTR};
+use windows::Win32::Foundation::{BOOL, HANDLE, S_OK};
+use windows::Win32::Media::Audio;
+use windows::Win32::System::Threading;
+
+type EventCallback = dyn Fn(StreamEvent) + Send + Sync;
+
+/// Where the session notifications of a stream record why it was disconnected, shared with the
+/// streams re-created in its place.
+pub(crate) type DisconnectReasonSlot = Arc<Mutex<Option<DisconnectReason>>>;
+
+/// Keeps the session notifications of a stream registered as long as it lives.
+pub struct SessionEvents {
+    session_control: Audio::IAudioSessionControl,
+    events: Audio::IAudioSessionEvents,
+    disconnect_reason: DisconnectReasonSlot,
+}
+
+impl SessionEvents {
+    /// Wrap `error_callback` so that the `DeviceNotAvailable` errors following the disconnection
+    /// of the session report why it was disconnected.
+    pub fn explain_disconnection<E>(&self, mut error_callback: E) -> impl FnMut(StreamError) + Send
+    where
+        E: FnMut(StreamError) + Send,
+    {
+        let disconnect_reason = self.disconnect_reason.clone();
+        move |err| match err {
+            StreamError::DeviceNotAvailable => match *disconnect_reason.lock().unwrap() {
+                Some(reason) => error_callback(StreamError::Disconnected { reason }),
+                None => error_callback(err),
+            },
+            err => error_callback(err),
+        }
+    }
+
+    /// Forget the disconnection of the session of a stream that was re-created since.
+    pub fn forget_disconnection(&self) {
+        *self.disconnect_reason.lock().unwrap() = None;
+    }
+}
+
+impl Drop for SessionEvents {
+    fn drop(&mut self) {
+        unsafe {
+            let _ = self
+                .session_control
+                .UnregisterAudioSessionNotification(&self.events);
+        }
+    }
+}
+
+/// Register for the notifications of the session of the initialized `audio_client`, reporting
+/// them to the event callback of `options`, if any. `wake_event` is signalled when the session is
+/// disconnected, so that the stream thread notices without waiting for the device, and the reason
+/// is recorded in `disconnect_reason`.
+///
+/// The notifications are only informational, so failures are ignored and none are reported.
+pub(crate) unsafe fn register(
+    audio_client: &Audio::IAudioClient,
+    options: &StreamOptions,
+    wake_event: Option<HANDLE>,
+    disconnect_reason: &DisconnectReasonSlot,
+) -> Option<SessionEvents> {
+    let session_control = audio_client
+        .GetService::<Audio::IAudioSessionControl>()
+        .ok()?;
+    let disconnect_reason = disconnect_reason.clone();
+    let events: Audio::IAudioSessionEvents = com::into_interface(EventHandler {
+        vtable: &VTABLE,
+        refs: AtomicU32::new(0),
+        callback: options.event_callback.clone(),
+        disconnect_reason: disconnect_reason.clone(),
+        wake_event,
+    });
+    session_control
+        .RegisterAudioSessionNotification(&events)
+        .ok()?;
+    Some(SessionEvents {
+        session_control,
+        events,
+        disconnect_reason,
+    })
+}
+
+/// An `IAudioSessionEvents` forwarding the notifications it receives to an event callback.
+#[repr(C)]
+struct EventHandler {
+    // Must come first, as pointers to the interface point to the pointer to its vtable.
+    vtable: &'static Audio::IAudioSessionEvents_Vtbl,
+    refs: AtomicU32,
+    callback: Option<Arc<EventCallback>>,
+    disconnect_reason: DisconnectReasonSlot,
+    wake_event: Option<HANDLE>,
+}
+
+unsafe impl ComObject for EventHandler {
+    const IIDS: &'static [GUID] = &[Audio::IAudioSessionEvents::IID];
+
+    fn refs(&self) -> &AtomicU32 {
+        &self.refs
+    }
+}
+
+static VTABLE: Audio::IAudioSessionEvents_Vtbl = Audio::IAudioSessionEvents_Vtbl {
+    base__: IUnknown_Vtbl {
+        QueryInterface: com::query_interface::<EventHandler>,
+        AddRef: com::add_ref::<EventHandler>,
+        Release: com::release::<EventHandler>,
+    },
+    OnDisplayNameChanged: on_display_name_changed,
+    OnIconPathChanged: on_icon_path_changed,
+    OnSimpleVolumeChanged: on_simple_volume_changed,
+    OnChannelVolumeChanged: on_channel_volume_changed,
+    OnGroupingParamChanged: on_grouping_param_changed,
+    OnStateChanged: on_state_changed,
+    OnSessionDisconnected: on_session_disconnected,
+};
+
+unsafe extern "system" fn on_simple_volume_changed(
+    this: *mut c_void,
+    volume: f32,
+    muted: BOOL,
+    _context: *const GUID,
+) -> HRESULT {
+    let handler = &*(this as *const EventHandler);
+    if let Some(callback) = &handler.callback {
+        callback(StreamEvent::VolumeChanged {
+            volume,
+            muted: muted.as_bool(),
+        });
+    }
+    S_OK
+}
+
+unsafe extern "system" fn on_display_name_changed(
+    _this: *mut c_void,
+    _name: PCWSTR,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_icon_path_changed(
+    _this: *mut c_void,
+    _path: PCWSTR,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_channel_volume_changed(
+    _this: *mut c_void,
+    _channel_count: u32,
+    _volumes: *const f32,
+    _changed_channel: u32,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_grouping_param_changed(
+    _this: *mut c_void,
+    _grouping_param: *const GUID,
+    _context: *const GUID,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_state_changed(
+    _this: *mut c_void,
+    _state: Audio::AudioSessionState,
+) -> HRESULT {
+    S_OK
+}
+
+unsafe extern "system" fn on_session_disconnected(
+    this: *mut c_void,
+    reason: Audio::AudioSessionDisconnectReason,
+) -> HRESULT {
+    let handler = &*(this as *const EventHandler);
+    let reason = match reason {
+        Audio::DisconnectReasonFormatChanged => DisconnectReason::FormatChanged,
+        Audio::DisconnectReasonExclusiveModeOverride => DisconnectReason::ExclusiveModeOverride,
+        Audio::DisconnectReasonServerShutdown => DisconnectReason::ServerShutdown,
+        Audio::DisconnectReasonSessionLogoff | Audio::DisconnectReasonSessionDisconnected => {
+            DisconnectReason::SessionEnded
+        }
+        _ => DisconnectReason::DeviceRemoved,
+    };
+    *handler.disconnect_reason.lock().unwrap() = Some(reason);
+    if let Some(event) = handler.wake_event {
+        Threading::SetEvent(event);
+    }
+    S_OK
+}
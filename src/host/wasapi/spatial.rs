@@ -0,0 +1,376 @@
+//! Output streams of dynamic audio objects rendered by the platform spatializer, such as Windows
+//! Sonic or Dolby Atmos, using `ISpatialAudioClient`.
+
+use std::mem;
+use std::ptr;
+use std::sync::mpsc::{channel, Receiver, SendError, Sender};
+
+use super::com;
+use super::stream::{wait_for_handle_signal, Command};
+use super::windows_err_to_cpal_err;
+use crate::thread::{self, AudioThread};
+use crate::traits::StreamTrait;
+use crate::{
+    BackendSpecificError, BuildStreamError, PauseStreamError, PlayStreamError, SampleRate,
+    StreamError, StreamOptions, SupportedStreamConfigsError,
+};
+use windows::Win32::Foundation;
+use windows::Win32::Media::{Audio, KernelStreaming, Multimedia};
+use windows::Win32::System::Com::{self, StructuredStorage, BLOB, VT_BLOB};
+use windows::Win32::System::Threading;
+
+/// The spatial audio capabilities of an output device, retrieved via
+/// [`Device::spatial_config`](super::Device::spatial_config).
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct SpatialConfig {
+    /// The sample rate of the objects of spatial streams on the device.
+    pub sample_rate: SampleRate,
+    /// The maximum number of dynamic objects a spatial stream on the device can render, or `0` if
+    /// no spatializer is enabled for the device.
+    pub max_objects: u32,
+}
+
+/// A dynamic audio object of a spatial output stream, updated by its data callback every period.
+///
+/// Positions are in meters relative to the listener, in a right-handed coordinate system: `x`
+/// points to the right, `y` up and `z` backwards.
+#[derive(Debug)]
+pub struct SpatialObject {
+    /// Whether the object is playing. Objects that stop playing release the resources of the
+    /// spatializer they used, which may be needed for other objects to play.
+    pub active: bool,
+    pub position: [f32; 3],
+    /// The volume of the object, from `0.0` to `1.0`.
+    pub volume: f32,
+    samples: Vec<f32>,
+    frames: usize,
+    object: Option<Audio::ISpatialAudioObject>,
+}
+
+impl SpatialObject {
+    fn new(max_frames: usize) -> Self {
+        SpatialObject {
+            active: false,
+            position: [0.0; 3],
+            volume: 1.0,
+            samples: vec![0.0; max_frames],
+            frames: 0,
+            object: None,
+        }
+    }
+
+    /// The mono samples of the object for the current period, silent until written.
+    pub fn samples_mut(&mut self) -> &mut [f32] {
+        &mut self.samples[..self.frames]
+    }
+}
+
+/// A stream of dynamic audio objects, built with
+/// [`Device::build_spatial_output_stream`](super::Device::build_spatial_output_stream).
+pub struct SpatialStream {
+    thread: Option<AudioThread>,
+    commands: Sender<Command>,
+    pending_scheduled_event: Foundation::HANDLE,
+}
+
+// The render stream of a spatial stream, along with the event it signals every period.
+struct RenderStream {
+    stream: Audio::ISpatialAudioObjectRenderStream,
+    event: Foundation::HANDLE,
+    max_frames: u32,
+}
+
+unsafe impl Send for RenderStream {}
+
+impl Drop for RenderStream {
+    fn drop(&mut self) {
+        unsafe {
+            Foundation::CloseHandle(self.event);
+        }
+    }
+}
+
+// Activate the spatial audio client of `device`, along with the formats of its objects.
+unsafe fn activate(
+    device: &Audio::IMMDevice,
+) -> Result<(Audio::ISpatialAudioClient, Audio::IAudioFormatEnumerator), windows::core::Error> {
+    let client: Audio::ISpatialAudioClient = device.Activate(Com::CLSCTX_INPROC_SERVER, None)?;
+    let formats = client.GetSupportedAudioObjectFormatEnumerator()?;
+    Ok((client, formats))
+}
+
+// The format preferred by the spatializer, which lives as long as `formats`.
+unsafe fn object_format(
+    formats: &Audio::IAudioFormatEnumerator,
+) -> Result<&Audio::WAVEFORMATEX, windows::core::Error> {
+    Ok(&*formats.GetFormat(0)?)
+}
+
+// Objects are mono 32-bit float, possibly described by a `WAVEFORMATEXTENSIBLE`.
+fn is_float_mono(format: &Audio::WAVEFORMATEX) -> bool {
+    let format_tag = format.wFormatTag as u32;
+    format.nChannels == 1
+        && format.wBitsPerSample == 32
+        && (format_tag == Multimedia::WAVE_FORMAT_IEEE_FLOAT
+            || format_tag == KernelStreaming::WAVE_FORMAT_EXTENSIBLE)
+}
+
+pub(crate) fn config(
+    device: &Audio::IMMDevice,
+) -> Result<SpatialConfig, SupportedStreamConfigsError> {
+    com::com_initialized();
+    unsafe {
+        let (client, formats) =
+            activate(device).map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        let format = object_format(&formats)
+            .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        let max_objects = client
+            .GetMaxDynamicObjectCount()
+            .map_err(windows_err_to_cpal_err::<SupportedStreamConfigsError>)?;
+        Ok(SpatialConfig {
+            sample_rate: SampleRate(format.nSamplesPerSec),
+            max_objects: if is_float_mono(format) {
+                max_objects
+            } else {
+                0
+            },
+        })
+    }
+}
+
+pub(crate) fn build_output_stream<D, E>(
+    device: &Audio::IMMDevice,
+    objects: u32,
+    options: &StreamOptions,
+    data_callback: D,
+    error_callback: E,
+) -> Result<SpatialStream, BuildStreamError>
+where
+    D: FnMut(&mut [SpatialObject]) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let thread_name = thread::thread_name(options, "cpal_wasapi_3d")?;
+    com::com_initialized();
+    let render_stream = unsafe { activate_render_stream(device, objects, options)? };
+
+    let pending_scheduled_event =
+        unsafe { Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null())) }
+            .expect("cpal: could not create spatial stream event");
+    let (tx, rx) = channel();
+
+    let thread = AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
+        run(
+            render_stream,
+            objects,
+            pending_scheduled_event,
+            rx,
+            data_callback,
+            error_callback,
+        )
+    })
+    .map_err(|err| {
+        unsafe {
+            Foundation::CloseHandle(pending_scheduled_event);
+        }
+        BackendSpecificError {
+            description: format!("failed to spawn the audio thread: {}", err),
+        }
+    })?;
+
+    Ok(SpatialStream {
+        thread: Some(thread),
+        commands: tx,
+        pending_scheduled_event,
+    })
+}
+
+unsafe fn activate_render_stream(
+    device: &Audio::IMMDevice,
+    objects: u32,
+    options: &StreamOptions,
+) -> Result<RenderStream, BuildStreamError> {
+    let (client, formats) =
+        activate(device).map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+    let format = object_format(&formats).map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+    let max_objects = client
+        .GetMaxDynamicObjectCount()
+        .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+    if objects == 0 || objects > max_objects || !is_float_mono(format) {
+        return Err(BuildStreamError::StreamConfigNotSupported);
+    }
+    let max_frames = client
+        .GetMaxFrameCount(format)
+        .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+
+    let event = Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
+        .map_err(|e| {
+            let description = format!("failed to create event: {}", e);
+            BuildStreamError::from(BackendSpecificError { description })
+        })?;
+    let params = Audio::SpatialAudioObjectRenderStreamActivationParams {
+        ObjectFormat: format,
+        StaticObjectTypeMask: Audio::AudioObjectType_None,
+        MinDynamicObjectCount: 0,
+        MaxDynamicObjectCount: objects,
+        Category: options.role.map_or(
+            Audio::AudioCategory_Other,
+            Audio::AUDIO_STREAM_CATEGORY::from,
+        ),
+        EventHandle: event,
+        NotifyObject: mem::ManuallyDrop::new(None),
+    };
+    // The parameters are passed as a blob, which is borrowed and must not be cleared.
+    let mut activation_params = StructuredStorage::PROPVARIANT::default();
+    let prop_variant = &mut *activation_params.Anonymous.Anonymous;
+    prop_variant.vt = VT_BLOB;
+    prop_variant.Anonymous.blob = BLOB {
+        cbSize: mem::size_of::<Audio::SpatialAudioObjectRenderStreamActivationParams>() as u32,
+        pBlobData: &params as *const _ as *mut u8,
+    };
+    match client.ActivateSpatialAudioStream(&activation_params) {
+        Ok(stream) => Ok(RenderStream {
+            stream,
+            event,
+            max_frames,
+        }),
+        Err(e) => {
+            Foundation::CloseHandle(event);
+            Err(windows_err_to_cpal_err(e))
+        }
+    }
+}
+
+impl SpatialStream {
+    fn push_command(&self, command: Command) -> Result<(), SendError<Command>> {
+        self.commands.send(command)?;
+        unsafe {
+            let result = Threading::SetEvent(self.pending_scheduled_event);
+            assert_ne!(result, false);
+        }
+        Ok(())
+    }
+}
+
+impl Drop for SpatialStream {
+    fn drop(&mut self) {
+        if self.push_command(Command::Terminate).is_ok() {
+            self.thread.take().unwrap().join();
+            unsafe {
+                Foundation::CloseHandle(self.pending_scheduled_event);
+            }
+        }
+    }
+}
+
+impl StreamTrait for SpatialStream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.push_command(Command::PlayStream)
+            .map_err(|_| PlayStreamError::DeviceNotAvailable)
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.push_command(Command::PauseStream)
+            .map_err(|_| PauseStreamError::DeviceNotAvailable)
+    }
+}
+
+fn run<D, E>(
+    render_stream: RenderStream,
+    objects: u32,
+    pending_scheduled_event: Foundation::HANDLE,
+    commands: Receiver<Command>,
+    mut data_callback: D,
+    mut error_callback: E,
+) where
+    D: FnMut(&mut [SpatialObject]),
+    E: FnMut(StreamError),
+{
+    com::com_initialized();
+    let handles = [pending_scheduled_event, render_stream.event];
+    let mut objects: Vec<_> = (0..objects)
+        .map(|_| SpatialObject::new(render_stream.max_frames as usize))
+        .collect();
+    loop {
+        for command in commands.try_iter() {
+            let result = unsafe {
+                match command {
+                    Command::PlayStream => render_stream.stream.Start(),
+                    Command::PauseStream => render_stream.stream.Stop(),
+                    Command::Terminate => return,
+                }
+            };
+            if let Err(err) = result {
+                error_callback(windows_err_to_cpal_err(err));
+                return;
+            }
+        }
+
+        match wait_for_handle_signal(&handles, None) {
+            Ok(Some(1)) => (),
+            Ok(_) => continue,
+            Err(err) => {
+                error_callback(err.into());
+                return;
+            }
+        }
+
+        let result = unsafe { update_objects(&render_stream, &mut objects, &mut data_callback) };
+        if let Err(err) = result {
+            error_callback(windows_err_to_cpal_err(err));
+            return;
+        }
+    }
+}
+
+// Have the data callback update `objects` for the next period and pass them to the spatializer.
+unsafe fn update_objects<D>(
+    render_stream: &RenderStream,
+    objects: &mut [SpatialObject],
+    data_callback: &mut D,
+) -> Result<(), windows::core::Error>
+where
+    D: FnMut(&mut [SpatialObject]),
+{
+    let (mut available, mut frames) = (0, 0);
+    render_stream
+        .stream
+        .BeginUpdatingAudioObjects(&mut available, &mut frames)?;
+    for object in objects.iter_mut() {
+        object.frames = (frames as usize).min(object.samples.len());
+        object.samples_mut().fill(0.0);
+    }
+    data_callback(objects);
+
+    for object in objects.iter_mut() {
+        if !object.active {
+            // Releases the resources of the object once the spatializer is done with it.
+            if let Some(audio_object) = object.object.take() {
+                audio_object.SetEndOfStream(0)?;
+            }
+            continue;
+        }
+        if object.object.is_none() {
+            // Objects that can't be rendered yet are left out until resources are available.
+            match render_stream
+                .stream
+                .ActivateSpatialAudioObject(Audio::AudioObjectType_Dynamic)
+            {
+                Ok(audio_object) => object.object = Some(audio_object),
+                Err(ref e) if e.code() == Audio::SPTLAUDCLNT_E_NO_MORE_OBJECTS => continue,
+                Err(e) => return Err(e),
+            }
+        }
+        let audio_object = object.object.as_ref().unwrap();
+        let mut buffer = ptr::null_mut();
+        let mut len = 0;
+        audio_object.GetBuffer(&mut buffer, &mut len)?;
+        let samples = &object.samples[..object.frames];
+        let bytes = (len as usize).min(mem::size_of_val(samples));
+        ptr::copy_nonoverlapping(samples.as_ptr() as *const u8, buffer, bytes);
+        let [x, y, z] = object.position;
+        audio_object.SetPosition(x, y, z)?;
+        audio_object.SetVolume(object.volume)?;
+    }
+
+    render_stream.stream.EndUpdatingAudioObjects()
+}
@@ -1,13 +1,19 @@
+use super::session_events::SessionEvents;
 use super::windows_err_to_cpal_err;
+use crate::stats::StreamStatsCollector;
+use crate::thread::{self, AudioThread};
 use crate::traits::StreamTrait;
+use crate::watchdog::{self, Watchdog};
 use crate::{
-    BackendSpecificError, Data, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, StreamError,
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PlayStreamError, SampleFormat, SignalPath, StreamError, StreamOptions,
+    StreamStats,
 };
 use std::mem;
 use std::ptr;
 use std::sync::mpsc::{channel, Receiver, SendError, Sender};
-use std::thread::{self, JoinHandle};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
 use windows::Win32::Foundation;
 use windows::Win32::Foundation::WAIT_OBJECT_0;
 use windows::Win32::Media::Audio;
@@ -20,7 +26,7 @@ pub struct Stream {
     /// Option used for moving out in destructor.
     ///
     /// TODO: Actually set the thread priority.
-    thread: Option<JoinHandle<()>>,
+    thread: Option<AudioThread>,
 
     // Commands processed by the `run()` method that is currently running.
     // `pending_scheduled_event` must be signalled whenever a command is added here, so that it
@@ -30,6 +36,13 @@ pub struct Stream {
     // This event is signalled after a new entry is added to `commands`, so that the `run()`
     // method can be notified.
     pending_scheduled_event: Foundation::HANDLE,
+
+    // Reports stalls of the audio thread, if requested.
+    watchdog: Option<Watchdog>,
+
+    stats: Arc<StreamStatsCollector>,
+
+    signal_path: SignalPath,
 }
 
 struct RunContext {
@@ -41,6 +54,9 @@ struct RunContext {
     handles: Vec<Foundation::HANDLE>,
 
     commands: Receiver<Command>,
+
+    // How long to wait for the device while playing before reporting a stall.
+    timeout: Option<Duration>,
 }
 
 // Once we start running the eventloop, the RunContext will not be moved.
@@ -52,6 +68,9 @@ pub enum Command {
     Terminate,
 }
 
+/// Re-creates an invalidated stream, see [`StreamOptions::reactivate`].
+pub type Reactivate = Box<dyn FnMut() -> Result<StreamInner, BuildStreamError> + Send>;
+
 pub enum AudioClientFlow {
     Render {
         render_client: Audio::IAudioRenderClient,
@@ -64,8 +83,13 @@ pub enum AudioClientFlow {
 pub struct StreamInner {
     pub audio_client: Audio::IAudioClient,
     pub audio_clock: Audio::IAudioClock,
+    // The number of `audio_clock` position units per second.
+    pub clock_frequency: u64,
+    // Number of frames written to the buffer of an output stream since it was created.
+    pub frames_written: u64,
     pub client_flow: AudioClientFlow,
-    // Event that is signalled by WASAPI whenever audio data must be written.
+    // Event that is signalled by WASAPI, or a periodic timer when polling, whenever audio data
+    // must be exchanged.
     pub event: Foundation::HANDLE,
     // True if the stream is currently playing. False if paused.
     pub playing: bool,
@@ -77,73 +101,139 @@ pub struct StreamInner {
     pub config: crate::StreamConfig,
     // The sample format with which the stream was created.
     pub sample_format: SampleFormat,
+    // Runtime statistics, updated by the audio thread.
+    pub stats: Arc<StreamStatsCollector>,
+    // Whether the stream is bit-perfect and the format of the hardware.
+    pub signal_path: SignalPath,
+    // Reports the session events of the stream while registered.
+    pub session_events: Option<SessionEvents>,
+    // Re-creates the stream when its device is invalidated, if requested.
+    pub reactivate: Option<Reactivate>,
 }
 
 impl Stream {
     pub(crate) fn new_input<D, E>(
         stream_inner: StreamInner,
-        mut data_callback: D,
-        mut error_callback: E,
-    ) -> Stream
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        let buffer_duration = Duration::from_secs_f64(
+            stream_inner.max_frames_in_buffer as f64 / stream_inner.config.sample_rate.0 as f64,
+        );
+        let thread_name = thread::thread_name(options, "cpal_wasapi_in")?;
+        let (watchdog, mut data_callback, error_callback) = watchdog::watch_input(
+            options
+                .watchdog_periods
+                .map(|periods| buffer_duration * periods),
+            data_callback,
+            error_callback,
+        )?;
+        let mut error_callback = explain_disconnection(&stream_inner, error_callback);
+
         let pending_scheduled_event = unsafe {
             Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
         }
         .expect("cpal: could not create input stream event");
         let (tx, rx) = channel();
 
+        let stats = stream_inner.stats.clone();
+        let signal_path = stream_inner.signal_path.clone();
         let run_context = RunContext {
             handles: vec![pending_scheduled_event, stream_inner.event],
             stream: stream_inner,
             commands: rx,
+            timeout,
         };
 
-        let thread = thread::Builder::new()
-            .name("cpal_wasapi_in".to_owned())
-            .spawn(move || run_input(run_context, &mut data_callback, &mut error_callback))
-            .unwrap();
+        let thread =
+            AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
+                run_input(run_context, &mut data_callback, &mut error_callback)
+            })
+            .map_err(|err| {
+                unsafe {
+                    Foundation::CloseHandle(pending_scheduled_event);
+                }
+                BackendSpecificError {
+                    description: format!("failed to spawn the audio thread: {}", err),
+                }
+            })?;
 
-        Stream {
+        Ok(Stream {
             thread: Some(thread),
             commands: tx,
             pending_scheduled_event,
-        }
+            watchdog,
+            stats,
+            signal_path,
+        })
     }
 
     pub(crate) fn new_output<D, E>(
         stream_inner: StreamInner,
-        mut data_callback: D,
-        mut error_callback: E,
-    ) -> Stream
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
+        let buffer_duration = Duration::from_secs_f64(
+            stream_inner.max_frames_in_buffer as f64 / stream_inner.config.sample_rate.0 as f64,
+        );
+        let thread_name = thread::thread_name(options, "cpal_wasapi_out")?;
+        let (watchdog, mut data_callback, error_callback) = watchdog::watch_output(
+            options
+                .watchdog_periods
+                .map(|periods| buffer_duration * periods),
+            data_callback,
+            error_callback,
+        )?;
+        let mut error_callback = explain_disconnection(&stream_inner, error_callback);
+
         let pending_scheduled_event = unsafe {
             Threading::CreateEventA(None, false, false, windows::core::PCSTR(ptr::null()))
         }
         .expect("cpal: could not create output stream event");
         let (tx, rx) = channel();
 
+        let stats = stream_inner.stats.clone();
+        let signal_path = stream_inner.signal_path.clone();
         let run_context = RunContext {
             handles: vec![pending_scheduled_event, stream_inner.event],
             stream: stream_inner,
             commands: rx,
+            timeout,
         };
 
-        let thread = thread::Builder::new()
-            .name("cpal_wasapi_out".to_owned())
-            .spawn(move || run_output(run_context, &mut data_callback, &mut error_callback))
-            .unwrap();
+        let thread =
+            AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
+                run_output(run_context, &mut data_callback, &mut error_callback)
+            })
+            .map_err(|err| {
+                unsafe {
+                    Foundation::CloseHandle(pending_scheduled_event);
+                }
+                BackendSpecificError {
+                    description: format!("failed to spawn the audio thread: {}", err),
+                }
+            })?;
 
-        Stream {
+        Ok(Stream {
             thread: Some(thread),
             commands: tx,
             pending_scheduled_event,
-        }
+            watchdog,
+            stats,
+            signal_path,
+        })
     }
 
     #[inline]
@@ -160,8 +250,9 @@ impl Stream {
 impl Drop for Stream {
     #[inline]
     fn drop(&mut self) {
+        log_debug!("dropping WASAPI stream");
         if let Ok(_) = self.push_command(Command::Terminate) {
-            self.thread.take().unwrap().join().unwrap();
+            self.thread.take().unwrap().join();
             unsafe {
                 Foundation::CloseHandle(self.pending_scheduled_event);
             }
@@ -173,24 +264,54 @@ impl StreamTrait for Stream {
     fn play(&self) -> Result<(), PlayStreamError> {
         self.push_command(Command::PlayStream)
             .map_err(|_| crate::error::PlayStreamError::DeviceNotAvailable)?;
+        self.stats.restart();
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
         Ok(())
     }
     fn pause(&self) -> Result<(), PauseStreamError> {
         self.push_command(Command::PauseStream)
             .map_err(|_| crate::error::PauseStreamError::DeviceNotAvailable)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
         Ok(())
     }
+    fn stats(&self) -> Option<StreamStats> {
+        Some(self.stats.snapshot())
+    }
+
+    fn signal_path(&self) -> Option<SignalPath> {
+        Some(self.signal_path.clone())
+    }
 }
 
 impl Drop for StreamInner {
     #[inline]
     fn drop(&mut self) {
+        // Unregistered first, as the session notifications may signal the event.
+        self.session_events = None;
         unsafe {
             Foundation::CloseHandle(self.event);
         }
     }
 }
 
+// Wrap `error_callback` so that it reports why the stream was disconnected, when known.
+fn explain_disconnection<E>(
+    stream: &StreamInner,
+    error_callback: E,
+) -> Box<dyn FnMut(StreamError) + Send>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    match stream.session_events {
+        Some(ref session_events) => Box::new(session_events.explain_disconnection(error_callback)),
+        None => Box::new(error_callback),
+    }
+}
+
 // Process any pending commands that are queued within the `RunContext`.
 // Returns `true` if the loop should continue running, `false` if it should terminate.
 fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
@@ -225,24 +346,31 @@ fn process_commands(run_context: &mut RunContext) -> Result<bool, StreamError> {
 
     Ok(true)
 }
-// Wait for any of the given handles to be signalled.
+// Wait for any of the given handles to be signalled, for at most `timeout`.
 //
-// Returns the index of the `handle` that was signalled, or an `Err` if
+// Returns the index of the `handle` that was signalled, `None` on timeout or an `Err` if
 // `WaitForMultipleObjectsEx` fails.
 //
 // This is called when the `run` thread is ready to wait for the next event. The
 // next event might be some command submitted by the user (the first handle) or
 // might indicate that one of the streams is ready to deliver or receive audio.
-fn wait_for_handle_signal(handles: &[Foundation::HANDLE]) -> Result<usize, BackendSpecificError> {
+pub(super) fn wait_for_handle_signal(
+    handles: &[Foundation::HANDLE],
+    timeout: Option<Duration>,
+) -> Result<Option<usize>, BackendSpecificError> {
     debug_assert!(handles.len() <= SystemServices::MAXIMUM_WAIT_OBJECTS as usize);
+    let timeout_ms = match timeout {
+        // `INFINITE` is `u32::MAX`, so stay below it.
+        Some(timeout) => timeout.as_millis().min(u32::MAX as u128 - 1) as u32,
+        None => WindowsProgramming::INFINITE,
+    };
     let result = unsafe {
-        Threading::WaitForMultipleObjectsEx(
-            handles,
-            false,                        // Don't wait for all, just wait for the first
-            WindowsProgramming::INFINITE, // TODO: allow setting a timeout
-            false,                        // irrelevant parameter here
-        )
+        // Don't wait for all, just wait for the first. The last parameter is irrelevant here.
+        Threading::WaitForMultipleObjectsEx(handles, false, timeout_ms, false)
     };
+    if result == Foundation::WAIT_TIMEOUT {
+        return Ok(None);
+    }
     if result == Foundation::WAIT_FAILED {
         let err = unsafe { Foundation::GetLastError() };
         let description = format!("`WaitForMultipleObjectsEx failed: {}", err.0);
@@ -251,7 +379,7 @@ fn wait_for_handle_signal(handles: &[Foundation::HANDLE]) -> Result<usize, Backe
     }
     // Notifying the corresponding task handler.
     let handle_idx = (result.0 - WAIT_OBJECT_0.0) as usize;
-    Ok(handle_idx)
+    Ok(Some(handle_idx))
 }
 
 // Get the number of available frames that are available for writing/reading.
@@ -271,21 +399,22 @@ fn run_input(
     error_callback: &mut dyn FnMut(StreamError),
 ) {
     loop {
-        match process_commands_and_await_signal(&mut run_ctxt, error_callback) {
-            Some(ControlFlow::Break) => break,
-            Some(ControlFlow::Continue) => continue,
-            None => (),
-        }
-        let capture_client = match run_ctxt.stream.client_flow {
-            AudioClientFlow::Capture { ref capture_client } => capture_client.clone(),
-            _ => unreachable!(),
-        };
-        match process_input(
-            &run_ctxt.stream,
-            capture_client,
-            data_callback,
-            error_callback,
-        ) {
+        let flow = with_reactivation(&mut run_ctxt, error_callback, |run_ctxt, error_callback| {
+            if let Some(flow) = process_commands_and_await_signal(run_ctxt, error_callback) {
+                return flow;
+            }
+            let capture_client = match run_ctxt.stream.client_flow {
+                AudioClientFlow::Capture { ref capture_client } => capture_client.clone(),
+                _ => unreachable!(),
+            };
+            process_input(
+                &run_ctxt.stream,
+                capture_client,
+                data_callback,
+                error_callback,
+            )
+        });
+        match flow {
             ControlFlow::Break => break,
             ControlFlow::Continue => continue,
         }
@@ -298,27 +427,81 @@ fn run_output(
     error_callback: &mut dyn FnMut(StreamError),
 ) {
     loop {
-        match process_commands_and_await_signal(&mut run_ctxt, error_callback) {
-            Some(ControlFlow::Break) => break,
-            Some(ControlFlow::Continue) => continue,
-            None => (),
-        }
-        let render_client = match run_ctxt.stream.client_flow {
-            AudioClientFlow::Render { ref render_client } => render_client.clone(),
-            _ => unreachable!(),
-        };
-        match process_output(
-            &run_ctxt.stream,
-            render_client,
-            data_callback,
-            error_callback,
-        ) {
+        let flow = with_reactivation(&mut run_ctxt, error_callback, |run_ctxt, error_callback| {
+            if let Some(flow) = process_commands_and_await_signal(run_ctxt, error_callback) {
+                return flow;
+            }
+            let render_client = match run_ctxt.stream.client_flow {
+                AudioClientFlow::Render { ref render_client } => render_client.clone(),
+                _ => unreachable!(),
+            };
+            process_output(
+                &mut run_ctxt.stream,
+                render_client,
+                data_callback,
+                error_callback,
+            )
+        });
+        match flow {
             ControlFlow::Break => break,
             ControlFlow::Continue => continue,
         }
     }
 }
 
+// Run `process`, re-creating the stream instead of reporting the `DeviceNotAvailable` error of an
+// invalidated device if the stream can be re-created.
+fn with_reactivation<F>(
+    run_context: &mut RunContext,
+    error_callback: &mut dyn FnMut(StreamError),
+    process: F,
+) -> ControlFlow
+where
+    F: FnOnce(&mut RunContext, &mut dyn FnMut(StreamError)) -> ControlFlow,
+{
+    if run_context.stream.reactivate.is_none() {
+        return process(run_context, error_callback);
+    }
+    let mut invalidated = false;
+    let flow = process(run_context, &mut |err| match err {
+        StreamError::DeviceNotAvailable => invalidated = true,
+        err => error_callback(err),
+    });
+    if !invalidated {
+        return flow;
+    }
+    match reactivate(run_context) {
+        Ok(()) => ControlFlow::Continue,
+        Err(_) => {
+            error_callback(StreamError::DeviceNotAvailable);
+            ControlFlow::Break
+        }
+    }
+}
+
+// Replace the invalidated stream of `run_context` with a new one, playing if it was playing.
+fn reactivate(run_context: &mut RunContext) -> Result<(), BuildStreamError> {
+    let reactivate = run_context
+        .stream
+        .reactivate
+        .as_mut()
+        .expect("only streams that can be re-created are reactivated");
+    let mut stream = reactivate()?;
+    stream.stats = run_context.stream.stats.clone();
+    if run_context.stream.playing {
+        unsafe { stream.audio_client.Start() }
+            .map_err(windows_err_to_cpal_err::<BuildStreamError>)?;
+        stream.playing = true;
+    }
+    run_context.handles[1] = stream.event;
+    // Drops the invalidated stream, unregistering its session notifications.
+    run_context.stream = stream;
+    if let Some(ref session_events) = run_context.stream.session_events {
+        session_events.forget_disconnection();
+    }
+    Ok(())
+}
+
 enum ControlFlow {
     Break,
     Continue,
@@ -338,9 +521,15 @@ fn process_commands_and_await_signal(
         }
     };
 
-    // Wait for any of the handles to be signalled.
-    let handle_idx = match wait_for_handle_signal(&run_context.handles) {
-        Ok(idx) => idx,
+    // Wait for any of the handles to be signalled. A paused stream is not expected to become
+    // ready, so only apply the timeout while playing.
+    let timeout = run_context.timeout.filter(|_| run_context.stream.playing);
+    let handle_idx = match wait_for_handle_signal(&run_context.handles, timeout) {
+        Ok(Some(idx)) => idx,
+        Ok(None) => {
+            error_callback(StreamError::Stalled);
+            return Some(ControlFlow::Continue);
+        }
         Err(err) => {
             error_callback(err.into());
             return Some(ControlFlow::Break);
@@ -397,6 +586,11 @@ fn process_input(
 
             debug_assert!(!buffer.is_null());
 
+            let buffer_flags: u32 = flags.assume_init();
+            if buffer_flags & Audio::AUDCLNT_BUFFERFLAGS_DATA_DISCONTINUITY.0 as u32 != 0 {
+                stream.stats.record_xrun();
+            }
+
             let data = buffer as *mut ();
             let len = frames_available as usize * stream.bytes_per_frame as usize
                 / stream.sample_format.sample_size();
@@ -410,8 +604,17 @@ fn process_input(
                     return ControlFlow::Break;
                 }
             };
-            let info = InputCallbackInfo { timestamp };
+            let info = InputCallbackInfo {
+                timestamp,
+                transport: None,
+            };
+            let start = Instant::now();
             data_callback(&data, &info);
+            stream.stats.record_callback(
+                start,
+                frames_available as usize,
+                stream.config.sample_rate,
+            );
 
             // Release the buffer.
             let result = capture_client
@@ -427,7 +630,7 @@ fn process_input(
 
 // The loop for writing output data.
 fn process_output(
-    stream: &StreamInner,
+    stream: &mut StreamInner,
     render_client: Audio::IAudioRenderClient,
     data_callback: &mut dyn FnMut(&mut Data, &OutputCallbackInfo),
     error_callback: &mut dyn FnMut(StreamError),
@@ -458,37 +661,46 @@ fn process_output(
             / stream.sample_format.sample_size();
         let mut data = Data::from_parts(data, len, stream.sample_format);
         let sample_rate = stream.config.sample_rate;
-        let timestamp = match output_timestamp(stream, frames_available, sample_rate) {
+        let timestamp = match output_timestamp(stream) {
             Ok(ts) => ts,
             Err(err) => {
                 error_callback(err);
                 return ControlFlow::Break;
             }
         };
-        let info = OutputCallbackInfo { timestamp };
+        let info = OutputCallbackInfo {
+            timestamp,
+            transport: None,
+        };
+        let start = Instant::now();
         data_callback(&mut data, &info);
+        stream
+            .stats
+            .record_callback(start, frames_available as usize, sample_rate);
 
         if let Err(err) = render_client.ReleaseBuffer(frames_available, 0) {
             error_callback(windows_err_to_cpal_err(err));
             return ControlFlow::Break;
         }
+        stream.frames_written += frames_available as u64;
     }
 
     ControlFlow::Continue
 }
 
 /// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
-fn frames_to_duration(frames: u32, rate: crate::SampleRate) -> std::time::Duration {
+fn frames_to_duration(frames: u64, rate: crate::SampleRate) -> std::time::Duration {
     let secsf = frames as f64 / rate.0 as f64;
     let secs = secsf as u64;
     let nanos = ((secsf - secs as f64) * 1_000_000_000.0) as u32;
     std::time::Duration::new(secs, nanos)
 }
 
-/// Use the stream's `IAudioClock` to produce the current stream instant.
+/// Use the stream's `IAudioClock` to produce the current stream instant, along with the number of
+/// frames the device had played at that instant.
 ///
-/// Uses the QPC position produced via the `GetPosition` method.
-fn stream_instant(stream: &StreamInner) -> Result<crate::StreamInstant, StreamError> {
+/// Uses the device position and the QPC position produced via the `GetPosition` method.
+fn clock_position(stream: &StreamInner) -> Result<(crate::StreamInstant, u64), StreamError> {
     let mut position: u64 = 0;
     let mut qpc_position: u64 = 0;
     unsafe {
@@ -501,7 +713,14 @@ fn stream_instant(stream: &StreamInner) -> Result<crate::StreamInstant, StreamEr
     let qpc_nanos = qpc_position as i128 * 100;
     let instant = crate::StreamInstant::from_nanos_i128(qpc_nanos)
         .expect("performance counter out of range of `StreamInstant` representation");
-    Ok(instant)
+    let frames = position as u128 * stream.config.sample_rate.0 as u128
+        / stream.clock_frequency.max(1) as u128;
+    Ok((instant, frames as u64))
+}
+
+/// Use the stream's `IAudioClock` to produce the current stream instant.
+fn stream_instant(stream: &StreamInner) -> Result<crate::StreamInstant, StreamError> {
+    clock_position(stream).map(|(instant, _)| instant)
 }
 
 /// Produce the input stream timestamp.
@@ -523,23 +742,15 @@ fn input_timestamp(
 
 /// Produce the output stream timestamp.
 ///
-/// `frames_available` is the number of frames available for writing as reported by subtracting the
-/// result of `GetCurrentPadding` from the maximum buffer size.
-///
-/// `sample_rate` is the rate at which audio frames are processed by the device.
-///
-/// TODO: The returned `playback` is an estimate that assumes audio is delivered immediately after
-/// `frames_available` are consumed. The reality is that there is likely a tiny amount of latency
-/// after this, but not sure how to determine this.
-fn output_timestamp(
-    stream: &StreamInner,
-    frames_available: u32,
-    sample_rate: crate::SampleRate,
-) -> Result<crate::OutputStreamTimestamp, StreamError> {
-    let callback = stream_instant(stream)?;
-    let buffer_duration = frames_to_duration(frames_available, sample_rate);
+/// The device position reported by the `IAudioClock` is that of the frame currently being played
+/// by the hardware, including the latency of e.g. Bluetooth devices, so the first frame written by
+/// the callback is played once the frames written before it have been.
+fn output_timestamp(stream: &StreamInner) -> Result<crate::OutputStreamTimestamp, StreamError> {
+    let (callback, frames_played) = clock_position(stream)?;
+    let frames_queued = stream.frames_written.saturating_sub(frames_played);
+    let queued_duration = frames_to_duration(frames_queued, stream.config.sample_rate);
     let playback = callback
-        .add(buffer_duration)
+        .add(queued_duration)
         .expect("`playback` occurs beyond representation supported by `StreamInstant`");
     Ok(crate::OutputStreamTimestamp { callback, playback })
 }
@@ -0,0 +1,362 @@
+//! A host for wasm modules running outside of the browser, e.g. on a server or in a plugin
+//! runtime. WASI doesn't define an audio interface yet, so the runtime embedding the module plays
+//! and captures the streams itself, through functions imported from the `cpal` module:
+//!
+//! - `default_config(input: u32, channels: *mut u32, sample_rate: *mut u32) -> i32` writes the
+//!   config of the default output, or input if `input` is 1, and returns 0, or a negative value
+//!   if there is no such device.
+//! - `open_stream(stream: u32, input: u32, channels: u32, sample_rate: u32, frames: u32,
+//!   buffer: *mut f32) -> i32` starts a stream of interleaved `f32` samples exchanged through
+//!   `buffer`, which holds `frames` frames and lives until the stream is closed. Returns 0, or a
+//!   negative value if the config isn't supported.
+//! - `play_stream(stream: u32) -> i32` and `pause_stream(stream: u32) -> i32` return 0, or a
+//!   negative value on failure.
+//! - `close_stream(stream: u32)` stops the stream, after which `buffer` isn't used anymore.
+//!
+//! The runtime calls the exported `cpal_process(stream: u32, callback: f64, device: f64)` for
+//! every buffer, after writing the captured samples for an input stream, or before reading the
+//! samples to play for an output stream. `callback` is the current time of the runtime's clock
+//! and `device` the time at which the buffer was captured or is played, in seconds.
+//!
+//! The interface is made of core wasm imports and exports, so it has to be adapted for
+//! components, e.g. those built for `wasm32-wasip2`.
+
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
+use crate::{
+    BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
+    DeviceNameError, DevicesError, InputCallbackInfo, InputStreamTimestamp, OutputCallbackInfo,
+    OutputStreamTimestamp, PauseStreamError, PlayStreamError, SampleFormat, SampleRate,
+    StreamConfig, StreamError, StreamInstant, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
+};
+
+mod ffi {
+    #[link(wasm_import_module = "cpal")]
+    extern "C" {
+        pub fn default_config(input: u32, channels: *mut u32, sample_rate: *mut u32) -> i32;
+        pub fn open_stream(
+            stream: u32,
+            input: u32,
+            channels: u32,
+            sample_rate: u32,
+            frames: u32,
+            buffer: *mut f32,
+        ) -> i32;
+        pub fn play_stream(stream: u32) -> i32;
+        pub fn pause_stream(stream: u32) -> i32;
+        pub fn close_stream(stream: u32);
+    }
+}
+
+/// The buffer size of streams with the default buffer size.
+const DEFAULT_BUFFER_SIZE: u32 = 512;
+
+/// Processes the samples of a buffer, given the callback and device times.
+type Callback = Box<dyn FnMut(&mut [f32], f64, f64) + Send>;
+
+/// The callback of a stream, along with the buffer its samples are exchanged through.
+struct Process {
+    buffer: Vec<f32>,
+    callback: Callback,
+}
+
+/// The streams that are open, by the id the runtime knows them by.
+static STREAMS: Mutex<Option<HashMap<u32, Arc<Mutex<Process>>>>> = Mutex::new(None);
+static NEXT_STREAM: AtomicU32 = AtomicU32::new(0);
+
+#[derive(Debug)]
+pub struct Host;
+
+pub struct Devices(std::vec::IntoIter<Device>);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Device {
+    input: bool,
+}
+
+pub struct Stream {
+    id: u32,
+}
+
+pub type SupportedInputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+pub type SupportedOutputConfigs = std::vec::IntoIter<SupportedStreamConfigRange>;
+
+impl Host {
+    pub fn new() -> Result<Self, crate::HostUnavailable> {
+        Ok(Host)
+    }
+}
+
+impl Device {
+    /// The config of the device, if the runtime has such a device.
+    fn config(&self) -> Option<SupportedStreamConfigRange> {
+        let (mut channels, mut sample_rate) = (0, 0);
+        let result =
+            unsafe { ffi::default_config(self.input as u32, &mut channels, &mut sample_rate) };
+        if result < 0 || channels == 0 || channels > u16::MAX as u32 {
+            return None;
+        }
+        Some(SupportedStreamConfigRange {
+            channels: channels as u16,
+            min_sample_rate: SampleRate(sample_rate),
+            max_sample_rate: SampleRate(sample_rate),
+            buffer_size: SupportedBufferSize::Unknown,
+            sample_format: SampleFormat::F32,
+        })
+    }
+
+    fn configs(&self, input: bool) -> SupportedInputConfigs {
+        let config = if self.input == input {
+            self.config()
+        } else {
+            None
+        };
+        config.into_iter().collect::<Vec<_>>().into_iter()
+    }
+
+    fn default_config(
+        &self,
+        input: bool,
+    ) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        if self.input != input {
+            return Err(DefaultStreamConfigError::StreamTypeNotSupported);
+        }
+        self.config()
+            .map(|config| config.with_max_sample_rate())
+            .ok_or(DefaultStreamConfigError::DeviceNotAvailable)
+    }
+
+    fn open(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        callback: Callback,
+    ) -> Result<Stream, BuildStreamError> {
+        if sample_format != SampleFormat::F32 {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        let frames = match config.buffer_size {
+            BufferSize::Fixed(0) => return Err(BuildStreamError::StreamConfigNotSupported),
+            BufferSize::Fixed(frames) => frames,
+            BufferSize::Default => DEFAULT_BUFFER_SIZE,
+        };
+        let process = Arc::new(Mutex::new(Process {
+            buffer: vec![0.0; frames as usize * config.channels as usize],
+            callback,
+        }));
+        let buffer = process.lock().unwrap().buffer.as_mut_ptr();
+
+        // Register the stream before opening it, as the runtime may process it right away.
+        let id = NEXT_STREAM.fetch_add(1, Ordering::Relaxed);
+        streams(|streams| streams.insert(id, process));
+        let result = unsafe {
+            ffi::open_stream(
+                id,
+                self.input as u32,
+                config.channels as u32,
+                config.sample_rate.0,
+                frames,
+                buffer,
+            )
+        };
+        if result < 0 {
+            streams(|streams| streams.remove(&id));
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        Ok(Stream { id })
+    }
+}
+
+impl HostTrait for Host {
+    type Devices = Devices;
+    type Device = Device;
+
+    fn is_available() -> bool {
+        // The module couldn't have been instantiated without the functions of the runtime.
+        true
+    }
+
+    fn devices(&self) -> Result<Self::Devices, DevicesError> {
+        let devices: Vec<_> = [Device { input: false }, Device { input: true }]
+            .into_iter()
+            .filter(|device| device.config().is_some())
+            .collect();
+        Ok(Devices(devices.into_iter()))
+    }
+
+    fn default_input_device(&self) -> Option<Self::Device> {
+        let device = Device { input: true };
+        device.config().map(|_| device)
+    }
+
+    fn default_output_device(&self) -> Option<Self::Device> {
+        let device = Device { input: false };
+        device.config().map(|_| device)
+    }
+}
+
+impl DeviceTrait for Device {
+    type SupportedInputConfigs = SupportedInputConfigs;
+    type SupportedOutputConfigs = SupportedOutputConfigs;
+    type Stream = Stream;
+
+    fn name(&self) -> Result<String, DeviceNameError> {
+        Ok(if self.input {
+            "Default Input Device".to_owned()
+        } else {
+            "Default Output Device".to_owned()
+        })
+    }
+
+    fn supported_input_configs(
+        &self,
+    ) -> Result<Self::SupportedInputConfigs, SupportedStreamConfigsError> {
+        Ok(self.configs(true))
+    }
+
+    fn supported_output_configs(
+        &self,
+    ) -> Result<Self::SupportedOutputConfigs, SupportedStreamConfigsError> {
+        Ok(self.configs(false))
+    }
+
+    fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(true)
+    }
+
+    fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
+        self.default_config(false)
+    }
+
+    fn build_input_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        _error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !self.input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if timeout.is_some() {
+            log_warn!("the WASI host can't detect stalled streams, ignoring the timeout");
+        }
+        self.open(
+            config,
+            sample_format,
+            Box::new(move |buffer, callback, capture| {
+                let data = buffer.as_mut_ptr() as *mut ();
+                let data = unsafe { Data::from_parts(data, buffer.len(), SampleFormat::F32) };
+                let callback = StreamInstant::from_secs_f64(callback);
+                let capture = StreamInstant::from_secs_f64(capture);
+                let info = InputCallbackInfo {
+                    timestamp: InputStreamTimestamp { callback, capture },
+                    transport: None,
+                };
+                data_callback(&data, &info);
+            }),
+        )
+    }
+
+    fn build_output_stream_raw<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        mut data_callback: D,
+        _error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if self.input {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if timeout.is_some() {
+            log_warn!("the WASI host can't detect stalled streams, ignoring the timeout");
+        }
+        self.open(
+            config,
+            sample_format,
+            Box::new(move |buffer, callback, playback| {
+                let data = buffer.as_mut_ptr() as *mut ();
+                let mut data = unsafe { Data::from_parts(data, buffer.len(), SampleFormat::F32) };
+                let callback = StreamInstant::from_secs_f64(callback);
+                let playback = StreamInstant::from_secs_f64(playback);
+                let info = OutputCallbackInfo {
+                    timestamp: OutputStreamTimestamp { callback, playback },
+                    transport: None,
+                };
+                data_callback(&mut data, &info);
+            }),
+        )
+    }
+}
+
+impl StreamTrait for Stream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        match unsafe { ffi::play_stream(self.id) } {
+            0.. => Ok(()),
+            err => Err(error(err).into()),
+        }
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        match unsafe { ffi::pause_stream(self.id) } {
+            0.. => Ok(()),
+            err => Err(error(err).into()),
+        }
+    }
+}
+
+impl Drop for Stream {
+    fn drop(&mut self) {
+        unsafe { ffi::close_stream(self.id) };
+        streams(|streams| streams.remove(&self.id));
+    }
+}
+
+impl Iterator for Devices {
+    type Item = Device;
+
+    fn next(&mut self) -> Option<Device> {
+        self.0.next()
+    }
+}
+
+/// Called by the runtime for every buffer of the stream `stream`.
+#[no_mangle]
+pub extern "C" fn cpal_process(stream: u32, callback: f64, device: f64) {
+    // The stream may be dropped from its own callback, so the streams aren't kept locked.
+    let process = match streams(|streams| streams.get(&stream).cloned()) {
+        Some(process) => process,
+        None => return,
+    };
+    let mut process = process.lock().unwrap();
+    let Process {
+        buffer,
+        callback: process,
+    } = &mut *process;
+    process(buffer, callback, device);
+}
+
+fn streams<T>(f: impl FnOnce(&mut HashMap<u32, Arc<Mutex<Process>>>) -> T) -> T {
+    let mut streams = STREAMS.lock().unwrap();
+    f(streams.get_or_insert_with(HashMap::new))
+}
+
+fn error(code: i32) -> BackendSpecificError {
+    let description = format!("the runtime failed with error {}", code);
+    BackendSpecificError { description }
+}
@@ -0,0 +1,123 @@
+//! The devices listed by `navigator.mediaDevices.enumerateDevices()`, which resolves
+//! asynchronously, so the host keeps those of the last enumeration for `devices()`.
+//!
+//! Browsers hide the devices until the user allowed the page to capture one of them, listing at
+//! most an anonymous entry per kind, which can't be told apart from the default device. Speakers
+//! are only listed by browsers that let contexts play to them.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+
+use super::js_sys::Array;
+use super::wasm_bindgen::prelude::*;
+use super::wasm_bindgen::JsCast;
+use super::wasm_bindgen_futures::{spawn_local, JsFuture};
+use super::web_sys::{MediaDeviceInfo, MediaDeviceKind, MediaDevices};
+use super::{closure, is_output_selectable, Device, Kind};
+use crate::{BackendSpecificError, DevicesError};
+
+thread_local! {
+    static DEVICES: RefCell<Vec<Device>> = const { RefCell::new(Vec::new()) };
+}
+
+/// The devices found by the last enumeration that succeeded.
+pub fn cached() -> Vec<Device> {
+    DEVICES.with(|devices| devices.borrow().clone())
+}
+
+/// Enumerate the devices, keeping them for `cached`.
+pub async fn enumerate() -> Result<Vec<Device>, DevicesError> {
+    let infos = async {
+        let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+        let media_devices = window.navigator().media_devices()?;
+        JsFuture::from(media_devices.enumerate_devices()?)
+            .await?
+            .dyn_into::<Array>()
+    }
+    .await
+    .map_err(devices_error)?;
+
+    let outputs = is_output_selectable();
+    let mut hidden = false;
+    let mut devices = Vec::new();
+    for info in infos.iter() {
+        let info: MediaDeviceInfo = info.unchecked_into();
+        let input = match info.kind() {
+            MediaDeviceKind::Audioinput => true,
+            MediaDeviceKind::Audiooutput if outputs => false,
+            _ => continue,
+        };
+        let (id, label) = (info.device_id(), info.label());
+        if id.is_empty() || label.is_empty() {
+            hidden = true;
+            continue;
+        }
+        // Browsers alias the default devices as "default", which the default device stands for.
+        if id == "default" {
+            continue;
+        }
+        let kind = if input {
+            Kind::Input { id, label }
+        } else {
+            Kind::Output { id, label }
+        };
+        devices.push(Device(kind));
+    }
+    if hidden && devices.is_empty() {
+        let description =
+            "the devices are hidden until the user allows the page to use the microphone".into();
+        return Err(BackendSpecificError { description }.into());
+    }
+
+    DEVICES.with(|cached| *cached.borrow_mut() = devices.clone());
+    Ok(devices)
+}
+
+/// Enumerates the devices whenever they change, until dropped.
+pub struct DeviceWatch {
+    media_devices: MediaDevices,
+    listener: Closure<dyn FnMut()>,
+}
+
+impl DeviceWatch {
+    /// Enumerate the devices whenever the browser fires `devicechange`, passing them to
+    /// `callback`.
+    pub fn new<F>(callback: F) -> Result<Self, DevicesError>
+    where
+        F: FnMut(Result<Vec<Device>, DevicesError>) + 'static,
+    {
+        let media_devices = web_sys::window()
+            .ok_or_else(|| JsValue::from_str("no window"))
+            .and_then(|window| window.navigator().media_devices())
+            .map_err(devices_error)?;
+        let callback = Rc::new(RefCell::new(callback));
+        let listener = closure(Box::new(move || {
+            let callback = callback.clone();
+            spawn_local(async move {
+                let devices = enumerate().await;
+                (callback.borrow_mut())(devices);
+            });
+        }) as Box<dyn FnMut()>);
+        media_devices
+            .add_event_listener_with_callback("devicechange", listener.as_ref().unchecked_ref())
+            .map_err(devices_error)?;
+        Ok(DeviceWatch {
+            media_devices,
+            listener,
+        })
+    }
+}
+
+impl Drop for DeviceWatch {
+    fn drop(&mut self) {
+        let _ = self.media_devices.remove_event_listener_with_callback(
+            "devicechange",
+            self.listener.as_ref().unchecked_ref(),
+        );
+    }
+}
+
+fn devices_error(err: JsValue) -> DevicesError {
+    let description = format!("{:?}", err);
+    BackendSpecificError { description }.into()
+}
@@ -0,0 +1,86 @@
+//! Resuming the context of a stream on the next user gesture, as the autoplay policies of
+//! browsers keep contexts suspended until the user interacted with the page.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::closure;
+use super::js_sys::eval;
+use super::wasm_bindgen::prelude::*;
+use super::wasm_bindgen::JsCast;
+use super::wasm_bindgen_futures::{spawn_local, JsFuture};
+use super::web_sys::{AudioContext, AudioContextState};
+
+/// The events that count as user activation, letting pages start audio.
+const EVENTS: [&str; 4] = ["keydown", "mousedown", "pointerup", "touchend"];
+
+type Listener = Rc<RefCell<Option<Closure<dyn FnMut()>>>>;
+
+/// Listens for user gestures until the context resumes, or until dropped.
+pub struct Gesture {
+    listener: Listener,
+}
+
+impl Gesture {
+    /// Resume `ctx` on the next user gesture.
+    pub fn listen(ctx: Arc<AudioContext>) -> Self {
+        let listener: Listener = Rc::new(RefCell::new(None));
+        let listener_handle = listener.clone();
+        let closure = closure(Box::new(move || {
+            // Contexts can only be resumed from within the handler of the gesture.
+            let resume = match ctx.resume() {
+                Ok(resume) => resume,
+                Err(_) => return,
+            };
+            let ctx = ctx.clone();
+            let listener = listener_handle.clone();
+            spawn_local(async move {
+                let _ = JsFuture::from(resume).await;
+                if ctx.state() == AudioContextState::Running {
+                    remove(&listener);
+                }
+            });
+        }) as Box<dyn FnMut()>);
+        if let Some(window) = web_sys::window() {
+            for event in EVENTS {
+                let _ = window
+                    .add_event_listener_with_callback(event, closure.as_ref().unchecked_ref());
+            }
+        }
+        listener.borrow_mut().replace(closure);
+        Gesture { listener }
+    }
+}
+
+impl Drop for Gesture {
+    fn drop(&mut self) {
+        remove(&self.listener);
+    }
+}
+
+fn remove(listener: &Listener) {
+    let closure = match listener.borrow_mut().take() {
+        Some(closure) => closure,
+        None => return,
+    };
+    if let Some(window) = web_sys::window() {
+        for event in EVENTS {
+            let _ =
+                window.remove_event_listener_with_callback(event, closure.as_ref().unchecked_ref());
+        }
+    }
+}
+
+/// Whether the autoplay policy keeps a suspended context from resuming, as the user didn't
+/// interact with the page yet. Browsers that can't tell are assumed to let it resume.
+pub fn is_autoplay_blocked(ctx: &AudioContext) -> bool {
+    const ACTIVE: &str = "typeof navigator === 'undefined' \
+        || navigator.userActivation === undefined \
+        || navigator.userActivation.hasBeenActive";
+    ctx.state() == AudioContextState::Suspended
+        && !eval(ACTIVE)
+            .ok()
+            .and_then(|active| active.as_bool())
+            .unwrap_or(true)
+}
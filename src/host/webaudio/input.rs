@@ -0,0 +1,200 @@
+//! Input streams, capturing the microphone through `getUserMedia` into an `AudioWorkletNode`
+//! whose processor posts the captured buffers to the main thread.
+//!
+//! Both the permission prompt and the loading of the processor are asynchronous, so the stream
+//! is connected in the background once built, and any failure is reported to its error callback.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::js_sys::{Float32Array, Object, Reflect};
+use super::wasm_bindgen::prelude::*;
+use super::wasm_bindgen::JsCast;
+use super::wasm_bindgen_futures::{spawn_local, JsFuture};
+use super::web_sys::{
+    AudioContext, AudioWorkletNode, AudioWorkletNodeOptions, ChannelCountMode, MediaStream,
+    MediaStreamConstraints, MediaStreamTrack, MessageEvent,
+};
+use super::{closure, current_time, stream_error, worklet};
+use crate::{
+    Data, InputCallbackInfo, InputStreamTimestamp, SampleFormat, StreamError, StreamInstant,
+};
+
+/// The capture of an input stream, disconnected when dropped.
+pub struct Input {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    closed: bool,
+    capture: Option<Capture>,
+}
+
+struct Capture {
+    media_stream: MediaStream,
+    node: AudioWorkletNode,
+    _on_message: Closure<dyn FnMut(MessageEvent)>,
+}
+
+impl Input {
+    /// Start capturing `channels` channels of the microphone `device_id`, or of the default one,
+    /// into `ctx`, calling `data_callback` with every `frames` frames.
+    pub fn open<D, E>(
+        ctx: Arc<AudioContext>,
+        device_id: Option<String>,
+        channels: u16,
+        frames: usize,
+        data_callback: D,
+        mut error_callback: E,
+    ) -> Self
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let state = Rc::new(RefCell::new(State::default()));
+        let state_handle = state.clone();
+        spawn_local(async move {
+            let result = capture(&ctx, device_id, channels, frames, data_callback).await;
+            let mut state = state_handle.borrow_mut();
+            match result {
+                Ok(capture) if state.closed => capture.close(),
+                Ok(capture) => state.capture = Some(capture),
+                Err(_) if state.closed => (),
+                Err(err) => error_callback(stream_error(err)),
+            }
+        });
+        Input { state }
+    }
+}
+
+impl Drop for Input {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.closed = true;
+        if let Some(capture) = state.capture.take() {
+            capture.close();
+        }
+    }
+}
+
+impl Capture {
+    fn close(self) {
+        if let Ok(port) = self.node.port() {
+            port.set_onmessage(None);
+        }
+        let _ = self.node.disconnect();
+        stop(&self.media_stream);
+    }
+}
+
+/// Stop the tracks of `media_stream`, releasing the microphone.
+fn stop(media_stream: &MediaStream) {
+    for track in media_stream.get_tracks().iter() {
+        if let Ok(track) = track.dyn_into::<MediaStreamTrack>() {
+            track.stop();
+        }
+    }
+}
+
+async fn capture<D>(
+    ctx: &AudioContext,
+    device_id: Option<String>,
+    channels: u16,
+    frames: usize,
+    mut data_callback: D,
+) -> Result<Capture, JsValue>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+{
+    let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+    let media_devices = window.navigator().media_devices()?;
+
+    // Capture what the microphone records, without the processing meant for calls.
+    let audio = Object::new();
+    if let Some(device_id) = device_id {
+        let exact = Object::new();
+        Reflect::set(&exact, &"exact".into(), &device_id.into())?;
+        Reflect::set(&audio, &"deviceId".into(), &exact)?;
+    }
+    Reflect::set(&audio, &"channelCount".into(), &JsValue::from(channels))?;
+    for processing in ["echoCancellation", "noiseSuppression", "autoGainControl"] {
+        Reflect::set(&audio, &processing.into(), &JsValue::FALSE)?;
+    }
+    let constraints = MediaStreamConstraints::new();
+    constraints.set_audio(&audio);
+    let media_stream: MediaStream =
+        JsFuture::from(media_devices.get_user_media_with_constraints(&constraints)?)
+            .await?
+            .dyn_into()?;
+
+    let result = async {
+        worklet::add_module(ctx).await?;
+        let processor_options = Object::new();
+        Reflect::set(
+            &processor_options,
+            &"channels".into(),
+            &JsValue::from(channels),
+        )?;
+        Reflect::set(
+            &processor_options,
+            &"frames".into(),
+            &JsValue::from(frames as u32),
+        )?;
+        let options = AudioWorkletNodeOptions::new();
+        options.set_number_of_inputs(1);
+        options.set_number_of_outputs(0);
+        options.set_channel_count(channels as u32);
+        options.set_channel_count_mode(ChannelCountMode::Explicit);
+        options.set_processor_options(Some(&processor_options));
+        let node = AudioWorkletNode::new_with_options(ctx, worklet::INPUT_PROCESSOR, &options)?;
+
+        let mut buffer = vec![0f32; frames * channels as usize];
+        let ctx_handle = ctx.clone();
+        let on_message = closure(Box::new(move |event: MessageEvent| {
+            let message = event.data();
+            let samples = Reflect::get(&message, &"samples".into())
+                .ok()
+                .and_then(|samples| samples.dyn_into::<Float32Array>().ok());
+            let time = Reflect::get(&message, &"time".into())
+                .ok()
+                .and_then(|time| time.as_f64());
+            let (samples, time) = match (samples, time) {
+                (Some(samples), Some(time)) if samples.length() as usize == buffer.len() => {
+                    (samples, time)
+                }
+                _ => return,
+            };
+            samples.copy_to(&mut buffer);
+            let data = buffer.as_mut_ptr() as *mut ();
+            let data = unsafe { Data::from_parts(data, buffer.len(), SampleFormat::F32) };
+            let callback = StreamInstant::from_secs_f64(current_time(&ctx_handle));
+            let capture = StreamInstant::from_secs_f64(time.max(0.0));
+            let info = InputCallbackInfo {
+                timestamp: InputStreamTimestamp { callback, capture },
+                transport: None,
+            };
+            data_callback(&data, &info);
+        }) as Box<dyn FnMut(MessageEvent)>);
+        node.port()?
+            .set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+
+        ctx.create_media_stream_source(&media_stream)?
+            .connect_with_audio_node(&node)?;
+        Ok((node, on_message))
+    }
+    .await;
+
+    match result {
+        Ok((node, on_message)) => Ok(Capture {
+            media_stream,
+            node,
+            _on_message: on_message,
+        }),
+        Err(err) => {
+            stop(&media_stream);
+            Err(err)
+        }
+    }
+}
@@ -1,33 +1,66 @@
 extern crate js_sys;
 extern crate wasm_bindgen;
+extern crate wasm_bindgen_futures;
 extern crate web_sys;
 
-use self::js_sys::eval;
+mod devices;
+mod gesture;
+mod input;
+mod output;
+mod ring;
+#[cfg(target_feature = "atomics")]
+mod worker;
+mod worklet;
+
+use self::js_sys::{eval, Function, Reflect};
+use self::wasm_bindgen::closure::WasmClosure;
 use self::wasm_bindgen::prelude::*;
 use self::wasm_bindgen::JsCast;
-use self::web_sys::{AudioContext, AudioContextOptions};
+use self::web_sys::{AudioContext, AudioContextOptions, AudioContextState, DomException};
+use crate::dsd;
+use crate::thread;
 use crate::traits::{DeviceTrait, HostTrait, StreamTrait};
 use crate::{
     BackendSpecificError, BufferSize, BuildStreamError, Data, DefaultStreamConfigError,
-    DeviceNameError, DevicesError, InputCallbackInfo, OutputCallbackInfo, PauseStreamError,
-    PlayStreamError, SampleFormat, SampleRate, StreamConfig, StreamError, SupportedBufferSize,
-    SupportedStreamConfig, SupportedStreamConfigRange, SupportedStreamConfigsError,
+    DeviceNameError, DevicesError, DsdMode, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PerformanceMode, PlayStreamError, SampleFormat, SampleRate, ShareMode,
+    StreamConfig, StreamError, StreamOptions, SupportedBufferSize, SupportedStreamConfig,
+    SupportedStreamConfigRange, SupportedStreamConfigsError,
 };
+use std::cell::{Cell, RefCell};
 use std::ops::DerefMut;
 use std::sync::{Arc, Mutex, RwLock};
 use std::time::Duration;
 
-/// Content is false if the iterator is empty.
-pub struct Devices(bool);
+pub use self::devices::DeviceWatch;
+use self::gesture::Gesture;
+use self::input::Input;
+use self::output::Output;
+
+pub struct Devices(std::vec::IntoIter<Device>);
 
 #[derive(Clone, Debug, PartialEq, Eq)]
-pub struct Device;
+pub struct Device(Kind);
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Kind {
+    /// The default output device and microphone.
+    Default,
+    /// A microphone listed by `enumerateDevices()`.
+    Input { id: String, label: String },
+    /// A speaker listed by `enumerateDevices()`, played through the `sinkId` of the context.
+    Output { id: String, label: String },
+}
 
 pub struct Host;
 
 pub struct Stream {
     ctx: Arc<AudioContext>,
     on_ended_closures: Vec<Arc<RwLock<Option<Closure<dyn FnMut()>>>>>,
+    // Set for input streams.
+    input: Option<Input>,
+    output: Option<Output>,
+    gesture: RefCell<Option<Gesture>>,
     config: StreamConfig,
     buffer_size_frames: usize,
 }
@@ -39,6 +72,7 @@ const MIN_CHANNELS: u16 = 1;
 const MAX_CHANNELS: u16 = 32;
 const MIN_SAMPLE_RATE: SampleRate = SampleRate(8_000);
 const MAX_SAMPLE_RATE: SampleRate = SampleRate(96_000);
+// The rate of streams when the browser can't tell that of the hardware.
 const DEFAULT_SAMPLE_RATE: SampleRate = SampleRate(44_100);
 const MIN_BUFFER_SIZE: u32 = 1;
 const MAX_BUFFER_SIZE: u32 = u32::MAX;
@@ -47,8 +81,37 @@ const SUPPORTED_SAMPLE_FORMAT: SampleFormat = SampleFormat::F32;
 
 impl Host {
     pub fn new() -> Result<Self, crate::HostUnavailable> {
+        if is_input_available() {
+            wasm_bindgen_futures::spawn_local(async {
+                let _ = devices::enumerate().await;
+            });
+        }
         Ok(Host)
     }
+
+    /// Enumerate the microphones, and the speakers where contexts can play to them (through
+    /// their `sinkId`), through `navigator.mediaDevices.enumerateDevices()`, which
+    /// [`devices`](HostTrait::devices) lists from then on besides the default device.
+    ///
+    /// Browsers hide the devices until the user allows the page to use the microphone, e.g. once
+    /// an input stream of the default device started capturing, and this returns an error until
+    /// then. The host enumerates the devices once when created.
+    pub async fn enumerate_devices(&self) -> Result<Vec<Device>, DevicesError> {
+        devices::enumerate().await
+    }
+
+    /// Enumerate the devices again whenever the browser reports that they changed, e.g. when a
+    /// headset is plugged in, passing the result to `callback`, until the returned watch is
+    /// dropped.
+    ///
+    /// Browsers only report changes of the devices the page can see, see
+    /// [`enumerate_devices`](Self::enumerate_devices).
+    pub fn watch_devices<F>(&self, callback: F) -> Result<DeviceWatch, DevicesError>
+    where
+        F: FnMut(Result<Vec<Device>, DevicesError>) + 'static,
+    {
+        DeviceWatch::new(callback)
+    }
 }
 
 impl HostTrait for Host {
@@ -80,43 +143,52 @@ impl Devices {
 }
 
 impl Device {
+    /// The `deviceId` of the device, or `None` for the default device.
+    pub fn device_id(&self) -> Option<&str> {
+        match &self.0 {
+            Kind::Default => None,
+            Kind::Input { id, .. } | Kind::Output { id, .. } => Some(id),
+        }
+    }
+
     #[inline]
     fn name(&self) -> Result<String, DeviceNameError> {
-        Ok("Default Device".to_owned())
+        match &self.0 {
+            Kind::Default => Ok("Default Device".to_owned()),
+            Kind::Input { label, .. } | Kind::Output { label, .. } => Ok(label.clone()),
+        }
     }
 
     #[inline]
     fn supported_input_configs(
         &self,
     ) -> Result<SupportedInputConfigs, SupportedStreamConfigsError> {
-        // TODO
-        Ok(Vec::new().into_iter())
+        if !is_input_available() || matches!(self.0, Kind::Output { .. }) {
+            return Ok(Vec::new().into_iter());
+        }
+        Ok(supported_configs().into_iter())
     }
 
     #[inline]
     fn supported_output_configs(
         &self,
     ) -> Result<SupportedOutputConfigs, SupportedStreamConfigsError> {
-        let buffer_size = SupportedBufferSize::Range {
-            min: MIN_BUFFER_SIZE,
-            max: MAX_BUFFER_SIZE,
-        };
-        let configs: Vec<_> = (MIN_CHANNELS..=MAX_CHANNELS)
-            .map(|channels| SupportedStreamConfigRange {
-                channels,
-                min_sample_rate: MIN_SAMPLE_RATE,
-                max_sample_rate: MAX_SAMPLE_RATE,
-                buffer_size: buffer_size.clone(),
-                sample_format: SUPPORTED_SAMPLE_FORMAT,
-            })
-            .collect();
-        Ok(configs.into_iter())
+        if let Kind::Input { .. } = self.0 {
+            return Ok(Vec::new().into_iter());
+        }
+        Ok(supported_configs().into_iter())
     }
 
     #[inline]
     fn default_input_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError> {
-        // TODO
-        Err(DefaultStreamConfigError::StreamTypeNotSupported)
+        let config = self
+            .supported_input_configs()
+            .map_err(|_| DefaultStreamConfigError::StreamTypeNotSupported)?
+            .max_by(|a, b| a.cmp_default_heuristics(b))
+            .ok_or(DefaultStreamConfigError::StreamTypeNotSupported)?
+            .with_sample_rate(default_sample_rate());
+
+        Ok(config)
     }
 
     #[inline]
@@ -127,7 +199,7 @@ impl Device {
             .expect(EXPECT)
             .max_by(|a, b| a.cmp_default_heuristics(b))
             .unwrap()
-            .with_sample_rate(DEFAULT_SAMPLE_RATE);
+            .with_sample_rate(default_sample_rate());
 
         Ok(config)
     }
@@ -169,64 +241,141 @@ impl DeviceTrait for Device {
 
     fn build_input_stream_raw<D, E>(
         &self,
-        _config: &StreamConfig,
-        _sample_format: SampleFormat,
-        _data_callback: D,
-        _error_callback: E,
-        _timeout: Option<Duration>,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        // TODO
-        Err(BuildStreamError::StreamConfigNotSupported)
+        self.build_input_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an input stream, capturing the microphone once the user allowed it.
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if !supports_options(options) {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if timeout.is_some() {
+            log_warn!("the web host can't detect stalled streams, ignoring the timeout");
+        }
+        if !is_input_available() {
+            return Err(BuildStreamError::DeviceNotAvailable);
+        }
+        if !valid_config(config, sample_format) || matches!(self.0, Kind::Output { .. }) {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+
+        let buffer_size_frames = buffer_size_frames(config)?;
+        let ctx = Arc::new(audio_context(config, options, None)?);
+        let input = Input::open(
+            ctx.clone(),
+            self.device_id().map(String::from),
+            config.channels,
+            buffer_size_frames,
+            data_callback,
+            error_callback,
+        );
+
+        Ok(Stream {
+            ctx,
+            on_ended_closures: Vec::new(),
+            input: Some(input),
+            output: None,
+            gesture: RefCell::new(None),
+            config: config.clone(),
+            buffer_size_frames,
+        })
     }
 
-    /// Create an output stream.
     fn build_output_stream_raw<D, E>(
         &self,
         config: &StreamConfig,
         sample_format: SampleFormat,
         data_callback: D,
-        _error_callback: E,
-        _timeout: Option<Duration>,
+        error_callback: E,
+        timeout: Option<Duration>,
     ) -> Result<Self::Stream, BuildStreamError>
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        if !valid_config(config, sample_format) {
+        self.build_output_stream_raw_with_options(
+            config,
+            sample_format,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an output stream, played by an `AudioWorkletNode` where available, and by scheduling
+    /// buffers through `AudioBufferSourceNode`s otherwise, to the speaker of the device.
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if !supports_options(options) {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        if timeout.is_some() {
+            log_warn!("the web host can't detect stalled streams, ignoring the timeout");
+        }
+        if !valid_config(config, sample_format) || matches!(self.0, Kind::Input { .. }) {
             return Err(BuildStreamError::StreamConfigNotSupported);
         }
 
         let n_channels = config.channels as usize;
 
-        let buffer_size_frames = match config.buffer_size {
-            BufferSize::Fixed(v) => {
-                if v == 0 {
-                    return Err(BuildStreamError::StreamConfigNotSupported);
-                } else {
-                    v as usize
-                }
-            }
-            BufferSize::Default => DEFAULT_BUFFER_SIZE,
-        };
+        let buffer_size_frames = buffer_size_frames(config)?;
         let buffer_size_samples = buffer_size_frames * n_channels;
         let buffer_time_step_secs = buffer_time_step_secs(buffer_size_frames, config.sample_rate);
 
-        let data_callback = Arc::new(Mutex::new(Box::new(data_callback)));
-
         // Create the WebAudio stream.
-        let mut stream_opts = AudioContextOptions::new();
-        stream_opts.sample_rate(config.sample_rate.0 as f32);
-        let ctx = AudioContext::new_with_context_options(&stream_opts).map_err(
-            |err| -> BuildStreamError {
-                let description = format!("{:?}", err);
-                let err = BackendSpecificError { description };
-                err.into()
-            },
-        )?;
+        let ctx = audio_context(config, options, self.device_id())?;
 
         let destination = ctx.destination();
 
@@ -239,6 +388,32 @@ impl DeviceTrait for Device {
 
         let ctx = Arc::new(ctx);
 
+        if is_worklet_available() {
+            let thread = match options.thread_spawner.clone() {
+                Some(spawner) => Some((spawner, thread::thread_name(options, "cpal_web_out")?)),
+                None => None,
+            };
+            let output = Output::open(
+                ctx.clone(),
+                config.channels,
+                buffer_size_frames,
+                thread,
+                data_callback,
+                error_callback,
+            );
+            return Ok(Stream {
+                ctx,
+                on_ended_closures: Vec::new(),
+                input: None,
+                output: Some(output),
+                gesture: RefCell::new(None),
+                config: config.clone(),
+                buffer_size_frames,
+            });
+        }
+
+        let data_callback = Arc::new(Mutex::new(Box::new(data_callback)));
+
         // A container for managing the lifecycle of the audio callbacks.
         let mut on_ended_closures: Vec<Arc<RwLock<Option<Closure<dyn FnMut()>>>>> = Vec::new();
 
@@ -278,7 +453,7 @@ impl DeviceTrait for Device {
             on_ended_closure
                 .write()
                 .unwrap()
-                .replace(Closure::wrap(Box::new(move || {
+                .replace(closure(Box::new(move || {
                     let now = ctx_handle.current_time();
                     let time_at_start_of_buffer = {
                         let time_at_start_of_buffer = time_handle
@@ -301,10 +476,16 @@ impl DeviceTrait for Device {
                         let data = temporary_buffer.as_mut_ptr() as *mut ();
                         let mut data = unsafe { Data::from_parts(data, len, sample_format) };
                         let mut data_callback = data_callback_handle.lock().unwrap();
-                        let callback = crate::StreamInstant::from_secs_f64(now);
-                        let playback = crate::StreamInstant::from_secs_f64(time_at_start_of_buffer);
+                        let callback =
+                            crate::StreamInstant::from_secs_f64(current_time(&ctx_handle));
+                        let playback = crate::StreamInstant::from_secs_f64(
+                            time_at_start_of_buffer + output_latency(&ctx_handle),
+                        );
                         let timestamp = crate::OutputStreamTimestamp { callback, playback };
-                        let info = OutputCallbackInfo { timestamp };
+                        let info = OutputCallbackInfo {
+                            timestamp,
+                            transport: None,
+                        };
                         (data_callback.deref_mut())(&mut data, &info);
                     }
 
@@ -356,6 +537,9 @@ impl DeviceTrait for Device {
         Ok(Stream {
             ctx,
             on_ended_closures,
+            input: None,
+            output: None,
+            gesture: RefCell::new(None),
             config: config.clone(),
             buffer_size_frames,
         })
@@ -368,6 +552,24 @@ impl Stream {
     pub fn audio_context(&self) -> &AudioContext {
         &*self.ctx
     }
+
+    /// The state of the context of the stream, which stays suspended after
+    /// [`play`](StreamTrait::play) until the autoplay policy of the browser lets it run.
+    pub fn state(&self) -> AudioContextState {
+        self.ctx.state()
+    }
+
+    /// Resume the context of the stream on the next user gesture, e.g. when
+    /// [`play`](StreamTrait::play) returned [`PlayStreamError::AutoplayBlocked`].
+    ///
+    /// Stops listening for gestures once the context runs, or when the stream is dropped.
+    pub fn resume_on_gesture(&self) {
+        if self.ctx.state() != AudioContextState::Running {
+            self.gesture
+                .borrow_mut()
+                .replace(Gesture::listen(self.ctx.clone()));
+        }
+    }
 }
 
 impl StreamTrait for Stream {
@@ -396,6 +598,9 @@ impl StreamTrait for Stream {
                         .unwrap();
                     offset_ms += time_step_ms;
                 }
+                if gesture::is_autoplay_blocked(&self.ctx) {
+                    return Err(PlayStreamError::AutoplayBlocked);
+                }
                 Ok(())
             }
             Err(err) => {
@@ -416,18 +621,40 @@ impl StreamTrait for Stream {
             }
         }
     }
+
+    /// The `baseLatency` and `outputLatency` of the context of output streams. Browsers don't
+    /// tell the latency of the microphone.
+    fn latency(&self) -> Option<Duration> {
+        if self.input.is_some() {
+            return None;
+        }
+        Some(Duration::from_secs_f64(output_latency(&self.ctx)))
+    }
 }
 
 impl Drop for Stream {
     fn drop(&mut self) {
-        let _ = self.ctx.close();
+        let closed = self.ctx.close();
+        // Processors keep running until the context is closed, so the output, which may own the
+        // memory its processor reads from, is only dropped once it is.
+        if let (Ok(closed), Some(output)) = (closed, self.output.take()) {
+            wasm_bindgen_futures::spawn_local(async move {
+                let _ = wasm_bindgen_futures::JsFuture::from(closed).await;
+                drop(output);
+            });
+        }
     }
 }
 
 impl Default for Devices {
     fn default() -> Devices {
         // We produce an empty iterator if the WebAudio API isn't available.
-        Devices(is_webaudio_available())
+        let mut devices = Vec::new();
+        if is_webaudio_available() {
+            devices.push(Device(Kind::Default));
+            devices.extend(devices::cached());
+        }
+        Devices(devices.into_iter())
     }
 }
 
@@ -435,25 +662,23 @@ impl Iterator for Devices {
     type Item = Device;
     #[inline]
     fn next(&mut self) -> Option<Device> {
-        if self.0 {
-            self.0 = false;
-            Some(Device)
-        } else {
-            None
-        }
+        self.0.next()
     }
 }
 
 #[inline]
 fn default_input_device() -> Option<Device> {
-    // TODO
-    None
+    if is_input_available() {
+        Some(Device(Kind::Default))
+    } else {
+        None
+    }
 }
 
 #[inline]
 fn default_output_device() -> Option<Device> {
     if is_webaudio_available() {
-        Some(Device)
+        Some(Device(Kind::Default))
     } else {
         None
     }
@@ -468,12 +693,215 @@ fn is_webaudio_available() -> bool {
     }
 }
 
+// Detects whether audio worklets are available, which browsers only allow in secure contexts.
+fn is_worklet_available() -> bool {
+    is_webaudio_available()
+        && eval("typeof AudioWorkletNode !== 'undefined'")
+            .ok()
+            .and_then(|available| available.as_bool())
+            .unwrap_or(false)
+}
+
+// Detects whether the microphone can be captured.
+fn is_input_available() -> bool {
+    const INPUT: &str = "typeof navigator !== 'undefined' && navigator.mediaDevices !== undefined";
+    is_worklet_available()
+        && eval(INPUT)
+            .ok()
+            .and_then(|available| available.as_bool())
+            .unwrap_or(false)
+}
+
+// Wrap `callback` in a `Closure`. Emscripten builds unwind on panics, which `Closure::wrap` only
+// allows through unwind safe callbacks, while nothing relies on a callback that panicked.
+#[cfg(target_os = "emscripten")]
+fn closure<T: ?Sized + WasmClosure>(callback: Box<T>) -> Closure<T> {
+    Closure::wrap_assert_unwind_safe(callback)
+}
+
+#[cfg(not(target_os = "emscripten"))]
+fn closure<T: ?Sized + WasmClosure>(callback: Box<T>) -> Closure<T> {
+    Closure::wrap(callback)
+}
+
+// Detects whether contexts can play to another speaker than the default one.
+fn is_output_selectable() -> bool {
+    const SELECTABLE: &str =
+        "typeof AudioContext !== 'undefined' && 'setSinkId' in AudioContext.prototype";
+    eval(SELECTABLE)
+        .ok()
+        .and_then(|selectable| selectable.as_bool())
+        .unwrap_or(false)
+}
+
+// The error of a stream whose worklet node failed to connect with `err`, such as the permission
+// to use the microphone being denied.
+fn stream_error(err: JsValue) -> StreamError {
+    let description = match err.dyn_ref::<DomException>() {
+        // The only constraint that can't be satisfied is the `deviceId` of a device that's gone.
+        Some(err) if matches!(&*err.name(), "NotFoundError" | "OverconstrainedError") => {
+            return StreamError::DeviceNotAvailable
+        }
+        Some(err) => format!("{}: {}", err.name(), err.message()),
+        None => format!("{:?}", err),
+    };
+    BackendSpecificError { description }.into()
+}
+
+fn supported_configs() -> Vec<SupportedStreamConfigRange> {
+    let buffer_size = SupportedBufferSize::Range {
+        min: MIN_BUFFER_SIZE,
+        max: MAX_BUFFER_SIZE,
+    };
+    let (min_sample_rate, max_sample_rate) = sample_rate_range();
+    (MIN_CHANNELS..=MAX_CHANNELS)
+        .map(|channels| SupportedStreamConfigRange {
+            channels,
+            min_sample_rate,
+            max_sample_rate,
+            buffer_size: buffer_size.clone(),
+            sample_format: SUPPORTED_SAMPLE_FORMAT,
+        })
+        .collect()
+}
+
+// Whether the options that aren't ignored can be honoured.
+fn supports_options(options: &StreamOptions) -> bool {
+    options.share_mode != ShareMode::Exclusive
+        && options.passthrough.is_none()
+        && options.dsd.is_none()
+        && options.device_channels.is_none()
+}
+
+// Create the context of a stream with the given configuration, hinting at the latency of its
+// buffer size, or at that of its performance mode, and playing to the speaker `sink_id` if any.
+fn audio_context(
+    config: &StreamConfig,
+    options: &StreamOptions,
+    sink_id: Option<&str>,
+) -> Result<AudioContext, BuildStreamError> {
+    let stream_opts = AudioContextOptions::new();
+    if let Some(sink_id) = sink_id {
+        Reflect::set(&stream_opts, &"sinkId".into(), &sink_id.into())
+            .map_err(|_| BuildStreamError::DeviceNotAvailable)?;
+    }
+    stream_opts.set_sample_rate(config.sample_rate.0 as f32);
+    match (config.buffer_size, options.performance_mode) {
+        (BufferSize::Fixed(frames), _) => {
+            stream_opts.set_latency_hint_f64(frames as f64 / config.sample_rate.0 as f64)
+        }
+        (BufferSize::Default, Some(PerformanceMode::LowLatency)) => {
+            stream_opts.set_latency_hint(&"interactive".into())
+        }
+        (BufferSize::Default, Some(PerformanceMode::PowerSaving)) => {
+            stream_opts.set_latency_hint(&"playback".into())
+        }
+        (BufferSize::Default, None) => (),
+    }
+    AudioContext::new_with_context_options(&stream_opts).map_err(|err| -> BuildStreamError {
+        match err.dyn_ref::<DomException>() {
+            // Browsers support other rates than the range every one of them must support.
+            Some(err) if err.name() == "NotSupportedError" => {
+                BuildStreamError::StreamConfigNotSupported
+            }
+            // The speaker is gone.
+            Some(err) if err.name() == "NotFoundError" => BuildStreamError::DeviceNotAvailable,
+            _ => {
+                let description = format!("{:?}", err);
+                let err = BackendSpecificError { description };
+                err.into()
+            }
+        }
+    })
+}
+
+// The rate of the hardware, at which contexts run unless given another one. Creating a context
+// to find it is costly, so it is only done once.
+fn hardware_sample_rate() -> Option<SampleRate> {
+    thread_local! {
+        static SAMPLE_RATE: Cell<Option<Option<SampleRate>>> = const { Cell::new(None) };
+    }
+    SAMPLE_RATE.with(|sample_rate| {
+        if sample_rate.get().is_none() {
+            let ctx = is_webaudio_available()
+                .then(AudioContext::new)
+                .and_then(Result::ok);
+            sample_rate.set(Some(ctx.map(|ctx| {
+                let _ = ctx.close();
+                SampleRate(ctx.sample_rate() as u32)
+            })));
+        }
+        sample_rate.get().flatten()
+    })
+}
+
+fn default_sample_rate() -> SampleRate {
+    hardware_sample_rate().unwrap_or(DEFAULT_SAMPLE_RATE)
+}
+
+// The rates that every browser supports, extended to that of the hardware.
+fn sample_rate_range() -> (SampleRate, SampleRate) {
+    match hardware_sample_rate() {
+        Some(rate) => (rate.min(MIN_SAMPLE_RATE), rate.max(MAX_SAMPLE_RATE)),
+        None => (MIN_SAMPLE_RATE, MAX_SAMPLE_RATE),
+    }
+}
+
+// The time between samples being rendered and them being audible, in seconds.
+fn output_latency(ctx: &AudioContext) -> f64 {
+    ["baseLatency", "outputLatency"]
+        .iter()
+        .filter_map(|latency| Reflect::get(ctx, &(*latency).into()).ok()?.as_f64())
+        .sum()
+}
+
+// The context time being rendered now, which `currentTime` only tells once per render quantum.
+// Where the browser reports the frame the device is playing, it is estimated from that frame, as
+// the frame rendered now is played after the output latency.
+fn current_time(ctx: &AudioContext) -> f64 {
+    match output_timestamp(ctx) {
+        Some((context_time, elapsed)) => context_time + elapsed + output_latency(ctx),
+        None => ctx.current_time(),
+    }
+}
+
+// The context time of the frame played by the device according to `getOutputTimestamp()`, along
+// with the seconds elapsed since.
+fn output_timestamp(ctx: &AudioContext) -> Option<(f64, f64)> {
+    fn call(target: &JsValue, method: &str) -> Option<JsValue> {
+        let method = Reflect::get(target, &method.into()).ok()?;
+        method.dyn_into::<Function>().ok()?.call0(target).ok()
+    }
+    let timestamp = call(ctx, "getOutputTimestamp")?;
+    let context_time = Reflect::get(&timestamp, &"contextTime".into())
+        .ok()?
+        .as_f64()?;
+    let performance_time = Reflect::get(&timestamp, &"performanceTime".into())
+        .ok()?
+        .as_f64()?;
+    // Browsers report zeros until the device starts playing.
+    if performance_time <= 0.0 {
+        return None;
+    }
+    let performance = Reflect::get(&js_sys::global(), &"performance".into()).ok()?;
+    let now = call(&performance, "now")?.as_f64()?;
+    Some((context_time, ((now - performance_time) / 1000.0).max(0.0)))
+}
+
+fn buffer_size_frames(config: &StreamConfig) -> Result<usize, BuildStreamError> {
+    match config.buffer_size {
+        BufferSize::Fixed(0) => Err(BuildStreamError::StreamConfigNotSupported),
+        BufferSize::Fixed(v) => Ok(v as usize),
+        BufferSize::Default => Ok(DEFAULT_BUFFER_SIZE),
+    }
+}
+
 // Whether or not the given stream configuration is valid for building a stream.
 fn valid_config(conf: &StreamConfig, sample_format: SampleFormat) -> bool {
     conf.channels <= MAX_CHANNELS
         && conf.channels >= MIN_CHANNELS
-        && conf.sample_rate <= MAX_SAMPLE_RATE
-        && conf.sample_rate >= MIN_SAMPLE_RATE
+        && conf.sample_rate <= sample_rate_range().1
+        && conf.sample_rate >= sample_rate_range().0
         && sample_format == SUPPORTED_SAMPLE_FORMAT
 }
 
@@ -0,0 +1,275 @@
+//! Output streams played by an `AudioWorkletNode`, whose processor runs on the audio rendering
+//! thread and asks the main thread for each buffer well before it is due, so that the page
+//! being busy for a while doesn't interrupt playback. Where the page can share memory with the
+//! processor, the main thread rather fills a [`Ring`] that the processor plays from, saving the
+//! latency of posting messages.
+//!
+//! The processor is loaded asynchronously, so the node is connected in the background once the
+//! stream is built, and any failure is reported to its error callback.
+
+use std::cell::RefCell;
+use std::rc::Rc;
+use std::sync::Arc;
+
+use super::js_sys::{Array, Float32Array, Object, Reflect};
+use super::ring::{self, Ring};
+use super::wasm_bindgen::prelude::*;
+use super::wasm_bindgen::JsCast;
+use super::wasm_bindgen_futures::spawn_local;
+use super::web_sys::{
+    AudioContext, AudioWorkletNode, AudioWorkletNodeOptions, ChannelCountMode, MessageEvent,
+};
+#[cfg(target_feature = "atomics")]
+use super::worker::Worker;
+use super::{closure, current_time, output_latency, stream_error, worklet};
+use crate::{
+    AudioThreadSpawner, Data, OutputCallbackInfo, OutputStreamTimestamp, SampleFormat, StreamError,
+    StreamInstant,
+};
+
+/// How many buffers the processor requests ahead, leaving the main thread about two buffers'
+/// worth of time to answer each request.
+const BUFFERS: u32 = 3;
+
+/// The spawner of the thread rendering a stream, along with the name of that thread.
+pub type Thread = (Arc<dyn AudioThreadSpawner>, String);
+
+/// The node of an output stream, disconnected when dropped.
+pub struct Output {
+    state: Rc<RefCell<State>>,
+}
+
+#[derive(Default)]
+struct State {
+    closed: bool,
+    node: Option<Node>,
+}
+
+struct Node {
+    node: AudioWorkletNode,
+    feed: Feed,
+}
+
+/// How the main thread feeds the processor.
+enum Feed {
+    /// Answering the requests the processor posts for each buffer.
+    Messages {
+        _on_message: Closure<dyn FnMut(MessageEvent)>,
+    },
+    /// Filling a ring shared with the processor on a timer.
+    Ring {
+        interval: i32,
+        _fill: Closure<dyn FnMut()>,
+    },
+    /// Leaving a spawned thread to fill a ring in the memory of the module.
+    #[cfg(target_feature = "atomics")]
+    Worker { _worker: Worker },
+}
+
+impl Output {
+    /// Start playing `channels` channels into the destination of `ctx`, calling `data_callback`
+    /// for every `frames` frames, on the given `thread` where the module shares its memory.
+    pub fn open<D, E>(
+        ctx: Arc<AudioContext>,
+        channels: u16,
+        frames: usize,
+        thread: Option<Thread>,
+        data_callback: D,
+        mut error_callback: E,
+    ) -> Self
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let state = Rc::new(RefCell::new(State::default()));
+        let state_handle = state.clone();
+        spawn_local(async move {
+            let result = connect(&ctx, channels, frames, thread, data_callback).await;
+            let mut state = state_handle.borrow_mut();
+            match result {
+                Ok(node) if state.closed => node.close(),
+                Ok(node) => state.node = Some(node),
+                Err(_) if state.closed => (),
+                Err(err) => error_callback(stream_error(err)),
+            }
+        });
+        Output { state }
+    }
+}
+
+impl Drop for Output {
+    fn drop(&mut self) {
+        let mut state = self.state.borrow_mut();
+        state.closed = true;
+        if let Some(node) = state.node.take() {
+            node.close();
+        }
+    }
+}
+
+impl Node {
+    fn close(self) {
+        match self.feed {
+            Feed::Messages { .. } => {
+                if let Ok(port) = self.node.port() {
+                    port.set_onmessage(None);
+                }
+            }
+            Feed::Ring { interval, .. } => {
+                if let Some(window) = web_sys::window() {
+                    window.clear_interval_with_handle(interval);
+                }
+            }
+            #[cfg(target_feature = "atomics")]
+            Feed::Worker { .. } => (),
+        }
+        let _ = self.node.disconnect();
+    }
+}
+
+/// Renders the buffers of a stream through its data callback.
+struct Render<D> {
+    ctx: AudioContext,
+    data_callback: D,
+    buffer: Vec<f32>,
+}
+
+impl<D> Render<D>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    /// Render the buffer whose rendering starts at the context time `playback`, which is heard
+    /// after the latency of the context.
+    fn render(&mut self, playback: f64) -> &[f32] {
+        let len = self.buffer.len();
+        let data = self.buffer.as_mut_ptr() as *mut ();
+        let mut data = unsafe { Data::from_parts(data, len, SampleFormat::F32) };
+        let callback = StreamInstant::from_secs_f64(current_time(&self.ctx));
+        let playback = StreamInstant::from_secs_f64(playback + output_latency(&self.ctx));
+        let info = OutputCallbackInfo {
+            timestamp: OutputStreamTimestamp { callback, playback },
+            transport: None,
+        };
+        (self.data_callback)(&mut data, &info);
+        &self.buffer
+    }
+}
+
+async fn connect<D>(
+    ctx: &AudioContext,
+    channels: u16,
+    frames: usize,
+    thread: Option<Thread>,
+    data_callback: D,
+) -> Result<Node, JsValue>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+{
+    worklet::add_module(ctx).await?;
+
+    #[cfg(target_feature = "atomics")]
+    if let Some((spawner, name)) = thread.filter(|_| ring::is_available()) {
+        let (worker, ring_options) = Worker::new(ctx, channels, BUFFERS * frames as u32)?;
+        let node = worklet_node(ctx, channels, frames, Some(&ring_options))?;
+        let sample_rate = ctx.sample_rate() as f64;
+        if let Err(err) = worker.spawn(&*spawner, &name, frames, sample_rate, data_callback) {
+            let _ = node.disconnect();
+            return Err(JsValue::from_str(&err.to_string()));
+        }
+        let feed = Feed::Worker { _worker: worker };
+        return Ok(Node { node, feed });
+    }
+    #[cfg(not(target_feature = "atomics"))]
+    let _ = thread;
+
+    let ring = ring::is_available().then(|| Ring::new(channels, BUFFERS * frames as u32));
+    let ring_options = ring.as_ref().map(Ring::processor_options).transpose()?;
+    let node = worklet_node(ctx, channels, frames, ring_options.as_ref())?;
+
+    let mut render = Render {
+        ctx: ctx.clone(),
+        data_callback,
+        buffer: vec![0f32; frames * channels as usize],
+    };
+    let feed = match ring {
+        Some(ring) => {
+            let sample_rate = ctx.sample_rate() as f64;
+            let mut fill = move || {
+                while ring.free() as usize >= frames {
+                    let playback = current_time(&render.ctx) + ring.queued() as f64 / sample_rate;
+                    ring.write(render.render(playback));
+                }
+            };
+            fill();
+            // Check for free frames twice per buffer.
+            let period_ms = (frames as f64 / sample_rate * 500.0).max(1.0) as i32;
+            let fill = closure(Box::new(fill) as Box<dyn FnMut()>);
+            let window = web_sys::window().ok_or_else(|| JsValue::from_str("no window"))?;
+            let interval = window.set_interval_with_callback_and_timeout_and_arguments_0(
+                fill.as_ref().unchecked_ref(),
+                period_ms,
+            )?;
+            Feed::Ring {
+                interval,
+                _fill: fill,
+            }
+        }
+        None => {
+            let port = node.port()?;
+            let port_handle = port.clone();
+            let on_message = closure(Box::new(move |event: MessageEvent| {
+                let playback = match event.data().as_f64() {
+                    Some(playback) => playback,
+                    None => return,
+                };
+                // Transfer a copy rather than sharing the memory of the module.
+                let samples = Float32Array::from(render.render(playback));
+                let _ = port_handle
+                    .post_message_with_transferable(&samples, &Array::of1(&samples.buffer()));
+            }) as Box<dyn FnMut(MessageEvent)>);
+            port.set_onmessage(Some(on_message.as_ref().unchecked_ref()));
+            Feed::Messages {
+                _on_message: on_message,
+            }
+        }
+    };
+    Ok(Node { node, feed })
+}
+
+/// An `AudioWorkletNode` playing `channels` channels into the destination of `ctx`, from the
+/// ring described by `ring_options` if any.
+fn worklet_node(
+    ctx: &AudioContext,
+    channels: u16,
+    frames: usize,
+    ring_options: Option<&Object>,
+) -> Result<AudioWorkletNode, JsValue> {
+    let processor_options = Object::new();
+    Reflect::set(
+        &processor_options,
+        &"channels".into(),
+        &JsValue::from(channels),
+    )?;
+    Reflect::set(
+        &processor_options,
+        &"frames".into(),
+        &JsValue::from(frames as u32),
+    )?;
+    Reflect::set(
+        &processor_options,
+        &"buffers".into(),
+        &JsValue::from(BUFFERS),
+    )?;
+    if let Some(ring_options) = ring_options {
+        Reflect::set(&processor_options, &"ring".into(), ring_options)?;
+    }
+    let options = AudioWorkletNodeOptions::new();
+    options.set_number_of_inputs(0);
+    options.set_number_of_outputs(1);
+    options.set_output_channel_count(&Array::of1(&JsValue::from(channels)));
+    options.set_channel_count_mode(ChannelCountMode::Explicit);
+    options.set_processor_options(Some(&processor_options));
+    let node = AudioWorkletNode::new_with_options(ctx, worklet::OUTPUT_PROCESSOR, &options)?;
+    node.connect_with_audio_node(&ctx.destination())?;
+    Ok(node)
+}
@@ -0,0 +1,89 @@
+//! A ring buffer in a `SharedArrayBuffer`, through which output streams feed their processor
+//! without posting a message for every buffer. Browsers only share memory with worklets in
+//! cross-origin isolated pages.
+//!
+//! The buffer starts with the read and write positions in frames, as two `Int32`s, followed by
+//! the interleaved `Float32` samples. Only the processor moves the read position, and only the
+//! stream moves the write position, so that each can rely on the one it owns.
+
+use super::js_sys::{eval, Atomics, Float32Array, Int32Array, Object, SharedArrayBuffer};
+use super::wasm_bindgen::JsValue;
+use super::worklet;
+
+const READ: u32 = 0;
+const WRITE: u32 = 1;
+const SAMPLES_OFFSET: u32 = 8;
+
+pub struct Ring {
+    buffer: SharedArrayBuffer,
+    positions: Int32Array,
+    samples: Float32Array,
+    channels: u32,
+    capacity: u32,
+}
+
+impl Ring {
+    /// A ring holding up to `frames` frames of `channels` channels.
+    pub fn new(channels: u16, frames: u32) -> Self {
+        let channels = channels as u32;
+        // One frame is left empty, telling a full ring apart from an empty one.
+        let capacity = frames + 1;
+        let buffer = SharedArrayBuffer::new(SAMPLES_OFFSET + capacity * channels * 4);
+        let positions = Int32Array::new_with_byte_offset_and_length(&buffer, 0, 2);
+        let samples = Float32Array::new_with_byte_offset(&buffer, SAMPLES_OFFSET);
+        Ring {
+            buffer,
+            positions,
+            samples,
+            channels,
+            capacity,
+        }
+    }
+
+    /// The ring options of the processor playing from the ring.
+    pub fn processor_options(&self) -> Result<Object, JsValue> {
+        worklet::ring_options(&self.buffer, 0, SAMPLES_OFFSET, self.capacity, None)
+    }
+
+    /// The frames written that the processor didn't play yet.
+    pub fn queued(&self) -> u32 {
+        let read = self.position(READ);
+        let write = self.position(WRITE);
+        (write + self.capacity - read) % self.capacity
+    }
+
+    /// How many frames can be written.
+    pub fn free(&self) -> u32 {
+        self.capacity - 1 - self.queued()
+    }
+
+    /// Append the interleaved `samples`, which must fit in the free frames.
+    pub fn write(&self, samples: &[f32]) {
+        let frames = samples.len() as u32 / self.channels;
+        debug_assert!(frames <= self.free());
+        let write = self.position(WRITE);
+        let split = ((self.capacity - write).min(frames) * self.channels) as usize;
+        let (head, tail) = samples.split_at(split);
+        let start = write * self.channels;
+        self.samples
+            .subarray(start, start + head.len() as u32)
+            .copy_from(head);
+        self.samples.subarray(0, tail.len() as u32).copy_from(tail);
+        let write = (write + frames) % self.capacity;
+        let _ = Atomics::store(&self.positions, WRITE, write as i32);
+    }
+
+    fn position(&self, index: u32) -> u32 {
+        Atomics::load(&self.positions, index).unwrap_or(0) as u32
+    }
+}
+
+/// Whether the page can share memory with worklets.
+pub fn is_available() -> bool {
+    const AVAILABLE: &str =
+        "typeof SharedArrayBuffer !== 'undefined' && globalThis.crossOriginIsolated === true";
+    eval(AVAILABLE)
+        .ok()
+        .and_then(|available| available.as_bool())
+        .unwrap_or(false)
+}
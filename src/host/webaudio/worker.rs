@@ -0,0 +1,150 @@
+//! Output streams rendered on a thread spawned through [`StreamOptions::thread_spawner`], e.g. a
+//! Web Worker sharing the memory of the module, so that heavy callbacks don't block the page and
+//! a busy page doesn't delay them. Only available when the module is built with shared memory,
+//! i.e. with the `atomics` target feature, in cross-origin isolated pages.
+//!
+//! The thread fills a ring in the memory of the module, which the processor plays from through
+//! views of that memory, checking for free frames twice per buffer.
+//!
+//! [`StreamOptions::thread_spawner`]: crate::StreamOptions::thread_spawner
+
+use std::cell::UnsafeCell;
+use std::io;
+use std::sync::atomic::{AtomicBool, AtomicI32, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::thread;
+use std::time::Duration;
+
+use super::js_sys::{Object, Reflect};
+use super::wasm_bindgen::{self, JsValue};
+use super::web_sys::AudioContext;
+use super::{output_latency, worklet};
+use crate::{
+    AudioThreadSpawner, Data, OutputCallbackInfo, OutputStreamTimestamp, SampleFormat,
+    StreamInstant,
+};
+
+/// The ring shared by the thread and the processor.
+struct Shared {
+    // The read position, moved by the processor, followed by the write position.
+    positions: [AtomicI32; 2],
+    // The context time at which the frame at the read position plays, as the bits of an `f64`
+    // written by the processor.
+    time: AtomicU64,
+    // The latency of the context when the stream was built, in seconds.
+    latency: f64,
+    stop: AtomicBool,
+    samples: Box<[UnsafeCell<f32>]>,
+    channels: u32,
+    capacity: u32,
+}
+
+// The thread only writes the samples the processor doesn't read, and the other way around.
+unsafe impl Sync for Shared {}
+
+/// The thread of an output stream, stopped when dropped.
+pub struct Worker {
+    shared: Arc<Shared>,
+}
+
+impl Worker {
+    /// A worker for a ring holding up to `frames` frames of `channels` channels, along with the
+    /// ring options of its processor.
+    pub fn new(ctx: &AudioContext, channels: u16, frames: u32) -> Result<(Self, Object), JsValue> {
+        let channels = channels as u32;
+        // One frame is left empty, telling a full ring apart from an empty one.
+        let capacity = frames + 1;
+        let samples = (0..capacity * channels)
+            .map(|_| UnsafeCell::new(0.0))
+            .collect();
+        let shared = Arc::new(Shared {
+            positions: [AtomicI32::new(0), AtomicI32::new(0)],
+            time: AtomicU64::new(ctx.current_time().to_bits()),
+            latency: output_latency(ctx),
+            stop: AtomicBool::new(false),
+            samples,
+            channels,
+            capacity,
+        });
+        let memory = Reflect::get(&wasm_bindgen::memory(), &"buffer".into())?;
+        let options = worklet::ring_options(
+            &memory,
+            shared.positions.as_ptr() as u32,
+            shared.samples.as_ptr() as u32,
+            capacity,
+            Some(&shared.time as *const AtomicU64 as u32),
+        )?;
+        Ok((Worker { shared }, options))
+    }
+
+    /// Render the buffers of `frames` frames through `data_callback` on the thread `name` spawned
+    /// by `spawner`, at the sample rate `sample_rate`.
+    pub fn spawn<D>(
+        &self,
+        spawner: &dyn AudioThreadSpawner,
+        name: &str,
+        frames: usize,
+        sample_rate: f64,
+        mut data_callback: D,
+    ) -> io::Result<()>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    {
+        let shared = self.shared.clone();
+        let period = Duration::from_secs_f64(frames as f64 / sample_rate / 2.0);
+        let body = move || {
+            let mut buffer = vec![0f32; frames * shared.channels as usize];
+            while !shared.stop.load(Ordering::Acquire) {
+                let read = shared.positions[0].load(Ordering::Acquire);
+                if shared.free(read) < frames as u32 {
+                    thread::sleep(period);
+                    continue;
+                }
+                let time = f64::from_bits(shared.time.load(Ordering::Acquire));
+                let queued = shared.capacity - 1 - shared.free(read);
+                let callback = StreamInstant::from_secs_f64(time);
+                let playback = StreamInstant::from_secs_f64(
+                    time + queued as f64 / sample_rate + shared.latency,
+                );
+                let info = OutputCallbackInfo {
+                    timestamp: OutputStreamTimestamp { callback, playback },
+                    transport: None,
+                };
+                let len = buffer.len();
+                let data = buffer.as_mut_ptr() as *mut ();
+                let mut data = unsafe { Data::from_parts(data, len, SampleFormat::F32) };
+                data_callback(&mut data, &info);
+                shared.write(&buffer);
+            }
+        };
+        spawner.spawn(name, Box::new(body))
+    }
+}
+
+impl Drop for Worker {
+    fn drop(&mut self) {
+        self.shared.stop.store(true, Ordering::Release);
+    }
+}
+
+impl Shared {
+    /// How many frames can be written, given the read position `read`.
+    fn free(&self, read: i32) -> u32 {
+        let write = self.positions[1].load(Ordering::Relaxed) as u32;
+        let queued = (write + self.capacity - read as u32) % self.capacity;
+        self.capacity - 1 - queued
+    }
+
+    /// Append the interleaved `samples`, which must fit in the free frames.
+    fn write(&self, samples: &[f32]) {
+        let mut write = self.positions[1].load(Ordering::Relaxed) as u32;
+        for frame in samples.chunks(self.channels as usize) {
+            let start = (write * self.channels) as usize;
+            for (sample, slot) in frame.iter().zip(&self.samples[start..]) {
+                unsafe { *slot.get() = *sample };
+            }
+            write = (write + 1) % self.capacity;
+        }
+        self.positions[1].store(write as i32, Ordering::Release);
+    }
+}
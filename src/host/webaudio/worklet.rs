@@ -0,0 +1,160 @@
+//! The `AudioWorkletProcessor`s behind the worklet nodes of the streams, loaded into the audio
+//! worklet of each stream's `AudioContext` from a blob URL, so that no script has to be served
+//! next to the application.
+
+use super::js_sys::{Array, Object, Reflect};
+use super::wasm_bindgen::JsValue;
+use super::wasm_bindgen_futures::JsFuture;
+use super::web_sys::{AudioContext, Blob, BlobPropertyBag, Url};
+
+/// The name of the processor capturing the input of input streams.
+pub const INPUT_PROCESSOR: &str = "cpal-input";
+/// The name of the processor playing the buffers of output streams.
+pub const OUTPUT_PROCESSOR: &str = "cpal-output";
+
+const PROCESSORS: &str = r#"
+class CpalInput extends AudioWorkletProcessor {
+    constructor(options) {
+        super();
+        this.channels = options.processorOptions.channels;
+        this.frames = options.processorOptions.frames;
+        this.buffer = new Float32Array(this.frames * this.channels);
+        this.offset = 0;
+    }
+
+    // Interleaves the input into buffers of `frames` frames, posting each with the context time
+    // at which its first frame was captured.
+    process(inputs) {
+        const input = inputs[0];
+        if (input.length === 0) {
+            return true;
+        }
+        for (let i = 0; i < input[0].length; i++) {
+            for (let channel = 0; channel < this.channels; channel++) {
+                this.buffer[this.offset++] = channel < input.length ? input[channel][i] : 0;
+            }
+            if (this.offset === this.buffer.length) {
+                const time = currentTime + (i + 1 - this.frames) / sampleRate;
+                this.port.postMessage({ samples: this.buffer, time }, [this.buffer.buffer]);
+                this.buffer = new Float32Array(this.frames * this.channels);
+                this.offset = 0;
+            }
+        }
+        return true;
+    }
+}
+
+registerProcessor("cpal-input", CpalInput);
+
+class CpalOutput extends AudioWorkletProcessor {
+    constructor(options) {
+        super();
+        this.channels = options.processorOptions.channels;
+        this.frames = options.processorOptions.frames;
+        const ring = options.processorOptions.ring;
+        if (ring !== undefined) {
+            this.positions = new Int32Array(ring.buffer, ring.positions, 2);
+            this.capacity = ring.capacity;
+            this.samples = new Float32Array(ring.buffer, ring.samples, ring.capacity * this.channels);
+            if (ring.time !== undefined) {
+                this.time = new Float64Array(ring.buffer, ring.time, 1);
+            }
+            return;
+        }
+        this.buffers = [];
+        this.offset = 0;
+        this.requested = 0;
+        this.played = 0;
+        this.port.onmessage = (event) => this.buffers.push(event.data);
+        for (let i = 0; i < options.processorOptions.buffers; i++) {
+            this.request(currentTime);
+        }
+    }
+
+    // Asks for the buffer following those requested so far, with the context time at which it
+    // starts playing, given that the frames played so far end at `now`.
+    request(now) {
+        this.port.postMessage(now + (this.requested - this.played) / sampleRate);
+        this.requested += this.frames;
+    }
+
+    // Plays the interleaved buffers in the order they were received, leaving the output silent
+    // whenever the next one is late.
+    process(inputs, outputs) {
+        if (this.samples !== undefined) {
+            return this.processRing(outputs[0]);
+        }
+        const output = outputs[0];
+        const length = output[0].length;
+        let completed = 0;
+        for (let i = 0; i < length && this.buffers.length > 0; i++) {
+            const buffer = this.buffers[0];
+            for (let channel = 0; channel < this.channels; channel++) {
+                output[channel][i] = buffer[this.offset++];
+            }
+            this.played++;
+            if (this.offset === buffer.length) {
+                this.buffers.shift();
+                this.offset = 0;
+                completed++;
+            }
+        }
+        for (let i = 0; i < completed; i++) {
+            this.request(currentTime + length / sampleRate);
+        }
+        return true;
+    }
+
+    // Plays the frames written to the ring, leaving the output silent once it is empty.
+    processRing(output) {
+        let read = Atomics.load(this.positions, 0);
+        const write = Atomics.load(this.positions, 1);
+        for (let i = 0; i < output[0].length && read !== write; i++) {
+            for (let channel = 0; channel < this.channels; channel++) {
+                output[channel][i] = this.samples[read * this.channels + channel];
+            }
+            read = (read + 1) % this.capacity;
+        }
+        if (this.time !== undefined) {
+            this.time[0] = currentTime + output[0].length / sampleRate;
+        }
+        Atomics.store(this.positions, 0, read);
+        return true;
+    }
+}
+
+registerProcessor("cpal-output", CpalOutput);
+"#;
+
+/// The options of an output processor playing from a ring in `buffer`, a `SharedArrayBuffer`,
+/// given the byte offsets of its read and write positions, of its `capacity` frames and of the
+/// context time at which the frame at the read position plays, if the processor should write it.
+pub fn ring_options(
+    buffer: &JsValue,
+    positions: u32,
+    samples: u32,
+    capacity: u32,
+    time: Option<u32>,
+) -> Result<Object, JsValue> {
+    let options = Object::new();
+    Reflect::set(&options, &"buffer".into(), buffer)?;
+    Reflect::set(&options, &"positions".into(), &positions.into())?;
+    Reflect::set(&options, &"samples".into(), &samples.into())?;
+    Reflect::set(&options, &"capacity".into(), &capacity.into())?;
+    if let Some(time) = time {
+        Reflect::set(&options, &"time".into(), &time.into())?;
+    }
+    Ok(options)
+}
+
+/// Add the processors to the audio worklet of `ctx`, which is only available in secure contexts.
+pub async fn add_module(ctx: &AudioContext) -> Result<(), JsValue> {
+    let options = BlobPropertyBag::new();
+    options.set_type("text/javascript");
+    let parts = Array::of1(&JsValue::from_str(PROCESSORS));
+    let blob = Blob::new_with_str_sequence_and_options(&parts, &options)?;
+    let url = Url::create_object_url_with_blob(&blob)?;
+    let result = async { JsFuture::from(ctx.audio_worklet()?.add_module(&url)?).await }.await;
+    let _ = Url::revoke_object_url(&url);
+    result.map(drop)
+}
@@ -155,33 +155,100 @@
 
 #![recursion_limit = "2048"]
 
-// Extern crate declarations with `#[macro_use]` must unfortunately be at crate root.
-#[cfg(target_os = "emscripten")]
-#[macro_use]
-extern crate wasm_bindgen;
-#[cfg(target_os = "emscripten")]
-extern crate js_sys;
-#[cfg(target_os = "emscripten")]
-extern crate web_sys;
-
 pub use error::*;
 pub use platform::{
     available_hosts, default_host, host_from_id, Device, Devices, Host, HostId, Stream,
     SupportedInputConfigs, SupportedOutputConfigs, ALL_HOSTS,
 };
 pub use samples_formats::{FromSample, Sample, SampleFormat, SizedSample, I24, I48, U24, U48};
+pub use stats::StreamStats;
 use std::convert::TryInto;
+use std::fmt;
 use std::ops::{Div, Mul};
+use std::sync::Arc;
 use std::time::Duration;
+pub use thread::AudioThreadSpawner;
 #[cfg(target_os = "emscripten")]
 use wasm_bindgen::prelude::*;
 
+#[cfg(all(target_os = "android", not(any(feature = "oboe", feature = "aaudio"))))]
+compile_error!(
+    "the `oboe` or `aaudio` feature must be enabled on Android, as they provide its hosts"
+);
+
+#[macro_use]
+mod logging;
+
+#[cfg_attr(not(target_os = "macos"), allow(dead_code))]
+mod channel_subset;
+mod dsd;
 mod error;
+mod fallback;
 mod host;
 pub mod platform;
 mod samples_formats;
 pub mod traits;
 
+// The crate-private parts of these modules are only used by the hosts that run their own audio
+// thread.
+#[cfg_attr(
+    not(any(
+        windows,
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    )),
+    allow(dead_code)
+)]
+mod stats;
+#[cfg(any(
+    target_os = "linux",
+    target_os = "dragonfly",
+    target_os = "freebsd",
+    target_os = "netbsd"
+))]
+#[cfg_attr(
+    not(any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        feature = "pipewire",
+        feature = "pulseaudio",
+        feature = "sndio"
+    )),
+    allow(dead_code)
+)]
+mod stream_thread;
+#[cfg_attr(
+    not(any(
+        windows,
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd"
+    )),
+    allow(dead_code)
+)]
+mod thread;
+// The watchdog is also used by the hosts whose callbacks are driven by the system, to honour the
+// build timeout.
+#[cfg_attr(
+    not(any(
+        windows,
+        target_os = "linux",
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        target_os = "netbsd",
+        target_os = "macos",
+        target_os = "ios",
+        target_os = "tvos",
+        target_os = "watchos",
+        all(target_os = "android", any(feature = "aaudio", feature = "oboe"))
+    )),
+    allow(dead_code)
+)]
+mod watchdog;
+
 /// A host's device iterator yielding only *input* devices.
 pub type InputDevices<I> = std::iter::Filter<I, fn(&<I as Iterator>::Item) -> bool>;
 
@@ -238,12 +305,14 @@ pub enum BufferSize {
 
 #[cfg(target_os = "emscripten")]
 impl wasm_bindgen::describe::WasmDescribe for BufferSize {
-    fn describe() {}
+    fn describe() {
+        <Option<FrameCount> as wasm_bindgen::describe::WasmDescribe>::describe()
+    }
 }
 
 #[cfg(target_os = "emscripten")]
 impl wasm_bindgen::convert::IntoWasmAbi for BufferSize {
-    type Abi = wasm_bindgen::convert::WasmOption<u32>;
+    type Abi = <Option<FrameCount> as wasm_bindgen::convert::IntoWasmAbi>::Abi;
     fn into_abi(self) -> Self::Abi {
         match self {
             Self::Default => None,
@@ -253,6 +322,17 @@ impl wasm_bindgen::convert::IntoWasmAbi for BufferSize {
     }
 }
 
+#[cfg(target_os = "emscripten")]
+impl wasm_bindgen::convert::FromWasmAbi for BufferSize {
+    type Abi = <Option<FrameCount> as wasm_bindgen::convert::FromWasmAbi>::Abi;
+    unsafe fn from_abi(js: Self::Abi) -> Self {
+        match Option::<FrameCount>::from_abi(js) {
+            None => Self::Default,
+            Some(fc) => Self::Fixed(fc),
+        }
+    }
+}
+
 /// The set of parameters used to describe how to open a stream.
 ///
 /// The sample format is omitted in favour of using a sample type.
@@ -264,6 +344,496 @@ pub struct StreamConfig {
     pub buffer_size: BufferSize,
 }
 
+/// Additional options used when building a stream with one of the `build_*_stream_with_options`
+/// methods of [`DeviceTrait`](traits::DeviceTrait).
+///
+/// Options that are not supported by a host are ignored by it.
+#[derive(Clone, Default)]
+pub struct StreamOptions {
+    /// Spawns the thread running the stream's callbacks on hosts where CPAL owns that thread.
+    ///
+    /// When `None`, CPAL spawns a new thread itself.
+    ///
+    /// On WebAudio, output streams only run their callbacks on a spawned thread when the module
+    /// is built with shared memory (the `atomics` target feature) and the page is cross-origin
+    /// isolated, e.g. with a spawner starting a Web Worker; otherwise, and when `None`, they run
+    /// on the main thread.
+    pub thread_spawner: Option<Arc<dyn AudioThreadSpawner>>,
+    /// The name given to the thread running the stream's callbacks on hosts where CPAL owns that
+    /// thread.
+    ///
+    /// When `None`, the name is derived from the host and the direction of the stream (e.g.
+    /// `cpal_alsa_in` or `cpal_wasapi_out`). Building the stream fails with
+    /// [`BuildStreamError::InvalidArgument`] if the name contains a NUL byte.
+    pub thread_name: Option<String>,
+    /// Report [`StreamError::Stalled`] through the error callback when the data callback has not
+    /// been invoked for this many periods while the stream is playing.
+    ///
+    /// Currently supported on ALSA, WASAPI, OSS, PipeWire, PulseAudio and sndio. Only streams that
+    /// have been started with [`play`](traits::StreamTrait::play) are watched. `Some(0)` fails
+    /// with [`BuildStreamError::InvalidArgument`].
+    pub watchdog_periods: Option<u32>,
+    /// Whether the stream shares the device with other streams and applications.
+    ///
+    /// Unlike most other options, [`ShareMode::Exclusive`] is never ignored: hosts that can't
+    /// give a stream exclusive access to its device fail with
+    /// [`BuildStreamError::StreamConfigNotSupported`]. Android is the exception, see
+    /// [`ShareMode::Exclusive`].
+    pub share_mode: ShareMode,
+    /// Carry the given compressed bitstream to an external decoder (e.g. an AV receiver connected
+    /// over S/PDIF or HDMI) instead of PCM samples.
+    ///
+    /// The data callback of the output stream writes the bitstream already packed into IEC 61937
+    /// bursts, as [`SampleFormat::I16`] samples, and the stream config describes the IEC 60958 link
+    /// carrying them rather than the encoded audio: usually 2 channels at the sample rate of the
+    /// encoded audio, or at four times that rate for E-AC-3.
+    ///
+    /// Like [`ShareMode::Exclusive`] this is never ignored: hosts and devices that can't pass the
+    /// bitstream through fail with [`BuildStreamError::StreamConfigNotSupported`], as do input
+    /// streams. Supported on:
+    ///
+    /// - WASAPI, using the IEC 61937 wave formats. Many drivers only accept these in exclusive
+    ///   mode.
+    /// - ALSA, for `iec958` and `hdmi` devices, whose channel status is set to non-audio.
+    pub passthrough: Option<PassthroughFormat>,
+    /// Play DSD instead of PCM on an output stream, using the given [`DsdMode`].
+    ///
+    /// The data callback writes [`SampleFormat::U8`] samples, each holding eight consecutive
+    /// one-bit DSD samples of its channel with the earliest in the most significant bit. The
+    /// sample rate of the stream config is the rate of these bytes, i.e. an eighth of the DSD rate
+    /// (352 800 for DSD64).
+    ///
+    /// This is never ignored: hosts and devices that can't play DSD in the requested mode fail with
+    /// [`BuildStreamError::StreamConfigNotSupported`], as do input streams.
+    pub dsd: Option<DsdMode>,
+    /// Open only the given channels of the device, as zero-based indices, leaving the others
+    /// untouched. The `n`th channel of the stream is the device channel at index `n`, so there
+    /// must be as many distinct indices as the stream config has channels.
+    ///
+    /// This is never ignored: hosts that can't open a subset of a device's channels fail with
+    /// [`BuildStreamError::StreamConfigNotSupported`], and invalid indices with
+    /// [`BuildStreamError::InvalidArgument`]. Supported on:
+    ///
+    /// - ASIO, which only creates buffers for the given channels.
+    /// - JACK, where the stream's ports are connected to the given system ports. Requires
+    ///   automatic connection to be enabled.
+    /// - CoreAudio on macOS, which maps the stream's channels to the given channels of the
+    ///   device. With voice processing or more than 64 mapped channels, all of the device's
+    ///   channels are exchanged instead, the other output channels being silent.
+    pub device_channels: Option<Vec<ChannelCount>>,
+    /// What the stream is used for, letting the system choose its routing, volume and
+    /// processing accordingly.
+    ///
+    /// This is only a hint: when `None` or on hosts without an equivalent, the system's default
+    /// for the stream applies. Mapped to:
+    ///
+    /// - the audio category of the stream on WASAPI.
+    /// - the usage and content type of output streams, and the input preset of communications and
+    ///   speech input streams, on Android.
+    /// - the `media.role` of the stream on PipeWire and PulseAudio.
+    pub role: Option<StreamRole>,
+    /// The audio session the stream belongs to. Streams of the same session are grouped together
+    /// by the system, e.g. sharing a single volume control and mixer entry, and are otherwise
+    /// grouped per application or process.
+    ///
+    /// Only supported on WASAPI, where the ID is used as the GUID of the session, and on the AAudio
+    /// host of Android, where it is an audio session ID, e.g. from
+    /// `AudioManager.generateAudioSessionId` or the `session_id` of another stream. All other
+    /// hosts ignore it without failing: on iOS all streams of an application already share its
+    /// audio session, Oboe can only allocate new sessions, and ALSA, JACK, PipeWire, PulseAudio,
+    /// macOS and the web have no equivalent.
+    pub session: Option<SessionId>,
+    /// Whether the system may lower the volume of the stream while communications (e.g. a voice
+    /// call) are taking place. When `None`, the system's default applies.
+    ///
+    /// Supported on WASAPI, whose default is to duck streams. Ignored elsewhere.
+    pub ducking: Option<Ducking>,
+    /// Bypass the effects the system applies to the stream's audio, such as noise suppression or
+    /// loudness equalization, where the device allows it.
+    ///
+    /// This is only a hint. Mapped to raw mode on WASAPI, ignored elsewhere.
+    pub raw: bool,
+    /// Called with the [`StreamEvent`]s of the stream, such as changes to its volume made by the
+    /// user or by other applications.
+    ///
+    /// The callback may be called from any thread, including while the data callback runs.
+    /// Currently supported on WASAPI, which reports the events of the stream's audio session, and
+    /// on CoreAudio, which reports changes to the format of the stream's device on macOS, and
+    /// changes to the audio route and interruptions of the stream on iOS. JACK reports the
+    /// toggling of freewheel mode, Android the route changes that disconnect the stream, and ALSA
+    /// the streams resuming after a suspend of the system.
+    pub event_callback: Option<Arc<dyn Fn(StreamEvent) + Send + Sync>>,
+    /// Wake the thread running the stream's callbacks on a timer, four times per buffer, rather
+    /// than whenever the device signals that it is ready to exchange samples.
+    ///
+    /// Device-driven streams have lower latency and use less CPU, so this is only worth enabling
+    /// for drivers that signal unreliably. Supported on WASAPI, ignored elsewhere.
+    pub polling: bool,
+    /// Re-create the stream on its device, with the same config, when the device is invalidated,
+    /// e.g. by a driver update or a change of its format in the sound control panel, rather than
+    /// reporting [`StreamError::DeviceNotAvailable`].
+    ///
+    /// The stream resumes playing if it was playing. An error is only reported if the stream
+    /// can't be re-created, e.g. because the device was removed. Supported on WASAPI, ignored
+    /// elsewhere.
+    pub reactivate: bool,
+    /// Let the system convert the samples of the stream to and from the format the device is
+    /// mixed in, so that any PCM sample rate, channel count and sample format can be used rather
+    /// than only those of the device's default config.
+    ///
+    /// When `None`, building a stream with a config the device doesn't run at fails. Supported on
+    /// WASAPI for shared mode streams, ignored elsewhere.
+    pub conversion: Option<ConversionQuality>,
+    /// Have the audio hardware mix the stream rather than the CPU, which can then sleep through
+    /// long-form playback, e.g. of music on a laptop.
+    ///
+    /// Supported on WASAPI for shared mode output streams on devices whose hardware supports
+    /// offloading the stream's [`role`](StreamOptions::role), media when `None`. Building the
+    /// stream fails with [`BuildStreamError::StreamConfigNotSupported`] otherwise. Ignored
+    /// elsewhere.
+    pub offload: bool,
+    /// Process the stream for voice calls: cancel the echo of the audio played on the device from
+    /// the recorded audio, control the gain of the microphone and suppress noise.
+    ///
+    /// Supported on CoreAudio on macOS and iOS, which exchange the samples of the stream through
+    /// the voice-processing I/O unit and cancel the echo of the voice-processing output streams
+    /// of the application. Ignored elsewhere.
+    pub voice_processing: bool,
+    /// Keep the system from sleeping while idle for as long as the stream exists, e.g. so that long
+    /// recordings aren't cut short.
+    ///
+    /// Supported on CoreAudio on macOS, which also keeps App Nap from throttling the process.
+    /// Ignored elsewhere.
+    pub keep_awake: bool,
+    /// Split the buffer of the device into this many periods, the data callback being invoked
+    /// whenever a period has been played or recorded. With a [`BufferSize::Fixed`] config, the
+    /// fixed size is then that of a period rather than of the whole buffer, e.g. a buffer of two
+    /// periods of 64 frames for `BufferSize::Fixed(64)` and `Some(2)`.
+    ///
+    /// Devices grant the nearest period size and count they support. Supported on ALSA, whose
+    /// streams report what was granted through `Stream::period_size` and `Stream::buffer_size`.
+    /// Ignored elsewhere.
+    pub periods: Option<u32>,
+    /// Exchange samples by mapping the buffer of the device into memory, the data callback reading
+    /// from or writing to it directly instead of to an intermediate buffer copied by the driver.
+    ///
+    /// Supported on ALSA for devices that allow interleaved memory mapped access, others falling
+    /// back to reads and writes. Ignored elsewhere.
+    pub mmap: bool,
+    /// Properties of the stream, e.g. `("media.role", "Game")`, `("application.icon_name", ...)`
+    /// or `("node.latency", "256/48000")`, which the audio server and the session manager apply
+    /// their routing and policies by. They take precedence over those cpal sets, e.g. for
+    /// [`role`](Self::role).
+    ///
+    /// Supported on PipeWire, as properties of the node of the stream, and on PulseAudio, as its
+    /// proplist. Ignored elsewhere, including by the ALSA plugins of PipeWire and PulseAudio.
+    pub properties: Vec<(String, String)>,
+    /// Whether the stream favors low latency or low power consumption. When `None`, the system's
+    /// default applies.
+    ///
+    /// Supported on Android, where the mode AAudio granted is reported by the stream's
+    /// `performance_mode`, and on WebAudio, as the `latencyHint` of streams with the default
+    /// buffer size. Ignored elsewhere.
+    pub performance_mode: Option<PerformanceMode>,
+}
+
+// Not all hosts support all options.
+#[allow(dead_code)]
+impl StreamOptions {
+    /// The [device channels](Self::device_channels) to open for a stream with `channels` channels
+    /// on a device with `device_channel_count` channels, or `None` if all of them are opened.
+    pub(crate) fn checked_device_channels(
+        &self,
+        channels: ChannelCount,
+        device_channel_count: ChannelCount,
+    ) -> Result<Option<&[ChannelCount]>, BuildStreamError> {
+        let device_channels = match &self.device_channels {
+            Some(device_channels) => device_channels,
+            None => return Ok(None),
+        };
+        let in_range = device_channels.iter().all(|&c| c < device_channel_count);
+        let distinct = device_channels
+            .iter()
+            .enumerate()
+            .all(|(i, c)| !device_channels[..i].contains(c));
+        if device_channels.len() != channels as usize || !in_range || !distinct {
+            return Err(BuildStreamError::InvalidArgument);
+        }
+        Ok(Some(device_channels))
+    }
+
+    /// Whether the options only ask for a shared stream of PCM samples in all the channels of
+    /// the device, which is all the hosts going through a sound server or a plain device file can
+    /// open.
+    pub(crate) fn is_shared_pcm(&self) -> bool {
+        self.share_mode == ShareMode::Shared
+            && self.passthrough.is_none()
+            && self.dsd.is_none()
+            && self.device_channels.is_none()
+    }
+
+    /// How long the watchdog of a stream with `config` waits for its data callback, if requested,
+    /// counting periods of `default_period` frames unless the buffer size is fixed.
+    pub(crate) fn watchdog_timeout(
+        &self,
+        config: &StreamConfig,
+        default_period: FrameCount,
+    ) -> Option<Duration> {
+        let frames = match config.buffer_size {
+            BufferSize::Fixed(frames) => frames,
+            BufferSize::Default => default_period,
+        };
+        let period = Duration::from_secs_f64(frames as f64 / config.sample_rate.0 as f64);
+        self.watchdog_periods.map(|periods| period * periods)
+    }
+}
+
+impl fmt::Debug for StreamOptions {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("StreamOptions")
+            .field("thread_spawner", &self.thread_spawner.is_some())
+            .field("thread_name", &self.thread_name)
+            .field("watchdog_periods", &self.watchdog_periods)
+            .field("share_mode", &self.share_mode)
+            .field("passthrough", &self.passthrough)
+            .field("dsd", &self.dsd)
+            .field("device_channels", &self.device_channels)
+            .field("role", &self.role)
+            .field("session", &self.session)
+            .field("ducking", &self.ducking)
+            .field("raw", &self.raw)
+            .field("event_callback", &self.event_callback.is_some())
+            .field("polling", &self.polling)
+            .field("reactivate", &self.reactivate)
+            .field("conversion", &self.conversion)
+            .field("offload", &self.offload)
+            .field("voice_processing", &self.voice_processing)
+            .field("keep_awake", &self.keep_awake)
+            .field("periods", &self.periods)
+            .field("mmap", &self.mmap)
+            .field("properties", &self.properties)
+            .field("performance_mode", &self.performance_mode)
+            .finish()
+    }
+}
+
+/// Whether a stream shares its device with other streams and applications, set through
+/// [`StreamOptions::share_mode`].
+#[derive(Clone, Copy, Debug, Default, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ShareMode {
+    /// The stream is mixed with the other streams playing on, or recording from, the device. The
+    /// system may convert the stream's samples to and from the device's own format and rate.
+    #[default]
+    Shared,
+    /// The stream has exclusive access to the device and its samples are exchanged with the
+    /// device without being mixed or converted, which allows bit-perfect playback and
+    /// minimum-latency capture. No other application can use the device while the stream
+    /// exists.
+    ///
+    /// Supported on:
+    ///
+    /// - WASAPI, using exclusive mode. Loopback capture of output devices is not supported. The
+    ///   configs accepted by a device in exclusive mode are listed by its
+    ///   `supported_exclusive_configs`.
+    /// - CoreAudio on macOS, using hog mode. The device's sample rate is changed to that of the
+    ///   stream, and its physical format to the stream's format if the device offers it, until
+    ///   the stream is dropped.
+    /// - ALSA, by opening the `hw:` device directly. This is only possible for `hw:` and
+    ///   `plughw:` devices.
+    /// - Android, using the exclusive sharing mode of AAudio, which bypasses the mixer on devices
+    ///   with a low latency path. AAudio falls back to shared mode when the device can't give the
+    ///   stream exclusive access, which is reported by the stream's `share_mode` rather than
+    ///   failing.
+    Exclusive,
+}
+
+/// A compressed audio format passed through to an external decoder, set through
+/// [`StreamOptions::passthrough`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PassthroughFormat {
+    /// Dolby Digital.
+    Ac3,
+    /// Dolby Digital Plus.
+    Eac3,
+    /// DTS, without the DTS-HD extensions.
+    Dts,
+}
+
+/// How the DSD of a stream is delivered to the device, set through [`StreamOptions::dsd`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum DsdMode {
+    /// DSD over PCM: the DSD is packed into 24-bit PCM samples at a sixteenth of the DSD rate
+    /// (176.4 kHz for DSD64), which DACs supporting DoP unpack again.
+    ///
+    /// Supported on all hosts able to play `I32` at that rate. The samples must reach the device
+    /// unmodified, which usually requires [`ShareMode::Exclusive`].
+    DoP,
+    /// The DSD is handed to the driver as is.
+    ///
+    /// Supported on ALSA, for devices accepting the `DSD_U8` format.
+    Native,
+}
+
+/// The quality of the sample rate conversion done by the system, set through
+/// [`StreamOptions::conversion`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum ConversionQuality {
+    /// The cheapest conversion, which may alias audibly.
+    Low,
+    /// The conversion the system uses for its own streams, at a higher CPU cost.
+    Default,
+}
+
+/// What a stream is used for, set through [`StreamOptions::role`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum StreamRole {
+    /// Music, video soundtracks and other media playback.
+    Media,
+    /// Voice or video calls and other real-time communication.
+    Communications,
+    /// The sound effects of a game.
+    Game,
+    /// The background music of a game.
+    GameMedia,
+    /// Voice chat between the players of a game.
+    GameChat,
+    /// Film and other video soundtracks, as opposed to music.
+    Movie,
+    /// Speech recognition or synthesis, e.g. voice assistants and dictation.
+    Speech,
+    /// User interface sounds, such as clicks and key presses.
+    SoundEffects,
+    /// Alerts and notifications.
+    Alert,
+}
+
+/// Identifies an audio session grouping streams, set through [`StreamOptions::session`].
+///
+/// Applications usually pick a fixed, random ID, so that their sessions are recognized across
+/// runs.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub struct SessionId(pub u128);
+
+/// Whether the system may duck a stream, set through [`StreamOptions::ducking`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum Ducking {
+    /// The stream is attenuated while communications are taking place.
+    Allow,
+    /// The stream keeps its volume while communications are taking place.
+    OptOut,
+}
+
+/// What a stream favors, set through [`StreamOptions::performance_mode`].
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum PerformanceMode {
+    /// The lowest latency the device allows, e.g. for games and instruments, at the cost of
+    /// power consumption.
+    LowLatency,
+    /// Larger buffers that let the system sleep between callbacks, e.g. for background playback.
+    PowerSaving,
+}
+
+/// An event of a stream, reported through [`StreamOptions::event_callback`].
+#[non_exhaustive]
+#[derive(Clone, Debug, PartialEq)]
+pub enum StreamEvent {
+    /// The volume of the stream, or of the session it belongs to, changed.
+    VolumeChanged {
+        /// The new volume, from `0.0` (silent) to `1.0` (full volume).
+        volume: f32,
+        /// Whether the stream is muted, regardless of its volume.
+        muted: bool,
+    },
+    /// The format of the stream's device changed, e.g. because the user changed its sample rate.
+    /// A change of the sample rate away from the rate of the stream also disconnects the stream
+    /// with [`DisconnectReason::FormatChanged`].
+    FormatChanged {
+        /// The new format of the device's hardware.
+        format: HardwareFormat,
+    },
+    /// The device the audio of the stream is routed to or from changed, e.g. because headphones
+    /// were plugged in. The stream keeps running on the new route, except on Android, where a
+    /// [`StreamError::Disconnected`] follows and the stream must be rebuilt.
+    RouteChanged {
+        /// Why the route changed.
+        reason: RouteChangeReason,
+    },
+    /// The system stopped the stream for an interruption, e.g. a phone call or a timer going off.
+    ///
+    /// The stream can't be played until the interruption ends, after which it stays paused until
+    /// played again.
+    Interrupted,
+    /// The interruption which stopped the stream ended.
+    InterruptionEnded {
+        /// Whether the interruption suggests playing the stream again, which it doesn't after
+        /// e.g. the user started playing music in another application.
+        should_resume: bool,
+    },
+    /// The server entered or left freewheel mode, in which it runs the data callback as fast as
+    /// possible rather than in real time, e.g. to render a session to a file.
+    FreewheelChanged {
+        /// Whether the server is now in freewheel mode.
+        enabled: bool,
+    },
+    /// The stream was suspended, e.g. while the system was asleep or its USB device was
+    /// autosuspended, and has resumed. The samples in between were lost, but the stream doesn't
+    /// need to be rebuilt. Currently reported by ALSA.
+    Resumed,
+}
+
+/// Why the route of a stream changed, reported through [`StreamEvent::RouteChanged`].
+#[non_exhaustive]
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum RouteChangeReason {
+    /// A device became available, e.g. headphones were plugged in.
+    NewDeviceAvailable,
+    /// The device of the previous route became unavailable, e.g. headphones were unplugged.
+    ///
+    /// Playback should usually be paused, rather than continuing out of the speaker.
+    OldDeviceUnavailable,
+    /// The category of the application's audio session changed.
+    CategoryChanged,
+    /// The route was overridden, e.g. to the speaker.
+    Override,
+    /// The device woke from sleep.
+    WakeFromSleep,
+    /// No route is suitable for the category of the application's audio session.
+    NoSuitableRoute,
+    /// The configuration of the route changed, e.g. its selected input or output.
+    ConfigurationChanged,
+    /// The host didn't tell.
+    Unknown,
+}
+
+/// How the samples of a stream are exchanged with the hardware, retrieved via
+/// [`StreamTrait::signal_path`](traits::StreamTrait::signal_path).
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct SignalPath {
+    /// Whether the samples reach the hardware, or the data callback, unmodified: without being
+    /// mixed with other streams, resampled, converted to another sample format or processed by
+    /// system effects.
+    pub bit_perfect: bool,
+    /// The format the hardware was running at when the stream was built, if the host can tell.
+    pub hardware_format: Option<HardwareFormat>,
+}
+
+/// The format a device's hardware is running at.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub struct HardwareFormat {
+    pub channels: ChannelCount,
+    pub sample_rate: SampleRate,
+    /// `None` if the hardware uses a sample format with no [`SampleFormat`] equivalent, e.g.
+    /// packed 24-bit samples.
+    pub sample_format: Option<SampleFormat>,
+}
+
 /// Describes the minimum and maximum supported buffer size for the device
 #[derive(Clone, Debug, Eq, PartialEq)]
 pub enum SupportedBufferSize {
@@ -331,7 +901,7 @@ pub struct Data {
 /// | coreaudio | `mach_absolute_time` |
 /// | wasapi | `QueryPerformanceCounter` |
 /// | asio | `timeGetTime` |
-/// | emscripten | `AudioContext.getOutputTimestamp` |
+/// | emscripten, webaudio | `AudioContext.getOutputTimestamp`, else `AudioContext.currentTime` |
 #[derive(Copy, Clone, Debug, Eq, Hash, PartialEq, PartialOrd, Ord)]
 pub struct StreamInstant {
     secs: i64,
@@ -364,6 +934,7 @@ pub struct OutputStreamTimestamp {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct InputCallbackInfo {
     timestamp: InputStreamTimestamp,
+    transport: Option<Transport>,
 }
 
 /// Information relevant to a single call to the user's output stream data callback.
@@ -371,6 +942,54 @@ pub struct InputCallbackInfo {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct OutputCallbackInfo {
     timestamp: OutputStreamTimestamp,
+    transport: Option<Transport>,
+}
+
+/// The state and position of the transport shared by the clients of a host, for playing and
+/// recording in sync with them, retrieved via [`InputCallbackInfo::transport`] and
+/// [`OutputCallbackInfo::transport`].
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Transport {
+    pub state: TransportState,
+    /// The position of the first frame of the buffer, in frames.
+    pub frame: u64,
+    /// The musical position of the first frame of the buffer, if a client provides one.
+    pub bbt: Option<Bbt>,
+}
+
+// Hosts leave out the musical positions whose floats are not finite, keeping the callback infos
+// `Eq`.
+impl Eq for Transport {}
+
+/// Whether the transport is moving.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+#[non_exhaustive]
+pub enum TransportState {
+    Stopped,
+    Rolling,
+    /// Waiting for the clients to be ready to roll.
+    Starting,
+}
+
+/// A musical position in bars, beats and ticks, along with the time signature and tempo.
+///
+/// The positions reported by hosts only hold finite floats.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Bbt {
+    /// The bar, starting at 1.
+    pub bar: u32,
+    /// The beat within the bar, starting at 1.
+    pub beat: u32,
+    /// The tick within the beat, starting at 0.
+    pub tick: u32,
+    /// The number of ticks between the start of the transport and the first beat of the bar.
+    pub bar_start_tick: f64,
+    /// The numerator of the time signature.
+    pub beats_per_bar: f32,
+    /// The denominator of the time signature.
+    pub beat_type: f32,
+    pub ticks_per_beat: f64,
+    pub beats_per_minute: f64,
 }
 
 impl SupportedStreamConfig {
@@ -457,7 +1076,7 @@ impl StreamInstant {
     fn from_nanos(nanos: i64) -> Self {
         let secs = nanos / 1_000_000_000;
         let subsec_nanos = nanos - secs * 1_000_000_000;
-        Self::new(secs as i64, subsec_nanos as u32)
+        Self::new(secs, subsec_nanos as u32)
     }
 
     #[allow(dead_code)]
@@ -489,6 +1108,11 @@ impl InputCallbackInfo {
     pub fn timestamp(&self) -> InputStreamTimestamp {
         self.timestamp
     }
+
+    /// The transport at the start of the buffer, if the host has one (currently only JACK).
+    pub fn transport(&self) -> Option<Transport> {
+        self.transport
+    }
 }
 
 impl OutputCallbackInfo {
@@ -496,6 +1120,11 @@ impl OutputCallbackInfo {
     pub fn timestamp(&self) -> OutputStreamTimestamp {
         self.timestamp
     }
+
+    /// The transport at the start of the buffer, if the host has one (currently only JACK).
+    pub fn transport(&self) -> Option<Transport> {
+        self.transport
+    }
 }
 
 #[allow(clippy::len_without_is_empty)]
@@ -729,7 +1358,7 @@ impl SupportedStreamConfigRange {
 
 #[test]
 fn test_cmp_default_heuristics() {
-    let mut formats = vec![
+    let mut formats = [
         SupportedStreamConfigRange {
             buffer_size: SupportedBufferSize::Range { min: 256, max: 512 },
             channels: 2,
@@ -856,3 +1485,33 @@ fn test_stream_instant() {
     );
     assert_eq!(max.add(Duration::from_secs(1)), None);
 }
+
+#[test]
+fn test_checked_device_channels() {
+    let options = |device_channels: Option<Vec<ChannelCount>>| StreamOptions {
+        device_channels,
+        ..Default::default()
+    };
+    assert_eq!(options(None).checked_device_channels(2, 8).unwrap(), None);
+    assert_eq!(
+        options(Some(vec![5, 1]))
+            .checked_device_channels(2, 8)
+            .unwrap(),
+        Some(&[5, 1][..])
+    );
+    // Out of range.
+    assert!(matches!(
+        options(Some(vec![0, 8])).checked_device_channels(2, 8),
+        Err(BuildStreamError::InvalidArgument)
+    ));
+    // Duplicated.
+    assert!(matches!(
+        options(Some(vec![3, 3])).checked_device_channels(2, 8),
+        Err(BuildStreamError::InvalidArgument)
+    ));
+    // Not one per channel of the stream.
+    assert!(matches!(
+        options(Some(vec![0, 1, 2])).checked_device_channels(2, 8),
+        Err(BuildStreamError::InvalidArgument)
+    ));
+}
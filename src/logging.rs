@@ -0,0 +1,37 @@
+//! Internal logging macros forwarding to the `log` crate when the `log` feature is enabled.
+//!
+//! Without the feature the arguments are only type-checked and never evaluated. These macros must
+//! never be used on the real-time audio path.
+
+macro_rules! log_warn {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::warn!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+macro_rules! log_info {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::info!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
+
+macro_rules! log_debug {
+    ($($arg:tt)+) => {{
+        #[cfg(feature = "log")]
+        ::log::debug!($($arg)+);
+        #[cfg(not(feature = "log"))]
+        if false {
+            let _ = format_args!($($arg)+);
+        }
+    }};
+}
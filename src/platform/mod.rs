@@ -324,6 +324,10 @@ macro_rules! impl_platform_host {
                         }
                     )*
                 }
+                .map_err(|err| {
+                    log_warn!("failed to query supported input configs: {}", err);
+                    err
+                })
             }
 
             fn supported_output_configs(&self) -> Result<Self::SupportedOutputConfigs, crate::SupportedStreamConfigsError> {
@@ -337,24 +341,38 @@ macro_rules! impl_platform_host {
                         }
                     )*
                 }
+                .map_err(|err| {
+                    log_warn!("failed to query supported output configs: {}", err);
+                    err
+                })
             }
 
             fn default_input_config(&self) -> Result<crate::SupportedStreamConfig, crate::DefaultStreamConfigError> {
-                match self.0 {
+                let result = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => d.default_input_config(),
                     )*
+                };
+                match result {
+                    Ok(ref config) => log_debug!("default input config: {:?}", config),
+                    Err(ref err) => log_warn!("failed to query the default input config: {}", err),
                 }
+                result
             }
 
             fn default_output_config(&self) -> Result<crate::SupportedStreamConfig, crate::DefaultStreamConfigError> {
-                match self.0 {
+                let result = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => d.default_output_config(),
                     )*
+                };
+                match result {
+                    Ok(ref config) => log_debug!("default output config: {:?}", config),
+                    Err(ref err) => log_warn!("failed to query the default output config: {}", err),
                 }
+                result
             }
 
             fn build_input_stream_raw<D, E>(
@@ -369,7 +387,8 @@ macro_rules! impl_platform_host {
                 D: FnMut(&crate::Data, &crate::InputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
-                match self.0 {
+                log_debug!("building input stream: {:?}, {:?}", config, sample_format);
+                let result = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => d
@@ -377,13 +396,15 @@ macro_rules! impl_platform_host {
                                 config,
                                 sample_format,
                                 data_callback,
-                                error_callback,
+                                crate::platform::log_stream_errors(error_callback),
                                 timeout,
                             )
                             .map(StreamInner::$HostVariant)
                             .map(Stream::from),
                     )*
-                }
+                };
+                crate::platform::log_build_stream_result(&result);
+                result
             }
 
             fn build_output_stream_raw<D, E>(
@@ -398,7 +419,8 @@ macro_rules! impl_platform_host {
                 D: FnMut(&mut crate::Data, &crate::OutputCallbackInfo) + Send + 'static,
                 E: FnMut(crate::StreamError) + Send + 'static,
             {
-                match self.0 {
+                log_debug!("building output stream: {:?}, {:?}", config, sample_format);
+                let result = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         DeviceInner::$HostVariant(ref d) => d
@@ -406,13 +428,93 @@ macro_rules! impl_platform_host {
                                 config,
                                 sample_format,
                                 data_callback,
-                                error_callback,
+                                crate::platform::log_stream_errors(error_callback),
                                 timeout,
                             )
                             .map(StreamInner::$HostVariant)
                             .map(Stream::from),
                     )*
-                }
+                };
+                crate::platform::log_build_stream_result(&result);
+                result
+            }
+
+            fn build_input_stream_raw_with_options<D, E>(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+                options: &crate::StreamOptions,
+                data_callback: D,
+                error_callback: E,
+                timeout: Option<std::time::Duration>,
+            ) -> Result<Self::Stream, crate::BuildStreamError>
+            where
+                D: FnMut(&crate::Data, &crate::InputCallbackInfo) + Send + 'static,
+                E: FnMut(crate::StreamError) + Send + 'static,
+            {
+                log_debug!(
+                    "building input stream: {:?}, {:?}, {:?}",
+                    config,
+                    sample_format,
+                    options,
+                );
+                let result = match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_input_stream_raw_with_options(
+                                config,
+                                sample_format,
+                                options,
+                                data_callback,
+                                crate::platform::log_stream_errors(error_callback),
+                                timeout,
+                            )
+                            .map(StreamInner::$HostVariant)
+                            .map(Stream::from),
+                    )*
+                };
+                crate::platform::log_build_stream_result(&result);
+                result
+            }
+
+            fn build_output_stream_raw_with_options<D, E>(
+                &self,
+                config: &crate::StreamConfig,
+                sample_format: crate::SampleFormat,
+                options: &crate::StreamOptions,
+                data_callback: D,
+                error_callback: E,
+                timeout: Option<std::time::Duration>,
+            ) -> Result<Self::Stream, crate::BuildStreamError>
+            where
+                D: FnMut(&mut crate::Data, &crate::OutputCallbackInfo) + Send + 'static,
+                E: FnMut(crate::StreamError) + Send + 'static,
+            {
+                log_debug!(
+                    "building output stream: {:?}, {:?}, {:?}",
+                    config,
+                    sample_format,
+                    options,
+                );
+                let result = match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        DeviceInner::$HostVariant(ref d) => d
+                            .build_output_stream_raw_with_options(
+                                config,
+                                sample_format,
+                                options,
+                                data_callback,
+                                crate::platform::log_stream_errors(error_callback),
+                                timeout,
+                            )
+                            .map(StreamInner::$HostVariant)
+                            .map(Stream::from),
+                    )*
+                };
+                crate::platform::log_build_stream_result(&result);
+                result
             }
         }
 
@@ -437,33 +539,46 @@ macro_rules! impl_platform_host {
                         }
                     )*
                 }
+                .map_err(|err| {
+                    log_warn!("failed to enumerate {} devices: {}", self.id().name(), err);
+                    err
+                })
             }
 
             fn default_input_device(&self) -> Option<Self::Device> {
-                match self.0 {
+                let device = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         HostInner::$HostVariant(ref h) => {
                             h.default_input_device().map(DeviceInner::$HostVariant).map(Device::from)
                         }
                     )*
+                };
+                if device.is_none() {
+                    log_debug!("no default input device on {}", self.id().name());
                 }
+                device
             }
 
             fn default_output_device(&self) -> Option<Self::Device> {
-                match self.0 {
+                let device = match self.0 {
                     $(
                         $(#[cfg($feat)])?
                         HostInner::$HostVariant(ref h) => {
                             h.default_output_device().map(DeviceInner::$HostVariant).map(Device::from)
                         }
                     )*
+                };
+                if device.is_none() {
+                    log_debug!("no default output device on {}", self.id().name());
                 }
+                device
             }
         }
 
         impl crate::traits::StreamTrait for Stream {
             fn play(&self) -> Result<(), crate::PlayStreamError> {
+                log_debug!("play stream");
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -472,9 +587,14 @@ macro_rules! impl_platform_host {
                         }
                     )*
                 }
+                .map_err(|err| {
+                    log_warn!("failed to play stream: {}", err);
+                    err
+                })
             }
 
             fn pause(&self) -> Result<(), crate::PauseStreamError> {
+                log_debug!("pause stream");
                 match self.0 {
                     $(
                         $(#[cfg($feat)])?
@@ -483,6 +603,43 @@ macro_rules! impl_platform_host {
                         }
                     )*
                 }
+                .map_err(|err| {
+                    log_warn!("failed to pause stream: {}", err);
+                    err
+                })
+            }
+
+            fn stats(&self) -> Option<crate::StreamStats> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.stats()
+                        }
+                    )*
+                }
+            }
+
+            fn signal_path(&self) -> Option<crate::SignalPath> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.signal_path()
+                        }
+                    )*
+                }
+            }
+
+            fn latency(&self) -> Option<std::time::Duration> {
+                match self.0 {
+                    $(
+                        $(#[cfg($feat)])?
+                        StreamInner::$HostVariant(ref s) => {
+                            s.latency()
+                        }
+                    )*
+                }
             }
         }
 
@@ -554,7 +711,7 @@ macro_rules! impl_platform_host {
 
         /// Given a unique host identifier, initialise and produce the host if it is available.
         pub fn host_from_id(id: HostId) -> Result<Host, crate::HostUnavailable> {
-            match id {
+            let result = match id {
                 $(
                     $(#[cfg($feat)])?
                     HostId::$HostVariant => {
@@ -563,12 +720,72 @@ macro_rules! impl_platform_host {
                             .map(Host::from)
                     }
                 )*
+            };
+            match result {
+                Ok(_) => log_debug!("initialised host {}", id.name()),
+                Err(_) => log_warn!("host {} is unavailable", id.name()),
             }
+            result
         }
     };
 }
 
-// TODO: Add pulseaudio and jack here eventually.
+fn log_build_stream_result<S>(result: &Result<S, crate::BuildStreamError>) {
+    match result {
+        Ok(_) => log_info!("built stream"),
+        Err(err) => log_warn!("failed to build stream: {}", err),
+    }
+}
+
+/// Logs the errors reported to `error_callback`.
+///
+/// Error callbacks often run on the audio thread, so the errors are logged by a thread of their
+/// own. They aren't logged where that thread can't be spawned, e.g. on the web.
+#[cfg(feature = "log")]
+fn log_stream_errors<E>(mut error_callback: E) -> impl FnMut(crate::StreamError) + Send + 'static
+where
+    E: FnMut(crate::StreamError) + Send + 'static,
+{
+    use crate::StreamError;
+    use std::sync::{mpsc, Mutex, OnceLock};
+
+    static ERRORS: OnceLock<Option<Mutex<mpsc::Sender<StreamError>>>> = OnceLock::new();
+    let errors = ERRORS
+        .get_or_init(|| {
+            let (sender, receiver) = mpsc::channel::<StreamError>();
+            std::thread::Builder::new()
+                .name("cpal_log".to_owned())
+                .spawn(move || {
+                    for err in receiver {
+                        log_warn!("stream error: {}", err);
+                    }
+                })
+                .ok()
+                .map(|_| Mutex::new(sender))
+        })
+        .as_ref()
+        .map(|errors| errors.lock().unwrap().clone());
+    move |err| {
+        if let Some(ref errors) = errors {
+            let copy = match err {
+                StreamError::DeviceNotAvailable => StreamError::DeviceNotAvailable,
+                StreamError::Stalled => StreamError::Stalled,
+                StreamError::Disconnected { reason } => StreamError::Disconnected { reason },
+                StreamError::BackendSpecific { ref err } => {
+                    StreamError::BackendSpecific { err: err.clone() }
+                }
+            };
+            let _ = errors.send(copy);
+        }
+        error_callback(err)
+    }
+}
+
+#[cfg(not(feature = "log"))]
+fn log_stream_errors<E>(error_callback: E) -> E {
+    error_callback
+}
+
 #[cfg(any(
     target_os = "linux",
     target_os = "dragonfly",
@@ -576,6 +793,9 @@ macro_rules! impl_platform_host {
     target_os = "netbsd"
 ))]
 mod platform_impl {
+    pub use crate::host::alsa::{
+        ChannelPosition as AlsaChannelPosition, Enumeration as AlsaEnumeration,
+    };
     pub use crate::host::alsa::{
         Device as AlsaDevice, Devices as AlsaDevices, Host as AlsaHost, Stream as AlsaStream,
         SupportedInputConfigs as AlsaSupportedInputConfigs,
@@ -583,28 +803,106 @@ mod platform_impl {
     };
     #[cfg(feature = "jack")]
     pub use crate::host::jack::{
-        Device as JackDevice, Devices as JackDevices, Host as JackHost, Stream as JackStream,
+        Connections as JackConnections, Device as JackDevice, Devices as JackDevices,
+        Host as JackHost, ProcessCallback as JackProcessCallback, Stream as JackStream,
         SupportedInputConfigs as JackSupportedInputConfigs,
         SupportedOutputConfigs as JackSupportedOutputConfigs,
     };
 
-    impl_platform_host!(#[cfg(feature = "jack")] Jack jack "JACK", Alsa alsa "ALSA");
+    #[cfg(any(target_os = "dragonfly", target_os = "freebsd"))]
+    pub use crate::host::oss::{
+        Device as OssDevice, Devices as OssDevices, Host as OssHost, Stream as OssStream,
+        SupportedInputConfigs as OssSupportedInputConfigs,
+        SupportedOutputConfigs as OssSupportedOutputConfigs,
+    };
+
+    #[cfg(feature = "pipewire")]
+    pub use crate::host::pipewire::{
+        Device as PipeWireDevice, Devices as PipeWireDevices, Host as PipeWireHost,
+        Stream as PipeWireStream, SupportedInputConfigs as PipeWireSupportedInputConfigs,
+        SupportedOutputConfigs as PipeWireSupportedOutputConfigs,
+    };
+
+    #[cfg(feature = "pulseaudio")]
+    pub use crate::host::pulseaudio::{
+        Device as PulseAudioDevice, Devices as PulseAudioDevices, Host as PulseAudioHost,
+        Stream as PulseAudioStream, SupportedInputConfigs as PulseAudioSupportedInputConfigs,
+        SupportedOutputConfigs as PulseAudioSupportedOutputConfigs,
+    };
+
+    #[cfg(feature = "sndio")]
+    pub use crate::host::sndio::{
+        Device as SndioDevice, Devices as SndioDevices, Host as SndioHost, Stream as SndioStream,
+        SupportedInputConfigs as SndioSupportedInputConfigs,
+        SupportedOutputConfigs as SndioSupportedOutputConfigs,
+    };
+
+    impl_platform_host!(
+        #[cfg(feature = "pipewire")] PipeWire pipewire "PipeWire",
+        #[cfg(feature = "pulseaudio")] PulseAudio pulseaudio "PulseAudio",
+        #[cfg(feature = "jack")] Jack jack "JACK",
+        #[cfg(feature = "sndio")] Sndio sndio "sndio",
+        #[cfg(any(target_os = "dragonfly", target_os = "freebsd"))] Oss oss "OSS",
+        Alsa alsa "ALSA"
+    );
 
     /// The default host for the current compilation target platform.
+    ///
+    /// The first of the sound servers that is running, among those whose feature is enabled:
+    /// PipeWire, which also serves JACK and PulseAudio clients, then JACK, then PulseAudio.
+    /// Falls back to OSS on FreeBSD and DragonFly, whose native API it is, and to ALSA elsewhere.
     pub fn default_host() -> Host {
-        AlsaHost::new()
-            .expect("the default host should always be available")
+        #[cfg(feature = "pipewire")]
+        if <PipeWireHost as crate::traits::HostTrait>::is_available() {
+            if let Ok(host) = PipeWireHost::new() {
+                return host.into();
+            }
+        }
+        #[cfg(feature = "jack")]
+        if crate::host::jack::is_server_running() {
+            if let Ok(host) = JackHost::new() {
+                return host.into();
+            }
+        }
+        #[cfg(feature = "pulseaudio")]
+        if <PulseAudioHost as crate::traits::HostTrait>::is_available() {
+            if let Ok(host) = PulseAudioHost::new() {
+                return host.into();
+            }
+        }
+        #[cfg(any(target_os = "dragonfly", target_os = "freebsd"))]
+        let host = OssHost::new();
+        #[cfg(not(any(target_os = "dragonfly", target_os = "freebsd")))]
+        let host = AlsaHost::new();
+        host.expect("the default host should always be available")
             .into()
     }
 }
 
-#[cfg(any(target_os = "macos", target_os = "ios"))]
+#[cfg(any(
+    target_os = "macos",
+    target_os = "ios",
+    target_os = "tvos",
+    target_os = "watchos"
+))]
 mod platform_impl {
+    #[cfg(target_os = "macos")]
+    pub use crate::host::coreaudio::{
+        AggregateDeviceConfig as CoreAudioAggregateDeviceConfig,
+        ChannelInfo as CoreAudioChannelInfo, ChannelLabel as CoreAudioChannelLabel,
+        TapProcesses as CoreAudioTapProcesses, Workgroup as CoreAudioWorkgroup,
+        WorkgroupMembership as CoreAudioWorkgroupMembership,
+    };
     pub use crate::host::coreaudio::{
         Device as CoreAudioDevice, Devices as CoreAudioDevices, Host as CoreAudioHost,
         Stream as CoreAudioStream, SupportedInputConfigs as CoreAudioSupportedInputConfigs,
         SupportedOutputConfigs as CoreAudioSupportedOutputConfigs,
     };
+    #[cfg(any(target_os = "ios", target_os = "tvos", target_os = "watchos"))]
+    pub use crate::host::coreaudio::{
+        SessionCategory as CoreAudioSessionCategory, SessionConfig as CoreAudioSessionConfig,
+        SessionMode as CoreAudioSessionMode,
+    };
 
     impl_platform_host!(CoreAudio coreaudio "CoreAudio");
 
@@ -619,8 +917,9 @@ mod platform_impl {
 #[cfg(target_os = "emscripten")]
 mod platform_impl {
     pub use crate::host::emscripten::{
-        Device as EmscriptenDevice, Devices as EmscriptenDevices, Host as EmscriptenHost,
-        Stream as EmscriptenStream, SupportedInputConfigs as EmscriptenSupportedInputConfigs,
+        Device as EmscriptenDevice, DeviceWatch as EmscriptenDeviceWatch,
+        Devices as EmscriptenDevices, Host as EmscriptenHost, Stream as EmscriptenStream,
+        SupportedInputConfigs as EmscriptenSupportedInputConfigs,
         SupportedOutputConfigs as EmscriptenSupportedOutputConfigs,
     };
 
@@ -634,11 +933,16 @@ mod platform_impl {
     }
 }
 
-#[cfg(all(target_arch = "wasm32", feature = "wasm-bindgen"))]
+#[cfg(all(
+    target_arch = "wasm32",
+    not(target_os = "emscripten"),
+    feature = "wasm-bindgen"
+))]
 mod platform_impl {
     pub use crate::host::webaudio::{
-        Device as WebAudioDevice, Devices as WebAudioDevices, Host as WebAudioHost,
-        Stream as WebAudioStream, SupportedInputConfigs as WebAudioSupportedInputConfigs,
+        Device as WebAudioDevice, DeviceWatch as WebAudioDeviceWatch, Devices as WebAudioDevices,
+        Host as WebAudioHost, Stream as WebAudioStream,
+        SupportedInputConfigs as WebAudioSupportedInputConfigs,
         SupportedOutputConfigs as WebAudioSupportedOutputConfigs,
     };
 
@@ -652,21 +956,53 @@ mod platform_impl {
     }
 }
 
+#[cfg(all(target_os = "wasi", feature = "wasi"))]
+mod platform_impl {
+    pub use crate::host::wasi::{
+        Device as WasiDevice, Devices as WasiDevices, Host as WasiHost, Stream as WasiStream,
+        SupportedInputConfigs as WasiSupportedInputConfigs,
+        SupportedOutputConfigs as WasiSupportedOutputConfigs,
+    };
+
+    impl_platform_host!(Wasi wasi "WASI");
+
+    /// The default host for the current compilation target platform.
+    pub fn default_host() -> Host {
+        WasiHost::new()
+            .expect("the default host should always be available")
+            .into()
+    }
+}
+
 #[cfg(windows)]
 mod platform_impl {
     #[cfg(feature = "asio")]
     pub use crate::host::asio::{
-        Device as AsioDevice, Devices as AsioDevices, Host as AsioHost, Stream as AsioStream,
+        Device as AsioDevice, Devices as AsioDevices, Host as AsioHost,
+        LoadDriverError as AsioLoadDriverError, Stream as AsioStream,
         SupportedInputConfigs as AsioSupportedInputConfigs,
         SupportedOutputConfigs as AsioSupportedOutputConfigs,
     };
+    #[cfg(feature = "audiograph")]
+    pub use crate::host::audiograph::{
+        Device as AudioGraphDevice, Devices as AudioGraphDevices, Host as AudioGraphHost,
+        Stream as AudioGraphStream, SupportedInputConfigs as AudioGraphSupportedInputConfigs,
+        SupportedOutputConfigs as AudioGraphSupportedOutputConfigs,
+    };
     pub use crate::host::wasapi::{
-        Device as WasapiDevice, Devices as WasapiDevices, Host as WasapiHost,
+        Device as WasapiDevice, DevicePeriods as WasapiDevicePeriods, Devices as WasapiDevices,
+        DriverInfo as WasapiDriverInfo, EnginePeriods as WasapiEnginePeriods, Host as WasapiHost,
+        ProcessLoopbackMode as WasapiProcessLoopbackMode, SpatialConfig as WasapiSpatialConfig,
+        SpatialObject as WasapiSpatialObject, SpatialStream as WasapiSpatialStream,
         Stream as WasapiStream, SupportedInputConfigs as WasapiSupportedInputConfigs,
         SupportedOutputConfigs as WasapiSupportedOutputConfigs,
     };
 
-    impl_platform_host!(#[cfg(feature = "asio")] Asio asio "ASIO", Wasapi wasapi "WASAPI");
+    impl_platform_host!(
+        #[cfg(feature = "asio")] Asio asio "ASIO",
+        #[cfg(feature = "audiograph")] AudioGraph audiograph "AudioGraph",
+        Wasapi wasapi "WASAPI"
+    );
 
     /// The default host for the current compilation target platform.
     pub fn default_host() -> Host {
@@ -676,19 +1012,59 @@ mod platform_impl {
     }
 }
 
-#[cfg(target_os = "android")]
+#[cfg(all(target_os = "android", any(feature = "aaudio", feature = "oboe")))]
 mod platform_impl {
+    #[cfg(feature = "aaudio")]
+    pub use crate::host::aaudio::InputPreset as AAudioInputPreset;
+    #[cfg(feature = "aaudio")]
+    pub use crate::host::aaudio::{
+        Device as AAudioDevice, Devices as AAudioDevices, Host as AAudioHost,
+        Stream as AAudioStream, SupportedInputConfigs as AAudioSupportedInputConfigs,
+        SupportedOutputConfigs as AAudioSupportedOutputConfigs,
+    };
+    #[cfg(feature = "oboe")]
+    pub use crate::host::oboe::{
+        AudioFocus as OboeAudioFocus, ContentType as OboeContentType, DeviceType as OboeDeviceType,
+        InputPreset as OboeInputPreset, Usage as OboeUsage,
+    };
+    #[cfg(feature = "oboe")]
     pub use crate::host::oboe::{
         Device as OboeDevice, Devices as OboeDevices, Host as OboeHost, Stream as OboeStream,
         SupportedInputConfigs as OboeSupportedInputConfigs,
         SupportedOutputConfigs as OboeSupportedOutputConfigs,
     };
 
-    impl_platform_host!(Oboe oboe "Oboe");
+    impl_platform_host!(
+        #[cfg(feature = "oboe")] Oboe oboe "Oboe",
+        #[cfg(feature = "aaudio")] AAudio aaudio "AAudio"
+    );
+
+    /// The default host for the current compilation target platform.
+    ///
+    /// Oboe, unless only the `aaudio` feature is enabled.
+    pub fn default_host() -> Host {
+        #[cfg(feature = "oboe")]
+        let host = OboeHost::new();
+        #[cfg(not(feature = "oboe"))]
+        let host = AAudioHost::new();
+        host.expect("the default host should always be available")
+            .into()
+    }
+}
+
+#[cfg(target_os = "openbsd")]
+mod platform_impl {
+    pub use crate::host::sndio::{
+        Device as SndioDevice, Devices as SndioDevices, Host as SndioHost, Stream as SndioStream,
+        SupportedInputConfigs as SndioSupportedInputConfigs,
+        SupportedOutputConfigs as SndioSupportedOutputConfigs,
+    };
+
+    impl_platform_host!(Sndio sndio "sndio");
 
     /// The default host for the current compilation target platform.
     pub fn default_host() -> Host {
-        OboeHost::new()
+        SndioHost::new()
             .expect("the default host should always be available")
             .into()
     }
@@ -700,11 +1076,15 @@ mod platform_impl {
     target_os = "dragonfly",
     target_os = "freebsd",
     target_os = "netbsd",
+    target_os = "openbsd",
     target_os = "macos",
     target_os = "ios",
+    target_os = "tvos",
+    target_os = "watchos",
     target_os = "emscripten",
-    target_os = "android",
+    all(target_os = "android", any(feature = "aaudio", feature = "oboe")),
     all(target_arch = "wasm32", feature = "wasm-bindgen"),
+    all(target_os = "wasi", feature = "wasi"),
 )))]
 mod platform_impl {
     pub use crate::host::null::{
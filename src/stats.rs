@@ -0,0 +1,179 @@
+//! Runtime statistics of streams.
+
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{Duration, Instant};
+
+use crate::SampleRate;
+
+/// Runtime statistics of a stream, retrieved via [`StreamTrait::stats`].
+///
+/// [`StreamTrait::stats`]: crate::traits::StreamTrait::stats
+#[derive(Clone, Copy, Debug, Default, Eq, PartialEq)]
+pub struct StreamStats {
+    /// The number of times the data callback has been invoked.
+    pub callbacks: u64,
+    /// The total number of frames delivered to or requested from the data callback.
+    pub frames: u64,
+    /// How long the most recent invocation of the data callback took.
+    pub last_callback_duration: Duration,
+    /// How long the slowest invocation of the data callback took.
+    pub max_callback_duration: Duration,
+    /// The largest difference between the time elapsed from one invocation of the data callback
+    /// to the next and the duration of the audio processed by the former.
+    pub max_jitter: Duration,
+    /// The number of buffer underruns (output streams) or overruns (input streams) reported by
+    /// the host.
+    pub xruns: u64,
+}
+
+/// Collects [`StreamStats`] on the audio thread so that they can be read from any other thread.
+pub(crate) struct StreamStatsCollector {
+    origin: Instant,
+    callbacks: AtomicU64,
+    frames: AtomicU64,
+    last_callback_nanos: AtomicU64,
+    max_callback_nanos: AtomicU64,
+    max_jitter_nanos: AtomicU64,
+    xruns: AtomicU64,
+    // Start of and duration of audio processed by the previous callback, zero if none yet.
+    prev_start_nanos: AtomicU64,
+    prev_audio_nanos: AtomicU64,
+}
+
+impl StreamStatsCollector {
+    pub(crate) fn new() -> Self {
+        StreamStatsCollector {
+            origin: Instant::now(),
+            callbacks: AtomicU64::new(0),
+            frames: AtomicU64::new(0),
+            last_callback_nanos: AtomicU64::new(0),
+            max_callback_nanos: AtomicU64::new(0),
+            max_jitter_nanos: AtomicU64::new(0),
+            xruns: AtomicU64::new(0),
+            prev_start_nanos: AtomicU64::new(0),
+            prev_audio_nanos: AtomicU64::new(0),
+        }
+    }
+
+    /// Record an invocation of the data callback that started at `start`, processing `frames`.
+    pub(crate) fn record_callback(&self, start: Instant, frames: usize, sample_rate: SampleRate) {
+        let end = Instant::now();
+        let callback_nanos = nanos(end.duration_since(start));
+        let start_nanos = nanos(start.duration_since(self.origin)).max(1);
+        let audio_nanos = frames as u64 * 1_000_000_000 / sample_rate.0.max(1) as u64;
+
+        let prev_start_nanos = self.prev_start_nanos.load(Ordering::Relaxed);
+        if prev_start_nanos != 0 {
+            let interval = start_nanos.saturating_sub(prev_start_nanos);
+            let expected = self.prev_audio_nanos.load(Ordering::Relaxed);
+            self.max_jitter_nanos
+                .fetch_max(interval.abs_diff(expected), Ordering::Relaxed);
+        }
+        self.prev_start_nanos.store(start_nanos, Ordering::Relaxed);
+        self.prev_audio_nanos.store(audio_nanos, Ordering::Relaxed);
+
+        self.callbacks.fetch_add(1, Ordering::Relaxed);
+        self.frames.fetch_add(frames as u64, Ordering::Relaxed);
+        self.last_callback_nanos
+            .store(callback_nanos, Ordering::Relaxed);
+        self.max_callback_nanos
+            .fetch_max(callback_nanos, Ordering::Relaxed);
+    }
+
+    /// Record a buffer underrun or overrun.
+    pub(crate) fn record_xrun(&self) {
+        self.xruns.fetch_add(1, Ordering::Relaxed);
+        // The next interval between callbacks is not meaningful.
+        self.prev_start_nanos.store(0, Ordering::Relaxed);
+    }
+
+    /// Forget the previous callback, e.g. because the stream has been paused.
+    pub(crate) fn restart(&self) {
+        self.prev_start_nanos.store(0, Ordering::Relaxed);
+    }
+
+    pub(crate) fn snapshot(&self) -> StreamStats {
+        StreamStats {
+            callbacks: self.callbacks.load(Ordering::Relaxed),
+            frames: self.frames.load(Ordering::Relaxed),
+            last_callback_duration: Duration::from_nanos(
+                self.last_callback_nanos.load(Ordering::Relaxed),
+            ),
+            max_callback_duration: Duration::from_nanos(
+                self.max_callback_nanos.load(Ordering::Relaxed),
+            ),
+            max_jitter: Duration::from_nanos(self.max_jitter_nanos.load(Ordering::Relaxed)),
+            xruns: self.xruns.load(Ordering::Relaxed),
+        }
+    }
+}
+
+fn nanos(duration: Duration) -> u64 {
+    duration.as_nanos().try_into().unwrap_or(u64::MAX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::StreamStatsCollector;
+    use crate::SampleRate;
+    use std::time::{Duration, Instant};
+
+    const RATE: SampleRate = SampleRate(1000);
+
+    #[test]
+    fn counts_callbacks_and_frames() {
+        let stats = StreamStatsCollector::new();
+        assert_eq!(stats.snapshot(), Default::default());
+        stats.record_callback(Instant::now(), 10, RATE);
+        stats.record_callback(Instant::now(), 20, RATE);
+        let snapshot = stats.snapshot();
+        assert_eq!(snapshot.callbacks, 2);
+        assert_eq!(snapshot.frames, 30);
+        assert_eq!(snapshot.xruns, 0);
+    }
+
+    #[test]
+    fn counts_xruns() {
+        let stats = StreamStatsCollector::new();
+        stats.record_xrun();
+        stats.record_xrun();
+        assert_eq!(stats.snapshot().xruns, 2);
+        assert_eq!(stats.snapshot().callbacks, 0);
+    }
+
+    #[test]
+    fn tracks_last_and_max_durations() {
+        let stats = StreamStatsCollector::new();
+        let now = Instant::now();
+        stats.record_callback(now - Duration::from_millis(50), 10, RATE);
+        let slow = stats.snapshot();
+        assert!(slow.last_callback_duration >= Duration::from_millis(50));
+        assert_eq!(slow.max_callback_duration, slow.last_callback_duration);
+
+        stats.record_callback(Instant::now(), 10, RATE);
+        let fast = stats.snapshot();
+        assert!(fast.last_callback_duration < slow.last_callback_duration);
+        assert_eq!(fast.max_callback_duration, slow.max_callback_duration);
+    }
+
+    #[test]
+    fn jitter_is_reset_by_xruns_and_restarts() {
+        let stats = StreamStatsCollector::new();
+        // Starts in the future, so that the intervals between them are exact.
+        let start = Instant::now() + Duration::from_secs(1);
+        // 10 frames last 10ms, but the next callback comes 13ms later.
+        stats.record_callback(start, 10, RATE);
+        stats.record_callback(start + Duration::from_millis(13), 10, RATE);
+        assert_eq!(stats.snapshot().max_jitter, Duration::from_millis(3));
+
+        // The intervals across an xrun or a restart don't count.
+        stats.record_xrun();
+        stats.record_callback(start + Duration::from_millis(100), 10, RATE);
+        stats.restart();
+        stats.record_callback(start + Duration::from_millis(200), 10, RATE);
+        assert_eq!(stats.snapshot().max_jitter, Duration::from_millis(3));
+
+        stats.record_callback(start + Duration::from_millis(215), 10, RATE);
+        assert_eq!(stats.snapshot().max_jitter, Duration::from_millis(5));
+    }
+}
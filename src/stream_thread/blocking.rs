@@ -0,0 +1,292 @@
+//! Streams exchanging a period with their device at a time through blocking reads and writes, on
+//! a thread of their own.
+
+use crate::thread::AudioThread;
+use crate::traits::StreamTrait;
+use crate::watchdog::Watchdog;
+use crate::{
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo,
+    PauseStreamError, PlayStreamError, SampleFormat, SampleRate, StreamError, StreamOptions,
+};
+use std::sync::mpsc::{self, TryRecvError};
+use std::time::Duration;
+
+use super::{input_info, now, output_info, Built, Latency};
+
+/// A device opened in blocking mode, used by the thread of its stream.
+pub(crate) trait Handle: Send + 'static {
+    /// Start the device, as the stream is played.
+    fn start(&mut self) -> Result<(), BackendSpecificError>;
+
+    /// Stop the device, as the stream is paused.
+    fn stop(&mut self) -> Result<(), BackendSpecificError>;
+
+    /// Fill all of `buffer` with the samples recorded by the device.
+    fn read(&mut self, buffer: &mut [u8]) -> Result<(), StreamError>;
+
+    /// Write all of `buffer` to the device, blocking until it has room for it.
+    fn write(&mut self, buffer: &[u8]) -> Result<(), StreamError>;
+
+    /// The frames written but not played yet, or recorded but not read yet.
+    fn delay(&mut self) -> Result<u64, StreamError>;
+}
+
+type Reply = mpsc::Sender<Result<(), BackendSpecificError>>;
+
+// Sent to the thread of the stream, which handles them between periods.
+enum Command {
+    Play(Reply),
+    Pause(Reply),
+    Quit,
+}
+
+pub struct BlockingStream {
+    host: &'static str,
+    commands: mpsc::Sender<Command>,
+    thread: Option<AudioThread>,
+    latency: Latency,
+    // Reports the data callback no longer being invoked, if requested.
+    watchdog: Option<Watchdog>,
+}
+
+/// Build an input stream exchanging samples in `sample_format` at `sample_rate` with the device
+/// returned by `open`, along with a buffer of a period.
+///
+/// `open` is called on the thread of the stream, and `host` prefixes the description of the
+/// errors.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn new_input<H, O, D, E>(
+    host: &'static str,
+    sample_format: SampleFormat,
+    sample_rate: SampleRate,
+    options: &StreamOptions,
+    thread_name: String,
+    watchdog: Option<Watchdog>,
+    open: O,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<BlockingStream, BuildStreamError>
+where
+    H: Handle,
+    O: FnOnce() -> Result<(H, Vec<u8>), BuildStreamError> + Send + 'static,
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let latency = Latency::new();
+    let process_latency = latency.clone();
+    let process = move |handle: &mut H, buffer: &mut [u8]| {
+        handle.read(buffer)?;
+        let delay = frames_to_duration(handle.delay()?, sample_rate);
+        process_latency.set(delay);
+        let len = buffer.len() / sample_format.sample_size();
+        let data = unsafe { Data::from_parts(buffer.as_mut_ptr() as _, len, sample_format) };
+        data_callback(&data, &input_info(now(), delay));
+        Ok(())
+    };
+    BlockingStream::new(
+        host,
+        options,
+        thread_name,
+        watchdog,
+        latency,
+        open,
+        error_callback,
+        process,
+    )
+}
+
+/// Build an output stream exchanging samples in `sample_format` at `sample_rate` with the device
+/// returned by `open`, along with a buffer of a period.
+///
+/// `open` is called on the thread of the stream, and `host` prefixes the description of the
+/// errors.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn new_output<H, O, D, E>(
+    host: &'static str,
+    sample_format: SampleFormat,
+    sample_rate: SampleRate,
+    options: &StreamOptions,
+    thread_name: String,
+    watchdog: Option<Watchdog>,
+    open: O,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<BlockingStream, BuildStreamError>
+where
+    H: Handle,
+    O: FnOnce() -> Result<(H, Vec<u8>), BuildStreamError> + Send + 'static,
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let latency = Latency::new();
+    let process_latency = latency.clone();
+    let process = move |handle: &mut H, buffer: &mut [u8]| {
+        let delay = frames_to_duration(handle.delay()?, sample_rate);
+        process_latency.set(delay);
+        let len = buffer.len() / sample_format.sample_size();
+        let mut data = unsafe { Data::from_parts(buffer.as_mut_ptr() as _, len, sample_format) };
+        data_callback(&mut data, &output_info(now(), delay));
+        handle.write(buffer)
+    };
+    BlockingStream::new(
+        host,
+        options,
+        thread_name,
+        watchdog,
+        latency,
+        open,
+        error_callback,
+        process,
+    )
+}
+
+impl BlockingStream {
+    // Run the stream on a thread of its own, returning once its device is opened.
+    #[allow(clippy::too_many_arguments)]
+    fn new<H, O, E, P>(
+        host: &'static str,
+        options: &StreamOptions,
+        thread_name: String,
+        watchdog: Option<Watchdog>,
+        latency: Latency,
+        open: O,
+        error_callback: E,
+        process: P,
+    ) -> Result<BlockingStream, BuildStreamError>
+    where
+        H: Handle,
+        O: FnOnce() -> Result<(H, Vec<u8>), BuildStreamError> + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+        P: FnMut(&mut H, &mut [u8]) -> Result<(), StreamError> + Send + 'static,
+    {
+        let (commands, commands_rx) = mpsc::channel();
+        let thread = super::spawn(host, options, thread_name, move |built| {
+            run(open, built, commands_rx, error_callback, process)
+        })?;
+        Ok(BlockingStream {
+            host,
+            commands,
+            thread: Some(thread),
+            latency,
+            watchdog,
+        })
+    }
+
+    // Send `command` to the thread of the stream and wait for its reply.
+    fn request(&self, command: impl FnOnce(Reply) -> Command) -> Result<(), BackendSpecificError> {
+        let (result_tx, result_rx) = mpsc::channel();
+        let exited = || BackendSpecificError {
+            description: format!("{}: the thread of the stream has exited", self.host),
+        };
+        self.commands
+            .send(command(result_tx))
+            .map_err(|_| exited())?;
+        result_rx.recv().map_err(|_| exited())?
+    }
+}
+
+impl StreamTrait for BlockingStream {
+    fn play(&self) -> Result<(), PlayStreamError> {
+        self.request(Command::Play)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(true);
+        }
+        Ok(())
+    }
+
+    fn pause(&self) -> Result<(), PauseStreamError> {
+        self.request(Command::Pause)?;
+        if let Some(watchdog) = &self.watchdog {
+            watchdog.set_playing(false);
+        }
+        Ok(())
+    }
+
+    fn latency(&self) -> Option<Duration> {
+        self.latency.get()
+    }
+}
+
+impl Drop for BlockingStream {
+    fn drop(&mut self) {
+        let _ = self.commands.send(Command::Quit);
+        self.thread.take().unwrap().join();
+    }
+}
+
+// The body of the thread of the stream: open its device, report the result through `built`, then
+// exchange a period with the device at a time while playing, until told to quit.
+fn run<H, O, E, P>(
+    open: O,
+    built: Built,
+    commands: mpsc::Receiver<Command>,
+    mut error_callback: E,
+    mut process: P,
+) where
+    H: Handle,
+    O: FnOnce() -> Result<(H, Vec<u8>), BuildStreamError>,
+    E: FnMut(StreamError),
+    P: FnMut(&mut H, &mut [u8]) -> Result<(), StreamError>,
+{
+    let (mut handle, mut buffer) = match open() {
+        Ok(opened) => opened,
+        Err(err) => {
+            let _ = built.send(Err(err));
+            return;
+        }
+    };
+    let _ = built.send(Ok(()));
+
+    let mut playing = false;
+    loop {
+        // Block on the commands while paused, as there is nothing else to do.
+        let command = if playing {
+            match commands.try_recv() {
+                Ok(command) => Some(command),
+                Err(TryRecvError::Empty) => None,
+                Err(TryRecvError::Disconnected) => break,
+            }
+        } else {
+            match commands.recv() {
+                Ok(command) => Some(command),
+                Err(_) => break,
+            }
+        };
+        match command {
+            Some(Command::Play(reply)) => {
+                if !playing {
+                    if let Err(err) = handle.start() {
+                        let _ = reply.send(Err(err));
+                        continue;
+                    }
+                }
+                playing = true;
+                let _ = reply.send(Ok(()));
+            }
+            Some(Command::Pause(reply)) => {
+                if playing {
+                    if let Err(err) = handle.stop() {
+                        let _ = reply.send(Err(err));
+                        continue;
+                    }
+                }
+                playing = false;
+                let _ = reply.send(Ok(()));
+            }
+            Some(Command::Quit) => break,
+            None => {}
+        }
+
+        if playing {
+            if let Err(err) = process(&mut handle, &mut buffer) {
+                error_callback(err);
+                break;
+            }
+        }
+    }
+}
+
+// Convert the given duration in frames at the given sample rate to a `std::time::Duration`.
+fn frames_to_duration(frames: u64, rate: SampleRate) -> Duration {
+    Duration::from_secs_f64(frames as f64 / rate.0 as f64)
+}
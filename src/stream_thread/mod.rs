@@ -0,0 +1,122 @@
+//! The threads of the streams of the hosts that talk to a sound server or device file of a Unix
+//! system: OSS, PipeWire, PulseAudio and sndio.
+
+use crate::thread::AudioThread;
+use crate::{
+    BackendSpecificError, BuildStreamError, InputCallbackInfo, InputStreamTimestamp,
+    OutputCallbackInfo, OutputStreamTimestamp, StreamInstant, StreamOptions,
+};
+use std::mem;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{mpsc, Arc};
+use std::time::Duration;
+
+#[cfg_attr(
+    not(any(
+        target_os = "dragonfly",
+        target_os = "freebsd",
+        all(any(target_os = "linux", target_os = "netbsd"), feature = "sndio")
+    )),
+    allow(dead_code)
+)]
+pub(crate) mod blocking;
+
+// Stored as the latency of streams that have not run yet.
+const UNKNOWN_LATENCY: u64 = u64::MAX;
+
+/// The latency of the last period of a stream, updated by its thread.
+#[derive(Clone)]
+pub(crate) struct Latency(Arc<AtomicU64>);
+
+impl Latency {
+    pub(crate) fn new() -> Self {
+        Latency(Arc::new(AtomicU64::new(UNKNOWN_LATENCY)))
+    }
+
+    pub(crate) fn set(&self, latency: Duration) {
+        self.0.store(latency.as_nanos() as u64, Ordering::Relaxed);
+    }
+
+    /// `None` until the stream has run.
+    pub(crate) fn get(&self) -> Option<Duration> {
+        match self.0.load(Ordering::Relaxed) {
+            UNKNOWN_LATENCY => None,
+            nanos => Some(Duration::from_nanos(nanos)),
+        }
+    }
+}
+
+/// Reports whether the stream was built, from its thread.
+pub(crate) type Built = mpsc::Sender<Result<(), BuildStreamError>>;
+
+/// Run `body` on the thread of a stream, returning once it has reported through its [`Built`]
+/// whether the stream was built.
+///
+/// `body` must not report success before the stream can be played. The thread is joined if it
+/// fails. `host` prefixes the description of the errors.
+pub(crate) fn spawn<F>(
+    host: &str,
+    options: &StreamOptions,
+    thread_name: String,
+    body: F,
+) -> Result<AudioThread, BuildStreamError>
+where
+    F: FnOnce(Built) + Send + 'static,
+{
+    let (built_tx, built_rx) = mpsc::channel();
+    let thread = AudioThread::spawn(options.thread_spawner.as_deref(), thread_name, move || {
+        body(built_tx)
+    })
+    .map_err(|err| BackendSpecificError {
+        description: format!("failed to spawn the audio thread: {}", err),
+    })?;
+    match built_rx.recv() {
+        Ok(Ok(())) => Ok(thread),
+        Ok(Err(err)) => {
+            thread.join();
+            Err(err)
+        }
+        Err(_) => {
+            thread.join();
+            Err(BackendSpecificError {
+                description: format!(
+                    "{}: the thread of the stream exited before it was built",
+                    host
+                ),
+            }
+            .into())
+        }
+    }
+}
+
+/// The time on the monotonic clock of the system, which the servers and devices report their
+/// timing against.
+pub(crate) fn now() -> StreamInstant {
+    let mut now: libc::timespec = unsafe { mem::zeroed() };
+    unsafe { libc::clock_gettime(libc::CLOCK_MONOTONIC, &mut now) };
+    StreamInstant::from_nanos(now.tv_sec as i64 * 1_000_000_000 + now.tv_nsec as i64)
+}
+
+/// The information passed to the data callback of an input stream that is called at `callback`
+/// with samples recorded `delay` earlier.
+pub(crate) fn input_info(callback: StreamInstant, delay: Duration) -> InputCallbackInfo {
+    let capture = callback
+        .sub(delay)
+        .expect("`capture` is earlier than representation supported by `StreamInstant`");
+    InputCallbackInfo {
+        timestamp: InputStreamTimestamp { callback, capture },
+        transport: None,
+    }
+}
+
+/// The information passed to the data callback of an output stream that is called at `callback`
+/// for samples played `delay` later.
+pub(crate) fn output_info(callback: StreamInstant, delay: Duration) -> OutputCallbackInfo {
+    let playback = callback
+        .add(delay)
+        .expect("`playback` occurs beyond representation supported by `StreamInstant`");
+    OutputCallbackInfo {
+        timestamp: OutputStreamTimestamp { callback, playback },
+        transport: None,
+    }
+}
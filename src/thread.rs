@@ -0,0 +1,148 @@
+//! Control over the threads on which hosts run stream callbacks.
+
+use crate::{BuildStreamError, StreamOptions};
+use std::io;
+use std::sync::mpsc;
+use std::thread::{self, JoinHandle};
+
+/// Spawns the thread on which a stream runs its callbacks.
+///
+/// This is only used by hosts where CPAL owns the audio thread (currently ALSA, OSS, PipeWire,
+/// PulseAudio, sndio and WASAPI), and by WebAudio output streams in modules built with shared
+/// memory. Hosts whose callbacks are driven by the system ignore it.
+///
+/// An implementation may spawn a dedicated thread (e.g. to pin it to an isolated core or to pick
+/// a stack size) or hand `body` to a thread from an existing pool. `body` runs the stream's event
+/// loop and only returns once the stream is dropped, so it must not be run on the calling thread
+/// and the thread should not be used for anything else in the meantime.
+///
+/// Any `Fn(&str, Box<dyn FnOnce() + Send>) -> io::Result<()>` closure implements this trait:
+///
+/// ```no_run
+/// use std::sync::Arc;
+/// use cpal::StreamOptions;
+///
+/// let options = StreamOptions {
+///     thread_spawner: Some(Arc::new(|name: &str, body: Box<dyn FnOnce() + Send>| {
+///         std::thread::Builder::new()
+///             .name(name.to_owned())
+///             .stack_size(256 * 1024)
+///             .spawn(move || {
+///                 // Set the CPU affinity of the current thread here.
+///                 body()
+///             })
+///             .map(|_| ())
+///     })),
+///     ..Default::default()
+/// };
+/// ```
+pub trait AudioThreadSpawner: Send + Sync {
+    /// Run `body` on an audio thread.
+    ///
+    /// `name` is the name CPAL would have given the thread.
+    fn spawn(&self, name: &str, body: Box<dyn FnOnce() + Send>) -> io::Result<()>;
+}
+
+impl<F> AudioThreadSpawner for F
+where
+    F: Fn(&str, Box<dyn FnOnce() + Send>) -> io::Result<()> + Send + Sync,
+{
+    fn spawn(&self, name: &str, body: Box<dyn FnOnce() + Send>) -> io::Result<()> {
+        self(name, body)
+    }
+}
+
+/// The name of the thread running a stream's callbacks, `default` unless set in `options`.
+///
+/// Default names are kept within the 15 bytes Linux allows for thread names. Fails with
+/// [`BuildStreamError::InvalidArgument`] if the name contains a NUL byte, which thread names
+/// can't hold.
+pub(crate) fn thread_name(
+    options: &StreamOptions,
+    default: &str,
+) -> Result<String, BuildStreamError> {
+    match options.thread_name {
+        Some(ref name) if name.contains('\0') => Err(BuildStreamError::InvalidArgument),
+        Some(ref name) => Ok(name.clone()),
+        None => Ok(default.to_owned()),
+    }
+}
+
+/// A thread running a stream's event loop, spawned either by CPAL or by an
+/// [`AudioThreadSpawner`].
+pub(crate) enum AudioThread {
+    Std(JoinHandle<()>),
+    // Disconnected once the body has returned or unwound.
+    Spawned(mpsc::Receiver<()>),
+}
+
+impl AudioThread {
+    pub(crate) fn spawn<F>(
+        spawner: Option<&dyn AudioThreadSpawner>,
+        name: String,
+        body: F,
+    ) -> io::Result<Self>
+    where
+        F: FnOnce() + Send + 'static,
+    {
+        match spawner {
+            None => thread::Builder::new()
+                .name(name)
+                .spawn(body)
+                .map(AudioThread::Std),
+            Some(spawner) => {
+                let (done_tx, done_rx) = mpsc::channel();
+                spawner.spawn(
+                    &name,
+                    Box::new(move || {
+                        let _done_tx = done_tx;
+                        body();
+                    }),
+                )?;
+                Ok(AudioThread::Spawned(done_rx))
+            }
+        }
+    }
+
+    /// Block until the body of the thread has returned.
+    pub(crate) fn join(self) {
+        match self {
+            AudioThread::Std(handle) => handle.join().unwrap(),
+            AudioThread::Spawned(done) => {
+                let _ = done.recv();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::thread_name;
+    use crate::{BuildStreamError, StreamOptions};
+
+    #[test]
+    fn defaults_unless_named() {
+        let options = StreamOptions::default();
+        assert_eq!(
+            thread_name(&options, "cpal_alsa_in").unwrap(),
+            "cpal_alsa_in"
+        );
+        let options = StreamOptions {
+            thread_name: Some("synth".to_owned()),
+            ..Default::default()
+        };
+        assert_eq!(thread_name(&options, "cpal_alsa_in").unwrap(), "synth");
+    }
+
+    #[test]
+    fn rejects_nul_bytes() {
+        let options = StreamOptions {
+            thread_name: Some("syn\0th".to_owned()),
+            ..Default::default()
+        };
+        assert!(matches!(
+            thread_name(&options, "cpal_alsa_in"),
+            Err(BuildStreamError::InvalidArgument)
+        ));
+    }
+}
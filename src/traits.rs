@@ -2,11 +2,14 @@
 
 use std::time::Duration;
 
+use crate::dsd;
+use crate::fallback::{self, Lender};
 use crate::{
-    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError,
+    BuildStreamError, Data, DefaultStreamConfigError, DeviceNameError, DevicesError, DsdMode,
     InputCallbackInfo, InputDevices, OutputCallbackInfo, OutputDevices, PauseStreamError,
-    PlayStreamError, SampleFormat, SizedSample, StreamConfig, StreamError, SupportedStreamConfig,
-    SupportedStreamConfigRange, SupportedStreamConfigsError,
+    PlayStreamError, SampleFormat, ShareMode, SignalPath, SizedSample, StreamConfig, StreamError,
+    StreamOptions, StreamStats, SupportedStreamConfig, SupportedStreamConfigRange,
+    SupportedStreamConfigsError,
 };
 
 /// A [`Host`] provides access to the available audio devices on the system.
@@ -122,9 +125,37 @@ pub trait DeviceTrait {
     fn default_output_config(&self) -> Result<SupportedStreamConfig, DefaultStreamConfigError>;
 
     /// Create an input stream.
+    ///
+    /// If `timeout` is `Some`, [`StreamError::Stalled`] is reported through the error callback
+    /// whenever the device delivers no data for that long while the stream is playing. The web and
+    /// WASI hosts have no way of detecting this: they ignore it, logging a warning with the `log`
+    /// feature.
     fn build_input_stream<T, D, E>(
         &self,
         config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: SizedSample,
+        D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_input_stream_with_options(
+            config,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an input stream using the given additional [`StreamOptions`].
+    fn build_input_stream_with_options<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        options: &StreamOptions,
         mut data_callback: D,
         error_callback: E,
         timeout: Option<Duration>,
@@ -134,9 +165,10 @@ pub trait DeviceTrait {
         D: FnMut(&[T], &InputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        self.build_input_stream_raw(
+        self.build_input_stream_raw_with_options(
             config,
             T::FORMAT,
+            options,
             move |data, info| {
                 data_callback(
                     data.as_slice()
@@ -150,9 +182,34 @@ pub trait DeviceTrait {
     }
 
     /// Create an output stream.
+    ///
+    /// See [`build_input_stream`](Self::build_input_stream) for the meaning of `timeout`.
     fn build_output_stream<T, D, E>(
         &self,
         config: &StreamConfig,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        T: SizedSample,
+        D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        self.build_output_stream_with_options(
+            config,
+            &StreamOptions::default(),
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create an output stream using the given additional [`StreamOptions`].
+    fn build_output_stream_with_options<T, D, E>(
+        &self,
+        config: &StreamConfig,
+        options: &StreamOptions,
         mut data_callback: D,
         error_callback: E,
         timeout: Option<Duration>,
@@ -162,9 +219,10 @@ pub trait DeviceTrait {
         D: FnMut(&mut [T], &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static,
     {
-        self.build_output_stream_raw(
+        self.build_output_stream_raw_with_options(
             config,
             T::FORMAT,
+            options,
             move |data, info| {
                 data_callback(
                     data.as_slice_mut()
@@ -177,6 +235,71 @@ pub trait DeviceTrait {
         )
     }
 
+    /// Create a dynamically typed input stream from the first of the `candidates` that the device
+    /// accepts, trying them in order.
+    ///
+    /// Returns the stream along with the index of the candidate it was built from. When no
+    /// candidate is accepted, fails with the error of the last one, or
+    /// [`BuildStreamError::StreamConfigNotSupported`] if there are none.
+    ///
+    /// The callbacks are handed from one attempt to the next. See
+    /// [`build_input_stream`](Self::build_input_stream) for the meaning of `timeout`.
+    fn build_input_stream_with_fallback<D, E>(
+        &self,
+        candidates: &[(StreamConfig, SampleFormat)],
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<(Self::Stream, usize), BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let data_callback = Lender::new(data_callback);
+        let error_callback = Lender::new(error_callback);
+        fallback::build_first(candidates, |config, sample_format| {
+            self.build_input_stream_raw_with_options(
+                config,
+                sample_format,
+                options,
+                data_callback.input(),
+                error_callback.error(),
+                timeout,
+            )
+        })
+    }
+
+    /// Create a dynamically typed output stream from the first of the `candidates` that the
+    /// device accepts, trying them in order.
+    ///
+    /// See [`build_input_stream_with_fallback`](Self::build_input_stream_with_fallback).
+    fn build_output_stream_with_fallback<D, E>(
+        &self,
+        candidates: &[(StreamConfig, SampleFormat)],
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<(Self::Stream, usize), BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let data_callback = Lender::new(data_callback);
+        let error_callback = Lender::new(error_callback);
+        fallback::build_first(candidates, |config, sample_format| {
+            self.build_output_stream_raw_with_options(
+                config,
+                sample_format,
+                options,
+                data_callback.output(),
+                error_callback.error(),
+                timeout,
+            )
+        })
+    }
+
     /// Create a dynamically typed input stream.
     fn build_input_stream_raw<D, E>(
         &self,
@@ -202,6 +325,90 @@ pub trait DeviceTrait {
     where
         D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
         E: FnMut(StreamError) + Send + 'static;
+
+    /// Create a dynamically typed input stream using the given additional [`StreamOptions`].
+    ///
+    /// The default implementation ignores `options`, except for failing with
+    /// [`BuildStreamError::StreamConfigNotSupported`] if [`ShareMode::Exclusive`], a
+    /// [passthrough](StreamOptions::passthrough) format, [DSD](StreamOptions::dsd) or a subset of
+    /// the [device's channels](StreamOptions::device_channels) is requested. Hosts supporting any
+    /// of the options override this method.
+    fn build_input_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        self.build_input_stream_raw(
+            config,
+            sample_format,
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
+
+    /// Create a dynamically typed output stream using the given additional [`StreamOptions`].
+    ///
+    /// The default implementation ignores `options`, except for failing with
+    /// [`BuildStreamError::StreamConfigNotSupported`] if [`ShareMode::Exclusive`], a
+    /// [passthrough](StreamOptions::passthrough) format, native [DSD](StreamOptions::dsd) or a
+    /// subset of the [device's channels](StreamOptions::device_channels) is requested. DoP is
+    /// played through [`build_output_stream_raw`](Self::build_output_stream_raw).
+    /// Hosts supporting any of the options override this method.
+    fn build_output_stream_raw_with_options<D, E>(
+        &self,
+        config: &StreamConfig,
+        sample_format: SampleFormat,
+        options: &StreamOptions,
+        data_callback: D,
+        error_callback: E,
+        timeout: Option<Duration>,
+    ) -> Result<Self::Stream, BuildStreamError>
+    where
+        D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        if options.dsd == Some(DsdMode::DoP) {
+            return dsd::build_dop_output_stream(
+                self,
+                config,
+                sample_format,
+                options,
+                data_callback,
+                error_callback,
+                timeout,
+            );
+        }
+        if options.share_mode == ShareMode::Exclusive
+            || options.passthrough.is_some()
+            || options.dsd.is_some()
+            || options.device_channels.is_some()
+        {
+            return Err(BuildStreamError::StreamConfigNotSupported);
+        }
+        self.build_output_stream_raw(
+            config,
+            sample_format,
+            data_callback,
+            error_callback,
+            timeout,
+        )
+    }
 }
 
 /// A stream created from [`Device`](DeviceTrait), with methods to control playback.
@@ -218,4 +425,31 @@ pub trait StreamTrait {
     /// Note: Not all devices support suspending the stream at the hardware level. This method may
     /// fail in these cases.
     fn pause(&self) -> Result<(), PauseStreamError>;
+
+    /// Runtime statistics of the stream, such as the number of callbacks, their duration and the
+    /// number of buffer underruns or overruns.
+    ///
+    /// Reading the statistics does not interfere with the audio thread. Returns `None` if the host
+    /// does not collect statistics (currently only ALSA and WASAPI do).
+    fn stats(&self) -> Option<StreamStats> {
+        None
+    }
+
+    /// Whether the stream is bit-perfect and the format the hardware is running at.
+    ///
+    /// Returns `None` if the host can't tell (currently only ALSA, WASAPI and CoreAudio on macOS
+    /// can).
+    fn signal_path(&self) -> Option<SignalPath> {
+        None
+    }
+
+    /// The time between the data callback being invoked and its samples being played by, or the
+    /// first of its samples having been captured by, the hardware of the device. The timestamps
+    /// passed to the data callback include the same latency.
+    ///
+    /// Returns `None` if the host can't tell (currently only CoreAudio on macOS, JACK, OSS,
+    /// PipeWire, PulseAudio, sndio and WebAudio, for output streams, can).
+    fn latency(&self) -> Option<Duration> {
+        None
+    }
 }
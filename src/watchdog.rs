@@ -0,0 +1,313 @@
+//! Detection of streams whose data callback has silently stopped being invoked.
+
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant};
+
+use crate::{
+    BackendSpecificError, BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo,
+    StreamError,
+};
+
+/// Watches a stream from a separate thread and reports [`StreamError::Stalled`] through the
+/// stream's error callback when no data callback has been invoked for `timeout` while playing.
+///
+/// The watchdog thread is stopped when the `Watchdog` is dropped.
+pub(crate) struct Watchdog {
+    shared: Arc<Shared>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Shared {
+    // Bumped by every data callback and whenever the stream starts playing.
+    beats: AtomicU64,
+    playing: AtomicBool,
+    stopped: Mutex<bool>,
+    stop: Condvar,
+}
+
+impl Watchdog {
+    fn spawn<E>(timeout: Duration, error_callback: Arc<Mutex<E>>) -> Result<Self, BuildStreamError>
+    where
+        E: FnMut(StreamError) + Send + 'static,
+    {
+        let shared = Arc::new(Shared {
+            beats: AtomicU64::new(0),
+            playing: AtomicBool::new(false),
+            stopped: Mutex::new(false),
+            stop: Condvar::new(),
+        });
+        let thread_shared = shared.clone();
+        let thread = thread::Builder::new()
+            .name("cpal_watchdog".to_owned())
+            .spawn(move || run(&thread_shared, timeout, &error_callback))
+            .map_err(|err| BackendSpecificError {
+                description: format!("failed to spawn the watchdog thread: {}", err),
+            })?;
+        Ok(Watchdog {
+            shared,
+            thread: Some(thread),
+        })
+    }
+
+    /// Must be called whenever the stream is played or paused.
+    pub(crate) fn set_playing(&self, playing: bool) {
+        self.shared.set_playing(playing);
+    }
+}
+
+/// Lets other threads tell a [`Watchdog`] that its stream was played or paused, e.g. by the
+/// system.
+// Not all hosts pause streams by themselves.
+#[allow(dead_code)]
+#[derive(Clone)]
+pub(crate) struct WatchdogHandle(Arc<Shared>);
+
+#[allow(dead_code)]
+impl Watchdog {
+    pub(crate) fn handle(&self) -> WatchdogHandle {
+        WatchdogHandle(self.shared.clone())
+    }
+}
+
+#[allow(dead_code)]
+impl WatchdogHandle {
+    pub(crate) fn set_playing(&self, playing: bool) {
+        self.0.set_playing(playing);
+    }
+}
+
+impl Shared {
+    fn set_playing(&self, playing: bool) {
+        // Don't count the time spent paused towards the timeout.
+        self.beats.fetch_add(1, Ordering::Relaxed);
+        self.playing.store(playing, Ordering::Relaxed);
+    }
+}
+
+impl Drop for Watchdog {
+    fn drop(&mut self) {
+        *self.shared.stopped.lock().unwrap() = true;
+        self.shared.stop.notify_one();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+fn run<E>(shared: &Shared, timeout: Duration, error_callback: &Mutex<E>)
+where
+    E: FnMut(StreamError),
+{
+    let mut last_beats = shared.beats.load(Ordering::Relaxed);
+    let mut reported = false;
+    let mut stopped = shared.stopped.lock().unwrap();
+    let mut deadline = Instant::now() + timeout;
+    loop {
+        // Wait for the whole timeout, as `wait_timeout` may wake up early.
+        let now = Instant::now();
+        if now < deadline {
+            stopped = shared.stop.wait_timeout(stopped, deadline - now).unwrap().0;
+            if *stopped {
+                return;
+            }
+            continue;
+        }
+        deadline = now + timeout;
+        let beats = shared.beats.load(Ordering::Relaxed);
+        if beats != last_beats {
+            last_beats = beats;
+            reported = false;
+        } else if shared.playing.load(Ordering::Relaxed) && !reported {
+            // Only report each stall once.
+            reported = true;
+            if let Ok(mut error_callback) = error_callback.lock() {
+                error_callback(StreamError::Stalled);
+            }
+        }
+    }
+}
+
+/// Wraps the callbacks of an input stream so that they feed a [`Watchdog`], if `timeout` is
+/// `Some`.
+///
+/// The returned callbacks must be used to build the stream.
+#[allow(clippy::type_complexity)]
+pub(crate) fn watch_input<D, E>(
+    timeout: Option<Duration>,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<
+    (
+        Option<Watchdog>,
+        impl FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+        impl FnMut(StreamError) + Send + 'static,
+    ),
+    BuildStreamError,
+>
+where
+    D: FnMut(&Data, &InputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let (watchdog, beats, error_callback) = watch(timeout, error_callback)?;
+    let data_callback = move |data: &Data, info: &InputCallbackInfo| {
+        if let Some(shared) = &beats {
+            shared.beats.fetch_add(1, Ordering::Relaxed);
+        }
+        data_callback(data, info)
+    };
+    Ok((watchdog, data_callback, error_callback))
+}
+
+/// Wraps the callbacks of an output stream so that they feed a [`Watchdog`], if `timeout` is
+/// `Some`.
+///
+/// The returned callbacks must be used to build the stream.
+#[allow(clippy::type_complexity)]
+pub(crate) fn watch_output<D, E>(
+    timeout: Option<Duration>,
+    mut data_callback: D,
+    error_callback: E,
+) -> Result<
+    (
+        Option<Watchdog>,
+        impl FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+        impl FnMut(StreamError) + Send + 'static,
+    ),
+    BuildStreamError,
+>
+where
+    D: FnMut(&mut Data, &OutputCallbackInfo) + Send + 'static,
+    E: FnMut(StreamError) + Send + 'static,
+{
+    let (watchdog, beats, error_callback) = watch(timeout, error_callback)?;
+    let data_callback = move |data: &mut Data, info: &OutputCallbackInfo| {
+        if let Some(shared) = &beats {
+            shared.beats.fetch_add(1, Ordering::Relaxed);
+        }
+        data_callback(data, info)
+    };
+    Ok((watchdog, data_callback, error_callback))
+}
+
+#[allow(clippy::type_complexity)]
+fn watch<E>(
+    timeout: Option<Duration>,
+    error_callback: E,
+) -> Result<
+    (
+        Option<Watchdog>,
+        Option<Arc<Shared>>,
+        impl FnMut(StreamError) + Send + 'static,
+    ),
+    BuildStreamError,
+>
+where
+    E: FnMut(StreamError) + Send + 'static,
+{
+    if timeout == Some(Duration::ZERO) {
+        return Err(BuildStreamError::InvalidArgument);
+    }
+    let error_callback = Arc::new(Mutex::new(error_callback));
+    let watchdog = match timeout {
+        Some(timeout) => Some(Watchdog::spawn(timeout, error_callback.clone())?),
+        None => None,
+    };
+    let beats = watchdog.as_ref().map(|w| w.shared.clone());
+    let error_callback = move |err: StreamError| {
+        if let Ok(mut error_callback) = error_callback.lock() {
+            error_callback(err)
+        }
+    };
+    Ok((watchdog, beats, error_callback))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{watch_input, watch_output};
+    use crate::{
+        BuildStreamError, Data, InputCallbackInfo, OutputCallbackInfo, OutputStreamTimestamp,
+        SampleFormat, StreamError, StreamInstant,
+    };
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    const TIMEOUT: Duration = Duration::from_millis(5);
+
+    // The errors reported through the error callback of a watched output stream.
+    fn errors() -> (
+        Arc<Mutex<Vec<StreamError>>>,
+        impl FnMut(StreamError) + Send + 'static,
+    ) {
+        let errors = Arc::new(Mutex::new(Vec::new()));
+        let callback_errors = errors.clone();
+        let error_callback = move |err| callback_errors.lock().unwrap().push(err);
+        (errors, error_callback)
+    }
+
+    #[test]
+    fn reports_stall_once() {
+        let (errors, error_callback) = errors();
+        let (watchdog, mut data_callback, _) = watch_output(
+            Some(TIMEOUT),
+            |_: &mut Data, _: &OutputCallbackInfo| {},
+            error_callback,
+        )
+        .unwrap();
+        let watchdog = watchdog.unwrap();
+        watchdog.set_playing(true);
+        let mut samples = [0.0f32; 2];
+        let mut data = unsafe {
+            Data::from_parts(samples.as_mut_ptr() as _, samples.len(), SampleFormat::F32)
+        };
+        let info = OutputCallbackInfo {
+            timestamp: OutputStreamTimestamp {
+                callback: StreamInstant::new(0, 0),
+                playback: StreamInstant::new(0, 0),
+            },
+            transport: None,
+        };
+        data_callback(&mut data, &info);
+        thread::sleep(TIMEOUT * 20);
+        drop(watchdog);
+        let errors = errors.lock().unwrap();
+        assert_eq!(errors.len(), 1);
+        assert!(matches!(errors[0], StreamError::Stalled));
+    }
+
+    #[test]
+    fn ignores_paused_streams() {
+        let (errors, error_callback) = errors();
+        let (watchdog, _, _) = watch_output(
+            Some(TIMEOUT),
+            |_: &mut Data, _: &OutputCallbackInfo| {},
+            error_callback,
+        )
+        .unwrap();
+        let watchdog = watchdog.unwrap();
+        watchdog.set_playing(true);
+        watchdog.set_playing(false);
+        thread::sleep(TIMEOUT * 20);
+        drop(watchdog);
+        assert!(errors.lock().unwrap().is_empty());
+    }
+
+    #[test]
+    fn rejects_zero_timeout() {
+        let result = watch_input(
+            Some(Duration::ZERO),
+            |_: &Data, _: &InputCallbackInfo| {},
+            |_| {},
+        );
+        assert!(matches!(result, Err(BuildStreamError::InvalidArgument)));
+    }
+
+    #[test]
+    fn no_watchdog_without_timeout() {
+        let (watchdog, _, _) =
+            watch_input(None, |_: &Data, _: &InputCallbackInfo| {}, |_| {}).unwrap();
+        assert!(watchdog.is_none());
+    }
+}